@@ -0,0 +1,31 @@
+//! Runs the input through the whole assembler in every dialect: parsing, optimizing, assembling,
+//! applying the levels and fixes, and printing the errors. Programs start at a typical address and
+//! right before the end of the address space, and ORG directives place sections anywhere else.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use m68k_reloaded_assembler::assemble::assemble;
+use m68k_reloaded_assembler::peephole::{optimize, Rule};
+use m68k_reloaded_common::errors::{apply_fixes, Levels, PrintErrors};
+use m68k_reloaded_parser::parse::parse_dialect;
+use m68k_reloaded_scanner::{scan_dialect, Dialect, Token};
+
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    for dialect in Dialect::ALL.iter() {
+        for origin in &[0x1000, 0xffff_fffe] {
+            let mut errors = vec![];
+            let tokens: Vec<Token> = scan_dialect(source, *dialect, &mut errors).collect();
+            let mut program = parse_dialect(tokens, *dialect, &mut errors);
+            optimize(&mut program, &Rule::ALL, &mut errors);
+            assemble(&program, *origin, &mut errors);
+            Levels::new().apply(source, &mut errors);
+            apply_fixes(source, &errors);
+            errors.print();
+        }
+    }
+});
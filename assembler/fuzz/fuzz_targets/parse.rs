@@ -0,0 +1,19 @@
+//! Parses the input in every dialect.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use m68k_reloaded_parser::parse::parse_dialect;
+use m68k_reloaded_scanner::{scan_dialect, Dialect, Token};
+
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    for dialect in Dialect::ALL.iter() {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan_dialect(source, *dialect, &mut errors).collect();
+        parse_dialect(tokens, *dialect, &mut errors);
+    }
+});
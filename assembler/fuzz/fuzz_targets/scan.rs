@@ -0,0 +1,31 @@
+//! Scans the input in every dialect and checks that every byte ends up in exactly one token or
+//! error.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use m68k_reloaded_scanner::{scan_dialect, Dialect, Token};
+
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    for dialect in Dialect::ALL.iter() {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan_dialect(source, *dialect, &mut errors).collect();
+        let mut ranges: Vec<_> = tokens
+            .iter()
+            .map(Token::range)
+            .chain(errors.iter().map(|error| error.range.clone()))
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        let mut end = 0;
+        for range in ranges {
+            assert_eq!(range.start, end);
+            assert!(range.start < range.end);
+            end = range.end;
+        }
+        assert_eq!(end, source.len());
+    }
+});
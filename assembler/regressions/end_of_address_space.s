@@ -0,0 +1,9 @@
+ ORG $FFFFFFFC
+ MOVE.L #1,D0
+ BRA.S end
+end RTS
+ SECTION s_a,DATA
+ ORG $FFFFFFFE
+ NOP
+ SECTION s_b,BSS
+ ORG $FFFFFF00
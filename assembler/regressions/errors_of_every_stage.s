@@ -0,0 +1,8 @@
+ MOVE.W #$,D0
+ ADD.Q D0,D1
+ ADD.B 200(A0,D0),D1
+ BRA.L start
+ ADD.B D0,A0
+ JSR undefined
+start
+start RTS ; m68k-allow(long_branch, optimized)
@@ -0,0 +1,9 @@
+rts
+movel: bras movel
+	movel	%d0,rts
+ d0: MOVE.L D0,D1
+sp
+ BRA.S rts
+.add ADD.W #1,move
+move
+ XDEF rts,movel
@@ -0,0 +1,4 @@
+* Kommentar mit Umlauten: äöü
+start	MOVE.W	D0,D1 ; ü
+	ADD.L	D0,ä
+ende	RTS ; 漢語 🇺🇸
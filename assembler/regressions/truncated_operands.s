@@ -0,0 +1,9 @@
+ MOVE.W (
+ ADD.W 4(
+ LEA (4,
+ CLR -(
+ MOVE.L #
+ SECTION
+ XDEF
+ BNE.
+ ADD.W D0,
\ No newline at end of file
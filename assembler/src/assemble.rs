@@ -0,0 +1,715 @@
+//! Assembles a program into machine code at fixed addresses, without going through an object file
+//! and the linker. This is what the simulator runs and flat images contain. Programs that are
+//! linked with others are assembled into relocatable objects instead.
+
+use crate::encoding::{encode, label_references};
+use crate::layout::{lay_out, Layout};
+use crate::timing::{operation_timing, Timing};
+use crate::validation::validate;
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use m68k_reloaded_common::{Byte, LongWord, Range};
+use m68k_reloaded_linker::script::Script;
+use m68k_reloaded_linker::{link, Input};
+use m68k_reloaded_object::image::Segment;
+use m68k_reloaded_object::prg::Executable;
+use m68k_reloaded_object::{Object, Relocation, RelocationKind, Section, Symbol};
+use m68k_reloaded_parser::sections::split_into_sections;
+use m68k_reloaded_parser::statements::*;
+use m68k_reloaded_parser::symbols::{build_symbol_table, qualify, Visibility};
+use std::collections::{HashMap, HashSet};
+
+/// An operation and where it ended up in memory.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SourceLine {
+    pub address: LongWord,
+    pub size: LongWord,
+    /// The range of the operation's statement in the source.
+    pub range: Range,
+    pub timing: Option<Timing>,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct Assembled {
+    pub origin: LongWord,
+    /// The sections in the order they appear. Each one is placed at the address of its `ORG`, or
+    /// right after the section before it. The first one starts at `origin` without an `ORG`.
+    pub segments: Vec<Segment>,
+    /// The absolute addresses of all labels by their qualified names.
+    pub labels: HashMap<Label, LongWord>,
+    /// All operations, sorted by address.
+    pub lines: Vec<SourceLine>,
+    /// References to imported labels, which are left as zeros. Their section is the type of the
+    /// section they're in, but their offset is the address of the reference.
+    pub relocations: Vec<Relocation>,
+}
+
+impl Assembled {
+    /// The operation that contains the address.
+    pub fn line_at(&self, address: LongWord) -> Option<&SourceLine> {
+        self.lines
+            .iter()
+            .find(|line| (line.address..line.address + line.size).contains(&address))
+    }
+
+    /// The label at the address. If several labels point to the address, the first one in
+    /// alphabetical order is returned, so the result doesn't depend on the order of the map.
+    pub fn label_at(&self, address: LongWord) -> Option<&str> {
+        self.labels
+            .iter()
+            .filter(|(_, &label_address)| label_address == address)
+            .map(|(label, _)| label.as_str())
+            .min()
+    }
+
+    /// Reports every reference to an imported label. Programs that run without being linked,
+    /// like in the simulator, can't have any.
+    pub fn report_imports(&self, errors: &mut ErrorCollector) {
+        for relocation in &self.relocations {
+            if let Some(line) = self.line_at(relocation.offset) {
+                errors.push(Error::unresolved_import(
+                    line.range.clone(),
+                    &relocation.symbol,
+                ));
+            }
+        }
+    }
+}
+
+/// A reference that [encode_section] couldn't resolve.
+struct Unresolved {
+    /// The offset relative to the start of the section.
+    offset: LongWord,
+    kind: RelocationKind,
+    /// The qualified name of the label.
+    label: Label,
+}
+
+/// Encodes the operations of a laid out section into `bytes`, which starts at `start`, and
+/// appends them to `lines`. `resolve` looks up labels by their qualified names. References it
+/// doesn't know are left as zeros and returned, so they can become relocations. The scope of
+/// local labels carries over from the previous section.
+fn encode_section<'a>(
+    layout: &Layout<'a>,
+    start: LongWord,
+    resolve: &dyn Fn(&str, RelocationKind) -> Option<LongWord>,
+    scope: &mut Option<&'a str>,
+    bytes: &mut [Byte],
+    lines: &mut Vec<SourceLine>,
+) -> Vec<Unresolved> {
+    let mut unresolved = vec![];
+    for placed in &layout.statements {
+        let address = start + placed.address;
+        let operation = match &placed.statement.value {
+            Statement::Label(name) => {
+                if LabelScope::of(name) == LabelScope::Global {
+                    *scope = Some(name);
+                }
+                continue;
+            }
+            Statement::Operation(operation) => operation,
+            _ => continue,
+        };
+        lines.push(SourceLine {
+            address,
+            size: placed.size,
+            range: placed.statement.range.clone(),
+            timing: operation_timing(operation, placed.branch),
+        });
+
+        // Unknown labels are encoded as zeros and patched once they're known.
+        let kind = if operation.operation_type.is_branch() {
+            RelocationKind::PcRelative16
+        } else {
+            RelocationKind::Absolute32
+        };
+        let scope = *scope;
+        let placeholder = |name: &str| {
+            qualify(name, scope)
+                .and_then(|name| resolve(&name, kind))
+                .or(Some(address + 2))
+        };
+        let words = match encode(operation, address, placed.branch, &placeholder) {
+            Some(words) => words,
+            None => continue,
+        };
+        let offset = placed.address as usize;
+        for (index, word) in words.iter().enumerate() {
+            bytes[offset + 2 * index..offset + 2 * index + 2].copy_from_slice(&word.to_be_bytes());
+        }
+        for reference in label_references(operation, placed.branch) {
+            let label = match qualify(reference.label, scope) {
+                Some(label) => label,
+                None => continue,
+            };
+            if resolve(&label, reference.kind).is_some() {
+                continue;
+            }
+            let offset = placed.address + reference.offset;
+            let size = reference.kind.size() as usize;
+            bytes[offset as usize..offset as usize + size].fill(0);
+            unresolved.push(Unresolved {
+                offset,
+                kind: reference.kind,
+                label,
+            });
+        }
+    }
+    unresolved
+}
+
+/// The object file section that a section of the given type ends up in.
+fn object_section(section_type: SectionType) -> Section {
+    match section_type {
+        SectionType::Code => Section::Text,
+        SectionType::Data => Section::Data,
+        SectionType::Bss => Section::Bss,
+    }
+}
+
+/// Validates, lays out and encodes all sections of the program. Operations that can't be encoded
+/// are reported and filled with zeros, so the addresses of the following ones don't change.
+/// References to imported labels become relocations. Sections that overlap are only reported when
+/// they're put into an image.
+pub fn assemble(program: &Program, origin: LongWord, errors: &mut ErrorCollector) -> Assembled {
+    validate(program, errors);
+    let symbols = build_symbol_table(program, errors);
+    let imports: HashSet<&str> = symbols
+        .external_references()
+        .map(|reference| reference.name.as_str())
+        .collect();
+
+    let sections = split_into_sections(program, errors);
+    let mut layouts: Vec<(LongWord, Layout)> = vec![];
+    let mut labels = HashMap::new();
+    let mut address = origin;
+    for section in &sections {
+        if let Some(org) = section.address {
+            address = org.value;
+        }
+        let layout = lay_out(&section.statements, address, errors);
+        let size = layout.size;
+        // Sections start at even addresses, like in the linker.
+        let next = match address
+            .checked_add(size)
+            .and_then(|end| end.checked_add(size & 1))
+        {
+            Some(next) => next,
+            None => {
+                let overflowing = layout
+                    .statements
+                    .iter()
+                    .find(|placed| address.checked_add(placed.address + placed.size).is_none())
+                    .or_else(|| layout.statements.last())
+                    .unwrap();
+                errors.push(Error::section_exceeds_address_space(
+                    overflowing.statement.range.clone(),
+                    &section.name,
+                ));
+                break;
+            }
+        };
+        for (label, label_offset) in &layout.labels {
+            labels.insert(label.clone(), address + label_offset);
+        }
+        layouts.push((address, layout));
+        address = next;
+    }
+
+    let mut segments = vec![];
+    let mut lines = vec![];
+    let mut relocations = vec![];
+    let mut scope: Option<&str> = None;
+    let resolve = |label: &str, _| labels.get(label).copied();
+    for ((start, layout), section) in layouts.iter().zip(&sections) {
+        let mut bytes = vec![0; layout.size as usize];
+        for unresolved in
+            encode_section(layout, *start, &resolve, &mut scope, &mut bytes, &mut lines)
+        {
+            // References to undefined labels are already reported.
+            if imports.contains(unresolved.label.as_str()) {
+                relocations.push(Relocation {
+                    section: object_section(section.section_type),
+                    offset: start + unresolved.offset,
+                    kind: unresolved.kind,
+                    symbol: unresolved.label,
+                    addend: 0,
+                });
+            }
+        }
+        segments.push(Segment {
+            address: *start,
+            bytes,
+        });
+    }
+    lines.sort_by_key(|line| line.address);
+
+    Assembled {
+        origin,
+        segments,
+        labels,
+        lines,
+        relocations,
+    }
+}
+
+/// Assembles the program into a relocatable object. Sections of the same type are merged in the
+/// order they appear. Every label becomes a symbol, and every reference to a label becomes a
+/// relocation, except for branches within their own section. Branches to absolute addresses are
+/// reported, because moving the object would change their distance.
+pub fn assemble_object(program: &Program, errors: &mut ErrorCollector) -> Object {
+    validate(program, errors);
+    let symbols = build_symbol_table(program, errors);
+
+    let sections = split_into_sections(program, errors);
+    for address in sections.iter().filter_map(|section| section.address) {
+        errors.push(Error::org_in_relocatable_output(address.range.clone()));
+    }
+    for statement in sections.iter().flat_map(|section| &section.statements) {
+        if let Statement::Operation(operation) = &statement.value {
+            let is_absolute = matches!(
+                operation.operands.first().map(|operand| &operand.value),
+                Some(EffectiveAddress::AbsoluteWord(_) | EffectiveAddress::AbsoluteLongWord(_))
+            );
+            if operation.operation_type.is_branch() && is_absolute {
+                errors.push(Error::relocatable_branch_to_address(
+                    statement.range.clone(),
+                ));
+            }
+        }
+    }
+    let mut object = Object::default();
+    let mut layouts = vec![];
+    let mut labels = HashMap::new();
+    for section in &sections {
+        let target = object_section(section.section_type);
+        // Sections start at even offsets, like in the linker.
+        let offset = match target {
+            Section::Text => object.text.len() as LongWord,
+            Section::Data => object.data.len() as LongWord,
+            Section::Bss => object.bss_size,
+        };
+        let offset = offset + (offset & 1);
+        let layout = lay_out(&section.statements, offset, errors);
+        match target {
+            Section::Text => object.text.resize((offset + layout.size) as usize, 0),
+            Section::Data => object.data.resize((offset + layout.size) as usize, 0),
+            Section::Bss => object.bss_size = offset + layout.size,
+        }
+        for (label, address) in &layout.labels {
+            labels.insert(label.clone(), (target, offset + address));
+        }
+        layouts.push((target, offset, layout));
+    }
+    for symbol in &symbols.symbols {
+        if let Some(&(section, value)) = labels.get(&symbol.name) {
+            object.symbols.push(Symbol {
+                name: symbol.name.clone(),
+                section,
+                value,
+                global: symbol.visibility == Visibility::Exported,
+            });
+        }
+    }
+
+    let mut scope: Option<&str> = None;
+    for (target, offset, layout) in &layouts {
+        let resolve = |label: &str, kind| match kind {
+            RelocationKind::PcRelative16 => {
+                layout.labels.get(label).map(|address| offset + address)
+            }
+            _ => None,
+        };
+        // The BSS section isn't stored, so operations in it are only encoded to report errors.
+        let mut bss = vec![0; layout.size as usize];
+        let start = *offset as usize;
+        let bytes = match target {
+            Section::Text => &mut object.text[start..start + layout.size as usize],
+            Section::Data => &mut object.data[start..start + layout.size as usize],
+            Section::Bss => &mut bss[..],
+        };
+        let unresolved = encode_section(layout, *offset, &resolve, &mut scope, bytes, &mut vec![]);
+        if *target == Section::Bss {
+            continue;
+        }
+        for unresolved in unresolved {
+            object.relocations.push(Relocation {
+                section: *target,
+                offset: offset + unresolved.offset,
+                kind: unresolved.kind,
+                symbol: unresolved.label,
+                addend: 0,
+            });
+        }
+    }
+    object
+}
+
+/// Assembles the program into a GEMDOS executable by linking its object on its own. The absolute
+/// long references to its labels become fixups, so GEMDOS can load it at any address. Labels the
+/// program imports are reported, other problems of linking are returned as messages, prefixed
+/// with the name.
+pub fn assemble_executable(
+    program: &Program,
+    name: &str,
+    errors: &mut ErrorCollector,
+) -> Result<Executable, String> {
+    let object = assemble_object(program, errors);
+    // The symbol table's own errors are already reported.
+    for reference in build_symbol_table(program, &mut vec![]).external_references() {
+        errors.push(Error::unresolved_import(
+            reference.range.clone(),
+            &reference.name,
+        ));
+    }
+    let input = Input {
+        name: name.to_string(),
+        object,
+    };
+    let linked = link(&[input], &Script::default()).map_err(|errors| {
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        messages.join("\n")
+    })?;
+    linked
+        .executable()
+        .ok_or_else(|| "The linked sections don't follow each other from address 0.".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peephole::{optimize, Rule};
+    use m68k_reloaded_common::errors::{apply_fixes, Levels, PrintErrors};
+    use m68k_reloaded_object::{elf, prg};
+    use m68k_reloaded_parser::parse::{parse, parse_dialect};
+    use m68k_reloaded_scanner::keywords::MNEMONICS;
+    use m68k_reloaded_scanner::{scan, scan_dialect, Dialect, Token};
+    use proptest::prelude::*;
+
+    fn assemble_source(source: &str) -> (Assembled, Vec<&'static str>) {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(errors.is_empty(), "Parsing failed.");
+        let assembled = assemble(&program, 0x1000, &mut errors);
+        let codes = errors.iter().map(|error| error.code).collect();
+        (assembled, codes)
+    }
+
+    #[test]
+    fn test_assemble() {
+        let (assembled, errors) = assemble_source(
+            "start MOVEQ #3,D0\n.loop ADDQ.W #1,D1\n BNE .loop\n BSR sub\n SECTION data,DATA\nsub RTS",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            assembled.segments,
+            vec![
+                Segment {
+                    address: 0x1000,
+                    bytes: vec![0x70, 0x03, 0x52, 0x41, 0x66, 0xfc, 0x61, 0x00, 0x00, 0x02]
+                },
+                Segment {
+                    address: 0x100a,
+                    bytes: vec![0x4e, 0x75]
+                },
+            ]
+        );
+        assert_eq!(assembled.labels["start.loop"], 0x1002);
+        // Branches to other sections always get a word displacement.
+        assert_eq!(assembled.labels["sub"], 0x100a);
+        assert_eq!(assembled.label_at(0x1000), Some("start"));
+        assert_eq!(assembled.line_at(0x1005).unwrap().range, 38..47);
+        assert_eq!(assembled.lines.len(), 5);
+        let bne = assembled.line_at(0x1004).unwrap().timing.unwrap();
+        assert_eq!((bne.cycles, bne.not_taken), (10, Some(8)));
+    }
+
+    #[test]
+    fn test_assemble_reports_unencodable_operations() {
+        let (assembled, errors) = assemble_source(" ADD.W D0,A0\n NOP\n RTS");
+        assert_eq!(errors, vec!["invalid_destination_mode"]);
+        assert_eq!(&assembled.segments[0].bytes[..4], &[0, 0, 0x4e, 0x71]);
+    }
+
+    #[test]
+    fn test_assemble_places_sections_at_their_org() {
+        let (assembled, errors) = assemble_source(
+            " NOP\n SECTION rom,CODE\n ORG $FC0000\nstart BRA start\n SECTION more,DATA\n RTS",
+        );
+        assert!(errors.is_empty());
+        let addresses: Vec<LongWord> = assembled
+            .segments
+            .iter()
+            .map(|segment| segment.address)
+            .collect();
+        assert_eq!(addresses, vec![0x1000, 0xfc_0000, 0xfc_0002]);
+        assert_eq!(assembled.labels["start"], 0xfc_0000);
+        assert_eq!(assembled.segments[1].bytes, vec![0x60, 0xfe]);
+    }
+
+    #[test]
+    fn test_assemble_relocates_imports() {
+        let (assembled, errors) = assemble_source(
+            " XREF print,exit\nmain JSR print\n BRA exit\n SECTION data,DATA\n ADD.L D0,print",
+        );
+        assert!(errors.is_empty());
+        let bytes: Vec<Byte> = assembled
+            .segments
+            .iter()
+            .flat_map(|segment| segment.bytes.clone())
+            .collect();
+        assert_eq!(
+            bytes,
+            vec![0x4e, 0xb9, 0, 0, 0, 0, 0x60, 0x00, 0, 0, 0xd1, 0xb9, 0, 0, 0, 0]
+        );
+        let relocation = |section, offset, kind, symbol: &str| Relocation {
+            section,
+            offset,
+            kind,
+            symbol: symbol.to_string(),
+            addend: 0,
+        };
+        assert_eq!(
+            assembled.relocations,
+            vec![
+                relocation(Section::Text, 0x1002, RelocationKind::Absolute32, "print"),
+                relocation(Section::Text, 0x1008, RelocationKind::PcRelative16, "exit"),
+                relocation(Section::Data, 0x100c, RelocationKind::Absolute32, "print"),
+            ]
+        );
+
+        let mut errors = vec![];
+        assembled.report_imports(&mut errors);
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["unresolved_import"; 3]);
+        assert_eq!(errors[1].range, 33..41);
+    }
+
+    fn assemble_module(source: &str) -> Object {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        let object = assemble_object(&program, &mut errors);
+        assert!(errors.is_empty(), "Assembling failed.");
+        object
+    }
+
+    #[test]
+    fn test_assemble_object() {
+        let object = assemble_module(
+            " XREF print\n XDEF main\nmain JSR print\n.loop BRA .loop\n BRA.W next\n SECTION more,CODE\nnext LEA main,A0\n SECTION counters,BSS\ncounter",
+        );
+        assert_eq!(
+            object.text,
+            vec![0x4e, 0xb9, 0, 0, 0, 0, 0x60, 0xfe, 0x60, 0x00, 0, 0, 0x41, 0xf9, 0, 0, 0, 0]
+        );
+        let symbol = |name: &str, section, value, global| Symbol {
+            name: name.to_string(),
+            section,
+            value,
+            global,
+        };
+        assert_eq!(
+            object.symbols,
+            vec![
+                symbol("main", Section::Text, 0, true),
+                symbol("main.loop", Section::Text, 6, false),
+                symbol("next", Section::Text, 12, false),
+                symbol("counter", Section::Bss, 0, false),
+            ]
+        );
+        let relocation = |offset, kind, symbol: &str| Relocation {
+            section: Section::Text,
+            offset,
+            kind,
+            symbol: symbol.to_string(),
+            addend: 0,
+        };
+        assert_eq!(
+            object.relocations,
+            vec![
+                relocation(2, RelocationKind::Absolute32, "print"),
+                relocation(10, RelocationKind::PcRelative16, "next"),
+                relocation(14, RelocationKind::Absolute32, "main"),
+            ]
+        );
+
+        // Branches to absolute addresses would miss once the object is moved.
+        let mut errors = vec![];
+        let source = " BRA $1000\n BSR.S $10\n JSR $1000";
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assemble_object(&program, &mut errors);
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["relocatable_branch_to_address"; 2]);
+        assert_eq!(errors[1].range, 12..21);
+    }
+
+    #[test]
+    fn test_assemble_reports_sections_beyond_the_address_space() {
+        let mut errors = vec![];
+        let source = " MOVE.L D0,D1\n NOP\n SECTION data,DATA\n MOVEQ #1,D0";
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        let assembled = assemble(&program, 0xffff_fffa, &mut errors);
+        assert_eq!(assembled.segments[0].bytes, vec![0x22, 0x00, 0x4e, 0x71]);
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["section_exceeds_address_space"]);
+        assert_eq!(errors[0].range, 39..50);
+    }
+
+    #[test]
+    fn test_assemble_object_rejects_org() {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(" ORG $1000\n NOP", &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assemble_object(&program, &mut errors);
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["org_in_relocatable_output"]);
+    }
+
+    /// Assembles two modules that import from each other and links them.
+    #[test]
+    fn test_assemble_and_link() {
+        let main = assemble_module(
+            " XREF print,counter\n XDEF main\nmain JSR print\n ADDQ.W #1,counter\n BRA main",
+        );
+        let print = assemble_module(
+            " XDEF print,counter\nprint MOVEQ #1,D0\n RTS\n SECTION variables,BSS\ncounter",
+        );
+        let inputs = vec![
+            Input {
+                name: "main.o".to_string(),
+                object: elf::read(&elf::write(&main)).unwrap(),
+            },
+            Input {
+                name: "print.o".to_string(),
+                object: elf::read(&elf::write(&print)).unwrap(),
+            },
+        ];
+        let linked = link(&inputs, &Script::default()).unwrap();
+
+        assert_eq!(
+            linked.section(Section::Text).bytes,
+            vec![
+                0x4e, 0xb9, 0, 0, 0, 0x0e, // JSR print
+                0x52, 0x79, 0, 0, 0, 0x12, // ADDQ.W #1,counter
+                0x60, 0xf2, // BRA main
+                0x70, 0x01, // MOVEQ #1,D0
+                0x4e, 0x75, // RTS
+            ]
+        );
+        assert_eq!(linked.section(Section::Bss).address, 0x12);
+        assert_eq!(linked.absolute_references, vec![2, 8]);
+    }
+
+    #[test]
+    fn test_assemble_executable() {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(" LEA main,A0\nmain JSR main\n RTS", &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        let executable = assemble_executable(&program, "test.s", &mut errors).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(executable.fixups, vec![2, 8]);
+
+        let loaded = prg::load(&prg::write(&executable).unwrap(), 0x1_0000).unwrap();
+        assert_eq!(
+            loaded.memory,
+            vec![0x41, 0xf9, 0, 1, 0, 6, 0x4e, 0xb9, 0, 1, 0, 6, 0x4e, 0x75]
+        );
+
+        let tokens: Vec<Token> = scan(" XREF print\n JSR print", &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(assemble_executable(&program, "test.s", &mut errors).is_err());
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["unresolved_import"]);
+
+        // A branch into another section is only resolved by the linker.
+        let source = format!(
+            " BSR sub\n SECTION vars,DATA\n{}sub RTS",
+            " NOP\n".repeat(0x4000)
+        );
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(&source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert_eq!(
+            assemble_executable(&program, "test.s", &mut errors),
+            Err("test.s: The reference to sub resolves to 32770, which doesn't fit.".to_string())
+        );
+        assert!(errors.is_empty());
+    }
+
+    /// Lines that look like assembly, with operations of all sizes and operands of all addressing
+    /// modes in the Motorola and MIT syntax, so that most of them get past the parser of some
+    /// dialect. Some labels are named like mnemonics or registers.
+    fn source() -> impl Strategy<Value = String> {
+        let label = "\\.?(l_[a-d]|rts|movel|bra|bras|add|d0|sp|pc)";
+        let operand = format!(
+            concat!(
+                "D[0-7]|A[0-7]|SP|\\(A[0-7]\\)\\+?|-\\(A[0-7]\\)|#-?[0-9]{{1,10}}|#\\$[0-9A-F]{{1,8}}",
+                "|-?[0-9]{{1,5}}\\((A[0-7]|PC)(,[AD][0-7](\\.[BWL])?)?\\)|\\$[0-9A-F]{{1,8}}(\\.[WL])?",
+                "|%[ad][0-7]|%?([ad][0-7]|sp|pc)@([+-]|\\(-?[0-9]{{1,5}}(,%[ad][0-7]:[bwl])?\\))?",
+                "|0x[0-9a-f]{{1,8}}(:[wl])?|{}",
+            ),
+            label
+        );
+        let operation = format!(
+            " (?i:{})(\\.[BSWL]|[bswl])?( ({})(,({}))?)?( ; m68k-allow\\((optimized|short_branch_out_of_range)\\))?",
+            MNEMONICS.join("|"),
+            operand,
+            operand
+        );
+        let line = prop_oneof![
+            8 => proptest::string::string_regex(&operation).unwrap(),
+            2 => proptest::string::string_regex(&format!("{}:?", label)).unwrap(),
+            1 => proptest::string::string_regex(
+                " (SECTION s_[ab],(CODE|DATA|BSS)|ORG \\$[0-9A-F]{1,8}|XDEF l_[ab]|XREF l_[cd])"
+            )
+            .unwrap(),
+        ];
+        proptest::collection::vec(line, 0..40).prop_map(|lines| lines.join("\n"))
+    }
+
+    /// Runs the source through all stages, like the fuzz target of the same name.
+    fn assemble_anything(source: &str, dialect: Dialect, origin: LongWord) {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan_dialect(source, dialect, &mut errors).collect();
+        let mut program = parse_dialect(tokens, dialect, &mut errors);
+        optimize(&mut program, &Rule::ALL, &mut errors);
+        assemble(&program, origin, &mut errors);
+        Levels::new().apply(source, &mut errors);
+        apply_fixes(source, &errors);
+        errors.print();
+    }
+
+    proptest! {
+        /// Assembling reports errors instead of panicking, whatever the source, dialect and origin
+        /// are.
+        #[test]
+        fn test_assemble_any_source(
+            source in source(),
+            dialect in 0..4usize,
+            origin in any::<LongWord>(),
+        ) {
+            assemble_anything(&source, Dialect::ALL[dialect], origin);
+        }
+    }
+
+    /// Hand-written sources with non-ASCII text, errors of every stage, truncated operands, labels
+    /// named like mnemonics and registers, and sections at the end of the address space, run in
+    /// every dialect. Neither the fuzz targets nor the property tests found a panic yet, so none
+    /// of them is a minimized crash. The fuzz targets use them as their initial corpus, and crashes they find
+    /// belong here after running `cargo fuzz tmin`.
+    #[test]
+    fn test_regressions() {
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/regressions");
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let source = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            for dialect in Dialect::ALL.iter() {
+                for origin in &[0x1000, 0xffff_fffe] {
+                    assemble_anything(&source, *dialect, *origin);
+                }
+            }
+        }
+    }
+}
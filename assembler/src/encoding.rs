@@ -0,0 +1,470 @@
+//! Encodes operations into machine code.
+//!
+//! The encoding follows the 68000 programmer's reference manual. Operations are expected to be
+//! validated, so combinations that don't exist on the 68000 are simply not encoded.
+
+use crate::inference::infer_size;
+use crate::layout::BranchSize;
+use crate::sizes::extension_size;
+use m68k_reloaded_common::{LongWord, Word};
+use m68k_reloaded_object::RelocationKind;
+use m68k_reloaded_parser::statements::*;
+
+/// Looks up the address of a label by its name as written in the source.
+pub type Resolve<'a> = &'a dyn Fn(&str) -> Option<LongWord>;
+
+/// The six bits that select an effective address: the mode in the upper three bits and the
+/// register in the lower three. Immediates, absolute and PC-relative addresses share mode 7.
+pub fn mode_bits(address: &EffectiveAddress) -> Word {
+    let (mode, register) = match address {
+        EffectiveAddress::Dn(dn) => (0, dn.index),
+        EffectiveAddress::An(an) => (1, an.index),
+        EffectiveAddress::AnInd(an) => (2, an.index),
+        EffectiveAddress::AnIndWithPostInc(an) => (3, an.index),
+        EffectiveAddress::AnIndWithPreDec(an) => (4, an.index),
+        EffectiveAddress::AnIndWithDisplacement(_, an) => (5, an.index),
+        EffectiveAddress::AnIndWithIndex(_, an, _) => (6, an.index),
+        EffectiveAddress::AbsoluteWord(_) => (7, 0),
+        EffectiveAddress::AbsoluteLongWord(_) | EffectiveAddress::Label(_) => (7, 1),
+        EffectiveAddress::PcIndWithDisplacement(_) => (7, 2),
+        EffectiveAddress::PcIndWithIndex(_, _) => (7, 3),
+        EffectiveAddress::Immediate(_) => (7, 4),
+    };
+    (mode << 3) | Word::from(register)
+}
+
+/// The extension words that follow the operation word for an effective address.
+fn extension_words(address: &EffectiveAddress, size: Size, resolve: Resolve) -> Option<Vec<Word>> {
+    let long_word = |value: LongWord| vec![(value >> 16) as Word, value as Word];
+    Some(match address {
+        EffectiveAddress::Dn(_)
+        | EffectiveAddress::An(_)
+        | EffectiveAddress::AnInd(_)
+        | EffectiveAddress::AnIndWithPostInc(_)
+        | EffectiveAddress::AnIndWithPreDec(_) => vec![],
+        EffectiveAddress::AnIndWithDisplacement(displacement, _)
+        | EffectiveAddress::PcIndWithDisplacement(displacement) => vec![displacement.value],
+        EffectiveAddress::AnIndWithIndex(displacement, _, index)
+        | EffectiveAddress::PcIndWithIndex(displacement, index) => {
+            vec![brief_extension_word(displacement.value, index)]
+        }
+        EffectiveAddress::AbsoluteWord(address) => vec![address.value],
+        EffectiveAddress::AbsoluteLongWord(address) => long_word(address.value),
+        EffectiveAddress::Label(label) => long_word(resolve(label)?),
+        EffectiveAddress::Immediate(value) => match size {
+            Size::Byte => vec![value.value as Word & 0xff],
+            Size::Word => vec![value.value as Word],
+            Size::LongWord => long_word(value.value),
+        },
+    })
+}
+
+/// The extension word of indexed addresses: The index register, its size and an 8-bit
+/// displacement.
+fn brief_extension_word(displacement: Byte, index: &Index) -> Word {
+    let (is_address, register) = match &index.register {
+        Xn::Dn(dn) => (0, dn.index),
+        Xn::An(an) => (1, an.index),
+    };
+    let is_long = matches!(&index.size, Some(size) if size.value == Size::LongWord);
+    (is_address << 15)
+        | (Word::from(register) << 12)
+        | (Word::from(is_long) << 11)
+        | Word::from(displacement)
+}
+
+/// The size field used by most operations.
+fn size_bits(size: Size) -> Word {
+    match size {
+        Size::Byte => 0,
+        Size::Word => 1,
+        Size::LongWord => 2,
+    }
+}
+
+/// MOVE has its own order of sizes.
+fn move_size_bits(size: Size) -> Word {
+    match size {
+        Size::Byte => 1,
+        Size::Word => 3,
+        Size::LongWord => 2,
+    }
+}
+
+pub fn condition_bits(condition: Condition) -> Word {
+    match condition {
+        Condition::Hi => 2,
+        Condition::Ls => 3,
+        Condition::Cc => 4,
+        Condition::Cs => 5,
+        Condition::Ne => 6,
+        Condition::Eq => 7,
+        Condition::Vc => 8,
+        Condition::Vs => 9,
+        Condition::Pl => 10,
+        Condition::Mi => 11,
+        Condition::Ge => 12,
+        Condition::Lt => 13,
+        Condition::Gt => 14,
+        Condition::Le => 15,
+    }
+}
+
+/// Encodes an operation placed at `address`. Branches are encoded with the size chosen by the
+/// layout. Returns `None` if the operation doesn't exist on the 68000 or one of its labels can't
+/// be resolved.
+pub fn encode(
+    operation: &Operation,
+    address: LongWord,
+    branch: Option<BranchSize>,
+    resolve: Resolve,
+) -> Option<Vec<Word>> {
+    use EffectiveAddress::*;
+
+    let size = infer_size(operation)?;
+    let operands: Vec<&EffectiveAddress> = operation
+        .operands
+        .iter()
+        .map(|operand| &operand.value)
+        .collect();
+    let extension = |address: &EffectiveAddress| extension_words(address, size, resolve);
+    let with_extensions = |word: Word, addresses: &[&EffectiveAddress]| {
+        let mut words = vec![word];
+        for address in addresses {
+            words.extend(extension(address)?);
+        }
+        Some(words)
+    };
+    let immediate = |operand: &EffectiveAddress| match operand {
+        Immediate(value) => Some(value.value),
+        _ => None,
+    };
+
+    match (operation.operation_type.value, &operands[..]) {
+        (OperationType::Add, [Immediate(_), destination])
+            if !matches!(destination, Dn(_) | An(_)) =>
+        {
+            encode_addi(operands[0], destination, size, resolve)
+        }
+        (OperationType::Add, [source, Dn(dn)]) => with_extensions(
+            0xd000 | (Word::from(dn.index) << 9) | (size_bits(size) << 6) | mode_bits(source),
+            &[source],
+        ),
+        (OperationType::Add, [Dn(dn), destination]) if !matches!(destination, An(_)) => {
+            with_extensions(
+                0xd100
+                    | (Word::from(dn.index) << 9)
+                    | (size_bits(size) << 6)
+                    | mode_bits(destination),
+                &[destination],
+            )
+        }
+        (OperationType::Adda, [source, An(an)]) => {
+            let operation_mode = if size == Size::LongWord { 7 } else { 3 };
+            with_extensions(
+                0xd000 | (Word::from(an.index) << 9) | (operation_mode << 6) | mode_bits(source),
+                &[source],
+            )
+        }
+        (OperationType::Addi, [source, destination]) => {
+            encode_addi(source, destination, size, resolve)
+        }
+        (OperationType::Addq, [source, destination]) => {
+            let data = (immediate(source)? & 7) as Word;
+            with_extensions(
+                0x5000 | (data << 9) | (size_bits(size) << 6) | mode_bits(destination),
+                &[destination],
+            )
+        }
+        (OperationType::Addx, [source, destination]) => {
+            let (source, destination, is_memory) = match (source, destination) {
+                (Dn(source), Dn(destination)) => (source.index, destination.index, 0),
+                (AnIndWithPreDec(source), AnIndWithPreDec(destination)) => {
+                    (source.index, destination.index, 1)
+                }
+                _ => return None,
+            };
+            Some(vec![
+                0xd100
+                    | (Word::from(destination) << 9)
+                    | (size_bits(size) << 6)
+                    | (is_memory << 3)
+                    | Word::from(source),
+            ])
+        }
+        (OperationType::Bcc(_), [target])
+        | (OperationType::Bra, [target])
+        | (OperationType::Bsr, [target]) => {
+            let condition = match operation.operation_type.value {
+                OperationType::Bcc(condition) => condition_bits(condition),
+                OperationType::Bsr => 1,
+                _ => 0,
+            };
+            let target = match target {
+                Label(label) => resolve(label)?,
+                AbsoluteWord(address) => address.value as i16 as LongWord,
+                AbsoluteLongWord(address) => address.value,
+                _ => return None,
+            };
+            let displacement = target.wrapping_sub(address + 2) as i32;
+            let word = 0x6000 | (condition << 8);
+            // A short displacement of 0 would mean that a word displacement follows.
+            match branch? {
+                BranchSize::Short if (-0x80..0x80).contains(&displacement) && displacement != 0 => {
+                    Some(vec![word | (displacement as Word & 0xff)])
+                }
+                BranchSize::Word if (-0x8000..0x8000).contains(&displacement) => {
+                    Some(vec![word, displacement as Word])
+                }
+                _ => None,
+            }
+        }
+        (OperationType::Chk, [source, Dn(dn)]) => with_extensions(
+            0x4180 | (Word::from(dn.index) << 9) | mode_bits(source),
+            &[source],
+        ),
+        (OperationType::Clr, [destination]) => with_extensions(
+            0x4200 | (size_bits(size) << 6) | mode_bits(destination),
+            &[destination],
+        ),
+        (OperationType::Divs, [source, Dn(dn)]) => with_extensions(
+            0x81c0 | (Word::from(dn.index) << 9) | mode_bits(source),
+            &[source],
+        ),
+        (OperationType::Divu, [source, Dn(dn)]) => with_extensions(
+            0x80c0 | (Word::from(dn.index) << 9) | mode_bits(source),
+            &[source],
+        ),
+        (OperationType::Jsr, [target]) => with_extensions(0x4e80 | mode_bits(target), &[target]),
+        (OperationType::Lea, [source, An(an)]) => with_extensions(
+            0x41c0 | (Word::from(an.index) << 9) | mode_bits(source),
+            &[source],
+        ),
+        (OperationType::Move, [source, destination]) => {
+            // The destination's mode and register are swapped.
+            let destination_bits = mode_bits(destination);
+            let destination_bits = ((destination_bits & 7) << 3) | (destination_bits >> 3);
+            with_extensions(
+                (move_size_bits(size) << 12) | (destination_bits << 6) | mode_bits(source),
+                &[source, destination],
+            )
+        }
+        (OperationType::Moveq, [source, Dn(dn)]) => Some(vec![
+            0x7000 | (Word::from(dn.index) << 9) | (immediate(source)? as Word & 0xff),
+        ]),
+        (OperationType::Nop, []) => Some(vec![0x4e71]),
+        (OperationType::Rte, []) => Some(vec![0x4e73]),
+        (OperationType::Rts, []) => Some(vec![0x4e75]),
+        (OperationType::Stop, [source]) => with_extensions(0x4e72, &[source]),
+        (OperationType::Trap, [source]) => Some(vec![0x4e40 | (immediate(source)? as Word & 15)]),
+        (OperationType::Trapv, []) => Some(vec![0x4e76]),
+        _ => None,
+    }
+}
+
+/// A label whose address is part of an encoded operation.
+#[derive(Eq, PartialEq, Debug)]
+pub struct LabelReference<'a> {
+    /// The offset of the label's bytes relative to the operation word.
+    pub offset: LongWord,
+    pub kind: RelocationKind,
+    /// The label as written in the source.
+    pub label: &'a str,
+}
+
+/// The labels that end up in the encoding of an operation, so they can be patched once their
+/// addresses are known. Label operands are absolute long words and word branches have a
+/// displacement after the operation word. Short branches don't count, because their target is
+/// always in the same section.
+pub fn label_references(
+    operation: &Operation,
+    branch: Option<BranchSize>,
+) -> Vec<LabelReference<'_>> {
+    if operation.operation_type.is_branch() {
+        return match (
+            branch,
+            operation.operands.first().map(|operand| &operand.value),
+        ) {
+            (Some(BranchSize::Word), Some(EffectiveAddress::Label(label))) => {
+                vec![LabelReference {
+                    offset: 2,
+                    kind: RelocationKind::PcRelative16,
+                    label,
+                }]
+            }
+            _ => vec![],
+        };
+    }
+    let size = infer_size(operation).unwrap_or(Size::Word);
+    let mut references = vec![];
+    let mut offset = 2;
+    for (index, operand) in operation.operands.iter().enumerate() {
+        // The immediate data of ADDQ, MOVEQ and TRAP is part of the operation word.
+        let is_quick = matches!(
+            operation.operation_type.value,
+            OperationType::Addq | OperationType::Moveq | OperationType::Trap
+        );
+        if index == 0 && is_quick {
+            continue;
+        }
+        if let EffectiveAddress::Label(label) = &operand.value {
+            references.push(LabelReference {
+                offset,
+                kind: RelocationKind::Absolute32,
+                label,
+            });
+        }
+        offset += extension_size(operand, size);
+    }
+    references
+}
+
+fn encode_addi(
+    source: &EffectiveAddress,
+    destination: &EffectiveAddress,
+    size: Size,
+    resolve: Resolve,
+) -> Option<Vec<Word>> {
+    if !matches!(source, EffectiveAddress::Immediate(_)) {
+        return None;
+    }
+    let mut words = vec![0x0600 | (size_bits(size) << 6) | mode_bits(destination)];
+    words.extend(extension_words(source, size, resolve)?);
+    words.extend(extension_words(destination, size, resolve)?);
+    Some(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m68k_reloaded_parser::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn encode_source(source: &str, branch: Option<BranchSize>) -> Option<Vec<Word>> {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(errors.is_empty(), "Parsing {} failed.", source);
+        let resolve = |label: &str| match label {
+            "label" => Some(0x1234_5678),
+            "target" => Some(0x1010),
+            _ => None,
+        };
+        match &program[0].value {
+            Statement::Operation(operation) => encode(operation, 0x1000, branch, &resolve),
+            statement => panic!("Expected an operation, got {:?}.", statement),
+        }
+    }
+
+    fn encoded(source: &str) -> Vec<Word> {
+        encode_source(source, None).unwrap()
+    }
+
+    #[test]
+    fn test_encode_operations() {
+        assert_eq!(encoded(" ADD.W D1,D2"), vec![0xd441]);
+        assert_eq!(encoded(" ADD.L D3,4(A0)"), vec![0xd7a8, 0x0004]);
+        assert_eq!(encoded(" ADD.B #1,(A0)"), vec![0x0610, 0x0001]);
+        assert_eq!(encoded(" ADDA.L D0,A1"), vec![0xd3c0]);
+        assert_eq!(encoded(" ADDA.W (A0)+,A1"), vec![0xd2d8]);
+        assert_eq!(encoded(" ADDI.L #$10000,D0"), vec![0x0680, 0x0001, 0x0000]);
+        assert_eq!(encoded(" ADDQ.W #8,D1"), vec![0x5041]);
+        assert_eq!(encoded(" ADDX.L -(A0),-(A1)"), vec![0xd388]);
+        assert_eq!(encoded(" CLR.L label"), vec![0x42b9, 0x1234, 0x5678]);
+        assert_eq!(encoded(" JSR (A0)"), vec![0x4e90]);
+        assert_eq!(encoded(" LEA 2(PC,D0.L),A0"), vec![0x41fb, 0x0802]);
+        assert_eq!(encoded(" MOVE.L D0,-(A7)"), vec![0x2f00]);
+        assert_eq!(encoded(" MOVE.W $1234,A0"), vec![0x3078, 0x1234]);
+        assert_eq!(encoded(" MOVE.L D0,A0"), vec![0x2040]);
+        assert_eq!(encoded(" MOVE.L d0,sp"), vec![0x2e40]);
+        assert_eq!(encoded(" MOVE A0,A1"), vec![0x3248]);
+        assert_eq!(
+            encoded(" MOVE.B #$ff,(A1,A2.W)"),
+            vec![0x13bc, 0x00ff, 0xa000]
+        );
+        assert_eq!(encoded(" MOVEQ #-1,D7"), vec![0x7eff]);
+        assert_eq!(encoded(" RTS"), vec![0x4e75]);
+        assert_eq!(encoded(" NOP"), vec![0x4e71]);
+        assert_eq!(encoded(" CHK.W (A0),D1"), vec![0x4390]);
+        assert_eq!(encoded(" DIVU #3,D0"), vec![0x80fc, 0x0003]);
+        assert_eq!(encoded(" DIVS.W D1,D2"), vec![0x85c1]);
+        assert_eq!(encoded(" TRAP #15"), vec![0x4e4f]);
+        assert_eq!(encoded(" TRAPV"), vec![0x4e76]);
+        assert_eq!(encoded(" RTE"), vec![0x4e73]);
+        assert_eq!(encoded(" STOP #$2000"), vec![0x4e72, 0x2000]);
+    }
+
+    #[test]
+    fn test_encode_branches() {
+        assert_eq!(
+            encode_source(" BNE target", Some(BranchSize::Short)),
+            Some(vec![0x660e])
+        );
+        assert_eq!(
+            encode_source(" BSR target", Some(BranchSize::Word)),
+            Some(vec![0x6100, 0x000e])
+        );
+        assert_eq!(
+            encode_source(" BRA $ff0", Some(BranchSize::Short)),
+            Some(vec![0x60ee])
+        );
+        assert_eq!(encode_source(" BRA unknown", Some(BranchSize::Word)), None);
+        // The operation is at $1000 and the displacement is relative to $1002.
+        assert_eq!(encode_source(" BRA $1002", Some(BranchSize::Short)), None);
+        assert_eq!(encode_source(" BRA $1082", Some(BranchSize::Short)), None);
+        assert_eq!(
+            encode_source(" BRA $1082", Some(BranchSize::Word)),
+            Some(vec![0x6000, 0x0080])
+        );
+        assert_eq!(encode_source(" BRA $12000", Some(BranchSize::Word)), None);
+        assert_eq!(encode_source(" ADD.W D0,A0", None), None);
+    }
+
+    #[test]
+    fn test_label_references() {
+        let references = |source: &str, branch: Option<BranchSize>| {
+            let mut errors = vec![];
+            let tokens: Vec<Token> = scan(source, &mut errors).collect();
+            let program = parse(tokens, &mut errors);
+            match &program[0].value {
+                Statement::Operation(operation) => label_references(operation, branch)
+                    .iter()
+                    .map(|reference| {
+                        (
+                            reference.offset,
+                            reference.kind,
+                            reference.label.to_string(),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+                statement => panic!("Expected an operation, got {:?}.", statement),
+            }
+        };
+        let absolute = RelocationKind::Absolute32;
+        assert_eq!(
+            references(" MOVE.L #1,label", None),
+            vec![(6, absolute, "label".to_string())]
+        );
+        assert_eq!(
+            references(" ADDI.W #1,label", None),
+            vec![(4, absolute, "label".to_string())]
+        );
+        assert_eq!(
+            references(" ADDQ.L #1,label", None),
+            vec![(2, absolute, "label".to_string())]
+        );
+        assert_eq!(
+            references(" MOVE.L first,second", None),
+            vec![
+                (2, absolute, "first".to_string()),
+                (6, absolute, "second".to_string())
+            ]
+        );
+        assert_eq!(
+            references(" BSR target", Some(BranchSize::Word)),
+            vec![(2, RelocationKind::PcRelative16, "target".to_string())]
+        );
+        assert_eq!(references(" BNE target", Some(BranchSize::Short)), vec![]);
+        assert_eq!(references(" BRA $1000", Some(BranchSize::Word)), vec![]);
+    }
+}
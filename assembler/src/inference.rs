@@ -0,0 +1,75 @@
+//! Infers the size of operations written without one.
+//!
+//! Like in Motorola's assemblers, the default size is a word, even for operations on address
+//! registers like `MOVE A0,A1`. Operations that only exist in one size always use that size. The
+//! parser keeps the size as written, so sources can be formatted without adding sizes.
+
+use m68k_reloaded_common::LongWord;
+use m68k_reloaded_parser::statements::*;
+
+/// The size the operation works with. Returns `None` if the size is omitted and can't be inferred
+/// safely, like for `MOVE #$12345,D0`, where the default word size would silently cut off the
+/// immediate.
+pub fn infer_size(operation: &Operation) -> Option<Size> {
+    if let Some(size) = &operation.size {
+        return Some(size.value);
+    }
+    let operands: Vec<&EffectiveAddress> = operation
+        .operands
+        .iter()
+        .map(|operand| &operand.value)
+        .collect();
+    match (operation.operation_type.value, &operands[..]) {
+        (OperationType::Lea, _) | (OperationType::Moveq, _) => Some(Size::LongWord),
+        (_, operands) => {
+            let is_ambiguous = operands.iter().any(|operand| {
+                matches!(operand, EffectiveAddress::Immediate(value) if !fits_word(value.value))
+            });
+            if is_ambiguous {
+                None
+            } else {
+                Some(Size::Word)
+            }
+        }
+    }
+}
+
+/// Whether the value can be used as a word, either unsigned or sign-extended.
+fn fits_word(value: LongWord) -> bool {
+    value <= 0xffff || value >= 0xffff_8000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m68k_reloaded_parser::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn infer(source: &str) -> Option<Size> {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(errors.is_empty(), "Parsing {} failed.", source);
+        match &program[0].value {
+            Statement::Operation(operation) => infer_size(operation),
+            statement => panic!("Expected an operation, got {:?}.", statement),
+        }
+    }
+
+    #[test]
+    fn test_infer_size() {
+        assert_eq!(infer(" ADD.B D0,D1"), Some(Size::Byte));
+        assert_eq!(infer(" ADD D0,D1"), Some(Size::Word));
+        assert_eq!(infer(" MOVE #-1,D0"), Some(Size::Word));
+        assert_eq!(infer(" MOVEQ #1,D0"), Some(Size::LongWord));
+        assert_eq!(infer(" LEA 4(A0),A1"), Some(Size::LongWord));
+        assert_eq!(infer(" MOVE A0,A1"), Some(Size::Word));
+        assert_eq!(infer(" ADDA A0,A1"), Some(Size::Word));
+        assert_eq!(infer(" MOVE D0,A1"), Some(Size::Word));
+        assert_eq!(infer(" MOVE.L D0,A0"), Some(Size::LongWord));
+        assert_eq!(infer(" MOVE (A0),SP"), Some(Size::Word));
+        assert_eq!(infer(" ADDA D0,A1"), Some(Size::Word));
+        assert_eq!(infer(" MOVE #$12345,D0"), None);
+        assert_eq!(infer(" BRA label"), Some(Size::Word));
+    }
+}
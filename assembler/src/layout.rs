@@ -0,0 +1,342 @@
+//! Assigns addresses to the statements of a section.
+//!
+//! Branches without a size get the shortest encoding that reaches their target. Because choosing a
+//! longer branch moves all following statements, the layout starts with short branches everywhere
+//! and grows the ones that don't reach until the addresses don't change anymore. Branches only ever
+//! grow, so this terminates.
+
+use crate::sizes::operation_size;
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use m68k_reloaded_common::{LongWord, Range};
+use m68k_reloaded_parser::statements::*;
+use m68k_reloaded_parser::symbols::qualify;
+use std::collections::HashMap;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum BranchSize {
+    /// The displacement is part of the operation word (`.S`).
+    Short,
+    /// The displacement is a word after the operation word (`.W`).
+    Word,
+}
+
+impl BranchSize {
+    pub fn bytes(self) -> LongWord {
+        match self {
+            BranchSize::Short => 2,
+            BranchSize::Word => 4,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct PlacedStatement<'a> {
+    pub statement: &'a Stmt<Statement>,
+    /// The address relative to the start of the section.
+    pub address: LongWord,
+    pub size: LongWord,
+    /// The chosen size if the statement is a branch.
+    pub branch: Option<BranchSize>,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct Layout<'a> {
+    pub statements: Vec<PlacedStatement<'a>>,
+    /// The addresses of the section's labels by their qualified names.
+    pub labels: HashMap<Label, LongWord>,
+    pub size: LongWord,
+}
+
+enum Target {
+    /// The qualified name of a label.
+    Label(Label),
+    Absolute(LongWord),
+}
+
+impl Target {
+    /// The address relative to the start of the section, or `None` for labels of other sections.
+    fn relative_address(&self, start: LongWord, labels: &HashMap<Label, LongWord>) -> Option<i64> {
+        match self {
+            Target::Label(label) => labels.get(label).map(|&address| i64::from(address)),
+            Target::Absolute(address) => Some(i64::from(*address) - i64::from(start)),
+        }
+    }
+}
+
+struct Branch {
+    target: Option<Target>,
+    size: BranchSize,
+    /// Where the size was written in the source, if it was.
+    explicit_size: Option<Range>,
+}
+
+/// Lays out the statements of a section, like the ones returned by
+/// [m68k_reloaded_parser::sections::split_into_sections], which is placed at `start`. The start
+/// only matters for branches to absolute addresses.
+pub fn lay_out<'a>(
+    statements: &[&'a Stmt<Statement>],
+    start: LongWord,
+    errors: &mut ErrorCollector,
+) -> Layout<'a> {
+    let mut scope: Option<&str> = None;
+    let mut labels = vec![None; statements.len()];
+    let mut branches: Vec<Option<Branch>> = Vec::with_capacity(statements.len());
+    for (index, statement) in statements.iter().enumerate() {
+        branches.push(None);
+        match &statement.value {
+            Statement::Label(name) => {
+                if LabelScope::of(name) == LabelScope::Global {
+                    scope = Some(name);
+                }
+                labels[index] = qualify(name, scope);
+            }
+            Statement::Operation(operation) if operation.operation_type.is_branch() => {
+                let target = match operation.operands.first().map(|operand| &operand.value) {
+                    Some(EffectiveAddress::Label(label)) => {
+                        qualify(label, scope).map(Target::Label)
+                    }
+                    Some(EffectiveAddress::AbsoluteWord(address)) => {
+                        Some(Target::Absolute(address.value as i16 as LongWord))
+                    }
+                    Some(EffectiveAddress::AbsoluteLongWord(address)) => {
+                        Some(Target::Absolute(address.value))
+                    }
+                    _ => None,
+                };
+                let explicit_size = operation.size.as_ref().map(|size| size.range.clone());
+                let size = match operation.size.as_ref().map(|size| size.value) {
+                    Some(Size::Byte) => BranchSize::Short,
+                    Some(Size::Word) => BranchSize::Word,
+                    Some(Size::LongWord) => {
+                        errors.push(Error::long_branch(
+                            statement.range.clone(),
+                            explicit_size.clone().unwrap(),
+                        ));
+                        BranchSize::Word
+                    }
+                    None => BranchSize::Short,
+                };
+                branches[index] = Some(Branch {
+                    target,
+                    size,
+                    explicit_size,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let sizes: Vec<LongWord> = statements
+        .iter()
+        .map(|statement| match &statement.value {
+            Statement::Operation(operation) => operation_size(operation),
+            _ => 0,
+        })
+        .collect();
+    let size_of = |index: usize, branches: &[Option<Branch>]| match &branches[index] {
+        Some(branch) => branch.size.bytes(),
+        None => sizes[index],
+    };
+
+    let mut grown_explicit_branches = vec![];
+    let (addresses, label_addresses) = loop {
+        let mut addresses = Vec::with_capacity(statements.len());
+        let mut label_addresses = HashMap::new();
+        let mut address: LongWord = 0;
+        for (index, label) in labels.iter().enumerate() {
+            addresses.push(address);
+            if let Some(label) = label {
+                label_addresses.insert(label.clone(), address);
+            }
+            address += size_of(index, &branches);
+        }
+
+        let mut has_changed = false;
+        for (index, branch) in branches.iter_mut().enumerate() {
+            let branch = match branch {
+                Some(branch) if branch.size == BranchSize::Short => branch,
+                _ => continue,
+            };
+            let reaches = match branch
+                .target
+                .as_ref()
+                .map(|target| target.relative_address(start, &label_addresses))
+            {
+                Some(Some(target)) => fits_short(displacement(addresses[index], target)),
+                // Targets outside of this section are only known after linking and need a word
+                // displacement for the relocation.
+                Some(None) => false,
+                // Invalid targets are reported by the validation.
+                None => true,
+            };
+            if !reaches {
+                branch.size = BranchSize::Word;
+                has_changed = true;
+                if let Some(size) = &branch.explicit_size {
+                    grown_explicit_branches.push((index, size.clone()));
+                }
+            }
+        }
+        if !has_changed {
+            break (addresses, label_addresses);
+        }
+    };
+
+    for (index, size) in grown_explicit_branches {
+        errors.push(Error::short_branch_out_of_range(
+            statements[index].range.clone(),
+            size,
+        ));
+    }
+    for (index, branch) in branches.iter().enumerate() {
+        let target = match branch {
+            Some(Branch {
+                target: Some(target),
+                ..
+            }) => target.relative_address(start, &label_addresses),
+            _ => None,
+        };
+        if let Some(target) = target {
+            let displacement = displacement(addresses[index], target);
+            if !(-0x8000..0x8000).contains(&displacement) {
+                errors.push(Error::branch_out_of_range(statements[index].range.clone()));
+            }
+        }
+    }
+
+    let placed = statements
+        .iter()
+        .enumerate()
+        .map(|(index, statement)| PlacedStatement {
+            statement,
+            address: addresses[index],
+            size: size_of(index, &branches),
+            branch: branches[index].as_ref().map(|branch| branch.size),
+        })
+        .collect::<Vec<_>>();
+    let size = placed
+        .last()
+        .map(|statement| statement.address + statement.size)
+        .unwrap_or(0);
+    Layout {
+        statements: placed,
+        labels: label_addresses,
+        size,
+    }
+}
+
+/// Branch displacements are relative to the address after the operation word.
+fn displacement(address: LongWord, target: i64) -> i64 {
+    target - (i64::from(address) + 2)
+}
+
+/// A short displacement of 0 means that a word displacement follows, so branches to the next
+/// operation can't be short.
+fn fits_short(displacement: i64) -> bool {
+    (-0x80..0x80).contains(&displacement) && displacement != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m68k_reloaded_parser::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn lay_out_source(source: &str) -> (Vec<Option<BranchSize>>, LongWord, Vec<&'static str>) {
+        lay_out_source_at(source, 0)
+    }
+
+    fn lay_out_source_at(
+        source: &str,
+        start: LongWord,
+    ) -> (Vec<Option<BranchSize>>, LongWord, Vec<&'static str>) {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(errors.is_empty(), "Parsing failed.");
+        let statements: Vec<&Stmt<Statement>> = program.iter().collect();
+        let layout = lay_out(&statements, start, &mut errors);
+        let branches = layout
+            .statements
+            .iter()
+            .filter(|statement| statement.branch.is_some())
+            .map(|statement| statement.branch)
+            .collect();
+        let codes = errors.iter().map(|error| error.code).collect();
+        (branches, layout.size, codes)
+    }
+
+    #[test]
+    fn test_short_branches() {
+        let (branches, size, errors) = lay_out_source("loop ADDQ.W #1,D0\n BNE loop\n BRA.S loop");
+        assert_eq!(
+            branches,
+            vec![Some(BranchSize::Short), Some(BranchSize::Short)]
+        );
+        assert_eq!(size, 6);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_branches_to_the_next_operation_are_word_branches() {
+        let (branches, _, _) = lay_out_source(" BRA next\nnext ADD.W D0,D1");
+        assert_eq!(branches, vec![Some(BranchSize::Word)]);
+    }
+
+    #[test]
+    fn test_relaxation_until_stable() {
+        // At first, the first branch reaches `middle`. It doesn't anymore after the second branch
+        // grows.
+        let filler = " ADD.W D0,D1\n".repeat(62);
+        let source = format!(
+            " BRA middle\n BRA end\n{}middle\n{}{}end",
+            filler, filler, filler
+        );
+        let (branches, size, errors) = lay_out_source(&source);
+        assert_eq!(
+            branches,
+            vec![Some(BranchSize::Word), Some(BranchSize::Word)]
+        );
+        assert_eq!(size, 8 + 3 * 124);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_branch_errors() {
+        let filler = " ADD.W D0,D1\n".repeat(64);
+        let (branches, _, errors) = lay_out_source(&format!(" BRA.S end\n{}end", filler));
+        assert_eq!(branches, vec![Some(BranchSize::Word)]);
+        assert_eq!(errors, vec!["short_branch_out_of_range"]);
+
+        let (_, _, errors) = lay_out_source("loop\n BRA.L loop");
+        assert_eq!(errors, vec!["long_branch"]);
+
+        let filler = " ADDI.L #1,$12345678\n".repeat(3300);
+        let (_, _, errors) = lay_out_source(&format!("start\n{} BRA start", filler));
+        assert_eq!(errors, vec!["branch_out_of_range"]);
+    }
+
+    #[test]
+    fn test_branches_to_absolute_addresses() {
+        let (branches, _, errors) = lay_out_source_at(" BRA $1010\n BRA $1100\n BRA $1008", 0x1000);
+        assert_eq!(
+            branches,
+            vec![
+                Some(BranchSize::Short),
+                Some(BranchSize::Word),
+                Some(BranchSize::Word)
+            ]
+        );
+        assert!(errors.is_empty());
+
+        let (branches, _, errors) = lay_out_source_at(" BRA.S $1100", 0x1000);
+        assert_eq!(branches, vec![Some(BranchSize::Word)]);
+        assert_eq!(errors, vec!["short_branch_out_of_range"]);
+
+        let (_, _, errors) = lay_out_source_at(" BRA.S $20000", 0x1000);
+        assert_eq!(
+            errors,
+            vec!["short_branch_out_of_range", "branch_out_of_range"]
+        );
+    }
+}
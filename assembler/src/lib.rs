@@ -0,0 +1,8 @@
+pub mod assemble;
+pub mod encoding;
+pub mod inference;
+pub mod layout;
+pub mod peephole;
+pub mod sizes;
+pub mod timing;
+pub mod validation;
@@ -0,0 +1,278 @@
+use m68k_reloaded_assembler::assemble::{assemble, assemble_executable, assemble_object};
+use m68k_reloaded_assembler::layout::lay_out;
+use m68k_reloaded_assembler::peephole::{optimize, Rule};
+use m68k_reloaded_assembler::timing::{blocks, operation_timing};
+use m68k_reloaded_assembler::validation::validate;
+use m68k_reloaded_common::errors::registry::lookup;
+use m68k_reloaded_common::errors::{apply_fixes, ErrorCollector, Levels, PrintErrors, Severity};
+use m68k_reloaded_common::{Byte, LongWord};
+use m68k_reloaded_object::image::{self, ImageOptions, Padding};
+use m68k_reloaded_object::{elf, prg};
+use m68k_reloaded_parser::format::format;
+use m68k_reloaded_parser::parse::parse_dialect;
+use m68k_reloaded_parser::sections::split_into_sections;
+use m68k_reloaded_parser::statements::{Program, Statement};
+use m68k_reloaded_parser::symbols::build_symbol_table;
+use m68k_reloaded_scanner::{scan_dialect, Dialect, Token};
+
+const USAGE: &str =
+    "Usage: assembler [--dialect=<dialect>] [--optimize[=<rule>,...]] [--fix] [<levels>...]
+                 [--object=<object.o> | --prg=<program.prg> | --binary=<image.bin>]
+                 [--org=<address>] [--fill=<byte>] [--pad | --rom-size=<bytes>] <source.s>
+       assembler [--dialect=<dialect>] --format=<dialect> <source.s>
+       assembler --explain <code>
+
+Without an output, a listing with the address, size and cycles of every operation is printed.
+--object writes an ELF object for the linker instead, --prg a GEMDOS executable, whose fixups
+relocate all absolute long references.
+--binary writes a flat image for ROMs. Sections are placed at the address of their ORG, or after
+the section before them, starting at the --org address (0 by default). The image starts at the
+--org address or, without one, at the lowest section. Gaps are filled with the --fill byte ($FF
+by default). --pad pads the image to the next power of two and --rom-size to the given size.
+Numbers are written like 1024, $400 or 0x400.
+
+Dialects are motorola (the default), mit, devpac and vasm. --format prints the source in another
+dialect.
+
+Without rules, --optimize enables all of them: move-to-moveq, add-to-addq, clr-to-moveq,
+remove-empty-lea and remove-zero-displacement.
+--fix applies the suggested fixes of the errors to the source file.
+
+Levels change how warnings and infos are reported: --allow=<code>,... silences them,
+--info=<code>,..., --warn=<code>,... and --deny=<code>,... change their severity and -Werror
+turns all warnings into errors. A comment like `; m68k-allow(<code>,...)` silences them on its
+line, or on the next line if the comment is on its own line.";
+
+fn main() {
+    let mut path = None;
+    let mut rules = vec![];
+    let mut fix = false;
+    let mut levels = Levels::new();
+    let mut dialect = Dialect::Motorola;
+    let mut format_dialect = None;
+    let mut output = None;
+    let mut origin = None;
+    let mut image_options = ImageOptions::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--explain" {
+            return match args.next() {
+                Some(code) => explain(&code),
+                None => fail(USAGE),
+            };
+        } else if levels.parse_arg(&arg) {
+            continue;
+        } else if let Some(name) = arg.strip_prefix("--dialect=") {
+            match Dialect::from_name(name) {
+                Some(from) => dialect = from,
+                None => fail(&format!("Unknown dialect {}.\n{}", name, USAGE)),
+            }
+        } else if let Some(name) = arg.strip_prefix("--format=") {
+            match Dialect::from_name(name) {
+                Some(to) => format_dialect = Some(to),
+                None => fail(&format!("Unknown dialect {}.\n{}", name, USAGE)),
+            }
+        } else if let Some(path) = arg.strip_prefix("--object=") {
+            output = Some((Output::Object, path.to_string()));
+        } else if let Some(path) = arg.strip_prefix("--prg=") {
+            output = Some((Output::Executable, path.to_string()));
+        } else if let Some(path) = arg.strip_prefix("--binary=") {
+            output = Some((Output::Binary, path.to_string()));
+        } else if let Some(number) = arg.strip_prefix("--org=") {
+            origin = Some(parse_number(&arg, number));
+        } else if let Some(number) = arg.strip_prefix("--fill=") {
+            image_options.fill = match parse_number(&arg, number) {
+                fill if fill <= 0xff => fill as Byte,
+                _ => fail(&format!("{} has to be a byte from 0 to $FF.", arg)),
+            };
+        } else if let Some(number) = arg.strip_prefix("--rom-size=") {
+            image_options.padding = Padding::RomSize(parse_number(&arg, number));
+        } else if arg == "--pad" {
+            image_options.padding = Padding::PowerOfTwo;
+        } else if arg == "--fix" {
+            fix = true;
+        } else if arg == "--optimize" {
+            rules = Rule::ALL.to_vec();
+        } else if let Some(names) = arg.strip_prefix("--optimize=") {
+            for name in names.split(',') {
+                match Rule::from_name(name) {
+                    Some(rule) => rules.push(rule),
+                    None => fail(&format!("Unknown optimization {}.\n{}", name, USAGE)),
+                }
+            }
+        } else if arg.starts_with('-') {
+            fail(&format!("Unknown option {}.\n{}", arg, USAGE));
+        } else {
+            path = Some(arg);
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => fail(USAGE),
+    };
+    let source = std::fs::read_to_string(&path).expect("Couldn't read the source.");
+    let mut errors = Default::default();
+
+    let tokens: Vec<Token> = scan_dialect(&source, dialect, &mut errors).collect();
+    let mut program = parse_dialect(tokens, dialect, &mut errors);
+    if let Some(format_dialect) = format_dialect {
+        print!("{}", format(&program, format_dialect));
+        errors.print();
+        return;
+    }
+    let bytes = match &output {
+        Some((output, _)) => {
+            optimize(&mut program, &rules, &mut errors);
+            assemble_output(output, &path, &program, origin, &image_options, &mut errors)
+        }
+        None => {
+            validate(&program, &mut errors);
+            optimize(&mut program, &rules, &mut errors);
+            print_listing(&source, &program, &mut errors);
+            None
+        }
+    };
+    levels.apply(&source, &mut errors);
+    errors.print();
+
+    if fix {
+        let (fixed, count) = apply_fixes(&source, &errors);
+        if count > 0 {
+            std::fs::write(&path, fixed).expect("Couldn't write the fixed source.");
+        }
+        eprintln!(
+            "Applied {} fix{}.",
+            count,
+            if count == 1 { "" } else { "es" }
+        );
+    }
+
+    if errors.iter().any(|error| error.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+    if let (Some(bytes), Some((_, output_path))) = (bytes, output) {
+        match bytes {
+            Ok(bytes) => std::fs::write(output_path, bytes).expect("Couldn't write the output."),
+            Err(error) => fail(&error),
+        }
+    }
+}
+
+/// What the assembler writes instead of printing a listing.
+enum Output {
+    /// An ELF object for the linker.
+    Object,
+    /// A GEMDOS executable.
+    Executable,
+    /// A flat image, which is placed at a fixed address.
+    Binary,
+}
+
+/// Assembles the program into the bytes of the output. Problems of linking and of the output
+/// format, like an image that doesn't fit into the ROM, are returned as messages. Images start at
+/// the origin or, without one, at their lowest section.
+fn assemble_output(
+    output: &Output,
+    name: &str,
+    program: &Program,
+    origin: Option<LongWord>,
+    image_options: &ImageOptions,
+    errors: &mut ErrorCollector,
+) -> Option<Result<Vec<Byte>, String>> {
+    match output {
+        Output::Object => Some(Ok(elf::write(&assemble_object(program, errors)))),
+        Output::Executable => Some(
+            assemble_executable(program, name, errors)
+                .and_then(|executable| prg::write(&executable).map_err(|error| error.to_string())),
+        ),
+        Output::Binary => {
+            let assembled = assemble(program, origin.unwrap_or(0), errors);
+            assembled.report_imports(errors);
+            let lowest = assembled
+                .segments
+                .iter()
+                .filter(|segment| !segment.bytes.is_empty())
+                .map(|segment| segment.address)
+                .min();
+            let image_options = ImageOptions {
+                origin: origin.or(lowest).unwrap_or(0),
+                ..*image_options
+            };
+            Some(
+                image::build(&assembled.segments, &image_options)
+                    .map_err(|error| error.to_string()),
+            )
+        }
+    }
+}
+
+/// Prints a listing with the address, size and cycles of every statement, followed by the totals
+/// of the blocks between labels.
+fn print_listing(source: &str, program: &Program, errors: &mut ErrorCollector) {
+    build_symbol_table(program, errors);
+    for section in split_into_sections(program, errors) {
+        println!("SECTION {}", section.name);
+        let start = section.address.map_or(0, |address| address.value);
+        let layout = lay_out(&section.statements, start, errors);
+        for placed in &layout.statements {
+            let timing = match &placed.statement.value {
+                Statement::Operation(operation) => operation_timing(operation, placed.branch)
+                    .map(|timing| timing.to_string())
+                    .unwrap_or_else(|| "?".to_string()),
+                _ => String::new(),
+            };
+            println!(
+                "{:08X} {:2} {:>5}  {}",
+                // Sections that run past the end of the address space are only reported when
+                // they're assembled.
+                start.wrapping_add(placed.address),
+                placed.size,
+                timing,
+                &source[placed.statement.range.clone()]
+            );
+        }
+        println!();
+        for block in blocks(&layout) {
+            println!(
+                "{:<20} {:5} bytes {:6}-{} cycles",
+                block.label.as_deref().unwrap_or("(start)"),
+                block.size,
+                block.best,
+                block.worst
+            );
+        }
+    }
+}
+
+fn explain(code: &str) {
+    match lookup(code) {
+        Some(code) => println!(
+            "{} ({}, reported by the {:?})\n\n{}",
+            code.code, code.severity, code.source, code.explanation
+        ),
+        None => fail(&format!("There's no error with the code {}.", code)),
+    }
+}
+
+/// Prints the message, like the usage, and exits with a failure.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+/// Parses the number of the option, like 1024, $400 or 0x400.
+fn parse_number(arg: &str, number: &str) -> LongWord {
+    let parsed = match number
+        .strip_prefix('$')
+        .or_else(|| number.strip_prefix("0x"))
+    {
+        Some(hex) => LongWord::from_str_radix(hex, 16).ok(),
+        None => number.parse().ok(),
+    };
+    parsed.unwrap_or_else(|| {
+        fail(&format!(
+            "{} expects a number like 1024, $400 or 0x400.",
+            arg
+        ))
+    })
+}
@@ -0,0 +1,338 @@
+//! A peephole optimizer that replaces single operations with shorter or faster ones that have the
+//! same effect, including on the condition codes.
+//!
+//! All rules are opt-in, because some programmers rely on the exact encoding they wrote (for
+//! example in self-modifying code). Every rewrite is reported as an info diagnostic.
+
+use crate::inference::infer_size;
+use crate::validation::validate_operation;
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use m68k_reloaded_common::Range;
+use m68k_reloaded_parser::statements::*;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Rule {
+    /// `MOVE.L #n,Dn` with -128 ≤ n ≤ 127 becomes `MOVEQ #n,Dn`.
+    MoveToMoveq,
+    /// `ADD`, `ADDA` and `ADDI` with an immediate 1 ≤ n ≤ 8 become `ADDQ #n`.
+    AddToAddq,
+    /// `CLR.L Dn` becomes `MOVEQ #0,Dn`.
+    ClrToMoveq,
+    /// `LEA 0(An),An` is removed, because it doesn't do anything.
+    RemoveEmptyLea,
+    /// `0(An)` becomes `(An)`.
+    RemoveZeroDisplacement,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 5] = [
+        Rule::MoveToMoveq,
+        Rule::AddToAddq,
+        Rule::ClrToMoveq,
+        Rule::RemoveEmptyLea,
+        Rule::RemoveZeroDisplacement,
+    ];
+
+    /// The name used to enable the rule on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::MoveToMoveq => "move-to-moveq",
+            Rule::AddToAddq => "add-to-addq",
+            Rule::ClrToMoveq => "clr-to-moveq",
+            Rule::RemoveEmptyLea => "remove-empty-lea",
+            Rule::RemoveZeroDisplacement => "remove-zero-displacement",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.iter().copied().find(|rule| rule.name() == name)
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Rule::MoveToMoveq => "Replaced MOVE.L with MOVEQ.",
+            Rule::AddToAddq => "Replaced the addition of a small immediate with ADDQ.",
+            Rule::ClrToMoveq => "Replaced CLR.L with MOVEQ #0.",
+            Rule::RemoveEmptyLea => "Removed a LEA that doesn't change the register.",
+            Rule::RemoveZeroDisplacement => "Removed a displacement of 0.",
+        }
+    }
+}
+
+/// Applies the enabled rules to the program. Operations that don't exist on the 68000 are kept as
+/// written, so that they are still reported by the validation.
+pub fn optimize(program: &mut Program, rules: &[Rule], errors: &mut ErrorCollector) {
+    let is_enabled = |rule: Rule| rules.contains(&rule);
+
+    if is_enabled(Rule::RemoveEmptyLea) {
+        program.retain(|statement| {
+            let is_empty_lea = matches!(&statement.value, Statement::Operation(operation)
+                if is_empty_lea(operation) && is_valid(operation, statement.range.clone()));
+            if is_empty_lea {
+                errors.push(Error::optimized(
+                    statement.range.clone(),
+                    Rule::RemoveEmptyLea.description(),
+                ));
+            }
+            !is_empty_lea
+        });
+    }
+
+    for statement in program.iter_mut() {
+        let operation = match &mut statement.value {
+            Statement::Operation(operation) if is_valid(operation, statement.range.clone()) => {
+                operation
+            }
+            _ => continue,
+        };
+        if is_enabled(Rule::RemoveZeroDisplacement) {
+            for operand in &mut operation.operands {
+                if remove_zero_displacement(&mut operand.value) {
+                    errors.push(Error::optimized(
+                        operand.range.clone(),
+                        Rule::RemoveZeroDisplacement.description(),
+                    ));
+                }
+            }
+        }
+        let rule = match operation.operation_type.value {
+            OperationType::Move if is_enabled(Rule::MoveToMoveq) => {
+                move_to_moveq(operation).then_some(Rule::MoveToMoveq)
+            }
+            OperationType::Add | OperationType::Adda | OperationType::Addi
+                if is_enabled(Rule::AddToAddq) =>
+            {
+                add_to_addq(operation).then_some(Rule::AddToAddq)
+            }
+            OperationType::Clr if is_enabled(Rule::ClrToMoveq) => {
+                clr_to_moveq(operation).then_some(Rule::ClrToMoveq)
+            }
+            _ => None,
+        };
+        if let Some(rule) = rule {
+            errors.push(Error::optimized(
+                statement.range.clone(),
+                rule.description(),
+            ));
+        }
+    }
+}
+
+fn is_valid(operation: &Operation, range: Range) -> bool {
+    let mut errors = vec![];
+    validate_operation(operation, range, &mut errors);
+    errors.is_empty()
+}
+
+fn is_empty_lea(operation: &Operation) -> bool {
+    match (&operation.operation_type.value, &operation.operands[..]) {
+        (OperationType::Lea, [source, destination]) => matches!(
+            (&source.value, &destination.value),
+            (EffectiveAddress::AnIndWithDisplacement(displacement, source), EffectiveAddress::An(destination))
+                if displacement.value == 0 && source.index == destination.index
+        ),
+        _ => false,
+    }
+}
+
+fn remove_zero_displacement(address: &mut EffectiveAddress) -> bool {
+    let is_zero = matches!(address, EffectiveAddress::AnIndWithDisplacement(displacement, _) if displacement.value == 0);
+    if !is_zero {
+        return false;
+    }
+    let placeholder = EffectiveAddress::Immediate(Stmt {
+        range: 0..0,
+        value: 0,
+    });
+    if let EffectiveAddress::AnIndWithDisplacement(_, an) = std::mem::replace(address, placeholder)
+    {
+        *address = EffectiveAddress::AnInd(an);
+    }
+    true
+}
+
+fn move_to_moveq(operation: &mut Operation) -> bool {
+    let is_small = match &operation.operands[..] {
+        [source, destination] => matches!(
+            (&source.value, &destination.value),
+            (EffectiveAddress::Immediate(value), EffectiveAddress::Dn(_))
+                if (-128..=127).contains(&(value.value as i32))
+        ),
+        _ => false,
+    };
+    if infer_size(operation) != Some(Size::LongWord) || !is_small {
+        return false;
+    }
+    operation.operation_type.value = OperationType::Moveq;
+    true
+}
+
+fn add_to_addq(operation: &mut Operation) -> bool {
+    let is_quick = match &operation.operands[..] {
+        [source, destination] => matches!(
+            (&source.value, &destination.value),
+            (EffectiveAddress::Immediate(value), destination)
+                if (1..=8).contains(&value.value)
+                    // ADDQ can't operate on bytes of address registers.
+                    && !(matches!(destination, EffectiveAddress::An(_)) && infer_size(operation) == Some(Size::Byte))
+        ),
+        _ => false,
+    };
+    if !is_quick {
+        return false;
+    }
+    operation.operation_type.value = OperationType::Addq;
+    true
+}
+
+fn clr_to_moveq(operation: &mut Operation) -> bool {
+    let range = match &operation.operands[..] {
+        [destination] if matches!(destination.value, EffectiveAddress::Dn(_)) => {
+            operation.operation_type.range.clone()
+        }
+        _ => return false,
+    };
+    if infer_size(operation) != Some(Size::LongWord) {
+        return false;
+    }
+    operation.operation_type.value = OperationType::Moveq;
+    operation.operands.insert(
+        0,
+        Stmt {
+            range: range.clone(),
+            value: EffectiveAddress::Immediate(Stmt { range, value: 0 }),
+        },
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m68k_reloaded_parser::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn optimize_source(source: &str, rules: &[Rule]) -> (Program, Vec<&'static str>) {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let mut program = parse(tokens, &mut errors);
+        assert!(errors.is_empty(), "Parsing failed.");
+        optimize(&mut program, rules, &mut errors);
+        let codes = errors.iter().map(|error| error.code).collect();
+        (program, codes)
+    }
+
+    fn operation_types(program: &Program) -> Vec<OperationType> {
+        program
+            .iter()
+            .filter_map(|statement| match &statement.value {
+                Statement::Operation(operation) => Some(operation.operation_type.value),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rules_are_opt_in() {
+        let source = " MOVE.L #1,D0\n ADD.W #1,D0\n CLR.L D0\n LEA.L 0(A0),A0";
+        let (program, codes) = optimize_source(source, &[]);
+        assert_eq!(
+            operation_types(&program),
+            vec![
+                OperationType::Move,
+                OperationType::Add,
+                OperationType::Clr,
+                OperationType::Lea
+            ]
+        );
+        assert!(codes.is_empty());
+
+        let (program, codes) = optimize_source(source, &Rule::ALL);
+        assert_eq!(
+            operation_types(&program),
+            vec![
+                OperationType::Moveq,
+                OperationType::Addq,
+                OperationType::Moveq
+            ]
+        );
+        assert_eq!(codes.len(), 4);
+    }
+
+    #[test]
+    fn test_rules_keep_semantics() {
+        let (program, codes) = optimize_source(
+            " MOVE.L #128,D0\n MOVE.W #1,D0\n MOVE.L #1,(A0)\n MOVE.L #-128,D0\n ADDI.W #9,D0\n ADDA.W #8,A0\n CLR.W D0\n LEA.L 0(A0),A1",
+            &Rule::ALL,
+        );
+        assert_eq!(
+            operation_types(&program),
+            vec![
+                OperationType::Move,
+                OperationType::Move,
+                OperationType::Move,
+                OperationType::Moveq,
+                OperationType::Addi,
+                OperationType::Addq,
+                OperationType::Clr,
+                OperationType::Lea,
+            ]
+        );
+        assert_eq!(codes, vec!["optimized", "optimized", "optimized"]);
+    }
+
+    #[test]
+    fn test_invalid_operations_are_kept() {
+        let (program, codes) = optimize_source(
+            " ADDI.W #1,A0\n ADD.W #2,A0\n MOVE.L #1,D0,D1\n CLR.L A0\n ADD.B 0(A0),A1",
+            &Rule::ALL,
+        );
+        assert_eq!(
+            operation_types(&program),
+            vec![
+                OperationType::Addi,
+                OperationType::Add,
+                OperationType::Move,
+                OperationType::Clr,
+                OperationType::Add,
+            ]
+        );
+        assert!(codes.is_empty());
+    }
+
+    #[test]
+    fn test_clr_to_moveq_adds_an_immediate() {
+        let (program, codes) = optimize_source(" CLR.L D3", &[Rule::ClrToMoveq]);
+        match &program[0].value {
+            Statement::Operation(operation) => assert!(matches!(
+                &operation.operands[..],
+                [zero, dn] if matches!(&zero.value, EffectiveAddress::Immediate(value) if value.value == 0)
+                    && matches!(&dn.value, EffectiveAddress::Dn(dn) if dn.index == 3)
+            )),
+            statement => panic!("Expected an operation, got {:?}.", statement),
+        }
+        assert_eq!(codes, vec!["optimized"]);
+    }
+
+    #[test]
+    fn test_remove_zero_displacement() {
+        let (program, codes) = optimize_source(
+            " ADD.W 0(A2),D0\n ADD.W 2(A2),D0",
+            &[Rule::RemoveZeroDisplacement],
+        );
+        let operands: Vec<&EffectiveAddress> = program
+            .iter()
+            .filter_map(|statement| match &statement.value {
+                Statement::Operation(operation) => Some(&operation.operands[0].value),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(operands[0], EffectiveAddress::AnInd(an) if an.index == 2));
+        assert!(matches!(
+            operands[1],
+            EffectiveAddress::AnIndWithDisplacement(_, _)
+        ));
+        assert_eq!(codes, vec!["optimized"]);
+        assert_eq!(Rule::from_name("add-to-addq"), Some(Rule::AddToAddq));
+    }
+}
@@ -0,0 +1,84 @@
+//! The number of bytes operations take up in memory.
+
+use crate::inference::infer_size;
+use m68k_reloaded_common::LongWord;
+use m68k_reloaded_parser::statements::*;
+
+/// The size of the extension words that follow the operation word for an effective address.
+pub fn extension_size(address: &EffectiveAddress, size: Size) -> LongWord {
+    match address {
+        EffectiveAddress::Dn(_)
+        | EffectiveAddress::An(_)
+        | EffectiveAddress::AnInd(_)
+        | EffectiveAddress::AnIndWithPostInc(_)
+        | EffectiveAddress::AnIndWithPreDec(_) => 0,
+        EffectiveAddress::AnIndWithDisplacement(_, _)
+        | EffectiveAddress::AnIndWithIndex(_, _, _)
+        | EffectiveAddress::AbsoluteWord(_)
+        | EffectiveAddress::PcIndWithDisplacement(_)
+        | EffectiveAddress::PcIndWithIndex(_, _) => 2,
+        EffectiveAddress::AbsoluteLongWord(_) | EffectiveAddress::Label(_) => 4,
+        EffectiveAddress::Immediate(_) => match size {
+            Size::LongWord => 4,
+            Size::Byte | Size::Word => 2,
+        },
+    }
+}
+
+/// The size of an operation in bytes. The size of branches depends on the distance to their
+/// target, so the layout chooses it; unsized branches count as word branches here.
+pub fn operation_size(operation: &Operation) -> LongWord {
+    let size = infer_size(operation).unwrap_or(Size::Word);
+    let operands = match operation.operation_type.value {
+        OperationType::Bcc(_) | OperationType::Bra | OperationType::Bsr => {
+            return match size {
+                Size::Byte => 2,
+                Size::Word | Size::LongWord => 4,
+            }
+        }
+        // The immediate data of ADDQ, MOVEQ and TRAP is part of the operation word.
+        OperationType::Addq | OperationType::Moveq | OperationType::Trap => {
+            &operation.operands[operation.operands.len().min(1)..]
+        }
+        _ => &operation.operands[..],
+    };
+    2 + operands
+        .iter()
+        .map(|operand| extension_size(operand, size))
+        .sum::<LongWord>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m68k_reloaded_parser::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn size_of(source: &str) -> LongWord {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(errors.is_empty(), "Parsing {} failed.", source);
+        match &program[0].value {
+            Statement::Operation(operation) => operation_size(operation),
+            statement => panic!("Expected an operation, got {:?}.", statement),
+        }
+    }
+
+    #[test]
+    fn test_operation_size() {
+        assert_eq!(size_of(" ADD.W D0,D1"), 2);
+        assert_eq!(size_of(" ADD.W 4(A0),D1"), 4);
+        assert_eq!(size_of(" ADDI.W #1,$ff8240"), 8);
+        assert_eq!(size_of(" ADDI.L #1,label"), 10);
+        assert_eq!(size_of(" ADDQ.L #1,(A0)+"), 2);
+        assert_eq!(size_of(" MOVEQ.L #-1,D0"), 2);
+        assert_eq!(size_of(" MOVE.L #-1,D0"), 6);
+        assert_eq!(size_of(" BRA.S label"), 2);
+        assert_eq!(size_of(" BNE label"), 4);
+        assert_eq!(size_of(" MOVE #1,D0"), 4);
+        assert_eq!(size_of(" LEA $12345678,A0"), 6);
+        assert_eq!(size_of(" TRAP #1"), 2);
+        assert_eq!(size_of(" STOP #$2700"), 4);
+    }
+}
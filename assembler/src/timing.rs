@@ -0,0 +1,390 @@
+//! Cycle counts of the 68000, as listed in the timing tables of the MC68000 user's manual.
+//!
+//! The times assume zero wait states, so memory accesses take four cycles each. Effective addresses
+//! add the time it takes to calculate the address and to fetch the operand.
+
+use crate::inference::infer_size;
+use crate::layout::{BranchSize, Layout};
+use m68k_reloaded_common::{LongWord, Range};
+use m68k_reloaded_parser::statements::*;
+use std::fmt::{self, Display};
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Timing {
+    /// The cycles the operation takes. For conditional branches, that's when the branch is taken.
+    pub cycles: u32,
+    /// The cycles a conditional branch takes if it's not taken.
+    pub not_taken: Option<u32>,
+}
+
+impl Timing {
+    fn fixed(cycles: u32) -> Timing {
+        Timing {
+            cycles,
+            not_taken: None,
+        }
+    }
+
+    pub fn best(self) -> u32 {
+        self.not_taken
+            .map_or(self.cycles, |not_taken| not_taken.min(self.cycles))
+    }
+
+    pub fn worst(self) -> u32 {
+        self.not_taken
+            .map_or(self.cycles, |not_taken| not_taken.max(self.cycles))
+    }
+}
+
+impl Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.not_taken {
+            Some(not_taken) => write!(f, "{}/{}", self.cycles, not_taken),
+            None => write!(f, "{}", self.cycles),
+        }
+    }
+}
+
+/// The time it takes to calculate an effective address and read the operand.
+pub fn address_time(address: &EffectiveAddress, size: Size) -> u32 {
+    let is_long = size == Size::LongWord;
+    let (short, long) = match address {
+        EffectiveAddress::Dn(_) | EffectiveAddress::An(_) => (0, 0),
+        EffectiveAddress::AnInd(_) | EffectiveAddress::AnIndWithPostInc(_) => (4, 8),
+        EffectiveAddress::AnIndWithPreDec(_) => (6, 10),
+        EffectiveAddress::AnIndWithDisplacement(_, _)
+        | EffectiveAddress::AbsoluteWord(_)
+        | EffectiveAddress::PcIndWithDisplacement(_) => (8, 12),
+        EffectiveAddress::AnIndWithIndex(_, _, _) | EffectiveAddress::PcIndWithIndex(_, _) => {
+            (10, 14)
+        }
+        EffectiveAddress::AbsoluteLongWord(_) | EffectiveAddress::Label(_) => (12, 16),
+        EffectiveAddress::Immediate(_) => (4, 8),
+    };
+    if is_long {
+        long
+    } else {
+        short
+    }
+}
+
+/// The time it takes to write the destination of a MOVE. Unlike reading, writing to `-(An)`
+/// doesn't take extra time.
+fn move_destination_time(address: &EffectiveAddress, size: Size) -> u32 {
+    match (address, size) {
+        (EffectiveAddress::AnIndWithPreDec(_), Size::LongWord) => 8,
+        (EffectiveAddress::AnIndWithPreDec(_), _) => 4,
+        (address, size) => address_time(address, size),
+    }
+}
+
+fn is_register_or_immediate(address: &EffectiveAddress) -> bool {
+    matches!(
+        address,
+        EffectiveAddress::Dn(_) | EffectiveAddress::An(_) | EffectiveAddress::Immediate(_)
+    )
+}
+
+/// The cycles an operation takes. Branches need the size chosen by the layout if the source
+/// doesn't specify one. Returns `None` for operations that don't exist, like `ADDX` with memory
+/// operands other than `-(An)`, and for operations with an ambiguous size.
+pub fn operation_timing(operation: &Operation, branch: Option<BranchSize>) -> Option<Timing> {
+    let size = infer_size(operation)?;
+    let is_long = size == Size::LongWord;
+    let by_size = |short: u32, long: u32| if is_long { long } else { short };
+    let operands: Vec<&EffectiveAddress> = operation
+        .operands
+        .iter()
+        .map(|operand| &operand.value)
+        .collect();
+
+    let cycles = match (operation.operation_type.value, &operands[..]) {
+        (OperationType::Add, [source, EffectiveAddress::Dn(_)]) => {
+            let extra = if is_long && is_register_or_immediate(source) {
+                2
+            } else {
+                0
+            };
+            by_size(4, 6) + extra + address_time(source, size)
+        }
+        (OperationType::Add, [EffectiveAddress::Immediate(_), destination])
+        | (OperationType::Addi, [EffectiveAddress::Immediate(_), destination]) => match destination
+        {
+            EffectiveAddress::Dn(_) => by_size(8, 16),
+            destination => by_size(12, 20) + address_time(destination, size),
+        },
+        (OperationType::Add, [EffectiveAddress::Dn(_), destination]) => {
+            by_size(8, 12) + address_time(destination, size)
+        }
+        (OperationType::Adda, [source, EffectiveAddress::An(_)]) => {
+            let extra = if is_long && is_register_or_immediate(source) {
+                2
+            } else {
+                0
+            };
+            by_size(8, 6) + extra + address_time(source, size)
+        }
+        (OperationType::Addq, [EffectiveAddress::Immediate(_), destination]) => match destination {
+            EffectiveAddress::Dn(_) => by_size(4, 8),
+            EffectiveAddress::An(_) => 8,
+            destination => by_size(8, 12) + address_time(destination, size),
+        },
+        (OperationType::Addx, [EffectiveAddress::Dn(_), EffectiveAddress::Dn(_)]) => by_size(4, 8),
+        (
+            OperationType::Addx,
+            [EffectiveAddress::AnIndWithPreDec(_), EffectiveAddress::AnIndWithPreDec(_)],
+        ) => by_size(18, 30),
+        // Without an exception. Taking one adds the time of the exception processing.
+        (OperationType::Chk, [source, EffectiveAddress::Dn(_)]) => 10 + address_time(source, size),
+        (OperationType::Clr, [EffectiveAddress::Dn(_)]) => by_size(4, 6),
+        (OperationType::Clr, [destination]) => by_size(8, 12) + address_time(destination, size),
+        (OperationType::Lea, [source, EffectiveAddress::An(_)]) => match source {
+            EffectiveAddress::AnInd(_) => 4,
+            EffectiveAddress::AnIndWithIndex(_, _, _) | EffectiveAddress::PcIndWithIndex(_, _) => {
+                12
+            }
+            // The remaining modes happen to take as long as reading a word.
+            source => address_time(source, Size::Word),
+        },
+        (OperationType::Move, [source, destination]) => {
+            4 + address_time(source, size) + move_destination_time(destination, size)
+        }
+        (OperationType::Moveq, [EffectiveAddress::Immediate(_), EffectiveAddress::Dn(_)]) => 4,
+        // The time of divisions depends on the operands, so this is the worst case.
+        (OperationType::Divs, [source, EffectiveAddress::Dn(_)]) => {
+            158 + address_time(source, size)
+        }
+        (OperationType::Divu, [source, EffectiveAddress::Dn(_)]) => {
+            140 + address_time(source, size)
+        }
+        // JSR doesn't read its target, so it has its own table.
+        (OperationType::Jsr, [target]) => match target {
+            EffectiveAddress::AnInd(_) => 16,
+            EffectiveAddress::AnIndWithIndex(_, _, _) | EffectiveAddress::PcIndWithIndex(_, _) => {
+                22
+            }
+            EffectiveAddress::AbsoluteLongWord(_) | EffectiveAddress::Label(_) => 20,
+            _ => 18,
+        },
+        (OperationType::Nop, []) => 4,
+        (OperationType::Rte, []) => 20,
+        (OperationType::Rts, []) => 16,
+        (OperationType::Stop, [_]) => 4,
+        (OperationType::Trap, [_]) => 34,
+        (OperationType::Trapv, []) => 4,
+        (OperationType::Bra, [_]) => 10,
+        (OperationType::Bsr, [_]) => 18,
+        (OperationType::Bcc(_), [_]) => {
+            // The layout may have grown an explicit short branch, so its size wins.
+            let is_short = match branch {
+                Some(branch) => branch == BranchSize::Short,
+                None => operation.size.as_ref().map(|size| size.value) == Some(Size::Byte),
+            };
+            return Some(Timing {
+                cycles: 10,
+                not_taken: Some(if is_short { 8 } else { 12 }),
+            });
+        }
+        _ => return None,
+    };
+    Some(Timing::fixed(cycles))
+}
+
+/// The size and timing of a statement, like they are shown in the listing or as an inlay hint in
+/// the editor.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Annotation {
+    pub range: Range,
+    pub address: LongWord,
+    pub size: LongWord,
+    pub timing: Option<Timing>,
+}
+
+impl Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes", self.size)?;
+        if let Some(timing) = self.timing {
+            write!(f, ", {} cycles", timing)?;
+        }
+        Ok(())
+    }
+}
+
+/// The statements between two labels.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Block {
+    /// The label at the start of the block, if there is one.
+    pub label: Option<Label>,
+    pub range: Range,
+    pub size: LongWord,
+    /// The cycles if all conditional branches take the faster path.
+    pub best: u32,
+    /// The cycles if all conditional branches take the slower path.
+    pub worst: u32,
+}
+
+/// Annotates every operation of the layout.
+pub fn annotate(layout: &Layout) -> Vec<Annotation> {
+    layout
+        .statements
+        .iter()
+        .filter_map(|placed| match &placed.statement.value {
+            Statement::Operation(operation) => Some(Annotation {
+                range: placed.statement.range.clone(),
+                address: placed.address,
+                size: placed.size,
+                timing: operation_timing(operation, placed.branch),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Sums up sizes and cycles between labels.
+pub fn blocks(layout: &Layout) -> Vec<Block> {
+    let mut blocks: Vec<Block> = vec![];
+    for placed in &layout.statements {
+        let range = placed.statement.range.clone();
+        match &placed.statement.value {
+            Statement::Label(name) => blocks.push(Block {
+                label: Some(name.clone()),
+                range,
+                size: 0,
+                best: 0,
+                worst: 0,
+            }),
+            Statement::Operation(operation) => {
+                if blocks.is_empty() {
+                    blocks.push(Block {
+                        label: None,
+                        range: range.clone(),
+                        size: 0,
+                        best: 0,
+                        worst: 0,
+                    });
+                }
+                let block = blocks.last_mut().unwrap();
+                block.range.end = range.end;
+                block.size += placed.size;
+                if let Some(timing) = operation_timing(operation, placed.branch) {
+                    block.best += timing.best();
+                    block.worst += timing.worst();
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::lay_out;
+    use m68k_reloaded_parser::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn program_of(source: &str) -> Program {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(errors.is_empty(), "Parsing {} failed.", source);
+        program
+    }
+
+    fn cycles_of(source: &str) -> Option<Timing> {
+        match &program_of(source)[0].value {
+            Statement::Operation(operation) => operation_timing(operation, None),
+            statement => panic!("Expected an operation, got {:?}.", statement),
+        }
+    }
+
+    fn cycles(cycles: u32) -> Option<Timing> {
+        Some(Timing::fixed(cycles))
+    }
+
+    #[test]
+    fn test_operation_timing() {
+        assert_eq!(cycles_of(" ADD.W D0,D1"), cycles(4));
+        assert_eq!(cycles_of(" ADD.L D0,D1"), cycles(8));
+        assert_eq!(cycles_of(" ADD.L (A0)+,D1"), cycles(14));
+        assert_eq!(cycles_of(" ADD.L #1,D1"), cycles(16));
+        assert_eq!(cycles_of(" ADD.W D1,-(A0)"), cycles(14));
+        assert_eq!(cycles_of(" ADDI.W #1,4(A0)"), cycles(20));
+        assert_eq!(cycles_of(" ADDA.W D0,A0"), cycles(8));
+        assert_eq!(cycles_of(" ADDQ.L #1,D0"), cycles(8));
+        assert_eq!(cycles_of(" ADDQ.W #1,A0"), cycles(8));
+        assert_eq!(cycles_of(" ADDX.L -(A0),-(A1)"), cycles(30));
+        assert_eq!(cycles_of(" ADDX.L (A0),(A1)"), None);
+        assert_eq!(cycles_of(" CLR.L D0"), cycles(6));
+        assert_eq!(cycles_of(" LEA.L 8(A0,D0),A1"), cycles(12));
+        assert_eq!(cycles_of(" LEA.L $1234,A1"), cycles(8));
+        assert_eq!(cycles_of(" MOVE.W D0,-(A0)"), cycles(8));
+        assert_eq!(cycles_of(" MOVE.L #1,D0"), cycles(12));
+        assert_eq!(cycles_of(" MOVE.W 2(A0,D0),4(A1,D1)"), cycles(24));
+        assert_eq!(cycles_of(" MOVEQ.L #1,D0"), cycles(4));
+        assert_eq!(cycles_of(" MOVE.L D0,A0"), cycles(4));
+        assert_eq!(cycles_of(" MOVE.W (A0),A1"), cycles(8));
+        assert_eq!(cycles_of(" BSR label"), cycles(18));
+        assert_eq!(cycles_of(" JSR label"), cycles(20));
+        assert_eq!(cycles_of(" JSR 2(A0)"), cycles(18));
+        assert_eq!(cycles_of(" RTS"), cycles(16));
+        assert_eq!(cycles_of(" DIVU.W (A0),D0"), cycles(144));
+        assert_eq!(cycles_of(" CHK #10,D0"), cycles(14));
+        assert_eq!(cycles_of(" TRAP #0"), cycles(34));
+        assert_eq!(
+            cycles_of(" BNE.S label"),
+            Some(Timing {
+                cycles: 10,
+                not_taken: Some(8)
+            })
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let program =
+            program_of(" MOVEQ.L #0,D0\nloop ADDQ.W #1,D0\n BNE loop\n\nnext MOVE.L D0,(A0)");
+        let statements: Vec<&Stmt<Statement>> = program.iter().collect();
+        let layout = lay_out(&statements, 0, &mut vec![]);
+
+        let annotations = annotate(&layout);
+        assert_eq!(annotations.len(), 4);
+        assert_eq!(annotations[2].to_string(), "2 bytes, 10/8 cycles");
+
+        let blocks = blocks(&layout);
+        let summary: Vec<(Option<&str>, LongWord, u32, u32)> = blocks
+            .iter()
+            .map(|block| (block.label.as_deref(), block.size, block.best, block.worst))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, 2, 4, 4),
+                (Some("loop"), 4, 12, 14),
+                (Some("next"), 2, 12, 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timing_of_grown_short_branches() {
+        let filler = " ADD.W D0,D1\n".repeat(64);
+        let program = program_of(&format!(" BNE.S end\n{}end", filler));
+        let statements: Vec<&Stmt<Statement>> = program.iter().collect();
+        let layout = lay_out(&statements, 0, &mut vec![]);
+        let branch = &layout.statements[0];
+        let operation = match &branch.statement.value {
+            Statement::Operation(operation) => operation,
+            statement => panic!("Expected an operation, got {:?}.", statement),
+        };
+        assert_eq!(branch.branch, Some(BranchSize::Word));
+        assert_eq!(
+            operation_timing(operation, branch.branch),
+            Some(Timing {
+                cycles: 10,
+                not_taken: Some(12)
+            })
+        );
+    }
+}
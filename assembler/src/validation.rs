@@ -0,0 +1,336 @@
+//! Checks that operations exist on the 68000 before they are laid out and encoded.
+//!
+//! The parser accepts any size and any addressing mode for every operation, so that a single
+//! mistake results in a precise error instead of a confusing parse error.
+
+use crate::inference::infer_size;
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use m68k_reloaded_common::Range;
+use m68k_reloaded_parser::statements::*;
+
+/// Groups of addressing modes, named like in the 68000 programmer's reference manual.
+#[derive(Clone, Copy)]
+enum Modes {
+    All,
+    Dn,
+    An,
+    Immediate,
+    PreDec,
+    Data,
+    DataAlterable,
+    MemoryAlterable,
+    Alterable,
+    Control,
+    /// Addresses that branches can jump to.
+    Target,
+}
+
+impl Modes {
+    fn allow(self, address: &EffectiveAddress) -> bool {
+        use EffectiveAddress::*;
+
+        let is_memory_alterable = matches!(
+            address,
+            AnInd(_)
+                | AnIndWithPostInc(_)
+                | AnIndWithPreDec(_)
+                | AnIndWithDisplacement(_, _)
+                | AnIndWithIndex(_, _, _)
+                | AbsoluteWord(_)
+                | AbsoluteLongWord(_)
+                | Label(_)
+        );
+        match self {
+            Modes::All => true,
+            Modes::Dn => matches!(address, Dn(_)),
+            Modes::An => matches!(address, An(_)),
+            Modes::Immediate => matches!(address, Immediate(_)),
+            Modes::PreDec => matches!(address, AnIndWithPreDec(_)),
+            Modes::Data => !matches!(address, An(_)),
+            Modes::DataAlterable => is_memory_alterable || matches!(address, Dn(_)),
+            Modes::MemoryAlterable => is_memory_alterable,
+            Modes::Alterable => is_memory_alterable || matches!(address, Dn(_) | An(_)),
+            Modes::Control => !matches!(
+                address,
+                Dn(_) | An(_) | AnIndWithPostInc(_) | AnIndWithPreDec(_) | Immediate(_)
+            ),
+            Modes::Target => matches!(address, AbsoluteWord(_) | AbsoluteLongWord(_) | Label(_)),
+        }
+    }
+}
+
+fn mode_name(address: &EffectiveAddress) -> &'static str {
+    match address {
+        EffectiveAddress::Dn(_) => "a data register",
+        EffectiveAddress::An(_) => "an address register",
+        EffectiveAddress::AnInd(_) => "an indirect address",
+        EffectiveAddress::AnIndWithPostInc(_) => "a post-increment address",
+        EffectiveAddress::AnIndWithPreDec(_) => "a pre-decrement address",
+        EffectiveAddress::AnIndWithDisplacement(_, _) => "an address with displacement",
+        EffectiveAddress::AnIndWithIndex(_, _, _) => "an indexed address",
+        EffectiveAddress::AbsoluteWord(_) | EffectiveAddress::AbsoluteLongWord(_) => {
+            "an absolute address"
+        }
+        EffectiveAddress::PcIndWithDisplacement(_) | EffectiveAddress::PcIndWithIndex(_, _) => {
+            "a PC-relative address"
+        }
+        EffectiveAddress::Immediate(_) => "an immediate value",
+        EffectiveAddress::Label(_) => "a label",
+    }
+}
+
+const ALL_SIZES: &[Size] = &[Size::Byte, Size::Word, Size::LongWord];
+
+/// The sizes and the combinations of addressing modes an operation supports.
+fn forms(operation_type: OperationType) -> (&'static [Size], &'static [&'static [Modes]]) {
+    match operation_type {
+        OperationType::Add => (
+            ALL_SIZES,
+            &[
+                &[Modes::All, Modes::Dn],
+                &[Modes::Dn, Modes::MemoryAlterable],
+                &[Modes::Immediate, Modes::DataAlterable],
+            ],
+        ),
+        OperationType::Adda => (&[Size::Word, Size::LongWord], &[&[Modes::All, Modes::An]]),
+        OperationType::Addi => (ALL_SIZES, &[&[Modes::Immediate, Modes::DataAlterable]]),
+        OperationType::Addq => (ALL_SIZES, &[&[Modes::Immediate, Modes::Alterable]]),
+        OperationType::Addx => (
+            ALL_SIZES,
+            &[&[Modes::Dn, Modes::Dn], &[Modes::PreDec, Modes::PreDec]],
+        ),
+        OperationType::Bcc(_) | OperationType::Bra | OperationType::Bsr => {
+            (&[Size::Byte, Size::Word], &[&[Modes::Target]])
+        }
+        OperationType::Chk | OperationType::Divs | OperationType::Divu => {
+            (&[Size::Word], &[&[Modes::Data, Modes::Dn]])
+        }
+        OperationType::Clr => (ALL_SIZES, &[&[Modes::DataAlterable]]),
+        OperationType::Jsr => (&[], &[&[Modes::Control]]),
+        OperationType::Nop | OperationType::Rte | OperationType::Rts | OperationType::Trapv => {
+            (&[], &[&[]])
+        }
+        OperationType::Stop | OperationType::Trap => (&[], &[&[Modes::Immediate]]),
+        OperationType::Lea => (&[Size::LongWord], &[&[Modes::Control, Modes::An]]),
+        // Moving to an address register is MOVEA, which MOVE is encoded as.
+        OperationType::Move => (
+            ALL_SIZES,
+            &[
+                &[Modes::All, Modes::DataAlterable],
+                &[Modes::All, Modes::An],
+            ],
+        ),
+        OperationType::Moveq => (&[Size::LongWord], &[&[Modes::Immediate, Modes::Dn]]),
+    }
+}
+
+fn size_names(sizes: &[Size]) -> String {
+    sizes
+        .iter()
+        .map(|size| match size {
+            Size::Byte => ".B",
+            Size::Word => ".W",
+            Size::LongWord => ".L",
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks all operations of the program.
+pub fn validate(program: &Program, errors: &mut ErrorCollector) {
+    for statement in program {
+        if let Statement::Operation(operation) = &statement.value {
+            validate_operation(operation, statement.range.clone(), errors);
+        }
+    }
+}
+
+pub fn validate_operation(operation: &Operation, range: Range, errors: &mut ErrorCollector) {
+    let operation_type = operation.operation_type.value;
+    let name = operation_type.to_string();
+    let (sizes, forms) = forms(operation_type);
+
+    for operand in &operation.operands {
+        if let EffectiveAddress::AnIndWithIndex(_, _, index)
+        | EffectiveAddress::PcIndWithIndex(_, index) = &operand.value
+        {
+            if let Some(size) = &index.size {
+                if size.value == Size::Byte {
+                    errors.push(Error::invalid_index_size(size.range.clone()));
+                }
+            }
+        }
+    }
+
+    // Branches report long sizes while they are laid out.
+    if let Some(size) = &operation.size {
+        if !sizes.contains(&size.value) && !operation_type.is_branch() {
+            errors.push(Error::invalid_size(
+                size.range.clone(),
+                &name,
+                &size_names(sizes),
+            ));
+            return;
+        }
+    }
+    match infer_size(operation) {
+        Some(Size::Byte) if !operation_type.is_branch() => {
+            for (index, operand) in operation.operands.iter().enumerate() {
+                if matches!(operand.value, EffectiveAddress::An(_)) {
+                    let is_destination = index + 1 == operation.operands.len();
+                    errors.push(Error::byte_access_to_address_register(
+                        operand.range.clone(),
+                        address_variant(operation).filter(|_| is_destination),
+                    ));
+                }
+            }
+        }
+        Some(_) => {}
+        None => errors.push(Error::unspecified_size(
+            operation.operation_type.range.clone(),
+        )),
+    }
+
+    let operands = &operation.operands;
+    let expected = forms[0].len();
+    if operands.len() != expected {
+        errors.push(Error::wrong_operand_count(range, &name, expected));
+        return;
+    }
+    let matches = |form: &&[Modes]| {
+        form.iter()
+            .zip(operands)
+            .all(|(modes, operand)| modes.allow(operand))
+    };
+    if !forms.iter().any(matches) {
+        let source_matches = forms.iter().any(|form| form[0].allow(&operands[0]));
+        let error = if !source_matches {
+            let constructor = if expected == 1 {
+                Error::invalid_destination_mode
+            } else {
+                Error::invalid_source_mode
+            };
+            constructor(operands[0].range.clone(), &name, mode_name(&operands[0]))
+        } else {
+            let destination = operands.last().unwrap();
+            Error::invalid_destination_mode(
+                destination.range.clone(),
+                &name,
+                mode_name(destination),
+            )
+        };
+        errors.push(error);
+        return;
+    }
+
+    let quick_range = match operation_type {
+        OperationType::Addq => Some((1, 8)),
+        OperationType::Moveq => Some((-128, 127)),
+        OperationType::Trap => Some((0, 15)),
+        _ => None,
+    };
+    if let (Some((min, max)), Some(EffectiveAddress::Immediate(value))) =
+        (quick_range, operands.first().map(|operand| &operand.value))
+    {
+        if !(min..=max).contains(&i64::from(value.value as i32)) {
+            errors.push(Error::quick_immediate_out_of_range(
+                operands[0].range.clone(),
+                &name,
+                min,
+                max,
+            ));
+        }
+    }
+}
+
+/// The range of the operation with its size and the word variant that accepts an address register
+/// as its destination, like ADDA.W for ADD.B.
+fn address_variant(operation: &Operation) -> Option<(Range, &'static str)> {
+    let variant = match operation.operation_type.value {
+        OperationType::Add => "ADDA.W",
+        _ => return None,
+    };
+    let end = operation
+        .size
+        .as_ref()
+        .map_or(operation.operation_type.range.end, |size| size.range.end);
+    Some((operation.operation_type.range.start..end, variant))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m68k_reloaded_common::errors::apply_fixes;
+    use m68k_reloaded_parser::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn validate_source(source: &str) -> Vec<&'static str> {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(errors.is_empty(), "Parsing {} failed.", source);
+        validate(&program, &mut errors);
+        errors.iter().map(|error| error.code).collect()
+    }
+
+    #[test]
+    fn test_valid_operations() {
+        let source =
+            " ADD.B (A0)+,D0\n ADD.L D0,4(A1)\n ADD.W #1,$1234\n ADDA.W D0,A0\n ADDQ.L #8,A0
+ ADDX.B -(A0),-(A1)\n CLR.W (A0)\n LEA.L 2(PC,D0.L),A0\n MOVE.L A0,-(A7)\n MOVEQ.L #-128,D0
+ BNE.S label\n BRA $1000\n MOVE #1,D0\n MOVE.L D0,A0\n MOVE D0,A1\n MOVE.L d0,sp\n LEA (A0),A1\n MOVEQ #1,D0\n JSR label\n JSR 4(PC)\n RTS\n NOP
+ DIVU.W (A0),D1\n DIVS #3,D0\n CHK 2(A0),D7\n TRAP #15\n TRAPV\n RTE\n STOP #$2000";
+        assert_eq!(validate_source(source), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_invalid_operations() {
+        let cases = [
+            (" ADDA.B D0,A0", "invalid_size"),
+            (" LEA.W (A0),A1", "invalid_size"),
+            (" ADD.B A0,D0", "byte_access_to_address_register"),
+            (" ADDQ.B #1,A0", "byte_access_to_address_register"),
+            (" MOVE.B D0,A0", "byte_access_to_address_register"),
+            (" MOVE.W D0,#5", "invalid_destination_mode"),
+            (" ADD.W D0,A0", "invalid_destination_mode"),
+            (" ADDX.W D0,-(A1)", "invalid_destination_mode"),
+            (" LEA.L (A0)+,A1", "invalid_source_mode"),
+            (" ADDI.W D0,D1", "invalid_source_mode"),
+            (" CLR.W #1", "invalid_destination_mode"),
+            (" BRA (A0)", "invalid_destination_mode"),
+            (" CLR.W D0,D1", "wrong_operand_count"),
+            (" ADDQ.W #9,D0", "quick_immediate_out_of_range"),
+            (" ADDQ.W #0,D0", "quick_immediate_out_of_range"),
+            (" MOVEQ.L #128,D0", "quick_immediate_out_of_range"),
+            (" MOVE.W (A0,D0.B),D0", "invalid_index_size"),
+            (" MOVE #$10000,D0", "unspecified_size"),
+            (" RTS.W", "invalid_size"),
+            (" JSR (A0)+", "invalid_destination_mode"),
+            (" NOP D0", "wrong_operand_count"),
+            (" DIVU.L D0,D1", "invalid_size"),
+            (" DIVS.W A0,D1", "invalid_source_mode"),
+            (" CHK.W D0,(A0)", "invalid_destination_mode"),
+            (" TRAP #16", "quick_immediate_out_of_range"),
+            (" TRAP D0", "invalid_destination_mode"),
+        ];
+        for (source, code) in cases.iter() {
+            assert_eq!(validate_source(source), vec![*code], "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_address_register_fix() {
+        let source = " ADD.B D0,A0";
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        validate(&program, &mut errors);
+        let error = errors
+            .iter()
+            .find(|error| error.code == "byte_access_to_address_register")
+            .unwrap();
+        assert_eq!(error.details.fixes[0].edits[0].range, 1..6);
+        let (fixed, _) = apply_fixes(source, &errors);
+        assert_eq!(fixed, " ADDA.W D0,A0");
+    }
+}
@@ -0,0 +1,59 @@
+//! Runs the assembler binary on sources written to a temporary directory.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Writes the source to a directory of its own and assembles it with the arguments, followed by
+/// the path of the source. Returns the output of the assembler and the directory.
+fn assemble(name: &str, source: &str, args: &[&str]) -> (Output, PathBuf) {
+    let directory = std::env::temp_dir().join(format!("m68k-assembler-{}", name));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("source.s");
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_assembler"))
+        .current_dir(&directory)
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+    (output, directory)
+}
+
+#[test]
+fn test_binary_places_sections_at_their_org() {
+    let source = " ORG $FC0000\n MOVEQ #1,D0\n SECTION vectors,DATA\n ORG $FC0008\n RTS";
+    let (output, directory) = assemble("org", source, &["--binary=image.bin", "--fill=0"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read(directory.join("image.bin")).unwrap(),
+        vec![0x70, 0x01, 0, 0, 0, 0, 0, 0, 0x4e, 0x75]
+    );
+}
+
+#[test]
+fn test_binary_reports_overlapping_sections() {
+    let source = " ORG $1000\n MOVE.L #1,D0\n SECTION vectors,DATA\n ORG $1004\n RTS";
+    let (output, directory) = assemble("overlap", source, &["--binary=image.bin"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "The segments at $1000 and $1004 overlap.\n"
+    );
+    assert!(!directory.join("image.bin").exists());
+}
+
+#[test]
+fn test_optimize_keeps_invalid_operations() {
+    let source = " ADDI.W #1,A0\n RTS";
+    let (output, directory) = assemble("optimize", source, &["--optimize", "--object=out.o"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error[invalid_destination_mode] at 11..13: ADDI can't use an address register as its destination.\n"
+    );
+    assert!(!directory.join("out.o").exists());
+}
@@ -1,27 +1,46 @@
 use crate::errors::Error;
 use crate::errors::ErrorCollector;
+use crate::Range;
+
+/// Something a [CursorParser] consumes, like a token. Parsers match on the kind of items, which
+/// leaves out the data they carry, like the name of an identifier.
+pub trait Item {
+    type Kind: Copy + Eq;
+
+    fn kind(&self) -> Self::Kind;
+
+    /// Where the item is in the source.
+    fn range(&self) -> Range;
+}
+
+/// A position of a [CursorParser] to return to, including the errors registered so far.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Checkpoint {
+    cursor: usize,
+    errors: usize,
+}
 
 /// Provides a cursor-based parsing environment and error handling.
 ///
 /// This class is used by multiple parsers on different abstraction layers. It offers two features:
 /// Consuming the source items piece by piece and registering errors.
-/// Internally, it keeps a vector of items and a cursor position. All of the items before the cursor
-/// position are empty, while the items at or after the cursor position are guaranteed to be
-/// non-empty. By advancing the `CursorParser`, items are moved out of the `CursorParser` structure
-/// and the cursor is advanced. Contrary it standard `Iterator`s or `Peekable`s, the `CursorParser`
-/// allows peeking arbitrarily beyond the cursor position (peeking beyond the bounds of the vector
-/// just results in returning None).
-pub struct CursorParser<'a, T: Clone> {
-    items: Vec<Option<T>>,
+/// Internally, it borrows a slice of items and keeps a cursor position into it. Advancing returns
+/// references to the items, so nothing is moved out and the parser can go back to an earlier
+/// position: [CursorParser::checkpoint] remembers the position and [CursorParser::restore]
+/// returns to it, also removing the errors registered in between. That way, a parser can try one
+/// alternative and fall back to another one if it fails. Contrary to standard `Iterator`s or
+/// `Peekable`s, the `CursorParser` allows peeking arbitrarily beyond the cursor position (peeking
+/// beyond the bounds of the slice just results in returning None).
+pub struct CursorParser<'a, 'e, T: Item> {
+    items: &'a [T],
     cursor: usize,
-    error_registry: &'a mut ErrorCollector,
+    error_registry: &'e mut ErrorCollector,
 }
 
-impl<T: Clone> CursorParser<'_, T> {
-    /// Creates a new struct that contains the given items.
-    pub fn from<'a>(items: Vec<T>, error_registry: &'a mut ErrorCollector) -> CursorParser<'a, T> {
+impl<'a, 'e, T: Item> CursorParser<'a, 'e, T> {
+    pub fn new(items: &'a [T], error_registry: &'e mut ErrorCollector) -> CursorParser<'a, 'e, T> {
         CursorParser {
-            items: items.into_iter().map(Some).collect(),
+            items,
             cursor: 0,
             error_registry,
         }
@@ -31,8 +50,9 @@ impl<T: Clone> CursorParser<'_, T> {
         self.cursor
     }
 
+    /// Whether all items are consumed.
     pub fn is_done(&self) -> bool {
-        self.cursor < self.items.len()
+        self.cursor >= self.items.len()
     }
 
     /// Registers an error.
@@ -40,91 +60,248 @@ impl<T: Clone> CursorParser<'_, T> {
         self.error_registry.push(error);
     }
 
-    /// Returns the next item (the same that calling `advance()` would return).
-    pub fn peek(&self) -> Option<T> {
-        if self.cursor < self.items.len() {
-            let maybe_item: Option<T> = self.items[self.cursor].clone();
-            let item: T = maybe_item.unwrap();
-            Some(item)
-        } else {
-            None
+    /// Registers the error if the result is one.
+    pub fn check<V>(&mut self, result: Result<V, Error>) -> Option<V> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.register(error);
+                None
+            }
         }
     }
 
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            cursor: self.cursor,
+            errors: self.error_registry.len(),
+        }
+    }
+
+    /// Returns to the checkpoint and removes the errors registered since then.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.cursor = checkpoint.cursor;
+        self.error_registry.truncate(checkpoint.errors);
+    }
+
+    /// Removes the errors registered since the checkpoint and returns them. The cursor stays where
+    /// it is.
+    pub fn take_errors_since(&mut self, checkpoint: Checkpoint) -> Vec<Error> {
+        let start = checkpoint.errors.min(self.error_registry.len());
+        self.error_registry.split_off(start)
+    }
+
+    /// Returns the next item (the same that calling `advance()` would return).
+    pub fn peek(&self) -> Option<&'a T> {
+        self.peek_nth(0)
+    }
+
+    /// Returns the item `n` items after the next one.
+    pub fn peek_nth(&self, n: usize) -> Option<&'a T> {
+        self.items.get(self.cursor + n)
+    }
+
+    pub fn peek_kind(&self) -> Option<T::Kind> {
+        self.peek().map(Item::kind)
+    }
+
+    /// Whether the next item has the kind.
+    pub fn is_at(&self, kind: T::Kind) -> bool {
+        self.peek_kind() == Some(kind)
+    }
+
     pub fn peek_map<R, M>(&self, mapper: M) -> Option<R>
     where
-        M: FnOnce(&T) -> R,
+        M: FnOnce(&'a T) -> R,
     {
-        self.peek().map(|item| mapper(&item))
+        self.peek().map(mapper)
     }
 
-    /// Moves the current item out of the structure and advances the cursor.
-    pub fn advance(&mut self) -> Option<T> {
-        self.cursor += 1;
-        if self.cursor <= self.items.len() {
-            std::mem::replace(&mut self.items[self.cursor - 1], None)
-        } else {
-            None
+    /// The range of the next item. At the end, that's an empty range after the last item.
+    pub fn next_range(&self) -> Range {
+        match self.peek() {
+            Some(item) => item.range(),
+            None => {
+                let end = self.items.last().map_or(0, |item| item.range().end);
+                end..end
+            }
         }
     }
 
-    /// Advances the cursor n times and returns the last item.
-    pub fn advance_n(&mut self, n: usize) -> Vec<Option<T>> {
-        let mut items: Vec<Option<T>> = vec![];
-        for _ in 0..n {
-            items.push(self.advance());
-        }
-        items
+    /// Returns the current item and advances the cursor.
+    pub fn advance(&mut self) -> Option<&'a T> {
+        let item = self.peek()?;
+        self.cursor += 1;
+        Some(item)
+    }
+
+    /// Advances the cursor up to n times and returns the items passed.
+    pub fn advance_n(&mut self, n: usize) -> &'a [T] {
+        let start = self.cursor;
+        self.cursor = (self.cursor + n).min(self.items.len());
+        &self.items[start..self.cursor]
     }
 
-    /// Expects the predicate to match the next character. If it does, consumes the next item and
+    /// Expects the predicate to match the next item. If it does, consumes the next item and
     /// returns it. Otherwise returns `None`.
-    pub fn advance_if<P>(&mut self, predicate: P) -> Option<T>
+    pub fn advance_if<P>(&mut self, predicate: P) -> Option<&'a T>
     where
-        P: FnOnce(&T) -> bool,
+        P: FnOnce(&'a T) -> bool,
     {
         match self.peek() {
-            Some(item) if predicate(&item) => {
-                let item = self.advance()?;
-                Some(item)
-            }
+            Some(item) if predicate(item) => self.advance(),
             _ => None,
         }
     }
 
-    pub fn advance_if_map<S, P>(&mut self, predicate: P) -> Option<S>
+    /// Consumes the next item if the mapper returns a value for it.
+    pub fn advance_if_map<S, M>(&mut self, mapper: M) -> Option<S>
     where
-        P: FnOnce(T) -> Option<S>,
+        M: FnOnce(&'a T) -> Option<S>,
     {
-        match self.peek() {
-            None => None,
-            Some(item) => match predicate(item) {
-                Some(mapped_value) => Some(mapped_value),
-                None => None,
-            },
-        }
+        let value = self.peek().and_then(mapper)?;
+        self.cursor += 1;
+        Some(value)
+    }
+
+    /// Consumes the next item if it has the kind.
+    pub fn advance_kind(&mut self, kind: T::Kind) -> Option<&'a T> {
+        self.advance_if(|item| item.kind() == kind)
     }
 
     /// Advances the cursor multiple times until the predicate returns `false`. Then, returns a
-    /// vector containing all of the removed items.
-    pub fn advance_while<P>(&mut self, predicate: P) -> Vec<T>
+    /// slice containing all of the passed items.
+    pub fn advance_while<P>(&mut self, predicate: P) -> &'a [T]
+    where
+        P: Fn(&'a T) -> bool,
+    {
+        let start = self.cursor;
+        while self.advance_if(&predicate).is_some() {}
+        &self.items[start..self.cursor]
+    }
+
+    /// Consumes the next item if it has the kind. Otherwise, registers the error created from the
+    /// range of the unexpected item.
+    pub fn expect<E>(&mut self, kind: T::Kind, error: E) -> Option<&'a T>
     where
-        P: Fn(&T) -> bool,
+        E: FnOnce(Range) -> Error,
     {
-        self.advance_while_with_initial(predicate, vec![])
+        self.expect_map(|item| Some(item).filter(|item| item.kind() == kind), error)
     }
 
-    /// Like advance while but also accepts an initial vector that the removed items are added to.
-    pub fn advance_while_with_initial<P>(&mut self, predicate: P, initial: Vec<T>) -> Vec<T>
+    /// Consumes the next item if the mapper returns a value for it. Otherwise, registers the error
+    /// created from the range of the unexpected item.
+    pub fn expect_map<S, M, E>(&mut self, mapper: M, error: E) -> Option<S>
     where
-        P: Fn(&T) -> bool,
+        M: FnOnce(&'a T) -> Option<S>,
+        E: FnOnce(Range) -> Error,
     {
-        let mut elements = initial;
-        loop {
-            match self.advance_if(&predicate) {
-                Some(item) => elements.push(item),
-                None => break elements,
+        match self.advance_if_map(mapper) {
+            Some(value) => Some(value),
+            None => {
+                let range = self.next_range();
+                self.register(error(range));
+                None
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Letters with their position.
+    #[derive(Debug)]
+    struct Letter(char, usize);
+
+    impl Item for Letter {
+        type Kind = char;
+
+        fn kind(&self) -> char {
+            self.0
+        }
+
+        fn range(&self) -> Range {
+            self.1..self.1 + 1
+        }
+    }
+
+    fn letters(text: &str) -> Vec<Letter> {
+        text.chars()
+            .enumerate()
+            .map(|(index, letter)| Letter(letter, index))
+            .collect()
+    }
+
+    fn kinds(items: &[Letter]) -> String {
+        items.iter().map(Item::kind).collect()
+    }
+
+    #[test]
+    fn test_advance() {
+        let items = letters("aab");
+        let mut errors = vec![];
+        let mut parser = CursorParser::new(&items, &mut errors);
+        assert!(!parser.is_done());
+        assert_eq!(parser.peek_nth(2).map(Item::kind), Some('b'));
+        assert_eq!(parser.peek_nth(3).map(Item::kind), None);
+        assert_eq!(kinds(parser.advance_while(|item| item.0 == 'a')), "aa");
+        assert_eq!(parser.advance_kind('a').map(Item::kind), None);
+        assert_eq!(parser.advance_if_map(|item| Some(item.1)), Some(2));
+        assert!(parser.is_done());
+        assert!(parser.advance().is_none());
+        assert_eq!(parser.cursor(), 3);
+        assert_eq!(parser.next_range(), 3..3);
+    }
+
+    #[test]
+    fn test_advance_n() {
+        let items = letters("abc");
+        let mut errors = vec![];
+        let mut parser = CursorParser::new(&items, &mut errors);
+        assert_eq!(kinds(parser.advance_n(2)), "ab");
+        assert_eq!(kinds(parser.advance_n(2)), "c");
+        assert!(parser.is_done());
+    }
+
+    #[test]
+    fn test_expect() {
+        let items = letters("ab");
+        let mut errors = vec![];
+        let mut parser = CursorParser::new(&items, &mut errors);
+        assert!(parser.expect('a', error).is_some());
+        assert!(parser.expect('a', error).is_none());
+        assert_eq!(parser.cursor(), 1);
+        assert_eq!(parser.check(Ok::<_, Error>(2)), Some(2));
+        assert_eq!(parser.check::<()>(Err(error(0..0))), None);
+        parser.advance();
+        assert!(parser.expect_map(|_| Some(()), error).is_none());
+        let ranges: Vec<Range> = errors.iter().map(|error| error.range.clone()).collect();
+        assert_eq!(ranges, vec![1..2, 0..0, 2..2]);
+    }
+
+    #[test]
+    fn test_checkpoint_and_restore() {
+        let items = letters("abc");
+        let mut errors = vec![];
+        let mut parser = CursorParser::new(&items, &mut errors);
+        parser.advance();
+        let checkpoint = parser.checkpoint();
+        parser.advance();
+        parser.expect('a', error);
+        parser.restore(checkpoint);
+        assert_eq!(parser.peek_kind(), Some('b'));
+        assert!(parser.is_at('b'));
+
+        parser.expect('x', error);
+        parser.expect('y', error);
+        assert_eq!(parser.take_errors_since(checkpoint).len(), 2);
+        assert_eq!(parser.cursor(), 1);
+        assert!(errors.is_empty());
+    }
+
+    fn error(range: Range) -> Error {
+        Error::unexpected_token(range, "something else")
+    }
+}
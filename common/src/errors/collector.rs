@@ -7,7 +7,30 @@ pub trait PrintErrors {
 }
 
 impl PrintErrors for ErrorCollector {
+    /// Prints every error with its details indented below it.
     fn print(&self) {
-        unimplemented!()
+        for error in self {
+            eprintln!(
+                "{}[{}] at {:?}: {}",
+                error.severity, error.code, error.range, error.message
+            );
+            for related in &error.details.related {
+                eprintln!("  at {:?}: {}", related.range, related.message);
+            }
+            for note in &error.details.notes {
+                eprintln!("  note: {}", note);
+            }
+            if let Some(help) = &error.details.help {
+                eprintln!("  help: {}", help);
+            }
+            for fix in &error.details.fixes {
+                let edits: Vec<String> = fix
+                    .edits
+                    .iter()
+                    .map(|edit| format!("{:?} with '{}'", edit.range, edit.replacement))
+                    .collect();
+                eprintln!("  fix: {} (replaces {})", fix.message, edits.join(", "));
+            }
+        }
     }
 }
@@ -1,13 +1,319 @@
-use super::{super::Range, Error, Severity, Source};
+use super::{super::LongWord, super::Range, Error, Severity, Source};
 
 impl Error {
     pub fn unspecified_size(range: Range) -> Error {
-        Error {
-            code: "unspecified_size",
-            severity: Severity::Error,
-            source: Source::Compiler,
+        Error::new(
+            "unspecified_size",
+            Severity::Error,
+            Source::Compiler,
             range,
-            message: "A size attribute isn't present and could not be inferred.".to_string(),
+            "A size attribute isn't present and could not be inferred.".to_string(),
+        )
+        .with_help("Add a size like .B, .W or .L to the operation.")
+    }
+
+    pub fn conflicting_section_type(range: Range, name: &str) -> Error {
+        Error::new(
+            "conflicting_section_type",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!(
+                "The section '{}' was already started with a different type.",
+                name
+            ),
+        )
+    }
+
+    pub fn duplicate_org(range: Range, name: &str, first: Range) -> Error {
+        Error::new(
+            "duplicate_org",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!("The section '{}' is already placed by an ORG.", name),
+        )
+        .with_related(first, "placed here")
+        .with_help("Start a new section for code at another address.")
+    }
+
+    pub fn org_in_relocatable_output(range: Range) -> Error {
+        Error::new(
+            "org_in_relocatable_output",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            "Objects and executables can't place sections at fixed addresses.".to_string(),
+        )
+        .with_note("The linker script places the sections of objects, and GEMDOS loads executables anywhere.")
+    }
+
+    pub fn section_exceeds_address_space(range: Range, name: &str) -> Error {
+        Error::new(
+            "section_exceeds_address_space",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!(
+                "The section '{}' runs past the end of the address space.",
+                name
+            ),
+        )
+        .with_help("Place the program at a lower address.")
+    }
+
+    pub fn section_outside_memory(range: Range, memory_size: LongWord) -> Error {
+        Error::new(
+            "section_outside_memory",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!(
+                "The section doesn't fit into the simulated memory, which ends at ${:X}.",
+                memory_size
+            ),
+        )
+    }
+
+    pub fn undefined_label(range: Range, name: &str) -> Error {
+        Error::new(
+            "undefined_label",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!("The label '{}' isn't defined.", name),
+        )
+        .with_help("If it's defined in another program, import it using XREF.")
+    }
+
+    pub fn duplicate_label(range: Range, name: &str, first: Range) -> Error {
+        Error::new(
+            "duplicate_label",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!("The label '{}' is already defined.", name),
+        )
+        .with_related(first, "first defined here")
+    }
+
+    pub fn local_label_without_scope(range: Range, name: &str) -> Error {
+        Error::new(
+            "local_label_without_scope",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!("The local label '{}' needs a global label before it.", name),
+        )
+        .with_note("Local labels belong to the global label before them, like main.loop.")
+    }
+
+    pub fn undefined_export(range: Range, name: &str) -> Error {
+        Error::new(
+            "undefined_export",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!("The exported label '{}' isn't defined.", name),
+        )
+    }
+
+    pub fn imported_label_defined(range: Range, name: &str, definition: Range) -> Error {
+        Error::new(
+            "imported_label_defined",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!(
+                "The label '{}' is imported, but also defined in this program.",
+                name
+            ),
+        )
+        .with_related(definition, "defined here")
+        .with_help("Remove the definition or export it using XDEF instead.")
+    }
+
+    pub fn unresolved_import(range: Range, name: &str) -> Error {
+        Error::new(
+            "unresolved_import",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!(
+                "The label '{}' is imported, but its address isn't known.",
+                name
+            ),
+        )
+        .with_note("Imported labels are only resolved when the program is linked.")
+    }
+
+    /// The fix replaces the `.S` size in its range with `.W`.
+    pub fn short_branch_out_of_range(range: Range, size: Range) -> Error {
+        Error::new(
+            "short_branch_out_of_range",
+            Severity::Warning,
+            Source::Compiler,
+            range,
+            "The target is out of range for a short branch, so a word branch is used.".to_string(),
+        )
+        .with_replacement("Use a word branch", size, ".W")
+    }
+
+    pub fn branch_out_of_range(range: Range) -> Error {
+        Error::new(
+            "branch_out_of_range",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            "The target is more than 32 KiB away from the branch.".to_string(),
+        )
+        .with_help("Use JMP or JSR, which can reach every address.")
+    }
+
+    pub fn relocatable_branch_to_address(range: Range) -> Error {
+        Error::new(
+            "relocatable_branch_to_address",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            "Branches in objects and executables can't reach absolute addresses.".to_string(),
+        )
+        .with_note("The distance to the address changes when the program is moved.")
+        .with_help("Use JMP or JSR, which take absolute addresses.")
+    }
+
+    /// The fix replaces the `.L` size in its range with `.W`.
+    pub fn long_branch(range: Range, size: Range) -> Error {
+        Error::new(
+            "long_branch",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            "The 68000 only supports short (.S) and word (.W) branches.".to_string(),
+        )
+        .with_note("Long branches were added with the 68020.")
+        .with_replacement("Use a word branch", size, ".W")
+    }
+
+    pub fn optimized(range: Range, description: &str) -> Error {
+        Error::new(
+            "optimized",
+            Severity::Info,
+            Source::Compiler,
+            range,
+            description.to_string(),
+        )
+    }
+
+    pub fn invalid_size(range: Range, operation: &str, sizes: &str) -> Error {
+        Error::new(
+            "invalid_size",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            if sizes.is_empty() {
+                format!("{} doesn't have a size.", operation)
+            } else {
+                format!("{} only supports the sizes {}.", operation, sizes)
+            },
+        )
+    }
+
+    /// If the operation has a variant for address registers, the fix replaces the operation and
+    /// its size, which are in the range, with the word variant, like ADD.B with ADDA.W.
+    pub fn byte_access_to_address_register(
+        range: Range,
+        address_variant: Option<(Range, &str)>,
+    ) -> Error {
+        let error = Error::new(
+            "byte_access_to_address_register",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            "Address registers can't be accessed as bytes.".to_string(),
+        );
+        match address_variant {
+            Some((range, replacement)) => {
+                error.with_replacement(&format!("Use {}", replacement), range, replacement)
+            }
+            None => error,
         }
     }
+
+    pub fn wrong_operand_count(range: Range, operation: &str, expected: usize) -> Error {
+        Error::new(
+            "wrong_operand_count",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!(
+                "{} expects {} operand{}.",
+                operation,
+                expected,
+                if expected == 1 { "" } else { "s" }
+            ),
+        )
+    }
+
+    pub fn invalid_source_mode(range: Range, operation: &str, mode: &str) -> Error {
+        Error::new(
+            "invalid_source_mode",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!("{} can't use {} as its source.", operation, mode),
+        )
+    }
+
+    pub fn invalid_destination_mode(range: Range, operation: &str, mode: &str) -> Error {
+        Error::new(
+            "invalid_destination_mode",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!("{} can't use {} as its destination.", operation, mode),
+        )
+    }
+
+    pub fn quick_immediate_out_of_range(
+        range: Range,
+        operation: &str,
+        min: i64,
+        max: i64,
+    ) -> Error {
+        Error::new(
+            "quick_immediate_out_of_range",
+            Severity::Error,
+            Source::Compiler,
+            range,
+            format!(
+                "The immediate of {} has to be between {} and {}.",
+                operation, min, max
+            ),
+        )
+    }
+
+    /// The range is the one of the `.B` size.
+    pub fn invalid_index_size(range: Range) -> Error {
+        Error::new(
+            "invalid_index_size",
+            Severity::Error,
+            Source::Compiler,
+            range.clone(),
+            "Index registers can only be used as words (.W) or long words (.L).".to_string(),
+        )
+        .with_replacement("Use a word index", range, ".W")
+    }
+
+    pub fn unused_suppression(range: Range, code: &str) -> Error {
+        Error::new(
+            "unused_suppression",
+            Severity::Warning,
+            Source::Compiler,
+            range,
+            format!("The suppression of '{}' isn't used.", code),
+        )
+        .with_note("Only warnings and infos can be suppressed.")
+        .with_help("Remove it from the m68k-allow comment.")
+    }
 }
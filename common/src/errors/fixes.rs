@@ -0,0 +1,74 @@
+use super::{Edit, Error};
+
+/// Applies the first fix of every error to the source and returns the fixed source with the number
+/// of applied fixes. Fixes that overlap an earlier one are left out, so running it again may fix
+/// more.
+pub fn apply_fixes(source: &str, errors: &[Error]) -> (String, usize) {
+    let mut applied: Vec<&Edit> = vec![];
+    let mut count = 0;
+    for fix in errors
+        .iter()
+        .filter_map(|error| error.details.fixes.first())
+    {
+        let overlaps = fix.edits.iter().any(|edit| {
+            applied
+                .iter()
+                .any(|other| overlap(&edit.range, &other.range))
+        });
+        let is_valid = fix.edits.iter().all(|edit| {
+            edit.range.end <= source.len()
+                && source.is_char_boundary(edit.range.start)
+                && source.is_char_boundary(edit.range.end)
+        });
+        if !overlaps && is_valid {
+            applied.extend(&fix.edits);
+            count += 1;
+        }
+    }
+
+    applied.sort_by_key(|edit| edit.range.start);
+    let mut fixed = String::with_capacity(source.len());
+    let mut position = 0;
+    for edit in applied {
+        fixed.push_str(&source[position..edit.range.start]);
+        fixed.push_str(&edit.replacement);
+        position = edit.range.end;
+    }
+    fixed.push_str(&source[position..]);
+    (fixed, count)
+}
+
+/// Empty ranges at the same position overlap too, because their order would be unclear.
+fn overlap(a: &crate::Range, b: &crate::Range) -> bool {
+    a.start < b.end && b.start < a.end || a.start == b.start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_fixes() {
+        let source = " ADD.B D0,A0\n BRA.L loop";
+        let errors = vec![
+            Error::long_branch(14..24, 17..19),
+            Error::byte_access_to_address_register(10..12, Some((1..6, "ADDA.W"))),
+            Error::unspecified_size(1..4),
+        ];
+        let (fixed, count) = apply_fixes(source, &errors);
+        assert_eq!(fixed, " ADDA.W D0,A0\n BRA.W loop");
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_overlapping_fixes_are_skipped() {
+        let errors = vec![
+            Error::invalid_index_size(2..4),
+            Error::invalid_index_size(3..5),
+            Error::invalid_index_size(10..12),
+        ];
+        let (fixed, count) = apply_fixes("ab.Bc", &errors);
+        assert_eq!(fixed, "ab.Wc");
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,237 @@
+//! Lets users change the severity of diagnostics by their code, both from the command line and
+//! with `; m68k-allow(code)` comments in the source.
+//!
+//! Only warnings and infos can be silenced or lowered. Errors mean that the program can't be
+//! assembled, so they are always reported.
+
+use super::{Error, ErrorCollector, Severity};
+use crate::Range;
+use std::collections::HashMap;
+
+const ALLOW: &str = "m68k-allow(";
+
+#[derive(Default)]
+pub struct Levels {
+    /// The severity of diagnostics by their code, or `None` to silence them.
+    levels: HashMap<String, Option<Severity>>,
+    warnings_are_errors: bool,
+}
+
+impl Levels {
+    pub fn new() -> Levels {
+        Levels::default()
+    }
+
+    pub fn set(&mut self, code: &str, level: Option<Severity>) {
+        self.levels.insert(code.to_string(), level);
+    }
+
+    /// Reports all warnings as errors.
+    pub fn deny_warnings(&mut self) {
+        self.warnings_are_errors = true;
+    }
+
+    /// Applies a command line argument like `-Werror`, `--allow=<code>,...`, `--info=<code>,...`,
+    /// `--warn=<code>,...` or `--deny=<code>,...`. Returns whether it was one of them.
+    pub fn parse_arg(&mut self, arg: &str) -> bool {
+        if arg == "-Werror" {
+            self.deny_warnings();
+            return true;
+        }
+        let (codes, level) = if let Some(codes) = arg.strip_prefix("--allow=") {
+            (codes, None)
+        } else if let Some(codes) = arg.strip_prefix("--info=") {
+            (codes, Some(Severity::Info))
+        } else if let Some(codes) = arg.strip_prefix("--warn=") {
+            (codes, Some(Severity::Warning))
+        } else if let Some(codes) = arg.strip_prefix("--deny=") {
+            (codes, Some(Severity::Error))
+        } else {
+            return false;
+        };
+        for code in codes.split(',') {
+            self.set(code, level);
+        }
+        true
+    }
+
+    /// The severity the error is reported with, or `None` if it's silenced.
+    fn level_of(&self, error: &Error) -> Option<Severity> {
+        let level = match self.levels.get(error.code) {
+            Some(&level) if error.severity < Severity::Error => level,
+            _ => Some(error.severity),
+        };
+        match level {
+            Some(Severity::Warning) if self.warnings_are_errors => Some(Severity::Error),
+            level => level,
+        }
+    }
+
+    /// Removes the suppressed and silenced errors, changes the severity of the others and reports
+    /// suppressions in the source that didn't suppress anything.
+    pub fn apply(&self, source: &str, errors: &mut ErrorCollector) {
+        let mut suppressions = suppressions(source);
+        errors.retain(|error| {
+            if error.severity == Severity::Error {
+                return true;
+            }
+            let suppression = suppressions.iter_mut().find(|suppression| {
+                suppression.code == error.code && suppression.lines.contains(&error.range.start)
+            });
+            match suppression {
+                Some(suppression) => {
+                    suppression.is_used = true;
+                    false
+                }
+                None => true,
+            }
+        });
+        for suppression in suppressions {
+            if !suppression.is_used {
+                errors.push(Error::unused_suppression(
+                    suppression.range,
+                    &suppression.code,
+                ));
+            }
+        }
+
+        let mut remapped = vec![];
+        for mut error in errors.drain(..) {
+            if let Some(severity) = self.level_of(&error) {
+                error.severity = severity;
+                remapped.push(error);
+            }
+        }
+        *errors = remapped;
+    }
+}
+
+struct Suppression {
+    code: String,
+    /// Where the code is written in the comment.
+    range: Range,
+    /// The lines whose diagnostics it suppresses.
+    lines: Range,
+    is_used: bool,
+}
+
+/// Finds the `m68k-allow(code, ...)` comments. A comment after a statement suppresses the codes on
+/// its line, a comment on its own line suppresses them on the next line.
+fn suppressions(source: &str) -> Vec<Suppression> {
+    let mut suppressions = vec![];
+    let mut line_start = 0;
+    let lines: Vec<&str> = source.split('\n').collect();
+    for (index, line) in lines.iter().enumerate() {
+        let line_end = line_start + line.len();
+        let comment = line.find([';', '*']);
+        let allow = comment.and_then(|comment| {
+            line[comment..]
+                .find(ALLOW)
+                .map(|allow| comment + allow + ALLOW.len())
+        });
+        if let (Some(comment), Some(codes_start)) = (comment, allow) {
+            let lines = if line[..comment].trim().is_empty() {
+                let next_end = lines
+                    .get(index + 1)
+                    .map_or(line_end, |next| line_end + 1 + next.len());
+                line_end..next_end + 1
+            } else {
+                line_start..line_end + 1
+            };
+            let codes_end = line[codes_start..]
+                .find(')')
+                .map_or(line.len(), |end| codes_start + end);
+            let mut offset = codes_start;
+            for code in line[codes_start..codes_end].split(',') {
+                let trimmed = code.trim();
+                if !trimmed.is_empty() {
+                    let start = line_start + offset + code.find(trimmed).unwrap();
+                    suppressions.push(Suppression {
+                        code: trimmed.to_string(),
+                        range: start..start + trimmed.len(),
+                        lines: lines.clone(),
+                        is_used: false,
+                    });
+                }
+                offset += code.len() + 1;
+            }
+        }
+        line_start = line_end + 1;
+    }
+    suppressions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(errors: &ErrorCollector) -> Vec<(&str, Severity)> {
+        errors
+            .iter()
+            .map(|error| (error.code, error.severity))
+            .collect()
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut levels = Levels::new();
+        assert!(levels.parse_arg("--allow=optimized"));
+        assert!(levels.parse_arg("--deny=short_branch_out_of_range"));
+        assert!(levels.parse_arg("--warn=long_branch"));
+        assert!(!levels.parse_arg("--optimize"));
+        let mut errors = vec![
+            Error::optimized(0..1, "Removed it."),
+            Error::short_branch_out_of_range(0..1, 0..1),
+            Error::long_branch(0..1, 0..1),
+        ];
+        levels.apply("", &mut errors);
+        assert_eq!(
+            codes(&errors),
+            vec![
+                ("short_branch_out_of_range", Severity::Error),
+                ("long_branch", Severity::Error),
+            ]
+        );
+
+        let mut levels = Levels::new();
+        levels.parse_arg("-Werror");
+        levels.parse_arg("--info=optimized");
+        let mut errors = vec![
+            Error::short_branch_out_of_range(0..1, 0..1),
+            Error::optimized(0..1, "Removed it."),
+        ];
+        levels.apply("", &mut errors);
+        assert_eq!(
+            codes(&errors),
+            vec![
+                ("short_branch_out_of_range", Severity::Error),
+                ("optimized", Severity::Info),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suppressions() {
+        let source = " BRA.S far ; m68k-allow(short_branch_out_of_range)
+; m68k-allow(optimized, long_branch)
+ MOVE.L #0,D0
+ BRA.L far ; m68k-allow(long_branch)";
+        let mut errors = vec![
+            Error::short_branch_out_of_range(1..10, 4..6),
+            Error::optimized(89..101, "Replaced it with MOVEQ."),
+            Error::long_branch(103..112, 106..108),
+        ];
+        Levels::new().apply(source, &mut errors);
+        assert_eq!(
+            codes(&errors),
+            vec![
+                ("long_branch", Severity::Error),
+                ("unused_suppression", Severity::Warning),
+                ("unused_suppression", Severity::Warning),
+            ]
+        );
+        assert_eq!(&source[errors[1].range.clone()], "long_branch");
+        assert_eq!(errors[1].range, 75..86);
+        assert_eq!(&source[errors[2].range.clone()], "long_branch");
+    }
+}
@@ -1,11 +1,18 @@
 use crate::Range;
-pub use collector::ErrorCollector;
+pub use collector::{ErrorCollector, PrintErrors};
+pub use fixes::apply_fixes;
+pub use levels::Levels;
 pub use severity::Severity;
 
 mod collector;
 pub mod compiler;
+mod fixes;
+mod levels;
+pub mod parser;
+pub mod registry;
 pub mod scanner;
 mod severity;
+pub mod testing;
 
 pub struct Error {
     pub code: &'static str,
@@ -13,6 +20,21 @@ pub struct Error {
     pub source: Source,
     pub range: Range,
     pub message: String,
+    /// Boxed, so that results with errors stay small.
+    pub details: Box<Details>,
+}
+
+/// Everything about an error besides its message.
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Details {
+    /// Other places in the source that explain the error, like where a label was first defined.
+    pub related: Vec<Related>,
+    /// Background on why something is an error.
+    pub notes: Vec<String>,
+    /// What the user can do about it.
+    pub help: Option<String>,
+    /// Changes to the source that resolve the error and can be applied without asking.
+    pub fixes: Vec<Fix>,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -20,4 +42,79 @@ pub enum Source {
     Scanner,
     Parser,
     Compiler,
+    /// Tests of assembly routines, which are run in the simulator.
+    Test,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Related {
+    pub range: Range,
+    pub message: String,
+}
+
+/// A suggestion like "Use ADDA.W", made of edits that are applied together.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<Edit>,
+}
+
+/// Replaces the source in the range.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Edit {
+    pub range: Range,
+    pub replacement: String,
+}
+
+impl Error {
+    fn new(
+        code: &'static str,
+        severity: Severity,
+        source: Source,
+        range: Range,
+        message: String,
+    ) -> Error {
+        Error {
+            code,
+            severity,
+            source,
+            range,
+            message,
+            details: Box::default(),
+        }
+    }
+
+    pub fn with_related(mut self, range: Range, message: &str) -> Error {
+        self.details.related.push(Related {
+            range,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Error {
+        self.details.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Error {
+        self.details.help = Some(help.to_string());
+        self
+    }
+
+    /// Adds a fix that replaces the range.
+    pub fn with_replacement(self, message: &str, range: Range, replacement: &str) -> Error {
+        self.with_fix(Fix {
+            message: message.to_string(),
+            edits: vec![Edit {
+                range,
+                replacement: replacement.to_string(),
+            }],
+        })
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> Error {
+        self.details.fixes.push(fix);
+        self
+    }
 }
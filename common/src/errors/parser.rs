@@ -0,0 +1,73 @@
+use super::{super::Range, Error, Severity, Source};
+
+impl Error {
+    pub fn unexpected_token(range: Range, expected: &str) -> Error {
+        Error::new(
+            "unexpected_token",
+            Severity::Error,
+            Source::Parser,
+            range,
+            format!("Expected {}.", expected),
+        )
+    }
+
+    pub fn unknown_operation(range: Range, name: &str) -> Error {
+        Error::new(
+            "unknown_operation",
+            Severity::Error,
+            Source::Parser,
+            range,
+            format!("There's no operation or directive called '{}'.", name),
+        )
+    }
+
+    pub fn unknown_size(range: Range, name: &str) -> Error {
+        Error::new(
+            "unknown_size",
+            Severity::Error,
+            Source::Parser,
+            range,
+            format!("'{}' is not a size, use B, W or L.", name),
+        )
+    }
+
+    pub fn unknown_section_type(range: Range, name: &str) -> Error {
+        Error::new(
+            "unknown_section_type",
+            Severity::Error,
+            Source::Parser,
+            range,
+            format!("'{}' is not a section type, use CODE, DATA or BSS.", name),
+        )
+    }
+
+    pub fn number_out_of_range(range: Range, min: i64, max: i64) -> Error {
+        Error::new(
+            "number_out_of_range",
+            Severity::Error,
+            Source::Parser,
+            range,
+            format!("The number has to be between {} and {}.", min, max),
+        )
+    }
+
+    /// The label is quoted as written, and aliases like `sp` name the register they stand for.
+    pub fn label_shadows_register(range: Range, label: &str, register: &str) -> Error {
+        let message = if label.eq_ignore_ascii_case(register) {
+            format!("'{}' is a register, so it can't be used as a label.", label)
+        } else {
+            format!(
+                "'{}' is an alias of the register {}, so it can't be used as a label.",
+                label, register
+            )
+        };
+        Error::new(
+            "label_shadows_register",
+            Severity::Error,
+            Source::Parser,
+            range,
+            message,
+        )
+        .with_help("Rename the label.")
+    }
+}
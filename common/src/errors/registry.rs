@@ -0,0 +1,474 @@
+//! All codes of errors with their default severity, where they come from and a longer explanation,
+//! which `assembler --explain <code>` prints.
+
+use super::{Severity, Source};
+
+pub struct Code {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub source: Source,
+    pub explanation: &'static str,
+}
+
+/// Finds the registered code.
+pub fn lookup(code: &str) -> Option<&'static Code> {
+    CODES.iter().find(|registered| registered.code == code)
+}
+
+pub const CODES: &[Code] = &[
+    Code {
+        code: "no_match",
+        severity: Severity::Error,
+        source: Source::Scanner,
+        explanation: "A character can't start any token.
+
+Sources may only contain labels, operations, registers, numbers and the punctuation of
+addressing modes. Word processors often replace characters with lookalikes, like a minus with a
+dash, which the fix replaces again.
+
+    MOVE.W #–1,D0   ; the dash isn't a minus
+    MOVE.W #-1,D0",
+    },
+    Code {
+        code: "cannot_parse_decimal_number",
+        severity: Severity::Error,
+        source: Source::Scanner,
+        explanation: "A decimal number is too large.
+
+Numbers have to fit into 32 bits.
+
+    MOVE.L #4294967296,D0
+    MOVE.L #4294967295,D0",
+    },
+    Code {
+        code: "cannot_parse_hex_number",
+        severity: Severity::Error,
+        source: Source::Scanner,
+        explanation: "A hexadecimal number is empty or too large.
+
+Hexadecimal numbers start with a $ followed by one to eight digits.
+
+    MOVE.L #$,D0
+    MOVE.L #$123456789,D0
+    MOVE.L #$12345678,D0",
+    },
+    Code {
+        code: "unknown_register",
+        severity: Severity::Error,
+        source: Source::Scanner,
+        explanation: "A name with the % prefix of the MIT dialect isn't a register.
+
+    movel %d8,%d0
+    movel %d7,%d0",
+    },
+    Code {
+        code: "unexpected_token",
+        severity: Severity::Error,
+        source: Source::Parser,
+        explanation: "The parser expected something else at this point of the line.
+
+The message says what was expected. The rest of the line is skipped.
+
+    ADD.W 4(D0),D1    ; displacements need an address register or PC
+    ADD.W 4(A0),D1",
+    },
+    Code {
+        code: "unknown_operation",
+        severity: Severity::Error,
+        source: Source::Parser,
+        explanation: "There's no operation or directive with this name.
+
+Operations that are indented by whitespace can't be told apart from labels by their position,
+so a typo in an operation ends up here.
+
+    MOOVE.W D0,D1
+    MOVE.W D0,D1",
+    },
+    Code {
+        code: "unknown_size",
+        severity: Severity::Error,
+        source: Source::Parser,
+        explanation: "The size after the dot isn't B, W or L.
+
+Branches also accept S for short branches.
+
+    ADD.Q D0,D1
+    ADD.L D0,D1",
+    },
+    Code {
+        code: "unknown_section_type",
+        severity: Severity::Error,
+        source: Source::Parser,
+        explanation: "A section has a type other than CODE, DATA or BSS.
+
+    SECTION tables,ROM
+    SECTION tables,DATA",
+    },
+    Code {
+        code: "number_out_of_range",
+        severity: Severity::Error,
+        source: Source::Parser,
+        explanation: "A number doesn't fit into the place it's used.
+
+Displacements of d16(An) have 16 bits and the ones of d8(An,Xn) only 8 bits.
+
+    MOVE.W 200(A0,D0),D1
+    MOVE.W 100(A0,D0),D1",
+    },
+    Code {
+        code: "label_shadows_register",
+        severity: Severity::Error,
+        source: Source::Parser,
+        explanation: "A label has the name of a register.
+
+Registers are matched regardless of case, including SP, PC, SR, CCR and USP, so an operand with
+the name would be read as the register instead of the label.
+
+a0    MOVEQ #0,D0
+loop0 MOVEQ #0,D0",
+    },
+    Code {
+        code: "unspecified_size",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An operation doesn't have a size and it can't be inferred from its operands.
+
+Operations without a size are words, unless an immediate doesn't fit into a word. Then, the
+size has to be written after the operation.
+
+    MOVE #$10000,D0
+    MOVE.L #$10000,D0",
+    },
+    Code {
+        code: "conflicting_section_type",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A section is continued with a different type than it was started with.
+
+    SECTION tables,DATA
+    ...
+    SECTION tables,BSS",
+    },
+    Code {
+        code: "duplicate_org",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A section has more than one ORG.
+
+An ORG places the whole section it's in at an address. Code at another address needs a section
+of its own.
+
+    ORG $FC0000
+    ...
+    ORG $FC8000       ; the section is already placed
+    SECTION vectors,DATA
+    ORG $FC8000",
+    },
+    Code {
+        code: "org_in_relocatable_output",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An ORG is used in a program that's assembled into an object or executable.
+
+The sections of objects are placed by the linker script, and GEMDOS loads executables at any
+address. ORG only places sections in flat images (--binary) and in the simulator.",
+    },
+    Code {
+        code: "section_exceeds_address_space",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A section ends after the last address, $FFFFFFFF.
+
+Sections are placed one after another, starting at the origin. A program whose origin is close
+to the end of the address space doesn't fit.
+
+    assembler --binary=rom.bin --org=$FFFFFFFE rom.s
+    assembler --binary=rom.bin --org=$FC0000 rom.s",
+    },
+    Code {
+        code: "section_outside_memory",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An ORG places a section outside of the simulator's memory.
+
+The simulator has 1 MiB of RAM starting at address 0, and programs are loaded at $1000.
+
+    ORG $FC0000
+    ORG $8000",
+    },
+    Code {
+        code: "undefined_label",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An operand uses a label that isn't defined.
+
+Labels of other programs have to be imported using XREF and are resolved by the linker.
+
+    JSR print
+    XREF print
+    JSR print",
+    },
+    Code {
+        code: "duplicate_label",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A label is defined twice.
+
+Local labels like .loop only have to be unique within their global label.
+
+main
+main      ; already defined
+.loop     ; main.loop
+other
+.loop     ; fine, this is other.loop",
+    },
+    Code {
+        code: "local_label_without_scope",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A local label is used before any global label.
+
+Local labels like .loop or 1$ belong to the global label before them.
+
+.loop BRA .loop
+main
+.loop BRA .loop",
+    },
+    Code {
+        code: "undefined_export",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A label is exported using XDEF, but it isn't defined.
+
+    XDEF main
+main RTS",
+    },
+    Code {
+        code: "imported_label_defined",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A label is imported using XREF, but it's also defined in the program.
+
+Either remove the definition or export the label using XDEF instead.
+
+    XREF print
+print RTS",
+    },
+    Code {
+        code: "unresolved_import",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An imported label is used in a program that runs without being linked.
+
+Imported labels are only known after linking the programs together, so programs in the
+simulator and flat images can't use them. Assemble an object file instead and link it with the
+objects that export the label.",
+    },
+    Code {
+        code: "short_branch_out_of_range",
+        severity: Severity::Warning,
+        source: Source::Compiler,
+        explanation: "A branch with an explicit short size doesn't reach its target.
+
+Short branches reach 128 bytes back and 127 bytes ahead. A word branch is used instead, which
+the fix writes into the source.
+
+    BRA.S far
+    BRA.W far",
+    },
+    Code {
+        code: "branch_out_of_range",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "The target of a branch is more than 32 KiB away.
+
+Jumps and subroutine calls using JMP and JSR reach every address.
+
+    BSR far
+    JSR far",
+    },
+    Code {
+        code: "relocatable_branch_to_address",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A branch to an absolute address is assembled into an object or executable.
+
+Branches store the distance to their target. The linker and GEMDOS move the program, but not
+the address, so the distance would be wrong. Jumps and subroutine calls using JMP and JSR store
+the address itself.
+
+    BSR $FC0030
+    JSR $FC0030",
+    },
+    Code {
+        code: "long_branch",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "A branch has the size L.
+
+The 68000 only has short and word branches, long branches were added with the 68020.
+
+    BRA.L far
+    BRA.W far",
+    },
+    Code {
+        code: "optimized",
+        severity: Severity::Info,
+        source: Source::Compiler,
+        explanation: "The optimizer replaced an operation with a faster or shorter one.
+
+The message says which rule was applied. Rules are enabled using --optimize.
+
+    MOVE.L #1,D0   ; becomes MOVEQ.L #1,D0",
+    },
+    Code {
+        code: "invalid_size",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An operation doesn't support the size.
+
+    LEA.W (A0),A1
+    LEA.L (A0),A1",
+    },
+    Code {
+        code: "byte_access_to_address_register",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An address register is used with the size B.
+
+Address registers can only be accessed as words or long words. Additions to them use ADDA,
+which the fix suggests.
+
+    ADD.B D0,A0
+    ADDA.W D0,A0",
+    },
+    Code {
+        code: "wrong_operand_count",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An operation has too many or too few operands.
+
+    CLR.W D0,D1
+    CLR.W D0",
+    },
+    Code {
+        code: "invalid_source_mode",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An operation doesn't support the addressing mode of its source operand.
+
+    LEA.L (A0)+,A1
+    LEA.L (A0),A1",
+    },
+    Code {
+        code: "invalid_destination_mode",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An operation doesn't support the addressing mode of its destination operand.
+
+    MOVE.W D0,#5
+    MOVE.W D0,D5",
+    },
+    Code {
+        code: "quick_immediate_out_of_range",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "The immediate of a quick operation doesn't fit into it.
+
+ADDQ adds 1 to 8, MOVEQ moves -128 to 127 and TRAP takes vectors 0 to 15.
+
+    ADDQ.W #9,D0
+    ADDI.W #9,D0",
+    },
+    Code {
+        code: "invalid_index_size",
+        severity: Severity::Error,
+        source: Source::Compiler,
+        explanation: "An index register has the size B.
+
+Index registers are used as sign-extended words or as long words.
+
+    MOVE.W (A0,D0.B),D1
+    MOVE.W (A0,D0.W),D1",
+    },
+    Code {
+        code: "unused_suppression",
+        severity: Severity::Warning,
+        source: Source::Compiler,
+        explanation: "An m68k-allow comment doesn't suppress anything.
+
+Either nothing with the code is reported on its line, or it's an error. Errors mean that the
+program can't be assembled, so they can't be suppressed.
+
+    MOVE.L #1,D0 ; m68k-allow(long_branch)",
+    },
+    Code {
+        code: "invalid_test",
+        severity: Severity::Error,
+        source: Source::Test,
+        explanation: "The comment describing a test can't be read.
+
+Tests look like `; test name: D0=1 => D0=2`, with the registers, memory and flags to set up
+before the arrow and the expected ones after it.",
+    },
+    Code {
+        code: "test_without_routine",
+        severity: Severity::Error,
+        source: Source::Test,
+        explanation: "A test isn't followed by the global label of the routine it tests.
+
+    ; test doubles: D0=2 => D0=4
+    double: ADD.L D0,D0
+        RTS",
+    },
+    Code {
+        code: "test_failed",
+        severity: Severity::Error,
+        source: Source::Test,
+        explanation: "A routine didn't produce the expected result.
+
+The message lists every register, memory location or flag that differs. Routines that don't
+return within the instruction limit, which `limit=<steps>` changes, also fail.",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The constructors are found in their source, so that new ones can't be forgotten.
+    #[test]
+    fn test_all_codes_are_registered() {
+        let sources = [
+            include_str!("scanner.rs"),
+            include_str!("parser.rs"),
+            include_str!("compiler.rs"),
+            include_str!("testing.rs"),
+        ];
+        let mut count = 0;
+        for source in sources.iter() {
+            for constructor in source.split("Error::new(").skip(1) {
+                let mut arguments = constructor.split(',').map(str::trim);
+                let code = arguments.next().unwrap().trim_matches('"');
+                let severity = arguments.next().unwrap();
+                let origin = arguments.next().unwrap();
+                let registered =
+                    lookup(code).unwrap_or_else(|| panic!("The code {} isn't registered.", code));
+                assert_eq!(
+                    severity,
+                    format!("Severity::{:?}", registered.severity),
+                    "{}",
+                    code
+                );
+                assert_eq!(
+                    origin,
+                    format!("Source::{:?}", registered.source),
+                    "{}",
+                    code
+                );
+                count += 1;
+            }
+        }
+        assert_eq!(count, CODES.len());
+    }
+}
@@ -2,32 +2,60 @@ use super::{super::Range, Error, Severity, Source};
 
 impl Error {
     pub fn no_match(range: Range, current: char, next: char) -> Error {
-        Error {
-            code: "no_match",
-            severity: Severity::Error,
-            source: Source::Scanner,
-            range,
-            message: format!("No token matches for '{}', '{}'.", current, next),
+        let error = Error::new(
+            "no_match",
+            Severity::Error,
+            Source::Scanner,
+            range.clone(),
+            format!("No token matches for '{}', '{}'.", current, next),
+        );
+        // Word processors like to replace these while copying code around.
+        let lookalike = match current {
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => Some("-"),
+            '\u{00a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}' => Some(" "),
+            '\u{ff03}' => Some("#"),
+            _ => None,
+        };
+        match lookalike {
+            Some(replacement) => error.with_replacement(
+                &format!("Replace it with '{}'", replacement),
+                range,
+                replacement,
+            ),
+            None => error,
         }
     }
 
     pub fn cannot_parse_decimal_number(range: Range) -> Error {
-        Error {
-            code: "cannot_parse_decimal_number",
-            severity: Severity::Error,
-            source: Source::Scanner,
+        Error::new(
+            "cannot_parse_decimal_number",
+            Severity::Error,
+            Source::Scanner,
             range,
-            message: "Cannot parse the decimal number.".to_string(),
-        }
+            "Cannot parse the decimal number.".to_string(),
+        )
+        .with_note("Numbers have to fit into 32 bits, so they can be at most 4294967295.")
     }
 
     pub fn cannot_parse_hex_number(range: Range) -> Error {
-        Error {
-            code: "cannot_parse_hex_number",
-            severity: Severity::Error,
-            source: Source::Scanner,
+        Error::new(
+            "cannot_parse_hex_number",
+            Severity::Error,
+            Source::Scanner,
             range,
-            message: "Cannot parse the hexadecimal number.".to_string(),
-        }
+            "Cannot parse the hexadecimal number.".to_string(),
+        )
+        .with_help("Hexadecimal numbers have one to eight digits after the $, like $ff8240.")
+    }
+
+    pub fn unknown_register(range: Range, name: &str) -> Error {
+        Error::new(
+            "unknown_register",
+            Severity::Error,
+            Source::Scanner,
+            range,
+            format!("'%{}' is not a register.", name),
+        )
+        .with_help("Registers are D0 to D7, A0 to A7, SP, PC, SR, CCR and USP.")
     }
 }
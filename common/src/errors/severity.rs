@@ -1,7 +1,7 @@
 use std::cmp::Ordering;
 use std::fmt::{self, Display};
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Severity {
     Info,
     Warning,
@@ -0,0 +1,36 @@
+use super::{super::Range, Error, Severity, Source};
+
+impl Error {
+    pub fn invalid_test(range: Range, reason: &str) -> Error {
+        Error::new(
+            "invalid_test",
+            Severity::Error,
+            Source::Test,
+            range,
+            format!("The test can't be read: {}", reason),
+        )
+    }
+
+    pub fn test_without_routine(range: Range, name: &str) -> Error {
+        Error::new(
+            "test_without_routine",
+            Severity::Error,
+            Source::Test,
+            range,
+            format!(
+                "The test '{}' has to be followed by the global label of the routine it tests.",
+                name
+            ),
+        )
+    }
+
+    pub fn test_failed(range: Range, name: &str, reasons: &[String]) -> Error {
+        Error::new(
+            "test_failed",
+            Severity::Error,
+            Source::Test,
+            range,
+            format!("The test '{}' failed. {}", name, reasons.join(" ")),
+        )
+    }
+}
@@ -0,0 +1,668 @@
+//! Links relocatable objects into a program at fixed addresses.
+//!
+//! The sections of all objects are merged (in the order the objects are given), placed into memory
+//! according to a linker script, and all relocations get resolved: A relocation refers to a local
+//! symbol of its own object if there is one, otherwise to a global symbol of any object (`XDEF`).
+//! Symbols that no object defines are reported as undefined, global symbols defined by several
+//! objects as multiply defined.
+
+pub mod script;
+
+use m68k_reloaded_common::{Byte, LongWord};
+use m68k_reloaded_object::image::Segment;
+use m68k_reloaded_object::prg::Executable;
+use m68k_reloaded_object::{Object, RelocationKind, Section, Symbol};
+use script::Script;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+
+/// Sections of each object start at even addresses, so word and long word accesses stay legal.
+const SECTION_ALIGNMENT: LongWord = 2;
+
+/// An object to link together with the name it's reported as in errors (usually its file name).
+pub struct Input {
+    pub name: String,
+    pub object: Object,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct LinkedSection {
+    pub section: Section,
+    pub address: LongWord,
+    pub size: LongWord,
+    /// The content of the section. Empty for the BSS section.
+    pub bytes: Vec<Byte>,
+}
+
+impl LinkedSection {
+    pub fn end(&self) -> u64 {
+        u64::from(self.address) + u64::from(self.size)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct LinkedSymbol {
+    pub name: String,
+    pub section: Section,
+    pub address: LongWord,
+    pub global: bool,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct Linked {
+    /// The text, data and bss section, in this order.
+    pub sections: Vec<LinkedSection>,
+    pub symbols: Vec<LinkedSymbol>,
+    /// Addresses of all patched absolute long words. Loaders that relocate the program need them.
+    pub absolute_references: Vec<LongWord>,
+}
+
+impl Linked {
+    pub fn section(&self, section: Section) -> &LinkedSection {
+        self.sections
+            .iter()
+            .find(|linked| linked.section == section)
+            .unwrap()
+    }
+
+    /// The initialized sections as segments of a flat image.
+    pub fn segments(&self) -> Vec<Segment> {
+        self.sections
+            .iter()
+            .filter(|section| section.section != Section::Bss)
+            .map(|section| Segment {
+                address: section.address,
+                bytes: section.bytes.clone(),
+            })
+            .collect()
+    }
+
+    /// Converts the program into a GEMDOS executable. That's only possible if it was linked with
+    /// the default script, so that the sections follow each other starting at address 0.
+    pub fn executable(&self) -> Option<Executable> {
+        let text = self.section(Section::Text);
+        let data = self.section(Section::Data);
+        let bss = self.section(Section::Bss);
+        if text.address != 0 || data.address != text.size || bss.address != text.size + data.size {
+            return None;
+        }
+
+        let symbols = self
+            .symbols
+            .iter()
+            .map(|symbol| Symbol {
+                name: symbol.name.clone(),
+                section: symbol.section,
+                value: symbol.address - self.section(symbol.section).address,
+                global: symbol.global,
+            })
+            .collect();
+        Some(Executable {
+            text: text.bytes.clone(),
+            data: data.bytes.clone(),
+            bss_size: bss.size,
+            symbols,
+            fixups: self.absolute_references.clone(),
+            flags: 0,
+        })
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum LinkError {
+    UndefinedSymbol {
+        symbol: String,
+        object: String,
+    },
+    MultiplyDefinedSymbol {
+        symbol: String,
+        first: String,
+        second: String,
+    },
+    RelocationOutOfRange {
+        symbol: String,
+        object: String,
+        value: i64,
+    },
+    InvalidRelocation {
+        object: String,
+        offset: LongWord,
+    },
+    UnplacedSection(Section),
+    RegionOverflow {
+        region: String,
+        size: u64,
+        length: LongWord,
+    },
+    SymbolOutOfRange {
+        symbol: String,
+        object: String,
+        address: u64,
+    },
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::UndefinedSymbol { symbol, object } => {
+                write!(f, "{}: The symbol {} is not defined.", object, symbol)
+            }
+            LinkError::MultiplyDefinedSymbol {
+                symbol,
+                first,
+                second,
+            } => write!(
+                f,
+                "{}: The symbol {} is already defined in {}.",
+                second, symbol, first
+            ),
+            LinkError::RelocationOutOfRange {
+                symbol,
+                object,
+                value,
+            } => write!(
+                f,
+                "{}: The reference to {} resolves to {}, which doesn't fit.",
+                object, symbol, value
+            ),
+            LinkError::InvalidRelocation { object, offset } => write!(
+                f,
+                "{}: The relocation at offset ${:X} lies outside of its section.",
+                object, offset
+            ),
+            LinkError::UnplacedSection(section) => write!(
+                f,
+                "The linker script doesn't place the {:?} section.",
+                section
+            ),
+            LinkError::RegionOverflow {
+                region,
+                size,
+                length,
+            } => write!(
+                f,
+                "The region {} needs ${:X} bytes, but only has ${:X}.",
+                region, size, length
+            ),
+            LinkError::SymbolOutOfRange {
+                symbol,
+                object,
+                address,
+            } => write!(
+                f,
+                "{}: The symbol {} lies at ${:X}, beyond the end of the address space.",
+                object, symbol, address
+            ),
+        }
+    }
+}
+
+/// Links the objects according to the script.
+pub fn link(inputs: &[Input], script: &Script) -> Result<Linked, Vec<LinkError>> {
+    let mut errors = vec![];
+
+    // Merge the sections and remember where each object's part ended up.
+    let mut sections: Vec<LinkedSection> = [Section::Text, Section::Data, Section::Bss]
+        .iter()
+        .map(|section| LinkedSection {
+            section: *section,
+            address: 0,
+            size: 0,
+            bytes: vec![],
+        })
+        .collect();
+    let mut offsets: Vec<HashMap<Section, LongWord>> = vec![];
+    for input in inputs {
+        let mut object_offsets = HashMap::new();
+        for linked in &mut sections {
+            let offset = align(linked.size);
+            let size = match linked.section {
+                Section::Text => input.object.text.len() as LongWord,
+                Section::Data => input.object.data.len() as LongWord,
+                Section::Bss => input.object.bss_size,
+            };
+            match linked.section {
+                Section::Text => append(&mut linked.bytes, offset, &input.object.text),
+                Section::Data => append(&mut linked.bytes, offset, &input.object.data),
+                Section::Bss => {}
+            }
+            linked.size = offset + size;
+            object_offsets.insert(linked.section, offset);
+        }
+        offsets.push(object_offsets);
+    }
+    for linked in &mut sections {
+        linked.size = align(linked.size);
+        if linked.section != Section::Bss {
+            linked.bytes.resize(linked.size as usize, 0);
+        }
+    }
+
+    // Place the sections into the memory regions. The addresses are kept wide, so that sections
+    // overflowing the top of the address space are reported instead of wrapping around.
+    let mut section_addresses: HashMap<Section, u64> = sections
+        .iter()
+        .map(|linked| (linked.section, u64::from(linked.address)))
+        .collect();
+    let mut region_ends: HashMap<&str, u64> = script
+        .regions
+        .iter()
+        .map(|region| (region.name.as_str(), u64::from(region.origin)))
+        .collect();
+    for linked in &mut sections {
+        let placement = script
+            .placements
+            .iter()
+            .find(|placement| placement.section == linked.section);
+        let placement = match placement {
+            Some(placement) => placement,
+            None if linked.size == 0 => continue,
+            None => {
+                errors.push(LinkError::UnplacedSection(linked.section));
+                continue;
+            }
+        };
+        let region = script.region(&placement.region).unwrap();
+        let end = region_ends.get_mut(region.name.as_str()).unwrap();
+        let alignment = u64::from(SECTION_ALIGNMENT);
+        let address = end.div_ceil(alignment) * alignment;
+        section_addresses.insert(linked.section, address);
+        // Only an empty section can start right after the end of the address space.
+        linked.address = LongWord::try_from(address).unwrap_or(LongWord::MAX);
+        *end = address + u64::from(linked.size);
+        if *end > region.end() {
+            errors.push(LinkError::RegionOverflow {
+                region: region.name.clone(),
+                size: *end - u64::from(region.origin),
+                length: region.length,
+            });
+        }
+    }
+
+    // Collect the symbols with their final addresses.
+    let address_of = |object: usize, symbol: &Symbol| {
+        section_addresses[&symbol.section]
+            + u64::from(offsets[object][&symbol.section])
+            + u64::from(symbol.value)
+    };
+    let mut symbols: Vec<LinkedSymbol> = vec![];
+    let mut globals: HashMap<&str, (usize, u64)> = HashMap::new();
+    for (index, input) in inputs.iter().enumerate() {
+        for symbol in &input.object.symbols {
+            let address = address_of(index, symbol);
+            if symbol.global {
+                if let Some((first, _)) = globals.get(symbol.name.as_str()) {
+                    errors.push(LinkError::MultiplyDefinedSymbol {
+                        symbol: symbol.name.clone(),
+                        first: inputs[*first].name.clone(),
+                        second: input.name.clone(),
+                    });
+                    continue;
+                }
+                globals.insert(&symbol.name, (index, address));
+            }
+            let address = match LongWord::try_from(address) {
+                Ok(address) => address,
+                Err(_) => {
+                    errors.push(LinkError::SymbolOutOfRange {
+                        symbol: symbol.name.clone(),
+                        object: input.name.clone(),
+                        address,
+                    });
+                    continue;
+                }
+            };
+            symbols.push(LinkedSymbol {
+                name: symbol.name.clone(),
+                section: symbol.section,
+                address,
+                global: symbol.global,
+            });
+        }
+    }
+
+    // Resolve and apply the relocations.
+    let mut absolute_references = vec![];
+    for (index, input) in inputs.iter().enumerate() {
+        for relocation in &input.object.relocations {
+            let local = input
+                .object
+                .symbols
+                .iter()
+                .find(|symbol| !symbol.global && symbol.name == relocation.symbol);
+            let target = match local {
+                Some(symbol) => address_of(index, symbol),
+                None => match globals.get(relocation.symbol.as_str()) {
+                    Some((_, address)) => *address,
+                    None => {
+                        errors.push(LinkError::UndefinedSymbol {
+                            symbol: relocation.symbol.clone(),
+                            object: input.name.clone(),
+                        });
+                        continue;
+                    }
+                },
+            };
+
+            let section = sections
+                .iter_mut()
+                .find(|linked| linked.section == relocation.section)
+                .unwrap();
+            let position =
+                u64::from(offsets[index][&relocation.section]) + u64::from(relocation.offset);
+            let address = section_addresses[&relocation.section] + position;
+            let value = target as i64 + i64::from(relocation.addend);
+            let (value, fits) = match relocation.kind {
+                RelocationKind::Absolute32 => {
+                    (value, (-0x8000_0000..=0xffff_ffff).contains(&value))
+                }
+                RelocationKind::Absolute16 => (value, (-0x8000..=0xffff).contains(&value)),
+                RelocationKind::PcRelative16 => {
+                    let value = value - address as i64;
+                    (value, (-0x8000..0x8000).contains(&value))
+                }
+            };
+            if !fits {
+                errors.push(LinkError::RelocationOutOfRange {
+                    symbol: relocation.symbol.clone(),
+                    object: input.name.clone(),
+                    value,
+                });
+                continue;
+            }
+
+            let position = position as usize;
+            let size = relocation.kind.size() as usize;
+            let bytes = match section.bytes.get_mut(position..position + size) {
+                Some(bytes) => bytes,
+                None => {
+                    errors.push(LinkError::InvalidRelocation {
+                        object: input.name.clone(),
+                        offset: relocation.offset,
+                    });
+                    continue;
+                }
+            };
+            match relocation.kind {
+                RelocationKind::Absolute32 => {
+                    bytes.copy_from_slice(&(value as LongWord).to_be_bytes());
+                    // Without errors, every section lies inside of the address space.
+                    absolute_references.push(address as LongWord);
+                }
+                RelocationKind::Absolute16 | RelocationKind::PcRelative16 => {
+                    bytes.copy_from_slice(&(value as u16).to_be_bytes());
+                }
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    absolute_references.sort_unstable();
+    Ok(Linked {
+        sections,
+        symbols,
+        absolute_references,
+    })
+}
+
+fn align(size: LongWord) -> LongWord {
+    size.div_ceil(SECTION_ALIGNMENT) * SECTION_ALIGNMENT
+}
+
+fn append(bytes: &mut Vec<Byte>, offset: LongWord, content: &[Byte]) {
+    bytes.resize(offset as usize, 0);
+    bytes.extend_from_slice(content);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m68k_reloaded_object::Relocation;
+
+    fn symbol(name: &str, section: Section, value: LongWord, global: bool) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            section,
+            value,
+            global,
+        }
+    }
+
+    fn relocation(offset: LongWord, kind: RelocationKind, symbol: &str) -> Relocation {
+        Relocation {
+            section: Section::Text,
+            offset,
+            kind,
+            symbol: symbol.to_string(),
+            addend: 0,
+        }
+    }
+
+    /// `main` calls `print` from the other object and passes the local `message`.
+    fn inputs() -> Vec<Input> {
+        vec![
+            Input {
+                name: "main.o".to_string(),
+                object: Object {
+                    text: vec![
+                        0x41, 0xf9, 0, 0, 0, 0, // LEA message,A0
+                        0x61, 0x00, 0, 0, // BSR.W print
+                        0x4e, 0x75, // RTS
+                        0x4e, 0x71, // NOP
+                    ],
+                    data: b"Hi!".to_vec(),
+                    bss_size: 0,
+                    symbols: vec![
+                        symbol("message", Section::Data, 0, false),
+                        symbol("main", Section::Text, 0, true),
+                    ],
+                    relocations: vec![
+                        relocation(2, RelocationKind::Absolute32, "message"),
+                        relocation(8, RelocationKind::PcRelative16, "print"),
+                    ],
+                },
+            },
+            Input {
+                name: "print.o".to_string(),
+                object: Object {
+                    text: vec![0x4e, 0x75], // RTS
+                    data: b"!".to_vec(),
+                    bss_size: 4,
+                    symbols: vec![
+                        symbol("print", Section::Text, 0, true),
+                        symbol("message", Section::Data, 0, false),
+                        symbol("counter", Section::Bss, 2, true),
+                    ],
+                    relocations: vec![],
+                },
+            },
+        ]
+    }
+
+    #[test]
+    fn test_link_default_script() {
+        let linked = link(&inputs(), &Script::default()).unwrap();
+
+        assert_eq!(
+            linked.sections,
+            vec![
+                LinkedSection {
+                    section: Section::Text,
+                    address: 0,
+                    size: 16,
+                    bytes: vec![
+                        0x41, 0xf9, 0, 0, 0, 16, 0x61, 0x00, 0, 6, 0x4e, 0x75, 0x4e, 0x71, 0x4e,
+                        0x75
+                    ],
+                },
+                LinkedSection {
+                    section: Section::Data,
+                    address: 16,
+                    size: 6,
+                    bytes: vec![b'H', b'i', b'!', 0, b'!', 0],
+                },
+                LinkedSection {
+                    section: Section::Bss,
+                    address: 22,
+                    size: 4,
+                    bytes: vec![],
+                },
+            ]
+        );
+        assert_eq!(linked.absolute_references, vec![2]);
+        let counter = linked
+            .symbols
+            .iter()
+            .find(|symbol| symbol.name == "counter")
+            .unwrap();
+        assert_eq!(counter.address, 24);
+
+        let executable = linked.executable().unwrap();
+        assert_eq!(executable.fixups, vec![2]);
+        assert_eq!(executable.bss_size, 4);
+    }
+
+    #[test]
+    fn test_link_with_script() {
+        let script = script::parse(
+            "MEMORY {
+                rom : ORIGIN = $fc0000, LENGTH = 64K
+                ram : ORIGIN = $1000, LENGTH = 1K
+            }
+            SECTIONS {
+                text > rom
+                data > ram
+                bss > ram
+            }",
+        )
+        .unwrap();
+        let linked = link(&inputs(), &script).unwrap();
+
+        assert_eq!(linked.section(Section::Text).address, 0xfc_0000);
+        assert_eq!(linked.section(Section::Data).address, 0x1000);
+        assert_eq!(linked.section(Section::Bss).address, 0x1006);
+        assert_eq!(
+            &linked.section(Section::Text).bytes[..10],
+            &[0x41, 0xf9, 0, 0, 0x10, 0, 0x61, 0x00, 0, 6]
+        );
+        assert_eq!(linked.executable(), None);
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let mut inputs = inputs();
+        inputs[1].object.symbols[0].name = "main".to_string();
+        inputs[1].object.bss_size = 1024;
+        let script = script::parse(
+            "MEMORY { ram : ORIGIN = 0, LENGTH = 1K }
+            SECTIONS { text > ram data > ram }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            link(&inputs, &script),
+            Err(vec![
+                LinkError::UnplacedSection(Section::Bss),
+                LinkError::MultiplyDefinedSymbol {
+                    symbol: "main".to_string(),
+                    first: "main.o".to_string(),
+                    second: "print.o".to_string(),
+                },
+                LinkError::UndefinedSymbol {
+                    symbol: "print".to_string(),
+                    object: "main.o".to_string(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_link_out_of_range() {
+        let mut inputs = inputs();
+        inputs[1].object.text.resize(0x8000, 0);
+        inputs.swap(0, 1);
+        let script = script::parse(
+            "MEMORY { rom : ORIGIN = 0, LENGTH = 32K }
+            SECTIONS { text > rom data > rom bss > rom }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            link(&inputs, &script),
+            Err(vec![
+                LinkError::RegionOverflow {
+                    region: "rom".to_string(),
+                    size: 0x800e,
+                    length: 0x8000,
+                },
+                LinkError::RegionOverflow {
+                    region: "rom".to_string(),
+                    size: 0x8014,
+                    length: 0x8000,
+                },
+                LinkError::RegionOverflow {
+                    region: "rom".to_string(),
+                    size: 0x8018,
+                    length: 0x8000,
+                },
+                LinkError::RelocationOutOfRange {
+                    symbol: "print".to_string(),
+                    object: "main.o".to_string(),
+                    value: -0x8008,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_link_to_the_end_of_the_address_space() {
+        let script = script::parse(
+            "MEMORY { top : ORIGIN = $ffffffe6, LENGTH = 26 }
+            SECTIONS { text > top data > top bss > top }",
+        )
+        .unwrap();
+
+        let linked = link(&inputs(), &script).unwrap();
+        assert_eq!(linked.section(Section::Bss).address, 0xffff_fffc);
+        assert_eq!(linked.section(Section::Bss).end(), 0x1_0000_0000);
+        assert_eq!(linked.absolute_references, vec![0xffff_ffe8]);
+        assert_eq!(
+            &linked.section(Section::Text).bytes[2..6],
+            &[0xff, 0xff, 0xff, 0xf6]
+        );
+        let counter = linked
+            .symbols
+            .iter()
+            .find(|symbol| symbol.name == "counter")
+            .unwrap();
+        assert_eq!(counter.address, 0xffff_fffe);
+    }
+
+    #[test]
+    fn test_link_beyond_the_end_of_the_address_space() {
+        let script = script::parse(
+            "MEMORY { top : ORIGIN = $ffffffea, LENGTH = 22 }
+            SECTIONS { text > top data > top bss > top }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            link(&inputs(), &script),
+            Err(vec![
+                LinkError::RegionOverflow {
+                    region: "top".to_string(),
+                    size: 0x1a,
+                    length: 0x16,
+                },
+                LinkError::SymbolOutOfRange {
+                    symbol: "counter".to_string(),
+                    object: "print.o".to_string(),
+                    address: 0x1_0000_0002,
+                },
+            ])
+        );
+    }
+}
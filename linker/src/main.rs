@@ -0,0 +1,91 @@
+use m68k_reloaded_linker::script::{self, Script};
+use m68k_reloaded_linker::{link, Input};
+use m68k_reloaded_object::image::{self, ImageOptions};
+use m68k_reloaded_object::{elf, prg};
+
+const USAGE: &str = "Usage: linker [-T <script>] [--binary] -o <output> <object.o>...
+
+Without a script, the sections are placed after each other starting at address 0 and the output
+is a GEMDOS executable. With --binary, the output is a flat image starting at the lowest address.";
+
+fn main() {
+    let mut script_path = None;
+    let mut output = None;
+    let mut binary = false;
+    let mut objects = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-T" => script_path = args.next(),
+            "-o" => output = args.next(),
+            "--binary" => binary = true,
+            _ => objects.push(arg),
+        }
+    }
+    let output = match output {
+        Some(output) if !objects.is_empty() => output,
+        _ => fail(USAGE),
+    };
+
+    let script = match script_path {
+        Some(path) => {
+            let source = std::fs::read_to_string(&path).expect("Couldn't read the script.");
+            match script::parse(&source) {
+                Ok(script) => script,
+                Err(error) => fail(&format!("{}: {}", path, error)),
+            }
+        }
+        None => Script::default(),
+    };
+
+    let mut inputs = vec![];
+    for path in objects {
+        let bytes = std::fs::read(&path).expect("Couldn't read the object.");
+        match elf::read(&bytes) {
+            Ok(object) => inputs.push(Input { name: path, object }),
+            Err(error) => fail(&format!("{}: {}", path, error)),
+        }
+    }
+
+    let linked = match link(&inputs, &script) {
+        Ok(linked) => linked,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            std::process::exit(1);
+        }
+    };
+    let bytes = if binary {
+        let segments = linked.segments();
+        let origin = segments
+            .iter()
+            .filter(|segment| !segment.bytes.is_empty())
+            .map(|segment| segment.address)
+            .min()
+            .unwrap_or(0);
+        let options = ImageOptions {
+            origin,
+            ..Default::default()
+        };
+        match image::build(&segments, &options) {
+            Ok(image) => image,
+            Err(error) => fail(&error.to_string()),
+        }
+    } else {
+        match linked.executable() {
+            Some(executable) => match prg::write(&executable) {
+                Ok(bytes) => bytes,
+                Err(error) => fail(&error.to_string()),
+            },
+            None => fail("Executables need the default layout, use --binary."),
+        }
+    };
+    std::fs::write(output, bytes).expect("Couldn't write the output.");
+}
+
+/// Prints the message, like the usage, and exits with a failure.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
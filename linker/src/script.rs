@@ -0,0 +1,403 @@
+//! Linker scripts describe the memory of the target and which sections go where:
+//!
+//! ```text
+//! # A cartridge with 64 KiB of ROM and some RAM.
+//! MEMORY {
+//!     rom : ORIGIN = $fa0000, LENGTH = 64K
+//!     ram : ORIGIN = $10000, LENGTH = 0x8000
+//! }
+//! SECTIONS {
+//!     text > rom
+//!     data > ram
+//!     bss > ram
+//! }
+//! ```
+//!
+//! Sections are placed in the order they are listed, each one right after the previous section in
+//! the same region. Numbers can be decimal, hexadecimal (`$` or `0x`) and have a `K` or `M` suffix.
+//! Everything after a `#` is a comment.
+
+use m68k_reloaded_common::LongWord;
+use m68k_reloaded_object::Section;
+use std::fmt::{self, Display};
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct MemoryRegion {
+    pub name: String,
+    pub origin: LongWord,
+    pub length: LongWord,
+}
+
+impl MemoryRegion {
+    pub fn end(&self) -> u64 {
+        u64::from(self.origin) + u64::from(self.length)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Placement {
+    pub section: Section,
+    pub region: String,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Script {
+    pub regions: Vec<MemoryRegion>,
+    pub placements: Vec<Placement>,
+}
+
+impl Default for Script {
+    /// Places all sections right after each other, starting at address 0. That's the layout of
+    /// relocatable executables like `.PRG` files.
+    fn default() -> Script {
+        let region = "memory".to_string();
+        Script {
+            regions: vec![MemoryRegion {
+                name: region.clone(),
+                origin: 0,
+                length: LongWord::MAX,
+            }],
+            placements: [Section::Text, Section::Data, Section::Bss]
+                .iter()
+                .map(|section| Placement {
+                    section: *section,
+                    region: region.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Script {
+    pub fn region(&self, name: &str) -> Option<&MemoryRegion> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+enum ScriptToken {
+    Identifier(String),
+    Number(LongWord),
+    Symbol(char),
+}
+
+/// Parses a linker script.
+pub fn parse(source: &str) -> Result<Script, ScriptError> {
+    let mut parser = ScriptParser {
+        tokens: split_into_tokens(source)?,
+        cursor: 0,
+    };
+    let mut script = Script {
+        regions: vec![],
+        placements: vec![],
+    };
+
+    while let Some((line, token)) = parser.next() {
+        match token {
+            ScriptToken::Identifier(name) if name.eq_ignore_ascii_case("MEMORY") => {
+                parser.expect_symbol('{')?;
+                while !parser.advance_symbol('}') {
+                    let region = parser.parse_region()?;
+                    if script.region(&region.name).is_some() {
+                        return Err(parser.error(format!(
+                            "The region {} is defined multiple times.",
+                            region.name
+                        )));
+                    }
+                    script.regions.push(region);
+                }
+            }
+            ScriptToken::Identifier(name) if name.eq_ignore_ascii_case("SECTIONS") => {
+                parser.expect_symbol('{')?;
+                while !parser.advance_symbol('}') {
+                    let placement = parser.parse_placement()?;
+                    if script.region(&placement.region).is_none() {
+                        return Err(
+                            parser.error(format!("The region {} doesn't exist.", placement.region))
+                        );
+                    }
+                    if script
+                        .placements
+                        .iter()
+                        .any(|other| other.section == placement.section)
+                    {
+                        return Err(parser.error(format!(
+                            "The section {:?} is placed multiple times.",
+                            placement.section
+                        )));
+                    }
+                    script.placements.push(placement);
+                }
+            }
+            _ => {
+                return Err(ScriptError {
+                    line,
+                    message: "Expected MEMORY or SECTIONS.".to_string(),
+                })
+            }
+        }
+    }
+    Ok(script)
+}
+
+fn split_into_tokens(source: &str) -> Result<Vec<(usize, ScriptToken)>, ScriptError> {
+    let mut tokens = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap();
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+                let mut identifier = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    identifier.push(c);
+                    chars.next();
+                }
+                tokens.push((line_number, ScriptToken::Identifier(identifier)));
+            } else if c.is_ascii_digit() || c == '$' {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '$') {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                let value = parse_number(&number).ok_or_else(|| ScriptError {
+                    line: line_number,
+                    message: format!("{} is not a valid number.", number),
+                })?;
+                tokens.push((line_number, ScriptToken::Number(value)));
+            } else {
+                tokens.push((line_number, ScriptToken::Symbol(c)));
+                chars.next();
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(number: &str) -> Option<LongWord> {
+    let (number, factor) = match number.chars().last()? {
+        'k' | 'K' => (&number[..number.len() - 1], 1024),
+        'm' | 'M' => (&number[..number.len() - 1], 1024 * 1024),
+        _ => (number, 1),
+    };
+    let value = if let Some(hex) = number.strip_prefix('$') {
+        LongWord::from_str_radix(hex, 16).ok()?
+    } else if let Some(hex) = number.strip_prefix("0x") {
+        LongWord::from_str_radix(hex, 16).ok()?
+    } else {
+        number.parse().ok()?
+    };
+    value.checked_mul(factor)
+}
+
+struct ScriptParser {
+    tokens: Vec<(usize, ScriptToken)>,
+    cursor: usize,
+}
+
+impl ScriptParser {
+    fn next(&mut self) -> Option<(usize, ScriptToken)> {
+        let token = self.tokens.get(self.cursor).cloned();
+        self.cursor += 1;
+        token
+    }
+
+    fn line(&self) -> usize {
+        let index = self.cursor.min(self.tokens.len()).saturating_sub(1);
+        self.tokens.get(index).map(|(line, _)| *line).unwrap_or(1)
+    }
+
+    fn error(&self, message: String) -> ScriptError {
+        ScriptError {
+            line: self.line(),
+            message,
+        }
+    }
+
+    fn advance_symbol(&mut self, symbol: char) -> bool {
+        match self.tokens.get(self.cursor) {
+            Some((_, ScriptToken::Symbol(c))) if *c == symbol => {
+                self.cursor += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), ScriptError> {
+        if self.advance_symbol(symbol) {
+            Ok(())
+        } else {
+            self.cursor += 1;
+            Err(self.error(format!("Expected '{}'.", symbol)))
+        }
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, ScriptError> {
+        match self.next() {
+            Some((_, ScriptToken::Identifier(identifier))) => Ok(identifier),
+            _ => Err(self.error("Expected a name.".to_string())),
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ScriptError> {
+        match self.next() {
+            Some((_, ScriptToken::Identifier(identifier)))
+                if identifier.eq_ignore_ascii_case(keyword) =>
+            {
+                Ok(())
+            }
+            _ => Err(self.error(format!("Expected {}.", keyword))),
+        }
+    }
+
+    fn expect_number(&mut self) -> Result<LongWord, ScriptError> {
+        match self.next() {
+            Some((_, ScriptToken::Number(number))) => Ok(number),
+            _ => Err(self.error("Expected a number.".to_string())),
+        }
+    }
+
+    /// Parses `name : ORIGIN = number, LENGTH = number`.
+    fn parse_region(&mut self) -> Result<MemoryRegion, ScriptError> {
+        let name = self.expect_identifier()?;
+        self.expect_symbol(':')?;
+        self.expect_keyword("ORIGIN")?;
+        self.expect_symbol('=')?;
+        let origin = self.expect_number()?;
+        self.expect_symbol(',')?;
+        self.expect_keyword("LENGTH")?;
+        self.expect_symbol('=')?;
+        let length = self.expect_number()?;
+        let region = MemoryRegion {
+            name,
+            origin,
+            length,
+        };
+        if region.end() > 1 << 32 {
+            return Err(self.error(format!(
+                "The region {} exceeds the address space.",
+                region.name
+            )));
+        }
+        Ok(region)
+    }
+
+    /// Parses `section > region`.
+    fn parse_placement(&mut self) -> Result<Placement, ScriptError> {
+        let name = self.expect_identifier()?;
+        let section = match name.trim_start_matches('.').to_lowercase().as_str() {
+            "text" => Section::Text,
+            "data" => Section::Data,
+            "bss" => Section::Bss,
+            _ => return Err(self.error(format!("{} is not a section.", name))),
+        };
+        self.expect_symbol('>')?;
+        let region = self.expect_identifier()?;
+        Ok(Placement { section, region })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let script = parse(
+            "# A cartridge.
+            MEMORY {
+                rom : ORIGIN = $fa0000, LENGTH = 64K
+                ram : ORIGIN = 0x10000, LENGTH = 32768
+            }
+            SECTIONS {
+                .text > rom
+                data > ram
+                BSS > ram
+            }",
+        );
+
+        assert_eq!(
+            script,
+            Ok(Script {
+                regions: vec![
+                    MemoryRegion {
+                        name: "rom".to_string(),
+                        origin: 0xfa_0000,
+                        length: 0x1_0000,
+                    },
+                    MemoryRegion {
+                        name: "ram".to_string(),
+                        origin: 0x1_0000,
+                        length: 0x8000,
+                    },
+                ],
+                placements: vec![
+                    Placement {
+                        section: Section::Text,
+                        region: "rom".to_string(),
+                    },
+                    Placement {
+                        section: Section::Data,
+                        region: "ram".to_string(),
+                    },
+                    Placement {
+                        section: Section::Bss,
+                        region: "ram".to_string(),
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| parse(source).unwrap_err();
+
+        assert_eq!(error("FOO").line, 1);
+        assert_eq!(
+            error("MEMORY {\n rom : ORIGIN = 0, LENGTH = 1Q\n}").message,
+            "1Q is not a valid number."
+        );
+        assert_eq!(
+            error("MEMORY {\n rom : ORIGIN = 0 LENGTH = 1\n}"),
+            ScriptError {
+                line: 2,
+                message: "Expected ','.".to_string()
+            }
+        );
+        assert_eq!(
+            error("SECTIONS { text > rom }").message,
+            "The region rom doesn't exist."
+        );
+        assert_eq!(
+            error("MEMORY { rom : ORIGIN = 0, LENGTH = 1 }\nSECTIONS { text > rom text > rom }")
+                .message,
+            "The section Text is placed multiple times."
+        );
+        assert_eq!(
+            error("MEMORY { rom : ORIGIN = $ffffff00, LENGTH = 1K }").message,
+            "The region rom exceeds the address space."
+        );
+    }
+}
@@ -0,0 +1,1028 @@
+//! ELF32 relocatable objects for the 68000 (`EM_68K`), as produced and consumed by the GNU
+//! toolchain (`m68k-elf-as`, `m68k-elf-gcc -c`, `m68k-elf-ld`).
+//!
+//! Written objects contain the sections `.text`, `.data` and `.bss`, a symbol table and `RELA`
+//! relocation sections for all references that couldn't be resolved during assembly. When reading
+//! objects, every allocated section is merged into one of the three sections depending on its
+//! flags, so `.rodata` ends up in the data section and `.text.startup` in the text section.
+
+use crate::{
+    read_long_word, read_word, write_long_word, write_word, Object, Relocation, RelocationKind,
+    Section, Symbol,
+};
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use std::fmt::{self, Display};
+
+pub const MAGIC: [Byte; 4] = [0x7f, b'E', b'L', b'F'];
+const CLASS_32: Byte = 1;
+const DATA_BIG_ENDIAN: Byte = 2;
+const VERSION_CURRENT: Byte = 1;
+const TYPE_RELOCATABLE: Word = 1;
+const MACHINE_68K: Word = 4;
+
+const HEADER_SIZE: usize = 52;
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 16;
+const RELA_SIZE: usize = 12;
+
+const SECTION_TYPE_PROGBITS: LongWord = 1;
+const SECTION_TYPE_SYMTAB: LongWord = 2;
+const SECTION_TYPE_STRTAB: LongWord = 3;
+const SECTION_TYPE_RELA: LongWord = 4;
+const SECTION_TYPE_NOBITS: LongWord = 8;
+const SECTION_TYPE_REL: LongWord = 9;
+
+const SECTION_FLAG_WRITE: LongWord = 0x1;
+const SECTION_FLAG_ALLOC: LongWord = 0x2;
+const SECTION_FLAG_EXECINSTR: LongWord = 0x4;
+const SECTION_FLAG_INFO_LINK: LongWord = 0x40;
+
+const SECTION_INDEX_UNDEFINED: Word = 0;
+const SECTION_INDEX_ABSOLUTE: Word = 0xfff1;
+const SECTION_INDEX_COMMON: Word = 0xfff2;
+
+const SYMBOL_BINDING_LOCAL: Byte = 0;
+const SYMBOL_BINDING_GLOBAL: Byte = 1;
+const SYMBOL_TYPE_NOTYPE: Byte = 0;
+const SYMBOL_TYPE_SECTION: Byte = 3;
+const SYMBOL_TYPE_FILE: Byte = 4;
+
+const R_68K_32: Byte = 1;
+const R_68K_16: Byte = 2;
+const R_68K_PC16: Byte = 5;
+
+/// The 68000 has 24 address lines, so merged sections can't be larger than 16 MiB.
+const MAX_SECTION_SIZE: u64 = 0x0100_0000;
+
+/// Section indices of written objects. The relocation sections and `.shstrtab` follow afterwards.
+const TEXT_INDEX: Word = 1;
+const DATA_INDEX: Word = 2;
+const BSS_INDEX: Word = 3;
+const SYMTAB_INDEX: Word = 4;
+const STRTAB_INDEX: Word = 5;
+
+impl Section {
+    fn elf_name(&self) -> &'static str {
+        match self {
+            Section::Text => ".text",
+            Section::Data => ".data",
+            Section::Bss => ".bss",
+        }
+    }
+
+    fn elf_index(&self) -> Word {
+        match self {
+            Section::Text => TEXT_INDEX,
+            Section::Data => DATA_INDEX,
+            Section::Bss => BSS_INDEX,
+        }
+    }
+}
+
+impl RelocationKind {
+    fn elf_type(&self) -> Byte {
+        match self {
+            RelocationKind::Absolute32 => R_68K_32,
+            RelocationKind::Absolute16 => R_68K_16,
+            RelocationKind::PcRelative16 => R_68K_PC16,
+        }
+    }
+}
+
+/// A string table like `.strtab` or `.shstrtab`. It always starts with the empty string.
+struct StringTable {
+    bytes: Vec<Byte>,
+}
+
+impl StringTable {
+    fn new() -> StringTable {
+        StringTable { bytes: vec![0] }
+    }
+
+    fn add(&mut self, string: &str) -> LongWord {
+        let index = self.bytes.len() as LongWord;
+        self.bytes.extend_from_slice(string.as_bytes());
+        self.bytes.push(0);
+        index
+    }
+}
+
+#[derive(Default)]
+struct SectionHeader {
+    name: LongWord,
+    section_type: LongWord,
+    flags: LongWord,
+    offset: LongWord,
+    size: LongWord,
+    link: LongWord,
+    info: LongWord,
+    alignment: LongWord,
+    entry_size: LongWord,
+}
+
+/// Serializes the object into an ELF32 big-endian relocatable object.
+pub fn write(object: &Object) -> Vec<Byte> {
+    let mut section_names = StringTable::new();
+    let mut symbol_names = StringTable::new();
+
+    // Local symbols have to precede global ones. Imported symbols are global and undefined.
+    let mut symbol_order: Vec<(&str, Option<&Symbol>)> = vec![];
+    symbol_order.extend(
+        object
+            .symbols
+            .iter()
+            .filter(|symbol| !symbol.global)
+            .map(|symbol| (symbol.name.as_str(), Some(symbol))),
+    );
+    let first_global = symbol_order.len() + 1;
+    symbol_order.extend(
+        object
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.global)
+            .map(|symbol| (symbol.name.as_str(), Some(symbol))),
+    );
+    symbol_order.extend(
+        object
+            .undefined_symbols()
+            .into_iter()
+            .map(|name| (name, None)),
+    );
+
+    let mut symtab = vec![0; SYMBOL_SIZE];
+    for (name, symbol) in &symbol_order {
+        write_long_word(&mut symtab, symbol_names.add(name));
+        match symbol {
+            Some(symbol) => {
+                let binding = if symbol.global {
+                    SYMBOL_BINDING_GLOBAL
+                } else {
+                    SYMBOL_BINDING_LOCAL
+                };
+                write_long_word(&mut symtab, symbol.value);
+                write_long_word(&mut symtab, 0);
+                symtab.push(binding << 4 | SYMBOL_TYPE_NOTYPE);
+                symtab.push(0);
+                write_word(&mut symtab, symbol.section.elf_index());
+            }
+            None => {
+                write_long_word(&mut symtab, 0);
+                write_long_word(&mut symtab, 0);
+                symtab.push(SYMBOL_BINDING_GLOBAL << 4 | SYMBOL_TYPE_NOTYPE);
+                symtab.push(0);
+                write_word(&mut symtab, SECTION_INDEX_UNDEFINED);
+            }
+        }
+    }
+    let symbol_index = |name: &str| {
+        symbol_order
+            .iter()
+            .position(|(other, _)| *other == name)
+            .unwrap() as LongWord
+            + 1
+    };
+
+    let mut headers = vec![SectionHeader::default()];
+    let mut contents: Vec<Vec<Byte>> = vec![vec![]];
+    let mut add_section = |header: SectionHeader, content: Vec<Byte>| {
+        headers.push(header);
+        contents.push(content);
+        headers.len() as LongWord - 1
+    };
+    add_section(
+        SectionHeader {
+            name: section_names.add(".text"),
+            section_type: SECTION_TYPE_PROGBITS,
+            flags: SECTION_FLAG_ALLOC | SECTION_FLAG_EXECINSTR,
+            size: object.text.len() as LongWord,
+            alignment: 2,
+            ..Default::default()
+        },
+        object.text.clone(),
+    );
+    add_section(
+        SectionHeader {
+            name: section_names.add(".data"),
+            section_type: SECTION_TYPE_PROGBITS,
+            flags: SECTION_FLAG_ALLOC | SECTION_FLAG_WRITE,
+            size: object.data.len() as LongWord,
+            alignment: 2,
+            ..Default::default()
+        },
+        object.data.clone(),
+    );
+    add_section(
+        SectionHeader {
+            name: section_names.add(".bss"),
+            section_type: SECTION_TYPE_NOBITS,
+            flags: SECTION_FLAG_ALLOC | SECTION_FLAG_WRITE,
+            size: object.bss_size,
+            alignment: 2,
+            ..Default::default()
+        },
+        vec![],
+    );
+    add_section(
+        SectionHeader {
+            name: section_names.add(".symtab"),
+            section_type: SECTION_TYPE_SYMTAB,
+            size: symtab.len() as LongWord,
+            link: LongWord::from(STRTAB_INDEX),
+            info: first_global as LongWord,
+            alignment: 4,
+            entry_size: SYMBOL_SIZE as LongWord,
+            ..Default::default()
+        },
+        symtab,
+    );
+    add_section(
+        SectionHeader {
+            name: section_names.add(".strtab"),
+            section_type: SECTION_TYPE_STRTAB,
+            size: symbol_names.bytes.len() as LongWord,
+            alignment: 1,
+            ..Default::default()
+        },
+        symbol_names.bytes,
+    );
+    for section in &[Section::Text, Section::Data] {
+        let mut rela = vec![];
+        for relocation in object
+            .relocations
+            .iter()
+            .filter(|relocation| relocation.section == *section)
+        {
+            write_long_word(&mut rela, relocation.offset);
+            write_long_word(
+                &mut rela,
+                symbol_index(&relocation.symbol) << 8 | LongWord::from(relocation.kind.elf_type()),
+            );
+            write_long_word(&mut rela, relocation.addend as LongWord);
+        }
+        if rela.is_empty() {
+            continue;
+        }
+        add_section(
+            SectionHeader {
+                name: section_names.add(&format!(".rela{}", section.elf_name())),
+                section_type: SECTION_TYPE_RELA,
+                flags: SECTION_FLAG_INFO_LINK,
+                size: rela.len() as LongWord,
+                link: LongWord::from(SYMTAB_INDEX),
+                info: LongWord::from(section.elf_index()),
+                alignment: 4,
+                entry_size: RELA_SIZE as LongWord,
+                ..Default::default()
+            },
+            rela,
+        );
+    }
+    let shstrtab_name = section_names.add(".shstrtab");
+    let shstrtab_index = add_section(
+        SectionHeader {
+            name: shstrtab_name,
+            section_type: SECTION_TYPE_STRTAB,
+            size: section_names.bytes.len() as LongWord,
+            alignment: 1,
+            ..Default::default()
+        },
+        section_names.bytes,
+    );
+
+    let mut bytes = vec![0; HEADER_SIZE];
+    for (header, content) in headers.iter_mut().zip(contents.iter()).skip(1) {
+        align(&mut bytes, 4);
+        header.offset = bytes.len() as LongWord;
+        bytes.extend_from_slice(content);
+    }
+    align(&mut bytes, 4);
+    let section_headers_offset = bytes.len() as LongWord;
+    for header in &headers {
+        write_long_word(&mut bytes, header.name);
+        write_long_word(&mut bytes, header.section_type);
+        write_long_word(&mut bytes, header.flags);
+        write_long_word(&mut bytes, 0); // sh_addr
+        write_long_word(&mut bytes, header.offset);
+        write_long_word(&mut bytes, header.size);
+        write_long_word(&mut bytes, header.link);
+        write_long_word(&mut bytes, header.info);
+        write_long_word(&mut bytes, header.alignment);
+        write_long_word(&mut bytes, header.entry_size);
+    }
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&[CLASS_32, DATA_BIG_ENDIAN, VERSION_CURRENT]);
+    header.resize(16, 0);
+    write_word(&mut header, TYPE_RELOCATABLE);
+    write_word(&mut header, MACHINE_68K);
+    write_long_word(&mut header, LongWord::from(VERSION_CURRENT));
+    write_long_word(&mut header, 0); // e_entry
+    write_long_word(&mut header, 0); // e_phoff
+    write_long_word(&mut header, section_headers_offset);
+    write_long_word(&mut header, 0); // e_flags
+    write_word(&mut header, HEADER_SIZE as Word);
+    write_word(&mut header, 0); // e_phentsize
+    write_word(&mut header, 0); // e_phnum
+    write_word(&mut header, SECTION_HEADER_SIZE as Word);
+    write_word(&mut header, headers.len() as Word);
+    write_word(&mut header, shstrtab_index as Word);
+    bytes[..HEADER_SIZE].copy_from_slice(&header);
+    bytes
+}
+
+fn align(bytes: &mut Vec<Byte>, alignment: usize) {
+    let padding = (alignment - bytes.len() % alignment) % alignment;
+    bytes.resize(bytes.len() + padding, 0);
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum ReadError {
+    InvalidMagic,
+    Truncated,
+    Unsupported(&'static str),
+    UnsupportedRelocation(Byte),
+    InvalidIndex(LongWord),
+    TooLarge(u64),
+    OffsetOverflow(LongWord),
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::InvalidMagic => f.write_str("The file is not an ELF file."),
+            ReadError::Truncated => f.write_str("The file ends unexpectedly."),
+            ReadError::Unsupported(feature) => write!(f, "{} are not supported.", feature),
+            ReadError::UnsupportedRelocation(relocation_type) => write!(
+                f,
+                "The relocation type {} is not supported, only R_68K_32, R_68K_16 and R_68K_PC16 are.",
+                relocation_type
+            ),
+            ReadError::InvalidIndex(index) => {
+                write!(f, "The file refers to a nonexistent entry {}.", index)
+            }
+            ReadError::TooLarge(size) => write!(
+                f,
+                "The sections need ${:X} bytes, but the 68000 can only address ${:X}.",
+                size, MAX_SECTION_SIZE
+            ),
+            ReadError::OffsetOverflow(offset) => write!(
+                f,
+                "The offset ${:X} lies beyond the end of the address space.",
+                offset
+            ),
+        }
+    }
+}
+
+struct RawSection<'a> {
+    section_type: LongWord,
+    flags: LongWord,
+    size: LongWord,
+    link: LongWord,
+    info: LongWord,
+    alignment: LongWord,
+    content: &'a [Byte],
+}
+
+/// Reads an ELF32 big-endian relocatable object, like the ones `m68k-elf-gcc -c` produces.
+pub fn read(bytes: &[Byte]) -> Result<Object, ReadError> {
+    if bytes.get(..4) != Some(&MAGIC[..]) {
+        return Err(ReadError::InvalidMagic);
+    }
+    let header = bytes.get(..HEADER_SIZE).ok_or(ReadError::Truncated)?;
+    if header[4] != CLASS_32 {
+        return Err(ReadError::Unsupported("64-bit objects"));
+    }
+    if header[5] != DATA_BIG_ENDIAN {
+        return Err(ReadError::Unsupported("Little-endian objects"));
+    }
+    if read_word(header, 16) != Some(TYPE_RELOCATABLE) {
+        return Err(ReadError::Unsupported(
+            "Files other than relocatable objects",
+        ));
+    }
+    if read_word(header, 18) != Some(MACHINE_68K) {
+        return Err(ReadError::Unsupported("Machines other than the 68000"));
+    }
+    let section_headers_offset = read_long_word(header, 32).unwrap() as usize;
+    let section_header_size = read_word(header, 46).unwrap() as usize;
+    let section_count = read_word(header, 48).unwrap() as usize;
+    if section_header_size != SECTION_HEADER_SIZE {
+        return Err(ReadError::Unsupported("Unusual section header sizes"));
+    }
+
+    let sections = (0..section_count)
+        .map(|index| {
+            let header = index
+                .checked_mul(SECTION_HEADER_SIZE)
+                .and_then(|offset| offset.checked_add(section_headers_offset))
+                .and_then(|offset| bytes.get(offset..offset.checked_add(SECTION_HEADER_SIZE)?))
+                .ok_or(ReadError::Truncated)?;
+            let field = |index: usize| read_long_word(header, 4 * index).unwrap();
+            let section_type = field(1);
+            let content: &[Byte] = if section_type == SECTION_TYPE_NOBITS {
+                &[]
+            } else {
+                let start = field(4) as usize;
+                start
+                    .checked_add(field(5) as usize)
+                    .and_then(|end| bytes.get(start..end))
+                    .ok_or(ReadError::Truncated)?
+            };
+            Ok(RawSection {
+                section_type,
+                flags: field(2),
+                size: field(5),
+                link: field(6),
+                info: field(7),
+                alignment: field(8),
+                content,
+            })
+        })
+        .collect::<Result<Vec<_>, ReadError>>()?;
+
+    // Merge all allocated sections into text, data and bss.
+    let mut object = Object::default();
+    let mut placements: Vec<Option<(Section, LongWord)>> = vec![];
+    for section in &sections {
+        if section.flags & SECTION_FLAG_ALLOC == 0 {
+            placements.push(None);
+            continue;
+        }
+        // Alignments and sizes come straight from the file, so the merged sections are limited to
+        // what the 68000 can address before anything gets allocated.
+        let alignment = u64::from(section.alignment.max(1));
+        let place = |size: usize| {
+            let start = (size as u64).div_ceil(alignment) * alignment;
+            let end = start + u64::from(section.size);
+            if end > MAX_SECTION_SIZE {
+                return Err(ReadError::TooLarge(end));
+            }
+            Ok(start as LongWord)
+        };
+        let placement = if section.section_type == SECTION_TYPE_NOBITS {
+            let start = place(object.bss_size as usize)?;
+            object.bss_size = start + section.size;
+            (Section::Bss, start)
+        } else {
+            let (target, target_section) = if section.flags & SECTION_FLAG_EXECINSTR != 0 {
+                (&mut object.text, Section::Text)
+            } else {
+                (&mut object.data, Section::Data)
+            };
+            let start = place(target.len())?;
+            target.resize(start as usize, 0);
+            target.extend_from_slice(section.content);
+            (target_section, start)
+        };
+        placements.push(Some(placement));
+    }
+
+    // Symbols are referenced by relocations using their index. Section symbols get replaced by
+    // local symbols named after the section they were merged into.
+    let mut symbol_references: Vec<Vec<Option<(String, i32)>>> = vec![vec![]; sections.len()];
+    for (index, section) in sections.iter().enumerate() {
+        if section.section_type != SECTION_TYPE_SYMTAB {
+            continue;
+        }
+        let strtab = sections
+            .get(section.link as usize)
+            .ok_or(ReadError::InvalidIndex(section.link))?
+            .content;
+        let mut references = vec![];
+        for entry in section.content.chunks(SYMBOL_SIZE).skip(1) {
+            if entry.len() < SYMBOL_SIZE {
+                return Err(ReadError::Truncated);
+            }
+            let name = read_string(strtab, read_long_word(entry, 0).unwrap())?;
+            let value = read_long_word(entry, 4).unwrap();
+            let binding = entry[12] >> 4;
+            let symbol_type = entry[12] & 0xf;
+            let section_index = read_word(entry, 14).unwrap();
+
+            let reference = match section_index {
+                _ if symbol_type == SYMBOL_TYPE_FILE => None,
+                SECTION_INDEX_UNDEFINED => Some((name, 0)),
+                SECTION_INDEX_ABSOLUTE => return Err(ReadError::Unsupported("Absolute symbols")),
+                SECTION_INDEX_COMMON => return Err(ReadError::Unsupported("Common symbols")),
+                _ => match placements.get(section_index as usize) {
+                    None => return Err(ReadError::InvalidIndex(LongWord::from(section_index))),
+                    Some(None) => None,
+                    Some(Some((target, start))) if symbol_type == SYMBOL_TYPE_SECTION => {
+                        let name = target.elf_name().to_string();
+                        if object.symbol(&name).is_none() {
+                            object.symbols.push(Symbol {
+                                name: name.clone(),
+                                section: *target,
+                                value: 0,
+                                global: false,
+                            });
+                        }
+                        Some((name, *start as i32))
+                    }
+                    Some(Some((target, start))) => {
+                        object.symbols.push(Symbol {
+                            name: name.clone(),
+                            section: *target,
+                            value: start
+                                .checked_add(value)
+                                .ok_or(ReadError::OffsetOverflow(value))?,
+                            global: binding != SYMBOL_BINDING_LOCAL,
+                        });
+                        Some((name, 0))
+                    }
+                },
+            };
+            references.push(reference);
+        }
+        symbol_references[index] = references;
+    }
+
+    for section in &sections {
+        if section.section_type != SECTION_TYPE_RELA && section.section_type != SECTION_TYPE_REL {
+            continue;
+        }
+        let (target, start) = match placements.get(section.info as usize) {
+            None => return Err(ReadError::InvalidIndex(section.info)),
+            Some(None) => continue,
+            Some(Some(placement)) => *placement,
+        };
+        if section.section_type == SECTION_TYPE_REL {
+            return Err(ReadError::Unsupported("REL relocations"));
+        }
+        let references = symbol_references
+            .get(section.link as usize)
+            .ok_or(ReadError::InvalidIndex(section.link))?;
+        for entry in section.content.chunks(RELA_SIZE) {
+            if entry.len() < RELA_SIZE {
+                return Err(ReadError::Truncated);
+            }
+            let offset = read_long_word(entry, 0).unwrap();
+            let info = read_long_word(entry, 4).unwrap();
+            let addend = read_long_word(entry, 8).unwrap() as i32;
+            let kind = match info as Byte {
+                R_68K_32 => RelocationKind::Absolute32,
+                R_68K_16 => RelocationKind::Absolute16,
+                R_68K_PC16 => RelocationKind::PcRelative16,
+                relocation_type => return Err(ReadError::UnsupportedRelocation(relocation_type)),
+            };
+            let symbol_index = info >> 8;
+            let (symbol, symbol_addend) = symbol_index
+                .checked_sub(1)
+                .and_then(|index| references.get(index as usize))
+                .and_then(|reference| reference.clone())
+                .ok_or(ReadError::InvalidIndex(symbol_index))?;
+            object.relocations.push(Relocation {
+                section: target,
+                offset: start
+                    .checked_add(offset)
+                    .ok_or(ReadError::OffsetOverflow(offset))?,
+                kind,
+                symbol,
+                addend: addend.wrapping_add(symbol_addend),
+            });
+        }
+    }
+
+    Ok(object)
+}
+
+fn read_string(table: &[Byte], index: LongWord) -> Result<String, ReadError> {
+    let bytes = table
+        .get(index as usize..)
+        .ok_or(ReadError::InvalidIndex(index))?;
+    let length = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .ok_or(ReadError::Truncated)?;
+    Ok(String::from_utf8_lossy(&bytes[..length]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Object {
+        Object {
+            text: vec![
+                0x4e, 0xb9, 0, 0, 0, 0, // JSR external
+                0x41, 0xfa, 0, 0, // LEA message(PC),A0
+                0x4e, 0x75, // RTS
+            ],
+            data: b"Hi!\0".to_vec(),
+            bss_size: 8,
+            symbols: vec![
+                Symbol {
+                    name: "message".to_string(),
+                    section: Section::Data,
+                    value: 0,
+                    global: false,
+                },
+                Symbol {
+                    name: "main".to_string(),
+                    section: Section::Text,
+                    value: 0,
+                    global: true,
+                },
+                Symbol {
+                    name: "buffer".to_string(),
+                    section: Section::Bss,
+                    value: 4,
+                    global: true,
+                },
+            ],
+            relocations: vec![
+                Relocation {
+                    section: Section::Text,
+                    offset: 2,
+                    kind: RelocationKind::Absolute32,
+                    symbol: "external".to_string(),
+                    addend: 0,
+                },
+                Relocation {
+                    section: Section::Text,
+                    offset: 8,
+                    kind: RelocationKind::PcRelative16,
+                    symbol: "message".to_string(),
+                    addend: 0,
+                },
+                Relocation {
+                    section: Section::Data,
+                    offset: 0,
+                    kind: RelocationKind::Absolute16,
+                    symbol: "buffer".to_string(),
+                    addend: -4,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_write_header() {
+        let bytes = write(&example());
+
+        assert_eq!(&bytes[..4], &MAGIC);
+        assert_eq!(&bytes[4..7], &[CLASS_32, DATA_BIG_ENDIAN, VERSION_CURRENT]);
+        assert_eq!(read_word(&bytes, 16), Some(TYPE_RELOCATABLE));
+        assert_eq!(read_word(&bytes, 18), Some(MACHINE_68K));
+        assert_eq!(read_word(&bytes, 40), Some(HEADER_SIZE as Word));
+        assert_eq!(read_word(&bytes, 46), Some(SECTION_HEADER_SIZE as Word));
+        // null, .text, .data, .bss, .symtab, .strtab, .rela.text, .rela.data, .shstrtab
+        assert_eq!(read_word(&bytes, 48), Some(9));
+        assert_eq!(read_word(&bytes, 50), Some(8));
+    }
+
+    #[test]
+    fn test_write_omits_empty_relocation_sections() {
+        let mut object = example();
+        object.relocations.clear();
+        let bytes = write(&object);
+
+        assert_eq!(read_word(&bytes, 48), Some(7));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let object = example();
+        assert_eq!(read(&write(&object)), Ok(object));
+    }
+
+    /// Lays out raw sections the way `m68k-elf-gcc -c` does, with the section names in a
+    /// `.shstrtab` at the end.
+    fn raw_object(sections: Vec<(&str, SectionHeader, Vec<Byte>)>) -> Vec<Byte> {
+        let mut names = StringTable::new();
+        let mut headers = vec![SectionHeader::default()];
+        let mut bytes = vec![0; HEADER_SIZE];
+        for (name, mut header, content) in sections {
+            header.name = names.add(name);
+            align(&mut bytes, 4);
+            header.offset = bytes.len() as LongWord;
+            if header.section_type != SECTION_TYPE_NOBITS {
+                header.size = content.len() as LongWord;
+            }
+            bytes.extend_from_slice(&content);
+            headers.push(header);
+        }
+        let shstrtab_name = names.add(".shstrtab");
+        headers.push(SectionHeader {
+            name: shstrtab_name,
+            section_type: SECTION_TYPE_STRTAB,
+            offset: bytes.len() as LongWord,
+            size: names.bytes.len() as LongWord,
+            alignment: 1,
+            ..Default::default()
+        });
+        bytes.extend_from_slice(&names.bytes);
+        align(&mut bytes, 4);
+        let section_headers_offset = bytes.len() as LongWord;
+        for header in &headers {
+            for field in &[
+                header.name,
+                header.section_type,
+                header.flags,
+                0,
+                header.offset,
+                header.size,
+                header.link,
+                header.info,
+                header.alignment,
+                header.entry_size,
+            ] {
+                write_long_word(&mut bytes, *field);
+            }
+        }
+
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&[CLASS_32, DATA_BIG_ENDIAN, VERSION_CURRENT]);
+        header.resize(16, 0);
+        write_word(&mut header, TYPE_RELOCATABLE);
+        write_word(&mut header, MACHINE_68K);
+        write_long_word(&mut header, LongWord::from(VERSION_CURRENT));
+        write_long_word(&mut header, 0);
+        write_long_word(&mut header, 0);
+        write_long_word(&mut header, section_headers_offset);
+        write_long_word(&mut header, 0);
+        write_word(&mut header, HEADER_SIZE as Word);
+        write_word(&mut header, 0);
+        write_word(&mut header, 0);
+        write_word(&mut header, SECTION_HEADER_SIZE as Word);
+        write_word(&mut header, headers.len() as Word);
+        write_word(&mut header, headers.len() as Word - 1);
+        bytes[..HEADER_SIZE].copy_from_slice(&header);
+        bytes
+    }
+
+    /// A hand-built object shaped like the one `m68k-elf-gcc -O2 -g -c` produces for this C file:
+    ///
+    /// ```c
+    /// static int counter;
+    /// int value = 1;
+    /// void greet(void) { printf("Hello"); }
+    /// int main(void) { counter++; greet(); return *(const int *)"Bye"; }
+    /// ```
+    ///
+    /// It refers to `.rodata` and `.bss` through section symbols, puts `main` into
+    /// `.text.startup` and has sections that aren't loaded, some of them with REL relocations.
+    fn gcc_object() -> Vec<Byte> {
+        const TEXT: LongWord = SECTION_FLAG_ALLOC | SECTION_FLAG_EXECINSTR;
+        const DATA: LongWord = SECTION_FLAG_ALLOC | SECTION_FLAG_WRITE;
+        let section = |section_type, flags, alignment| SectionHeader {
+            section_type,
+            flags,
+            alignment,
+            ..Default::default()
+        };
+        let relocations = |target, entry_size, entries: &[(LongWord, LongWord, Byte, i32)]| {
+            let mut bytes = vec![];
+            for (offset, symbol, relocation_type, addend) in entries {
+                write_long_word(&mut bytes, *offset);
+                write_long_word(&mut bytes, symbol << 8 | LongWord::from(*relocation_type));
+                if entry_size == RELA_SIZE {
+                    write_long_word(&mut bytes, *addend as LongWord);
+                }
+            }
+            let section_type = if entry_size == RELA_SIZE {
+                SECTION_TYPE_RELA
+            } else {
+                SECTION_TYPE_REL
+            };
+            let header = SectionHeader {
+                section_type,
+                flags: SECTION_FLAG_INFO_LINK,
+                link: 12,
+                info: target,
+                alignment: 4,
+                entry_size: entry_size as LongWord,
+                ..Default::default()
+            };
+            (header, bytes)
+        };
+
+        let mut names = StringTable::new();
+        let mut symtab = vec![0; SYMBOL_SIZE];
+        let mut symbol = |name: &str, value, info: Byte, section_index: Word| {
+            let name = if name.is_empty() { 0 } else { names.add(name) };
+            write_long_word(&mut symtab, name);
+            write_long_word(&mut symtab, value);
+            write_long_word(&mut symtab, 0);
+            symtab.push(info);
+            symtab.push(0);
+            write_word(&mut symtab, section_index);
+        };
+        let local = SYMBOL_BINDING_LOCAL << 4;
+        let global = SYMBOL_BINDING_GLOBAL << 4;
+        symbol(
+            "main.c",
+            0,
+            local | SYMBOL_TYPE_FILE,
+            SECTION_INDEX_ABSOLUTE,
+        ); // 1
+        for index in &[1, 3, 4, 5, 7, 8, 9, 10] {
+            symbol("", 0, local | SYMBOL_TYPE_SECTION, *index); // 2 to 9
+        }
+        symbol("counter", 0, local | 1, 4); // 10
+        symbol("greet", 0, global | 2, 1); // 11
+        symbol("main", 0, global | 2, 5); // 12
+        symbol("printf", 0, global, SECTION_INDEX_UNDEFINED); // 13
+
+        let (rela_text, rela_text_content) =
+            relocations(1, RELA_SIZE, &[(2, 6, R_68K_32, 0), (8, 13, R_68K_32, 0)]);
+        let (rela_startup, rela_startup_content) = relocations(
+            5,
+            RELA_SIZE,
+            &[
+                (2, 4, R_68K_32, 0),
+                (8, 11, R_68K_32, 0),
+                (14, 6, R_68K_PC16, 6),
+            ],
+        );
+        let (rel_debug, rel_debug_content) = relocations(10, 8, &[(0, 2, R_68K_32, 0)]);
+        let mut bss = section(SECTION_TYPE_NOBITS, DATA, 2);
+        bss.size = 4;
+        let mut symtab_header = section(SECTION_TYPE_SYMTAB, 0, 4);
+        symtab_header.link = 13;
+        symtab_header.info = 11;
+        symtab_header.entry_size = SYMBOL_SIZE as LongWord;
+
+        raw_object(vec![
+            (
+                ".text",
+                section(SECTION_TYPE_PROGBITS, TEXT, 2),
+                vec![
+                    0x48, 0x79, 0, 0, 0, 0, // PEA .LC0
+                    0x4e, 0xb9, 0, 0, 0, 0, // JSR printf
+                    0x58, 0x8f, // ADDQ.L #4,SP
+                    0x4e, 0x75, // RTS
+                ],
+            ),
+            (".rela.text", rela_text, rela_text_content),
+            (
+                ".data",
+                section(SECTION_TYPE_PROGBITS, DATA, 2),
+                vec![0, 0, 0, 1],
+            ),
+            (".bss", bss, vec![]),
+            (
+                ".text.startup",
+                section(SECTION_TYPE_PROGBITS, TEXT, 2),
+                vec![
+                    0x52, 0xb9, 0, 0, 0, 0, // ADDQ.L #1,counter
+                    0x4e, 0xb9, 0, 0, 0, 0, // JSR greet
+                    0x20, 0x3a, 0, 0, // MOVE.L .LC1(PC),D0
+                    0x4e, 0x75, // RTS
+                ],
+            ),
+            (".rela.text.startup", rela_startup, rela_startup_content),
+            (
+                ".rodata",
+                section(SECTION_TYPE_PROGBITS, SECTION_FLAG_ALLOC, 1),
+                b"Hello\0Bye\0".to_vec(),
+            ),
+            (
+                ".comment",
+                section(SECTION_TYPE_PROGBITS, 0, 1),
+                b"GCC: (GNU) 13.2.0\0".to_vec(),
+            ),
+            (
+                ".note.GNU-stack",
+                section(SECTION_TYPE_PROGBITS, 0, 1),
+                vec![],
+            ),
+            (
+                ".debug_line",
+                section(SECTION_TYPE_PROGBITS, 0, 1),
+                vec![0; 4],
+            ),
+            (".rel.debug_line", rel_debug, rel_debug_content),
+            (".symtab", symtab_header, symtab),
+            (".strtab", section(SECTION_TYPE_STRTAB, 0, 1), names.bytes),
+        ])
+    }
+
+    #[test]
+    fn test_read_gcc_object() {
+        let object = read(&gcc_object()).unwrap();
+
+        // .text.startup follows .text, and .rodata follows .data.
+        assert_eq!(object.text.len(), 16 + 18);
+        assert_eq!(&object.text[16..18], &[0x52, 0xb9]);
+        assert_eq!(object.data, b"\0\0\0\x01Hello\0Bye\0".to_vec());
+        assert_eq!(object.bss_size, 4);
+
+        let symbol = |name: &str, section, value, global| Symbol {
+            name: name.to_string(),
+            section,
+            value,
+            global,
+        };
+        assert_eq!(
+            object.symbols,
+            vec![
+                symbol(".text", Section::Text, 0, false),
+                symbol(".data", Section::Data, 0, false),
+                symbol(".bss", Section::Bss, 0, false),
+                symbol("counter", Section::Bss, 0, false),
+                symbol("greet", Section::Text, 0, true),
+                symbol("main", Section::Text, 16, true),
+            ]
+        );
+
+        let relocation = |offset, kind, symbol: &str, addend| Relocation {
+            section: Section::Text,
+            offset,
+            kind,
+            symbol: symbol.to_string(),
+            addend,
+        };
+        assert_eq!(
+            object.relocations,
+            vec![
+                relocation(2, RelocationKind::Absolute32, ".data", 4),
+                relocation(8, RelocationKind::Absolute32, "printf", 0),
+                relocation(18, RelocationKind::Absolute32, ".bss", 0),
+                relocation(24, RelocationKind::Absolute32, "greet", 0),
+                relocation(30, RelocationKind::PcRelative16, ".data", 10),
+            ]
+        );
+        assert_eq!(object.undefined_symbols(), vec!["printf"]);
+    }
+
+    #[test]
+    fn test_read_rel_relocations_of_loaded_sections() {
+        let mut bytes = gcc_object();
+        // Turn .rela.text into a REL section.
+        let section_headers_offset = read_long_word(&bytes, 32).unwrap() as usize;
+        let type_offset = section_headers_offset + 2 * SECTION_HEADER_SIZE + 4;
+        bytes[type_offset..type_offset + 4].copy_from_slice(&SECTION_TYPE_REL.to_be_bytes());
+
+        assert_eq!(read(&bytes), Err(ReadError::Unsupported("REL relocations")));
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        assert_eq!(read(&[]), Err(ReadError::InvalidMagic));
+        assert_eq!(read(&MAGIC), Err(ReadError::Truncated));
+
+        let mut bytes = write(&example());
+        bytes[5] = 1;
+        assert_eq!(
+            read(&bytes),
+            Err(ReadError::Unsupported("Little-endian objects"))
+        );
+
+        let mut bytes = write(&example());
+        bytes[19] = 3;
+        assert_eq!(
+            read(&bytes),
+            Err(ReadError::Unsupported("Machines other than the 68000"))
+        );
+    }
+
+    #[test]
+    fn test_read_crafted_sizes() {
+        let gcc_object = gcc_object();
+        let section_headers_offset = read_long_word(&gcc_object, 32).unwrap() as usize;
+        let patch = |index: usize, field: usize, value: LongWord| {
+            let mut bytes = gcc_object.clone();
+            let offset = section_headers_offset + index * SECTION_HEADER_SIZE + 4 * field;
+            bytes[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+            bytes
+        };
+
+        // An alignment of 2^31 places .rodata far beyond the address space.
+        assert_eq!(
+            read(&patch(7, 8, 0x8000_0000)),
+            Err(ReadError::TooLarge(0x8000_000a))
+        );
+        // The size of .bss isn't backed by the file.
+        assert_eq!(
+            read(&patch(4, 5, 0xffff_fffe)),
+            Err(ReadError::TooLarge(0xffff_fffe))
+        );
+        // The section headers are read from the end of the address space.
+        let mut bytes = gcc_object.clone();
+        bytes[32..36].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+        assert_eq!(read(&bytes), Err(ReadError::Truncated));
+
+        // main is at offset 0 of .text.startup, which follows .text at 16.
+        let mut bytes = gcc_object.clone();
+        let symtab = read_long_word(
+            &bytes,
+            section_headers_offset + 12 * SECTION_HEADER_SIZE + 16,
+        )
+        .unwrap() as usize;
+        let value = symtab + 12 * SYMBOL_SIZE + 4;
+        bytes[value..value + 4].copy_from_slice(&0xffff_fff8u32.to_be_bytes());
+        assert_eq!(read(&bytes), Err(ReadError::OffsetOverflow(0xffff_fff8)));
+
+        let rela_startup = read_long_word(
+            &bytes,
+            section_headers_offset + 6 * SECTION_HEADER_SIZE + 16,
+        )
+        .unwrap() as usize;
+        let mut bytes = gcc_object.clone();
+        bytes[rela_startup..rela_startup + 4].copy_from_slice(&0xffff_fff8u32.to_be_bytes());
+        assert_eq!(read(&bytes), Err(ReadError::OffsetOverflow(0xffff_fff8)));
+    }
+}
@@ -0,0 +1,238 @@
+//! Flat binary images for cartridges, EPROMs and other targets that don't load executables.
+//!
+//! The image starts at the origin address (the first `ORG`) and contains every segment at its fixed
+//! address. Gaps between segments are filled with a configurable byte – usually `$FF`, because
+//! that's what erased EPROM cells read as.
+
+use m68k_reloaded_common::{Byte, LongWord};
+use std::fmt::{self, Display};
+
+/// Bytes that have to end up at a fixed address, like a section placed by an `ORG`.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Segment {
+    pub address: LongWord,
+    pub bytes: Vec<Byte>,
+}
+
+impl Segment {
+    fn end(&self) -> u64 {
+        u64::from(self.address) + self.bytes.len() as u64
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Padding {
+    /// The image ends right after the last segment.
+    None,
+    /// The image is padded to the next power of two, which is the size of the smallest ROM it
+    /// fits in.
+    PowerOfTwo,
+    /// The image is padded to the given ROM size, which needs to be a power of two.
+    RomSize(LongWord),
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct ImageOptions {
+    /// The address of the image's first byte.
+    pub origin: LongWord,
+    pub fill: Byte,
+    pub padding: Padding,
+}
+
+impl Default for ImageOptions {
+    fn default() -> ImageOptions {
+        ImageOptions {
+            origin: 0,
+            fill: 0xff,
+            padding: Padding::None,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum ImageError {
+    BelowOrigin { address: LongWord, origin: LongWord },
+    Overlap { first: LongWord, second: LongWord },
+    InvalidRomSize(LongWord),
+    ExceedsRomSize { size: u64, rom_size: LongWord },
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::BelowOrigin { address, origin } => write!(
+                f,
+                "The segment at ${:X} starts before the image origin ${:X}.",
+                address, origin
+            ),
+            ImageError::Overlap { first, second } => {
+                write!(f, "The segments at ${:X} and ${:X} overlap.", first, second)
+            }
+            ImageError::InvalidRomSize(size) => {
+                write!(f, "The ROM size ${:X} is not a power of two.", size)
+            }
+            ImageError::ExceedsRomSize { size, rom_size } => write!(
+                f,
+                "The image needs ${:X} bytes, but the ROM only has ${:X}.",
+                size, rom_size
+            ),
+        }
+    }
+}
+
+/// Places all segments into a single flat image.
+pub fn build(segments: &[Segment], options: &ImageOptions) -> Result<Vec<Byte>, ImageError> {
+    let mut segments: Vec<&Segment> = segments
+        .iter()
+        .filter(|segment| !segment.bytes.is_empty())
+        .collect();
+    segments.sort_by_key(|segment| segment.address);
+
+    for (previous, segment) in segments.iter().zip(segments.iter().skip(1)) {
+        if previous.end() > u64::from(segment.address) {
+            return Err(ImageError::Overlap {
+                first: previous.address,
+                second: segment.address,
+            });
+        }
+    }
+    if let Some(first) = segments.first() {
+        if first.address < options.origin {
+            return Err(ImageError::BelowOrigin {
+                address: first.address,
+                origin: options.origin,
+            });
+        }
+    }
+
+    let content_size = segments
+        .last()
+        .map(|segment| segment.end() - u64::from(options.origin))
+        .unwrap_or(0);
+    let size = match options.padding {
+        Padding::None => content_size,
+        Padding::PowerOfTwo if content_size == 0 => 0,
+        Padding::PowerOfTwo => content_size.next_power_of_two(),
+        Padding::RomSize(rom_size) => {
+            if !rom_size.is_power_of_two() {
+                return Err(ImageError::InvalidRomSize(rom_size));
+            }
+            if content_size > u64::from(rom_size) {
+                return Err(ImageError::ExceedsRomSize {
+                    size: content_size,
+                    rom_size,
+                });
+            }
+            u64::from(rom_size)
+        }
+    };
+
+    let mut image = vec![options.fill; size as usize];
+    for segment in segments {
+        let start = (segment.address - options.origin) as usize;
+        image[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(address: LongWord, bytes: &[Byte]) -> Segment {
+        Segment {
+            address,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_build_fills_gaps() {
+        let options = ImageOptions {
+            origin: 0x100,
+            fill: 0xaa,
+            padding: Padding::None,
+        };
+        let image = build(
+            &[segment(0x104, &[3, 4]), segment(0x100, &[1, 2])],
+            &options,
+        );
+
+        assert_eq!(image, Ok(vec![1, 2, 0xaa, 0xaa, 3, 4]));
+    }
+
+    #[test]
+    fn test_build_pads_to_power_of_two() {
+        let options = ImageOptions {
+            padding: Padding::PowerOfTwo,
+            ..Default::default()
+        };
+
+        assert_eq!(build(&[], &options), Ok(vec![]));
+        assert_eq!(
+            build(&[segment(0, &[1, 2, 3])], &options),
+            Ok(vec![1, 2, 3, 0xff])
+        );
+        assert_eq!(build(&[segment(0, &[1, 2])], &options), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_build_pads_to_rom_size() {
+        let options = ImageOptions {
+            origin: 0xfc_0000,
+            fill: 0,
+            padding: Padding::RomSize(8),
+        };
+
+        assert_eq!(
+            build(&[segment(0xfc_0002, &[1, 2])], &options),
+            Ok(vec![0, 0, 1, 2, 0, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let options = ImageOptions {
+            origin: 0x10,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            build(&[segment(0x10, &[1, 2, 3]), segment(0x12, &[4])], &options),
+            Err(ImageError::Overlap {
+                first: 0x10,
+                second: 0x12
+            })
+        );
+        assert_eq!(
+            build(&[segment(0x0e, &[1, 2])], &options),
+            Err(ImageError::BelowOrigin {
+                address: 0x0e,
+                origin: 0x10
+            })
+        );
+        assert_eq!(
+            build(
+                &[segment(0x10, &[1])],
+                &ImageOptions {
+                    padding: Padding::RomSize(3),
+                    ..options
+                }
+            ),
+            Err(ImageError::InvalidRomSize(3))
+        );
+        assert_eq!(
+            build(
+                &[segment(0x10, &[1, 2, 3])],
+                &ImageOptions {
+                    padding: Padding::RomSize(2),
+                    ..options
+                }
+            ),
+            Err(ImageError::ExceedsRomSize {
+                size: 3,
+                rom_size: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,104 @@
+pub mod elf;
+pub mod image;
+pub mod prg;
+
+use m68k_reloaded_common::{Byte, LongWord, Word};
+
+/// The segments an assembled program consists of.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+/// A named address inside of a section.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub section: Section,
+    /// The offset relative to the start of the section.
+    pub value: LongWord,
+    pub global: bool,
+}
+
+/// How a reference to a symbol gets patched into the code once the symbol's address is known.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum RelocationKind {
+    /// An absolute long word, like in `JMP label` or `DC.L label`.
+    Absolute32,
+    /// An absolute word, like in `MOVE.W label.W,D0`.
+    Absolute16,
+    /// A word relative to the address of the word itself, like in `LEA label(PC),A0` or `BRA.W`.
+    PcRelative16,
+}
+
+impl RelocationKind {
+    /// The number of bytes that get patched.
+    pub fn size(&self) -> LongWord {
+        match self {
+            RelocationKind::Absolute32 => 4,
+            RelocationKind::Absolute16 | RelocationKind::PcRelative16 => 2,
+        }
+    }
+}
+
+/// A reference from inside a section to a symbol whose address isn't known yet.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Relocation {
+    pub section: Section,
+    /// The offset of the patched bytes relative to the start of the section.
+    pub offset: LongWord,
+    pub kind: RelocationKind,
+    pub symbol: String,
+    pub addend: i32,
+}
+
+/// A relocatable object, as produced by assembling a single source file. Symbols that are
+/// referenced by relocations but not contained in `symbols` are imported from other objects.
+#[derive(Eq, PartialEq, Debug, Default)]
+pub struct Object {
+    pub text: Vec<Byte>,
+    pub data: Vec<Byte>,
+    pub bss_size: LongWord,
+    pub symbols: Vec<Symbol>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl Object {
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Names of all symbols that are referenced but not defined in this object.
+    pub fn undefined_symbols(&self) -> Vec<&str> {
+        let mut names = vec![];
+        for relocation in &self.relocations {
+            let name = relocation.symbol.as_str();
+            if self.symbol(name).is_none() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+fn write_word(bytes: &mut Vec<Byte>, value: Word) {
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_long_word(bytes: &mut Vec<Byte>, value: LongWord) {
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+fn read_word(bytes: &[Byte], offset: usize) -> Option<Word> {
+    let slice = bytes.get(offset..offset + 2)?;
+    Some(Word::from_be_bytes([slice[0], slice[1]]))
+}
+
+fn read_long_word(bytes: &[Byte], offset: usize) -> Option<LongWord> {
+    let slice = bytes.get(offset..offset + 4)?;
+    Some(LongWord::from_be_bytes([
+        slice[0], slice[1], slice[2], slice[3],
+    ]))
+}
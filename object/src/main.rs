@@ -0,0 +1,58 @@
+use m68k_reloaded_object::{elf, prg};
+
+const USAGE: &str = "Usage: object <program.prg | object.o>";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|arg| arg.as_str()) {
+        None => fail(USAGE),
+        Some(path) => dump(path),
+    }
+}
+
+fn dump(path: &str) {
+    let bytes = std::fs::read(path).expect("Couldn't read the file.");
+
+    if bytes.starts_with(&elf::MAGIC) {
+        match elf::read(&bytes) {
+            Ok(object) => {
+                println!("TEXT: {} bytes", object.text.len());
+                println!("DATA: {} bytes", object.data.len());
+                println!("BSS:  {} bytes", object.bss_size);
+                for symbol in &object.symbols {
+                    println!("{:?} {:08X} {}", symbol.section, symbol.value, symbol.name);
+                }
+                for relocation in &object.relocations {
+                    println!(
+                        "{:?}+{:X} {:?} {}{:+}",
+                        relocation.section,
+                        relocation.offset,
+                        relocation.kind,
+                        relocation.symbol,
+                        relocation.addend
+                    );
+                }
+            }
+            Err(error) => fail(&error.to_string()),
+        }
+        return;
+    }
+
+    match prg::load(&bytes, 0) {
+        Ok(program) => {
+            println!("TEXT: {} bytes", program.text_size);
+            println!("DATA: {} bytes", program.data_size);
+            println!("BSS:  {} bytes", program.bss_size);
+            for symbol in &program.symbols {
+                println!("{:?} {:08X} {}", symbol.section, symbol.value, symbol.name);
+            }
+        }
+        Err(error) => fail(&error.to_string()),
+    }
+}
+
+/// Prints the message, like the usage, and exits with a failure.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
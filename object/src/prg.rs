@@ -0,0 +1,623 @@
+//! GEMDOS executables (`.PRG`, `.TOS`, `.TTP`) as loaded by the Atari ST.
+//!
+//! A program file consists of a 28-byte header, the TEXT and DATA segments, an optional symbol
+//! table in the DRI format and the relocation fixups. The BSS segment isn't stored in the file, only
+//! its size. Because the ST loads programs at arbitrary addresses, every absolute long reference
+//! into the program is listed in the fixup stream and gets the load address added by the loader.
+
+use crate::{read_long_word, read_word, write_long_word, write_word, Section, Symbol};
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use std::fmt::{self, Display};
+
+/// A `BRA.S` over the header, which is the magic number of all GEMDOS executables.
+pub const MAGIC: Word = 0x601a;
+pub const HEADER_SIZE: usize = 28;
+/// The size of a single entry in the DRI symbol table.
+pub const SYMBOL_SIZE: usize = 14;
+/// DRI symbol names are null-padded to eight bytes. Longer names continue in an extended entry.
+pub const SYMBOL_NAME_SIZE: usize = 8;
+
+const SYMBOL_DEFINED: Word = 0x8000;
+const SYMBOL_GLOBAL: Word = 0x2000;
+const SYMBOL_DATA: Word = 0x0400;
+const SYMBOL_TEXT: Word = 0x0200;
+const SYMBOL_BSS: Word = 0x0100;
+/// HiSoft's extension: The name continues in the next entry, which only holds 14 more characters.
+const SYMBOL_EXTENDED_NAME: Word = 0x0048;
+
+/// The largest distance between two fixups that can be encoded in a single byte.
+const MAX_FIXUP_STEP: LongWord = 254;
+/// The 68000 has 24 address lines, so no program can be larger than 16 MiB.
+const MAX_PROGRAM_SIZE: LongWord = 0x0100_0000;
+
+/// A linked program that is ready to be written to a `.PRG` file.
+#[derive(Eq, PartialEq, Debug, Default)]
+pub struct Executable {
+    pub text: Vec<Byte>,
+    pub data: Vec<Byte>,
+    pub bss_size: LongWord,
+    /// Symbols to write into the DRI symbol table. If this is empty, no table is written.
+    pub symbols: Vec<Symbol>,
+    /// Offsets of all absolute long references, relative to the start of the TEXT segment.
+    pub fixups: Vec<LongWord>,
+    /// The `ph_prgflags` field (fast load, TT-RAM, memory protection, …).
+    pub flags: LongWord,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum WriteError {
+    /// A first fixup at offset 0 would mark the stream as empty.
+    FixupAtStart,
+    /// Fixups are encoded as even distances, so they need to be at even offsets.
+    OddFixup(LongWord),
+    FixupOutOfRange(LongWord),
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::FixupAtStart => {
+                f.write_str("The first long word of the TEXT segment can't be relocated.")
+            }
+            WriteError::OddFixup(offset) => write!(
+                f,
+                "The fixup at offset ${:X} is odd, but long words need to be at even offsets.",
+                offset
+            ),
+            WriteError::FixupOutOfRange(offset) => write!(
+                f,
+                "The fixup at offset ${:X} lies outside of the TEXT and DATA segments.",
+                offset
+            ),
+        }
+    }
+}
+
+/// Serializes the executable into the GEMDOS program file format.
+pub fn write(executable: &Executable) -> Result<Vec<Byte>, WriteError> {
+    let initialized_size = (executable.text.len() + executable.data.len()) as u64;
+    for offset in &executable.fixups {
+        if u64::from(*offset) + 4 > initialized_size {
+            return Err(WriteError::FixupOutOfRange(*offset));
+        }
+    }
+    let fixups = write_fixups(&executable.fixups)?;
+    let symbol_table = write_symbol_table(executable);
+
+    let mut bytes = Vec::with_capacity(
+        HEADER_SIZE + executable.text.len() + executable.data.len() + symbol_table.len(),
+    );
+    write_word(&mut bytes, MAGIC);
+    write_long_word(&mut bytes, executable.text.len() as LongWord);
+    write_long_word(&mut bytes, executable.data.len() as LongWord);
+    write_long_word(&mut bytes, executable.bss_size);
+    write_long_word(&mut bytes, symbol_table.len() as LongWord);
+    write_long_word(&mut bytes, 0); // ph_res1
+    write_long_word(&mut bytes, executable.flags);
+    write_word(&mut bytes, 0); // ph_absflag: The fixups are present.
+    bytes.extend_from_slice(&executable.text);
+    bytes.extend_from_slice(&executable.data);
+    bytes.extend_from_slice(&symbol_table);
+    bytes.extend(fixups);
+    Ok(bytes)
+}
+
+fn write_symbol_table(executable: &Executable) -> Vec<Byte> {
+    let mut bytes = Vec::with_capacity(executable.symbols.len() * SYMBOL_SIZE);
+    for symbol in &executable.symbols {
+        // Names longer than both entries together are truncated.
+        let mut name = [0; SYMBOL_NAME_SIZE + SYMBOL_SIZE];
+        for (target, source) in name.iter_mut().zip(symbol.name.bytes()) {
+            *target = source;
+        }
+        let extended = symbol.name.len() > SYMBOL_NAME_SIZE;
+        bytes.extend_from_slice(&name[..SYMBOL_NAME_SIZE]);
+
+        // DRI symbol values are relative to the start of the TEXT segment, not to the start of
+        // their own section.
+        let (section_type, section_start) = match symbol.section {
+            Section::Text => (SYMBOL_TEXT, 0),
+            Section::Data => (SYMBOL_DATA, executable.text.len()),
+            Section::Bss => (SYMBOL_BSS, executable.text.len() + executable.data.len()),
+        };
+        let visibility = if symbol.global { SYMBOL_GLOBAL } else { 0 };
+        let name_type = if extended { SYMBOL_EXTENDED_NAME } else { 0 };
+        write_word(
+            &mut bytes,
+            SYMBOL_DEFINED | visibility | section_type | name_type,
+        );
+        write_long_word(&mut bytes, section_start as LongWord + symbol.value);
+        if extended {
+            bytes.extend_from_slice(&name[SYMBOL_NAME_SIZE..]);
+        }
+    }
+    bytes
+}
+
+/// Compresses the fixup offsets into the GEMDOS relocation stream: The first offset is stored as a
+/// long word, every following one as the distance to its predecessor. Distances larger than 254
+/// bytes are split into steps of 254 bytes (encoded as `1`). A `0` byte ends the stream.
+/// A first offset of zero means there are no fixups at all, so the very first long word of the
+/// TEXT segment can't be relocated. A distance of 1 means a step, so distances have to be even.
+fn write_fixups(fixups: &[LongWord]) -> Result<Vec<Byte>, WriteError> {
+    let mut fixups = fixups.to_vec();
+    fixups.sort_unstable();
+    fixups.dedup();
+    if let Some(offset) = fixups.iter().find(|offset| *offset % 2 != 0) {
+        return Err(WriteError::OddFixup(*offset));
+    }
+    if fixups.first() == Some(&0) {
+        return Err(WriteError::FixupAtStart);
+    }
+
+    let mut bytes = vec![];
+    let (first, rest) = match fixups.split_first() {
+        Some(split) => split,
+        None => {
+            write_long_word(&mut bytes, 0);
+            return Ok(bytes);
+        }
+    };
+    write_long_word(&mut bytes, *first);
+    let mut previous = *first;
+    for offset in rest {
+        let mut distance = offset - previous;
+        while distance > MAX_FIXUP_STEP {
+            bytes.push(1);
+            distance -= MAX_FIXUP_STEP;
+        }
+        bytes.push(distance as Byte);
+        previous = *offset;
+    }
+    bytes.push(0);
+    Ok(bytes)
+}
+
+/// A program loaded into memory at a base address, with all fixups applied.
+#[derive(Eq, PartialEq, Debug)]
+pub struct LoadedProgram {
+    pub base: LongWord,
+    pub text_size: LongWord,
+    pub data_size: LongWord,
+    pub bss_size: LongWord,
+    /// TEXT, DATA and the zero-initialized BSS segment, in this order.
+    pub memory: Vec<Byte>,
+    pub symbols: Vec<Symbol>,
+    pub flags: LongWord,
+}
+
+impl LoadedProgram {
+    pub fn text_start(&self) -> LongWord {
+        self.base
+    }
+
+    pub fn data_start(&self) -> LongWord {
+        self.base + self.text_size
+    }
+
+    pub fn bss_start(&self) -> LongWord {
+        self.data_start() + self.data_size
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum LoadError {
+    InvalidMagic(Word),
+    Truncated,
+    FixupOutOfRange(LongWord),
+    TooLarge(u64),
+    /// The program needs more memory than is available at its base address.
+    OutOfMemory {
+        size: u64,
+        available: u64,
+    },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::InvalidMagic(magic) => {
+                write!(f, "Expected the magic number $601A, found ${:04X}.", magic)
+            }
+            LoadError::Truncated => f.write_str("The file ends unexpectedly."),
+            LoadError::FixupOutOfRange(offset) => write!(
+                f,
+                "The fixup at offset ${:X} lies outside of the TEXT and DATA segments.",
+                offset
+            ),
+            LoadError::TooLarge(size) => write!(
+                f,
+                "The program needs ${:X} bytes, but the 68000 can only address ${:X}.",
+                size, MAX_PROGRAM_SIZE
+            ),
+            LoadError::OutOfMemory { size, available } => write!(
+                f,
+                "The program needs ${:X} bytes, but only ${:X} are available.",
+                size, available
+            ),
+        }
+    }
+}
+
+/// Loads a program file the way GEMDOS' `Pexec` does: The segments are placed at `base` and the
+/// base address is added to every long word listed in the fixup stream.
+pub fn load(bytes: &[Byte], base: LongWord) -> Result<LoadedProgram, LoadError> {
+    let magic = read_word(bytes, 0).ok_or(LoadError::Truncated)?;
+    if magic != MAGIC {
+        return Err(LoadError::InvalidMagic(magic));
+    }
+    let header_long_word = |offset| read_long_word(bytes, offset).ok_or(LoadError::Truncated);
+    let text_size = header_long_word(2)?;
+    let data_size = header_long_word(6)?;
+    let bss_size = header_long_word(10)?;
+    let symbols_size = header_long_word(14)?;
+    let flags = header_long_word(22)?;
+    let absolute = read_word(bytes, 26).ok_or(LoadError::Truncated)? != 0;
+
+    let size = u64::from(text_size) + u64::from(data_size) + u64::from(bss_size);
+    if size > u64::from(MAX_PROGRAM_SIZE) {
+        return Err(LoadError::TooLarge(size));
+    }
+
+    // The sizes fit into 24 bits now, but the symbol table's size can still be anything.
+    let text_start = HEADER_SIZE;
+    let symbols_start = text_start + (text_size + data_size) as usize;
+    let fixups_start = symbols_start
+        .checked_add(symbols_size as usize)
+        .ok_or(LoadError::Truncated)?;
+
+    let mut memory = bytes
+        .get(text_start..symbols_start)
+        .ok_or(LoadError::Truncated)?
+        .to_vec();
+    let symbols = read_symbol_table(
+        bytes
+            .get(symbols_start..fixups_start)
+            .ok_or(LoadError::Truncated)?,
+        text_size,
+        data_size,
+    )?;
+    if !absolute {
+        let fixups = bytes.get(fixups_start..).ok_or(LoadError::Truncated)?;
+        for offset in read_fixups(fixups)? {
+            let position = offset as usize;
+            let value =
+                read_long_word(&memory, position).ok_or(LoadError::FixupOutOfRange(offset))?;
+            memory[position..position + 4].copy_from_slice(&value.wrapping_add(base).to_be_bytes());
+        }
+    }
+    memory.resize(memory.len() + bss_size as usize, 0);
+
+    Ok(LoadedProgram {
+        base,
+        text_size,
+        data_size,
+        bss_size,
+        memory,
+        symbols,
+        flags,
+    })
+}
+
+fn read_symbol_table(
+    bytes: &[Byte],
+    text_size: LongWord,
+    data_size: LongWord,
+) -> Result<Vec<Symbol>, LoadError> {
+    if !bytes.len().is_multiple_of(SYMBOL_SIZE) {
+        return Err(LoadError::Truncated);
+    }
+    let mut symbols = vec![];
+    let mut entries = bytes.chunks(SYMBOL_SIZE);
+    while let Some(entry) = entries.next() {
+        let symbol_type = read_word(entry, SYMBOL_NAME_SIZE).unwrap();
+        let value = read_long_word(entry, SYMBOL_NAME_SIZE + 2).unwrap();
+        let mut name = entry[..SYMBOL_NAME_SIZE].to_vec();
+        if symbol_type & SYMBOL_EXTENDED_NAME == SYMBOL_EXTENDED_NAME {
+            name.extend_from_slice(entries.next().ok_or(LoadError::Truncated)?);
+        }
+        let name_length = name
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(name.len());
+
+        let (section, section_start) = if symbol_type & SYMBOL_TEXT != 0 {
+            (Section::Text, 0)
+        } else if symbol_type & SYMBOL_DATA != 0 {
+            (Section::Data, text_size)
+        } else if symbol_type & SYMBOL_BSS != 0 {
+            (Section::Bss, text_size + data_size)
+        } else {
+            // Equates and absolute addresses aren't part of the program.
+            continue;
+        };
+        symbols.push(Symbol {
+            name: String::from_utf8_lossy(&name[..name_length]).into_owned(),
+            section,
+            value: value.wrapping_sub(section_start),
+            global: symbol_type & SYMBOL_GLOBAL != 0,
+        });
+    }
+    Ok(symbols)
+}
+
+fn read_fixups(bytes: &[Byte]) -> Result<Vec<LongWord>, LoadError> {
+    let first = read_long_word(bytes, 0).ok_or(LoadError::Truncated)?;
+    if first == 0 {
+        return Ok(vec![]);
+    }
+
+    let mut fixups = vec![first];
+    let mut offset = first;
+    for byte in &bytes[4..] {
+        match byte {
+            0 => return Ok(fixups),
+            1 => {
+                offset = offset
+                    .checked_add(MAX_FIXUP_STEP)
+                    .ok_or(LoadError::FixupOutOfRange(offset))?
+            }
+            distance => {
+                offset = offset
+                    .checked_add(LongWord::from(*distance))
+                    .ok_or(LoadError::FixupOutOfRange(offset))?;
+                fixups.push(offset);
+            }
+        }
+    }
+    Err(LoadError::Truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, section: Section, value: LongWord, global: bool) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            section,
+            value,
+            global,
+        }
+    }
+
+    #[test]
+    fn test_write_header() {
+        let executable = Executable {
+            text: vec![0x4e, 0x75],
+            data: vec![1, 2, 3, 4],
+            bss_size: 16,
+            ..Default::default()
+        };
+        let bytes = write(&executable).unwrap();
+
+        assert_eq!(bytes.len(), HEADER_SIZE + 2 + 4 + 4);
+        assert_eq!(
+            &bytes[..HEADER_SIZE],
+            &[
+                0x60, 0x1a, // ph_branch
+                0, 0, 0, 2, // ph_tlen
+                0, 0, 0, 4, // ph_dlen
+                0, 0, 0, 16, // ph_blen
+                0, 0, 0, 0, // ph_slen
+                0, 0, 0, 0, // ph_res1
+                0, 0, 0, 0, // ph_prgflags
+                0, 0, // ph_absflag
+            ]
+        );
+        assert_eq!(
+            &bytes[HEADER_SIZE..HEADER_SIZE + 6],
+            &[0x4e, 0x75, 1, 2, 3, 4]
+        );
+        assert_eq!(&bytes[HEADER_SIZE + 6..], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_write_fixups() {
+        assert_eq!(write_fixups(&[]), Ok(vec![0, 0, 0, 0]));
+        assert_eq!(write_fixups(&[6]), Ok(vec![0, 0, 0, 6, 0]));
+        assert_eq!(write_fixups(&[10, 2, 6]), Ok(vec![0, 0, 0, 2, 4, 4, 0]));
+        assert_eq!(write_fixups(&[2, 2 + 254]), Ok(vec![0, 0, 0, 2, 254, 0]));
+        assert_eq!(
+            write_fixups(&[0x100, 0x100 + 254 + 254 + 8]),
+            Ok(vec![0, 0, 1, 0, 1, 1, 8, 0])
+        );
+    }
+
+    #[test]
+    fn test_write_invalid_fixups() {
+        assert_eq!(write_fixups(&[0, 4]), Err(WriteError::FixupAtStart));
+        assert_eq!(write_fixups(&[2, 3]), Err(WriteError::OddFixup(3)));
+        let executable = Executable {
+            text: vec![0; 8],
+            fixups: vec![6],
+            ..Default::default()
+        };
+        assert_eq!(write(&executable), Err(WriteError::FixupOutOfRange(6)));
+    }
+
+    #[test]
+    fn test_write_symbol_table() {
+        let executable = Executable {
+            text: vec![0; 8],
+            data: vec![0; 4],
+            bss_size: 4,
+            symbols: vec![
+                symbol("start", Section::Text, 2, true),
+                symbol("a_very_long_name", Section::Data, 0, false),
+                symbol("buffer", Section::Bss, 0, false),
+            ],
+            ..Default::default()
+        };
+        let bytes = write(&executable).unwrap();
+        let table = &bytes[HEADER_SIZE + 12..HEADER_SIZE + 12 + 4 * SYMBOL_SIZE];
+
+        assert_eq!(
+            read_long_word(&bytes, 14),
+            Some(4 * SYMBOL_SIZE as LongWord)
+        );
+        assert_eq!(&table[..8], b"start\0\0\0");
+        assert_eq!(read_word(table, 8), Some(0xa200));
+        assert_eq!(read_long_word(table, 10), Some(2));
+        assert_eq!(&table[14..22], b"a_very_l");
+        assert_eq!(read_word(table, 22), Some(0x8448));
+        assert_eq!(read_long_word(table, 24), Some(8));
+        assert_eq!(&table[28..42], b"ong_name\0\0\0\0\0\0");
+        assert_eq!(read_word(table, 50), Some(0x8100));
+        assert_eq!(read_long_word(table, 52), Some(12));
+    }
+
+    #[test]
+    fn test_load_extended_names() {
+        let executable = Executable {
+            text: vec![0x4e, 0x75],
+            symbols: vec![
+                symbol("routine1", Section::Text, 0, true),
+                symbol("routine1.loop", Section::Text, 0, false),
+                symbol("routine2.loop", Section::Text, 2, false),
+                symbol("exactly_22_characters_", Section::Text, 2, false),
+            ],
+            ..Default::default()
+        };
+        let program = load(&write(&executable).unwrap(), 0).unwrap();
+        assert_eq!(program.symbols, executable.symbols);
+
+        // Only 22 characters fit into both entries.
+        let mut executable = executable;
+        executable.symbols[3].name.push_str("and_more");
+        let bytes = write(&executable).unwrap();
+        assert_eq!(
+            load(&bytes, 0).unwrap().symbols[3].name,
+            "exactly_22_characters_"
+        );
+    }
+
+    #[test]
+    fn test_load_applies_fixups() {
+        // LEA data(PC),A0 is position-independent, but MOVE.L #data,D0 and DC.L data are not.
+        let executable = Executable {
+            text: vec![0x20, 0x3c, 0, 0, 0, 8, 0x4e, 0x75],
+            data: vec![0, 0, 0, 8],
+            bss_size: 2,
+            symbols: vec![symbol("data", Section::Data, 0, false)],
+            fixups: vec![2, 8],
+            flags: 0,
+        };
+        let program = load(&write(&executable).unwrap(), 0x1_0000).unwrap();
+
+        assert_eq!(program.text_start(), 0x1_0000);
+        assert_eq!(program.data_start(), 0x1_0008);
+        assert_eq!(program.bss_start(), 0x1_000c);
+        assert_eq!(
+            program.memory,
+            vec![0x20, 0x3c, 0, 1, 0, 8, 0x4e, 0x75, 0, 1, 0, 8, 0, 0]
+        );
+        assert_eq!(program.symbols, executable.symbols);
+    }
+
+    #[test]
+    fn test_load_skips_symbols_outside_of_the_program() {
+        let executable = Executable {
+            text: vec![0x4e, 0x75],
+            symbols: vec![symbol("start", Section::Text, 0, true)],
+            ..Default::default()
+        };
+        let mut bytes = write(&executable).unwrap();
+        let mut entries = vec![];
+        // An equate, an absolute address and a text symbol with an extended name.
+        for (name, symbol_type, value) in &[
+            (&b"count\0\0\0"[..], 0xc000, 16),
+            (&b"vbl_list"[..], 0xa000, 0x0456),
+            (&b"a_very_l"[..], 0x8248, 2),
+        ] {
+            entries.extend_from_slice(name);
+            write_word(&mut entries, *symbol_type);
+            write_long_word(&mut entries, *value);
+        }
+        entries.extend_from_slice(b"ong_name\0\0\0\0\0\0");
+        let table_end = HEADER_SIZE + 2 + SYMBOL_SIZE;
+        bytes.splice(table_end..table_end, entries);
+        bytes[14..18].copy_from_slice(&(5 * SYMBOL_SIZE as LongWord).to_be_bytes());
+        let program = load(&bytes, 0x1000).unwrap();
+
+        assert_eq!(
+            program.symbols,
+            vec![
+                symbol("start", Section::Text, 0, true),
+                symbol("a_very_long_name", Section::Text, 2, false),
+            ]
+        );
+
+        // The continuation of the extended name is missing.
+        bytes.splice(
+            table_end + 3 * SYMBOL_SIZE..table_end + 4 * SYMBOL_SIZE,
+            vec![],
+        );
+        bytes[14..18].copy_from_slice(&(4 * SYMBOL_SIZE as LongWord).to_be_bytes());
+        assert_eq!(load(&bytes, 0x1000), Err(LoadError::Truncated));
+    }
+
+    #[test]
+    fn test_load_far_apart_fixups() {
+        let mut text = vec![0; 0x400];
+        text[0x3fc..].copy_from_slice(&[0, 0, 0, 4]);
+        let executable = Executable {
+            text,
+            fixups: vec![2, 0x3fc],
+            ..Default::default()
+        };
+        let program = load(&write(&executable).unwrap(), 0x800).unwrap();
+
+        assert_eq!(read_long_word(&program.memory, 2), Some(0x800));
+        assert_eq!(read_long_word(&program.memory, 0x3fc), Some(0x804));
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        assert_eq!(load(&[], 0), Err(LoadError::Truncated));
+        assert_eq!(load(&[0x4e, 0x75], 0), Err(LoadError::InvalidMagic(0x4e75)));
+
+        let mut bytes = write(&Executable {
+            text: vec![0; 8],
+            fixups: vec![4],
+            ..Default::default()
+        })
+        .unwrap();
+        bytes[HEADER_SIZE + 11] = 6;
+        assert_eq!(load(&bytes, 0), Err(LoadError::FixupOutOfRange(6)));
+        bytes.pop();
+        assert_eq!(load(&bytes, 0), Err(LoadError::Truncated));
+    }
+
+    #[test]
+    fn test_load_crafted_headers() {
+        let header = |text: LongWord, data: LongWord, bss: LongWord, symbols: LongWord| {
+            let mut bytes = vec![0x60, 0x1a];
+            for size in &[text, data, bss, symbols, 0, 0] {
+                write_long_word(&mut bytes, *size);
+            }
+            write_word(&mut bytes, 0);
+            bytes
+        };
+        assert_eq!(
+            load(&header(LongWord::MAX, 2, 0, 0), 0),
+            Err(LoadError::TooLarge(0x1_0000_0001))
+        );
+        assert_eq!(
+            load(&header(0, 0, 0x0100_0001, 0), 0),
+            Err(LoadError::TooLarge(0x0100_0001))
+        );
+        assert_eq!(
+            load(&header(0, 0, 0, LongWord::MAX), 0),
+            Err(LoadError::Truncated)
+        );
+
+        // The fixups step past the end of the address space.
+        let mut bytes = header(4, 0, 0, 0);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0xfe, 1, 0]);
+        assert_eq!(
+            load(&bytes, 0),
+            Err(LoadError::FixupOutOfRange(0xffff_fffe))
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! Prints programs in any dialect. Parsing a source in one dialect and formatting it in another
+//! converts it between them.
+
+use crate::statements::*;
+use m68k_reloaded_scanner::keywords::Register;
+use m68k_reloaded_scanner::Dialect;
+
+/// Formats the program with one statement per line. Labels and comments start in the first column,
+/// operations and directives are indented by a tab.
+pub fn format(program: &Program, dialect: Dialect) -> String {
+    let mut source = String::new();
+    for statement in program {
+        source.push_str(&format_statement(&statement.value, dialect));
+        source.push('\n');
+    }
+    source
+}
+
+pub fn format_statement(statement: &Statement, dialect: Dialect) -> String {
+    match statement {
+        Statement::Label(label) if dialect == Dialect::Mit => format!("{}:", label),
+        Statement::Label(label) => label.to_string(),
+        Statement::Operation(operation) => format_operation(operation, dialect),
+        Statement::Directive(directive) => format_directive(directive, dialect),
+        Statement::Comment(comment) => format_comment(comment, dialect),
+    }
+}
+
+fn format_operation(operation: &Operation, dialect: Dialect) -> String {
+    let mut mnemonic = operation.operation_type.to_string();
+    if let Some(size) = &operation.size {
+        let is_branch = operation.operation_type.is_branch();
+        let size = match size.value {
+            Size::Byte if is_branch => "S",
+            Size::Byte => "B",
+            Size::Word => "W",
+            Size::LongWord => "L",
+        };
+        if dialect != Dialect::Mit {
+            mnemonic.push('.');
+        }
+        mnemonic.push_str(size);
+    }
+    if dialect == Dialect::Mit {
+        mnemonic.make_ascii_lowercase();
+    }
+    if operation.operands.is_empty() {
+        return format!("\t{}", mnemonic);
+    }
+    let operands: Vec<String> = operation
+        .operands
+        .iter()
+        .map(|operand| format_operand(operand, dialect))
+        .collect();
+    format!("\t{}\t{}", mnemonic, operands.join(","))
+}
+
+fn format_directive(directive: &Directive, dialect: Dialect) -> String {
+    let (name, labels) = match directive {
+        Directive::Section(section) => {
+            let section_type = match section.section_type.value {
+                SectionType::Code => "CODE",
+                SectionType::Data => "DATA",
+                SectionType::Bss => "BSS",
+            };
+            return match dialect {
+                Dialect::Mit => format!(
+                    "\t.section\t{},{}",
+                    section.name.value,
+                    section_type.to_ascii_lowercase()
+                ),
+                _ => format!("\tSECTION\t{},{}", section.name.value, section_type),
+            };
+        }
+        Directive::Org(address) => {
+            return match dialect {
+                Dialect::Mit => format!("\t.org\t0x{:x}", address.value),
+                _ => format!("\tORG\t${:X}", address.value),
+            };
+        }
+        Directive::Export(labels) => ("XDEF", labels),
+        Directive::Import(labels) => ("XREF", labels),
+        Directive::Global(labels) => ("GLOBAL", labels),
+    };
+    let name = match (dialect, name) {
+        (Dialect::Mit, "XDEF") => ".xdef",
+        (Dialect::Mit, "XREF") => ".extern",
+        (Dialect::Mit, _) => ".globl",
+        _ => name,
+    };
+    let labels: Vec<&str> = labels.iter().map(|label| label.value.as_str()).collect();
+    format!("\t{}\t{}", name, labels.join(","))
+}
+
+/// Comments keep their text, but get the comment character of the dialect.
+fn format_comment(comment: &str, dialect: Dialect) -> String {
+    let original = comment
+        .chars()
+        .next()
+        .filter(|marker| ";*|#".contains(*marker));
+    let marker = match (dialect, original) {
+        (Dialect::Mit, Some(marker @ ('|' | '#'))) => marker,
+        (Dialect::Mit, _) => '|',
+        (_, Some(marker @ (';' | '*'))) => marker,
+        _ => ';',
+    };
+    match original {
+        Some(original) => format!("{}{}", marker, &comment[original.len_utf8()..]),
+        // Comments after the operands don't need a character in some dialects.
+        None => format!("{} {}", marker, comment),
+    }
+}
+
+pub fn format_operand(operand: &Operand, dialect: Dialect) -> String {
+    // Devpac only knows the old form of displacements, like `8(A0)`.
+    let displaced = |displacement: String, base: String, index: Option<&Stmt<Index>>| {
+        let index = index.map(|index| format_index(index, dialect));
+        match (dialect, index) {
+            (Dialect::Mit, None) => format!("{}@({})", base, displacement),
+            (Dialect::Mit, Some(index)) => format!("{}@({},{})", base, displacement, index),
+            (Dialect::Devpac, None) => format!("{}({})", displacement, base),
+            (Dialect::Devpac, Some(index)) => format!("{}({},{})", displacement, base, index),
+            (_, None) => format!("({},{})", displacement, base),
+            (_, Some(index)) => format!("({},{},{})", displacement, base, index),
+        }
+    };
+    let address = |an: &An| register(Register::An(an.index), dialect);
+    let pc = || register(Register::Pc, dialect);
+    match operand {
+        EffectiveAddress::Dn(dn) => register(Register::Dn(dn.index), dialect),
+        EffectiveAddress::An(an) => address(an),
+        EffectiveAddress::AnInd(an) if dialect == Dialect::Mit => {
+            format!("{}@", address(an))
+        }
+        EffectiveAddress::AnInd(an) => format!("({})", address(an)),
+        EffectiveAddress::AnIndWithPostInc(an) if dialect == Dialect::Mit => {
+            format!("{}@+", address(an))
+        }
+        EffectiveAddress::AnIndWithPostInc(an) => format!("({})+", address(an)),
+        EffectiveAddress::AnIndWithPreDec(an) if dialect == Dialect::Mit => {
+            format!("{}@-", address(an))
+        }
+        EffectiveAddress::AnIndWithPreDec(an) => format!("-({})", address(an)),
+        EffectiveAddress::AnIndWithDisplacement(displacement, an) => {
+            displaced((displacement.value as i16).to_string(), address(an), None)
+        }
+        EffectiveAddress::AnIndWithIndex(displacement, an, index) => displaced(
+            (displacement.value as i8).to_string(),
+            address(an),
+            Some(index),
+        ),
+        EffectiveAddress::AbsoluteWord(address) => {
+            format_absolute(address.value.into(), 'W', dialect)
+        }
+        EffectiveAddress::AbsoluteLongWord(address) => format_absolute(address.value, 'L', dialect),
+        EffectiveAddress::PcIndWithDisplacement(displacement) => {
+            displaced((displacement.value as i16).to_string(), pc(), None)
+        }
+        EffectiveAddress::PcIndWithIndex(displacement, index) => {
+            displaced((displacement.value as i8).to_string(), pc(), Some(index))
+        }
+        EffectiveAddress::Immediate(value) => format!("#{}", value.value),
+        EffectiveAddress::Label(label) => label.value.to_string(),
+    }
+}
+
+/// Absolute addresses always get their size, so that they are parsed the same way again.
+fn format_absolute(address: LongWord, size: char, dialect: Dialect) -> String {
+    match dialect {
+        Dialect::Mit => format!("0x{:x}:{}", address, size.to_ascii_lowercase()),
+        _ => format!("${:X}.{}", address, size),
+    }
+}
+
+fn format_index(index: &Index, dialect: Dialect) -> String {
+    let mut formatted = match &index.register {
+        Xn::An(an) => register(Register::An(an.index), dialect),
+        Xn::Dn(dn) => register(Register::Dn(dn.index), dialect),
+    };
+    if let Some(size) = &index.size {
+        formatted.push(if dialect == Dialect::Mit { ':' } else { '.' });
+        formatted.push(match (size.value, dialect) {
+            (Size::Byte, Dialect::Mit) => 'b',
+            (Size::Word, Dialect::Mit) => 'w',
+            (Size::LongWord, Dialect::Mit) => 'l',
+            (Size::Byte, _) => 'B',
+            (Size::Word, _) => 'W',
+            (Size::LongWord, _) => 'L',
+        });
+    }
+    formatted
+}
+
+/// Registers like `D0`, or `%d0` in the MIT dialect.
+fn register(register: Register, dialect: Dialect) -> String {
+    match dialect {
+        Dialect::Mit => format!("%{}", register.to_string().to_ascii_lowercase()),
+        _ => register.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_dialect;
+    use m68k_reloaded_scanner::keywords::{has_operands, register, MNEMONICS};
+    use m68k_reloaded_scanner::scan_dialect;
+    use proptest::prelude::*;
+
+    fn convert(source: &str, from: Dialect, to: Dialect) -> String {
+        let mut errors = vec![];
+        let tokens = scan_dialect(source, from, &mut errors).collect();
+        let program = parse_dialect(tokens, from, &mut errors);
+        assert!(errors.is_empty(), "Parsing {} failed.", source);
+        format(&program, to)
+    }
+
+    #[test]
+    fn test_format_converts_between_dialects() {
+        let source = "main
+; Copies the table.
+\tXDEF\tmain
+\tSECTION\ttables,DATA
+\tMOVE.L\t#$8000,D0
+\tLEA\t(8,A0),A1
+\tMOVE.W\t(A0)+,-(A1)
+\tADD.B\t(-1,A0,D1.W),D2
+\tMOVE.L\t($FFFF8240).W,(4,PC,A2.L)
+\tBNE.S\tmain
+\tRTS
+";
+        let motorola = convert(source, Dialect::Motorola, Dialect::Motorola);
+        assert_eq!(
+            convert(source, Dialect::Motorola, Dialect::Mit),
+            "main:
+| Copies the table.
+\t.xdef\tmain
+\t.section\ttables,data
+\tmovel\t#32768,%d0
+\tlea\t%a0@(8),%a1
+\tmovew\t%a0@+,%a1@-
+\taddb\t%a0@(-1,%d1:w),%d2
+\tmovel\t0x8240:w,%pc@(4,%a2:l)
+\tbnes\tmain
+\trts
+"
+        );
+        assert!(convert(source, Dialect::Motorola, Dialect::Devpac).contains("\tLEA\t8(A0),A1\n"));
+        for dialect in Dialect::ALL.iter() {
+            let converted = convert(source, Dialect::Motorola, *dialect);
+            assert_eq!(
+                convert(&converted, *dialect, Dialect::Motorola),
+                motorola,
+                "{:?}",
+                dialect
+            );
+        }
+    }
+
+    fn stmt<T>(value: T) -> Stmt<T> {
+        Stmt { range: 0..0, value }
+    }
+
+    /// Labels, including ones named like mnemonics with or without an MIT size suffix, like `rts`
+    /// or `.movel`.
+    fn label() -> impl Strategy<Value = Label> {
+        let mnemonic_like = (
+            "\\.?",
+            proptest::sample::select(MNEMONICS),
+            "[bwlsBWLS]?",
+            any::<bool>(),
+        )
+            .prop_map(|(dot, mnemonic, suffix, is_lower)| {
+                let label = format!("{}{}{}", dot, mnemonic, suffix);
+                if is_lower {
+                    label.to_ascii_lowercase()
+                } else {
+                    label
+                }
+            });
+        prop_oneof![
+            3 => "\\.?l_[a-z0-9_]{0,6}",
+            1 => mnemonic_like,
+        ]
+    }
+
+    /// Labels named like registers, which the parser reports and skips.
+    const REGISTER_LABEL: &str = "[aAdD][0-7]|[sS][pP]|[pP][cC]|[sS][rR]|[cC][cC][rR]|[uU][sS][pP]";
+
+    fn size() -> impl Strategy<Value = Size> {
+        prop_oneof![Just(Size::Byte), Just(Size::Word), Just(Size::LongWord)]
+    }
+
+    fn index() -> impl Strategy<Value = Index> {
+        (any::<bool>(), 0..8u8, proptest::option::of(size())).prop_map(|(is_an, index, size)| {
+            Index {
+                register: if is_an {
+                    Xn::An(stmt(An { index }))
+                } else {
+                    Xn::Dn(stmt(Dn { index }))
+                },
+                size: size.map(stmt),
+            }
+        })
+    }
+
+    fn operand() -> impl Strategy<Value = Operand> {
+        let an = || (0..8u8).prop_map(|index| stmt(An { index }));
+        prop_oneof![
+            (0..8u8).prop_map(|index| EffectiveAddress::Dn(stmt(Dn { index }))),
+            an().prop_map(EffectiveAddress::An),
+            an().prop_map(EffectiveAddress::AnInd),
+            an().prop_map(EffectiveAddress::AnIndWithPostInc),
+            an().prop_map(EffectiveAddress::AnIndWithPreDec),
+            (any::<Word>(), an()).prop_map(|(displacement, an)| {
+                EffectiveAddress::AnIndWithDisplacement(stmt(displacement), an)
+            }),
+            (any::<Byte>(), an(), index()).prop_map(|(displacement, an, index)| {
+                EffectiveAddress::AnIndWithIndex(stmt(displacement), an, stmt(index))
+            }),
+            any::<Word>().prop_map(|address| EffectiveAddress::AbsoluteWord(stmt(address))),
+            any::<LongWord>().prop_map(|address| EffectiveAddress::AbsoluteLongWord(stmt(address))),
+            any::<Word>().prop_map(|displacement| {
+                EffectiveAddress::PcIndWithDisplacement(stmt(displacement))
+            }),
+            (any::<Byte>(), index()).prop_map(|(displacement, index)| {
+                EffectiveAddress::PcIndWithIndex(stmt(displacement), stmt(index))
+            }),
+            any::<LongWord>().prop_map(|value| EffectiveAddress::Immediate(stmt(value))),
+            label().prop_map(|label| EffectiveAddress::Label(stmt(label))),
+        ]
+    }
+
+    fn operation_type() -> impl Strategy<Value = OperationType> {
+        use Condition::*;
+        use OperationType::*;
+        let conditions = [Hi, Ls, Cc, Cs, Ne, Eq, Vc, Vs, Pl, Mi, Ge, Lt, Gt, Le];
+        let mut types = vec![
+            Add, Adda, Addi, Addq, Addx, Bra, Bsr, Chk, Clr, Divs, Divu, Jsr, Lea, Move, Moveq,
+            Nop, Rte, Rts, Stop, Trap, Trapv,
+        ];
+        types.extend(conditions.iter().map(|condition| Bcc(*condition)));
+        proptest::sample::select(types)
+    }
+
+    fn statement() -> impl Strategy<Value = Statement> {
+        let labels = || proptest::collection::vec(label().prop_map(stmt), 1..4);
+        let section_type = prop_oneof![
+            Just(SectionType::Code),
+            Just(SectionType::Data),
+            Just(SectionType::Bss)
+        ];
+        prop_oneof![
+            label().prop_map(Statement::Label),
+            REGISTER_LABEL.prop_map(Statement::Label),
+            (
+                operation_type(),
+                proptest::option::of(size()),
+                proptest::collection::vec(operand(), 0..3)
+            )
+                .prop_map(|(operation_type, size, operands)| {
+                    // Some dialects read operands of operations without them as comments.
+                    let operands = if has_operands(&operation_type.to_string()) {
+                        operands.into_iter().map(stmt).collect()
+                    } else {
+                        vec![]
+                    };
+                    Statement::Operation(Operation {
+                        operation_type: stmt(operation_type),
+                        size: size.map(stmt),
+                        operands,
+                    })
+                }),
+            (label(), section_type).prop_map(|(name, section_type)| {
+                Statement::Directive(Directive::Section(Section {
+                    name: stmt(name),
+                    section_type: stmt(section_type),
+                }))
+            }),
+            any::<LongWord>()
+                .prop_map(|address| Statement::Directive(Directive::Org(stmt(address)))),
+            labels().prop_map(|labels| Statement::Directive(Directive::Export(labels))),
+            labels().prop_map(|labels| Statement::Directive(Directive::Import(labels))),
+            labels().prop_map(|labels| Statement::Directive(Directive::Global(labels))),
+            ";[ -~]*".prop_map(Statement::Comment),
+        ]
+    }
+
+    proptest! {
+        /// Formatting a program in any dialect and parsing it again results in the same program,
+        /// besides the ranges and the labels named like registers, which are reported instead.
+        /// The Motorola formatting of both is compared, because it contains everything but the
+        /// ranges.
+        #[test]
+        fn test_format_round_trips(
+            program in proptest::collection::vec(statement().prop_map(stmt), 0..20),
+            dialect in 0..4usize,
+        ) {
+            let dialect = Dialect::ALL[dialect];
+            let source = format(&program, dialect);
+            let mut errors = vec![];
+            let tokens = scan_dialect(&source, dialect, &mut errors).collect();
+            let parsed = parse_dialect(tokens, dialect, &mut errors);
+            let is_register_label = |statement: &Stmt<Statement>| {
+                matches!(&statement.value, Statement::Label(label) if register(label).is_some())
+            };
+            let codes: Vec<_> = errors.iter().map(|error| error.code).collect();
+            let register_labels = program.iter().filter(|statement| is_register_label(statement));
+            prop_assert_eq!(
+                codes,
+                vec!["label_shadows_register"; register_labels.count()],
+                "{}",
+                source
+            );
+            let mut expected = program;
+            expected.retain(|statement| !is_register_label(statement));
+            prop_assert_eq!(
+                format(&parsed, Dialect::Motorola),
+                format(&expected, Dialect::Motorola)
+            );
+        }
+    }
+}
@@ -1,2 +1,5 @@
+pub mod format;
 pub mod parse;
+pub mod sections;
 pub mod statements;
+pub mod symbols;
@@ -1,32 +1,1395 @@
-// use crate::statements::*;
-// use m68k_reloaded_common::errors::{Error, ErrorCollector};
-// use m68k_reloaded_common::{Cursored, Range};
-// use m68k_reloaded_scanner::Token;
-
-// pub fn parse(tokens: Vec<Token>, errors: &mut ErrorCollector) -> Parser {
-//     let tokens = tokens
-//         .into_iter()
-//         .filter(|token| !matches!(token, Token::Whitespace(_)))
-//         .collect();
-//     Parser::from_string(tokens, errors)
-// }
-
-// type Parser<'e> = CursorParser<'e>;
-
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     use m68k_reloaded_scanner::scan;
-
-//     #[test]
-//     fn test_parse() {
-//         let source = "MOVE.W D3, D6";
-//         let mut errors = vec![];
-
-//         let tokens: Vec<Token> = scan(source, &mut errors).collect();
-//         let statements: Vec<Statement> = parse(&tokens, &mut errors).collect();
-//         for statement in &statements {
-//             println!("Got statement {:?}.", statement);
-//         }
-//     }
-// }
+use crate::statements::*;
+use m68k_reloaded_common::cursor::CursorParser;
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use m68k_reloaded_common::Range;
+use m68k_reloaded_scanner::keywords::{Register, SizeSuffix};
+use m68k_reloaded_scanner::{Dialect, Token, TokenKind};
+
+/// Parses the tokens into a program. Lines that can't be parsed are skipped after registering an
+/// error, so a single typo doesn't hide the errors in the rest of the program.
+pub fn parse(tokens: Vec<Token>, errors: &mut ErrorCollector) -> Program {
+    parse_dialect(tokens, Dialect::Motorola, errors)
+}
+
+/// Parses tokens that were scanned in the dialect. All dialects result in the same statements.
+pub fn parse_dialect(tokens: Vec<Token>, dialect: Dialect, errors: &mut ErrorCollector) -> Program {
+    let mut parser = Parser {
+        tokens: CursorParser::new(&tokens, errors),
+        dialect,
+    };
+    let mut program = vec![];
+    while !parser.tokens.is_done() {
+        parser.parse_line(&mut program);
+    }
+    program
+}
+
+/// Parsing functions register an error before they return `None`, so the callers just pass the
+/// failure on.
+struct Parser<'a, 'e> {
+    tokens: CursorParser<'a, 'e, Token<'a>>,
+    dialect: Dialect,
+}
+
+/// One way to parse something, tried by [Parser::first_of].
+type Alternative<'f, P, R> = &'f dyn Fn(&mut P) -> Option<R>;
+
+impl Parser<'_, '_> {
+    fn parse_line(&mut self, program: &mut Program) {
+        if self.parse_statements(program).is_none() {
+            self.tokens
+                .advance_while(|token| !matches!(token, Token::Newline(_)));
+        }
+        self.advance(TokenKind::Newline);
+    }
+
+    /// Parses a line of the form `[label[:]] [operation] [comment]`. Labels either start in the
+    /// first column or end with a colon. Local labels like `.loop` or `1$` can also be indented
+    /// without a colon, because operations never look like that. Except in the MIT dialect, where
+    /// directives like `.text` do.
+    fn parse_statements(&mut self, program: &mut Program) -> Option<()> {
+        if let Some((range, name)) = self.advance_identifier() {
+            self.push_label(program, range, name);
+        } else {
+            self.skip_register_label(false);
+        }
+        self.skip_whitespace();
+
+        if self.skip_register_label(true) {
+            self.skip_whitespace();
+        }
+        if let Some((range, name)) = self.advance_identifier() {
+            let is_label = (LabelScope::of(&name).is_local() && self.dialect != Dialect::Mit)
+                || self.tokens.is_at(TokenKind::Colon);
+            if is_label {
+                self.push_label(program, range, name);
+                self.skip_whitespace();
+                if let Some((range, name)) = self.advance_identifier() {
+                    program.push(self.parse_operation_or_directive(range, name)?);
+                }
+            } else {
+                program.push(self.parse_operation_or_directive(range, name)?);
+            }
+        }
+        self.skip_whitespace();
+
+        if let Some(Token::Comment(range, comment)) = self.tokens.peek() {
+            self.tokens.advance();
+            program.push(Stmt {
+                range: range.clone(),
+                value: Statement::Comment(comment.to_string()),
+            });
+        }
+        if self.tokens.is_done() || self.tokens.is_at(TokenKind::Newline) {
+            Some(())
+        } else {
+            self.expected("the end of the line")
+        }
+    }
+
+    /// Labels can't have the name of a register, because operands using them would be ambiguous.
+    /// Registers the error and skips such a label, which is the case if it's followed by a colon
+    /// or doesn't need one. Returns whether it skipped a label.
+    fn skip_register_label(&mut self, needs_colon: bool) -> bool {
+        let (range, name, register) = match self.tokens.peek() {
+            Some(Token::Register(range, name, register)) => (range.clone(), *name, *register),
+            _ => return false,
+        };
+        let has_colon = matches!(self.tokens.peek_nth(1), Some(Token::Colon(_)));
+        if needs_colon && !has_colon {
+            return false;
+        }
+        self.tokens.advance_n(if has_colon { 2 } else { 1 });
+        self.tokens.register(Error::label_shadows_register(
+            range,
+            name,
+            &register.to_string(),
+        ));
+        true
+    }
+
+    /// Adds a label, including an optional colon after it.
+    fn push_label(&mut self, program: &mut Program, range: Range, name: Label) {
+        let range = match self.advance(TokenKind::Colon) {
+            Some(colon) => range.start..colon.end,
+            None => range,
+        };
+        program.push(Stmt {
+            range,
+            value: Statement::Label(name),
+        });
+    }
+
+    fn parse_operation_or_directive(
+        &mut self,
+        range: Range,
+        name: String,
+    ) -> Option<Stmt<Statement>> {
+        let operation_type = operation_type(&name);
+        let size = self.parse_size(operation_type.is_some_and(OperationType::is_branch))?;
+        // The MIT dialect writes directives with a dot, like `.text`.
+        let name = match self.dialect {
+            Dialect::Mit if operation_type.is_none() => {
+                name.strip_prefix('.').map(str::to_string).unwrap_or(name)
+            }
+            _ => name,
+        };
+        let section_type = match name.to_uppercase().as_str() {
+            "SECTION" => return self.parse_section(range),
+            "ORG" => return self.parse_org(range),
+            "TEXT" | "DATA" | "BSS" => self.section_type(&name),
+            _ if self.dialect.has_memory_types() => self.section_type(&name),
+            _ => None,
+        };
+        if let Some(directive) = self.parse_visibility(&name)? {
+            let end = match &directive {
+                Directive::Export(labels)
+                | Directive::Import(labels)
+                | Directive::Global(labels) => labels.last().unwrap().range.end,
+                Directive::Section(_) | Directive::Org(_) => unreachable!(),
+            };
+            return Some(Stmt {
+                range: range.start..end,
+                value: Statement::Directive(directive),
+            });
+        }
+        if let Some(section_type) = section_type {
+            return Some(Stmt {
+                range: range.clone(),
+                value: Statement::Directive(Directive::Section(Section {
+                    name: Stmt {
+                        range: range.clone(),
+                        value: name,
+                    },
+                    section_type: Stmt {
+                        range,
+                        value: section_type,
+                    },
+                })),
+            });
+        }
+
+        let operation_type = match operation_type {
+            Some(operation_type) => operation_type,
+            None => {
+                self.tokens.register(Error::unknown_operation(range, &name));
+                return None;
+            }
+        };
+        let operands = self.parse_operands()?;
+        let end = operands
+            .last()
+            .map(|operand| operand.range.end)
+            .or_else(|| size.as_ref().map(|size| size.range.end))
+            .unwrap_or(range.end);
+        Some(Stmt {
+            range: range.start..end,
+            value: Statement::Operation(Operation {
+                operation_type: Stmt {
+                    range,
+                    value: operation_type,
+                },
+                size,
+                operands,
+            }),
+        })
+    }
+
+    /// Parses the `.B`, `.W` or `.L` suffix of an operation. Branches also accept `.S`.
+    fn parse_size(&mut self, allow_short: bool) -> Option<Option<Stmt<Size>>> {
+        let (range, suffix) = match self.tokens.peek() {
+            Some(Token::Size(range, suffix)) => (range.clone(), *suffix),
+            // Sizes that the scanner doesn't know, like `.Q`.
+            Some(Token::Dot(_)) => {
+                self.tokens.advance();
+                let (range, name) = self.expect_identifier("a size")?;
+                self.tokens.register(Error::unknown_size(range, &name));
+                return None;
+            }
+            _ => return Some(None),
+        };
+        self.tokens.advance();
+        let size = match suffix {
+            SizeSuffix::Byte => Size::Byte,
+            SizeSuffix::Short if allow_short => Size::Byte,
+            SizeSuffix::Short => {
+                self.tokens
+                    .register(Error::unknown_size(range.end - 1..range.end, "S"));
+                return None;
+            }
+            SizeSuffix::Word => Size::Word,
+            SizeSuffix::LongWord => Size::LongWord,
+        };
+        Some(Some(Stmt { range, value: size }))
+    }
+
+    /// Parses `SECTION name[,type]`. If the type is omitted, the name has to be one of the types.
+    /// Dialects with memory types also accept `SECTION name,type,memory`.
+    fn parse_section(&mut self, directive: Range) -> Option<Stmt<Statement>> {
+        self.skip_whitespace();
+        let (name_range, name) = self.expect_identifier("a section name")?;
+        let (type_range, type_name) = if self.advance(TokenKind::Comma).is_some() {
+            self.skip_whitespace();
+            self.expect_identifier("a section type")?
+        } else {
+            (name_range.clone(), name.clone())
+        };
+        let section_type = match self.section_type(&type_name) {
+            Some(section_type) => section_type,
+            None => {
+                self.tokens
+                    .register(Error::unknown_section_type(type_range, &type_name));
+                return None;
+            }
+        };
+        let mut end = type_range.end;
+        if self.dialect.has_memory_types() && self.advance(TokenKind::Comma).is_some() {
+            self.skip_whitespace();
+            end = self.expect_identifier("a memory type")?.0.end;
+        }
+        Some(Stmt {
+            range: directive.start..end,
+            value: Statement::Directive(Directive::Section(Section {
+                name: Stmt {
+                    range: name_range,
+                    value: name,
+                },
+                section_type: Stmt {
+                    range: type_range,
+                    value: section_type,
+                },
+            })),
+        })
+    }
+
+    /// Parses `ORG address`.
+    fn parse_org(&mut self, directive: Range) -> Option<Stmt<Statement>> {
+        self.skip_whitespace();
+        let (range, address) = self.expect_number()?;
+        Some(Stmt {
+            range: directive.start..range.end,
+            value: Statement::Directive(Directive::Org(Stmt {
+                range,
+                value: address as LongWord,
+            })),
+        })
+    }
+
+    /// The type of a section like `CODE`. Dialects with memory types also accept types like
+    /// `CODE_C`, but the memory type is ignored, because the targets don't have chip memory.
+    fn section_type(&self, name: &str) -> Option<SectionType> {
+        let name = name.to_uppercase();
+        let name = match name.rsplit_once('_') {
+            Some((name, "C" | "F" | "P")) if self.dialect.has_memory_types() => name,
+            _ => &name,
+        };
+        match name {
+            "CODE" | "TEXT" => Some(SectionType::Code),
+            "DATA" => Some(SectionType::Data),
+            "BSS" => Some(SectionType::Bss),
+            _ => None,
+        }
+    }
+
+    /// Parses `XDEF`, `XREF`, `GLOBAL` and `PUBLIC` with their labels. The MIT dialect calls them
+    /// `.xdef`, `.extern` and `.globl`.
+    fn parse_visibility(&mut self, name: &str) -> Option<Option<Directive>> {
+        Some(Some(match name.to_uppercase().as_str() {
+            "XDEF" => Directive::Export(self.parse_labels()?),
+            "XREF" | "EXTERN" => Directive::Import(self.parse_labels()?),
+            "GLOBAL" | "GLOBL" | "PUBLIC" => Directive::Global(self.parse_labels()?),
+            _ => return Some(None),
+        }))
+    }
+
+    /// Parses a comma-separated list of at least one label.
+    fn parse_labels(&mut self) -> Option<Vec<Stmt<Label>>> {
+        self.skip_whitespace();
+        let mut labels = vec![];
+        loop {
+            if let Some(Token::Register(range, name, register)) = self.tokens.peek() {
+                self.tokens.register(Error::label_shadows_register(
+                    range.clone(),
+                    name,
+                    &register.to_string(),
+                ));
+                return None;
+            }
+            let (range, name) = self.expect_identifier("a label")?;
+            labels.push(Stmt { range, value: name });
+            if self.advance(TokenKind::Comma).is_none() {
+                break Some(labels);
+            }
+            self.skip_whitespace();
+        }
+    }
+
+    fn parse_operands(&mut self) -> Option<Vec<Stmt<Operand>>> {
+        self.skip_whitespace();
+        let mut operands = vec![];
+        if matches!(
+            self.tokens.peek(),
+            None | Some(Token::Newline(_)) | Some(Token::Comment(_, _))
+        ) {
+            return Some(operands);
+        }
+        loop {
+            operands.push(self.parse_operand()?);
+            if self.advance(TokenKind::Comma).is_none() {
+                break Some(operands);
+            }
+            self.skip_whitespace();
+        }
+    }
+
+    fn parse_operand(&mut self) -> Option<Stmt<Operand>> {
+        match self.tokens.peek() {
+            Some(Token::NumberSign(sign)) => {
+                self.tokens.advance();
+                let (range, value) = self.parse_number()?;
+                let value = self.tokens.check(to_long_word(range.clone(), value))?;
+                Some(Stmt {
+                    range: sign.start..range.end,
+                    value: EffectiveAddress::Immediate(Stmt { range, value }),
+                })
+            }
+            Some(Token::Register(range, _, register)) => {
+                self.tokens.advance();
+                let range = range.clone();
+                if let Some(at) = self.advance(TokenKind::At) {
+                    return self.parse_mit_indirect(range, *register, at);
+                }
+                let value = match *register {
+                    Register::An(index) => EffectiveAddress::An(Stmt {
+                        range: range.clone(),
+                        value: An { index },
+                    }),
+                    Register::Dn(index) => EffectiveAddress::Dn(Stmt {
+                        range: range.clone(),
+                        value: Dn { index },
+                    }),
+                    _ => {
+                        self.tokens
+                            .register(Error::unexpected_token(range, "an operand"));
+                        return None;
+                    }
+                };
+                Some(Stmt { range, value })
+            }
+            Some(Token::Identifier(range, name)) | Some(Token::Mnemonic(range, name)) => {
+                self.tokens.advance();
+                Some(Stmt {
+                    range: range.clone(),
+                    value: EffectiveAddress::Label(Stmt {
+                        range: range.clone(),
+                        value: name.to_string(),
+                    }),
+                })
+            }
+            Some(Token::OpeningParen(paren)) => {
+                self.tokens.advance();
+                self.parse_indirect(paren.clone())
+            }
+            Some(Token::Minus(minus)) => {
+                self.tokens.advance();
+                match self.advance(TokenKind::OpeningParen) {
+                    Some(_) => {
+                        let an = self.expect_an()?;
+                        let paren = self.expect(TokenKind::ClosingParen, "')'")?;
+                        Some(Stmt {
+                            range: minus.start..paren.end,
+                            value: EffectiveAddress::AnIndWithPreDec(an),
+                        })
+                    }
+                    None => {
+                        let (range, value) = self.expect_number()?;
+                        self.parse_number_operand(minus.start..range.end, -value)
+                    }
+                }
+            }
+            Some(Token::Number(_, _)) => {
+                let (range, value) = self.parse_number()?;
+                self.parse_number_operand(range, value)
+            }
+            _ => self.expected("an operand"),
+        }
+    }
+
+    /// Parses the rest of an operand after its opening parenthesis. That's either `(An)`, `(An)+`
+    /// or `(An,Xn)`, a displacement like `(d16,An)` or an absolute address like `(xxx).W`. They
+    /// are tried one after another.
+    fn parse_indirect(&mut self, paren: Range) -> Option<Stmt<Operand>> {
+        let start = paren.start;
+        self.first_of(&[
+            &|parser: &mut Self| parser.parse_register_indirect(paren.clone()),
+            &|parser: &mut Self| {
+                let (range, value) = parser.parse_number()?;
+                parser.expect(TokenKind::Comma, "','")?;
+                parser.parse_base(start, range, value)
+            },
+            &|parser: &mut Self| {
+                let (range, value) = parser.parse_number()?;
+                let end = parser.expect(TokenKind::ClosingParen, "')'")?;
+                let absolute = parser.parse_absolute(range, value)?;
+                let end = absolute.range.end.max(end.end);
+                Some(Stmt {
+                    range: start..end,
+                    value: absolute.value,
+                })
+            },
+        ])
+    }
+
+    /// Parses the rest of `(An)`, `(An)+` or `(An,Xn)` after the opening parenthesis.
+    fn parse_register_indirect(&mut self, paren: Range) -> Option<Stmt<Operand>> {
+        let an = self.expect_an()?;
+        if self.advance(TokenKind::Comma).is_some() {
+            let xn = self.expect_index()?;
+            let end = self.expect(TokenKind::ClosingParen, "')'")?;
+            let displacement = Stmt {
+                range: paren.clone(),
+                value: 0,
+            };
+            return Some(Stmt {
+                range: paren.start..end.end,
+                value: EffectiveAddress::AnIndWithIndex(displacement, an, xn),
+            });
+        }
+        let end = self.expect(TokenKind::ClosingParen, "')'")?;
+        match self.advance(TokenKind::Plus) {
+            Some(plus) => Some(Stmt {
+                range: paren.start..plus.end,
+                value: EffectiveAddress::AnIndWithPostInc(an),
+            }),
+            None => Some(Stmt {
+                range: paren.start..end.end,
+                value: EffectiveAddress::AnInd(an),
+            }),
+        }
+    }
+
+    /// Parses an operand that starts with a number: `d16(An)`, `d8(An,Xn)`, `d16(PC)`,
+    /// `d8(PC,Xn)` or an absolute address with an optional `.W` or `.L` suffix.
+    fn parse_number_operand(&mut self, range: Range, value: i64) -> Option<Stmt<Operand>> {
+        if self.advance(TokenKind::OpeningParen).is_none() {
+            return self.parse_absolute(range, value);
+        }
+        self.parse_base(range.start, range, value)
+    }
+
+    /// Parses the base register of a displacement with an optional index register and the
+    /// closing parenthesis, like `A0)` or `PC,D0.L)`. The operand starts at `start`.
+    fn parse_base(&mut self, start: usize, range: Range, value: i64) -> Option<Stmt<Operand>> {
+        let (base_range, base) = self.tokens.expect_map(
+            |token| match token {
+                Token::Register(range, _, register @ (Register::An(_) | Register::Pc)) => {
+                    Some((range.clone(), *register))
+                }
+                _ => None,
+            },
+            |range| Error::unexpected_token(range, "an address register or PC"),
+        )?;
+        let index = match self.advance(TokenKind::Comma) {
+            Some(_) => Some(self.expect_index()?),
+            None => None,
+        };
+        let end = self.expect(TokenKind::ClosingParen, "')'")?.end;
+        self.displaced(start..end, (base_range, base), range, value, index)
+    }
+
+    /// Parses the rest of an MIT operand after `An@` or `PC@`, which is `An@`, `An@+`, `An@-`,
+    /// `An@(d16)` or `An@(d8,Xn)`, or the last two with PC.
+    fn parse_mit_indirect(
+        &mut self,
+        base_range: Range,
+        base: Register,
+        at: Range,
+    ) -> Option<Stmt<Operand>> {
+        let start = base_range.start;
+        match base {
+            Register::An(index) if !self.tokens.is_at(TokenKind::OpeningParen) => {
+                let an = Stmt {
+                    range: base_range,
+                    value: An { index },
+                };
+                return Some(if let Some(plus) = self.advance(TokenKind::Plus) {
+                    Stmt {
+                        range: start..plus.end,
+                        value: EffectiveAddress::AnIndWithPostInc(an),
+                    }
+                } else if let Some(minus) = self.advance(TokenKind::Minus) {
+                    Stmt {
+                        range: start..minus.end,
+                        value: EffectiveAddress::AnIndWithPreDec(an),
+                    }
+                } else {
+                    Stmt {
+                        range: start..at.end,
+                        value: EffectiveAddress::AnInd(an),
+                    }
+                });
+            }
+            Register::An(_) | Register::Pc => {}
+            _ => {
+                self.tokens.register(Error::unexpected_token(
+                    base_range,
+                    "an address register or PC",
+                ));
+                return None;
+            }
+        }
+        self.expect(TokenKind::OpeningParen, "'('")?;
+        let (range, value) = self.parse_number()?;
+        let index = match self.advance(TokenKind::Comma) {
+            Some(_) => Some(self.expect_index()?),
+            None => None,
+        };
+        let end = self.expect(TokenKind::ClosingParen, "')'")?.end;
+        self.displaced(start..end, (base_range, base), range, value, index)
+    }
+
+    /// Combines a displacement, a base register and an optional index register into `d16(An)`,
+    /// `d8(An,Xn)`, `d16(PC)` or `d8(PC,Xn)`.
+    fn displaced(
+        &mut self,
+        operand: Range,
+        (base_range, base): (Range, Register),
+        range: Range,
+        value: i64,
+        index: Option<Stmt<Index>>,
+    ) -> Option<Stmt<Operand>> {
+        let value = match (base, index) {
+            (Register::An(index), None) => EffectiveAddress::AnIndWithDisplacement(
+                Stmt {
+                    range: range.clone(),
+                    value: self
+                        .tokens
+                        .check(to_displacement_word(range.clone(), value))?,
+                },
+                Stmt {
+                    range: base_range,
+                    value: An { index },
+                },
+            ),
+            (Register::An(an), Some(xn)) => EffectiveAddress::AnIndWithIndex(
+                Stmt {
+                    range: range.clone(),
+                    value: self
+                        .tokens
+                        .check(to_displacement_byte(range.clone(), value))?,
+                },
+                Stmt {
+                    range: base_range,
+                    value: An { index: an },
+                },
+                xn,
+            ),
+            (Register::Pc, None) => EffectiveAddress::PcIndWithDisplacement(Stmt {
+                range: range.clone(),
+                value: self
+                    .tokens
+                    .check(to_displacement_word(range.clone(), value))?,
+            }),
+            (Register::Pc, Some(xn)) => EffectiveAddress::PcIndWithIndex(
+                Stmt {
+                    range: range.clone(),
+                    value: self
+                        .tokens
+                        .check(to_displacement_byte(range.clone(), value))?,
+                },
+                xn,
+            ),
+            _ => unreachable!(),
+        };
+        Some(Stmt {
+            range: operand,
+            value,
+        })
+    }
+
+    /// Absolute addresses without a suffix are short if they can be sign-extended from a word.
+    fn parse_absolute(&mut self, range: Range, value: i64) -> Option<Stmt<Operand>> {
+        let size = self.parse_size(false)?;
+        let end = size
+            .as_ref()
+            .map(|size| size.range.end)
+            .unwrap_or(range.end);
+        let is_short = match size.map(|size| size.value) {
+            Some(Size::Word) => true,
+            Some(Size::LongWord) => false,
+            Some(Size::Byte) => {
+                self.tokens
+                    .register(Error::unknown_size(range.end..end, "B"));
+                return None;
+            }
+            None => {
+                (-0x8000..0x8000).contains(&value) || (0xffff_8000..=0xffff_ffff).contains(&value)
+            }
+        };
+        let value = if is_short {
+            EffectiveAddress::AbsoluteWord(Stmt {
+                range: range.clone(),
+                value: self.tokens.check(to_word(range.clone(), value))?,
+            })
+        } else {
+            EffectiveAddress::AbsoluteLongWord(Stmt {
+                range: range.clone(),
+                value: self.tokens.check(to_long_word(range.clone(), value))?,
+            })
+        };
+        Some(Stmt {
+            range: range.start..end,
+            value,
+        })
+    }
+
+    /// Parses a number with an optional minus in front of it.
+    fn parse_number(&mut self) -> Option<(Range, i64)> {
+        match self.advance(TokenKind::Minus) {
+            Some(minus) => {
+                let (range, value) = self.expect_number()?;
+                Some((minus.start..range.end, -value))
+            }
+            None => self.expect_number(),
+        }
+    }
+
+    fn expect_number(&mut self) -> Option<(Range, i64)> {
+        self.tokens.expect_map(
+            |token| match token {
+                Token::Number(range, value) => Some((range.clone(), i64::from(*value))),
+                _ => None,
+            },
+            |range| Error::unexpected_token(range, "a number"),
+        )
+    }
+
+    fn expect_an(&mut self) -> Option<Stmt<An>> {
+        self.tokens.expect_map(
+            |token| match token {
+                Token::Register(range, _, Register::An(index)) => Some(Stmt {
+                    range: range.clone(),
+                    value: An { index: *index },
+                }),
+                _ => None,
+            },
+            |range| Error::unexpected_token(range, "an address register"),
+        )
+    }
+
+    /// Parses an index register with an optional size, like `D0` or `A1.L`.
+    fn expect_index(&mut self) -> Option<Stmt<Index>> {
+        let register = self.tokens.expect_map(
+            |token| match token {
+                Token::Register(range, _, Register::An(index)) => Some(Xn::An(Stmt {
+                    range: range.clone(),
+                    value: An { index: *index },
+                })),
+                Token::Register(range, _, Register::Dn(index)) => Some(Xn::Dn(Stmt {
+                    range: range.clone(),
+                    value: Dn { index: *index },
+                })),
+                _ => None,
+            },
+            |range| Error::unexpected_token(range, "an index register"),
+        )?;
+        let range = match &register {
+            Xn::An(an) => an.range.clone(),
+            Xn::Dn(dn) => dn.range.clone(),
+        };
+        let size = self.parse_size(false)?;
+        let end = size
+            .as_ref()
+            .map(|size| size.range.end)
+            .unwrap_or(range.end);
+        Some(Stmt {
+            range: range.start..end,
+            value: Index { register, size },
+        })
+    }
+
+    fn expect_identifier(&mut self, expected: &str) -> Option<(Range, String)> {
+        self.tokens
+            .expect_map(identifier, |range| Error::unexpected_token(range, expected))
+    }
+
+    fn advance_identifier(&mut self) -> Option<(Range, String)> {
+        self.tokens.advance_if_map(identifier)
+    }
+
+    /// Consumes the next token if it has the kind and returns its range.
+    fn advance(&mut self, kind: TokenKind) -> Option<Range> {
+        self.tokens.advance_kind(kind).map(Token::range)
+    }
+
+    /// Like [Parser::advance], but registers an error if the next token has another kind.
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> Option<Range> {
+        self.tokens
+            .expect(kind, |range| Error::unexpected_token(range, expected))
+            .map(Token::range)
+    }
+
+    /// Registers that something else was expected at the next token.
+    fn expected<T>(&mut self, expected: &str) -> Option<T> {
+        let range = self.tokens.next_range();
+        self.tokens
+            .register(Error::unexpected_token(range, expected));
+        None
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.tokens
+            .advance_while(|token| matches!(token, Token::Whitespace(_)));
+    }
+
+    /// Tries the alternatives from the current position and returns the result of the first one
+    /// that succeeds. If all fail, only the errors of the one that got the furthest are kept.
+    fn first_of<R>(&mut self, alternatives: &[Alternative<Self, R>]) -> Option<R> {
+        let start = self.tokens.checkpoint();
+        let mut furthest: Option<(usize, Vec<Error>)> = None;
+        for alternative in alternatives {
+            if let Some(result) = alternative(self) {
+                return Some(result);
+            }
+            let reached = self.tokens.cursor();
+            let errors = self.tokens.take_errors_since(start);
+            self.tokens.restore(start);
+            if furthest
+                .as_ref()
+                .is_none_or(|(furthest, _)| reached > *furthest)
+            {
+                furthest = Some((reached, errors));
+            }
+        }
+        for error in furthest.map(|(_, errors)| errors).unwrap_or_default() {
+            self.tokens.register(error);
+        }
+        None
+    }
+}
+
+/// Mnemonics are identifiers too when they are used as labels.
+fn identifier(token: &Token) -> Option<(Range, String)> {
+    match token {
+        Token::Identifier(range, name) | Token::Mnemonic(range, name) => {
+            Some((range.clone(), name.to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn operation_type(name: &str) -> Option<OperationType> {
+    match name.to_uppercase().as_str() {
+        "ADD" => Some(OperationType::Add),
+        "ADDA" => Some(OperationType::Adda),
+        "ADDI" => Some(OperationType::Addi),
+        "ADDQ" => Some(OperationType::Addq),
+        "ADDX" => Some(OperationType::Addx),
+        "BRA" => Some(OperationType::Bra),
+        "BSR" => Some(OperationType::Bsr),
+        "CHK" => Some(OperationType::Chk),
+        "CLR" => Some(OperationType::Clr),
+        "DIVS" => Some(OperationType::Divs),
+        "DIVU" => Some(OperationType::Divu),
+        "JSR" => Some(OperationType::Jsr),
+        "LEA" => Some(OperationType::Lea),
+        "MOVE" => Some(OperationType::Move),
+        "MOVEQ" => Some(OperationType::Moveq),
+        "NOP" => Some(OperationType::Nop),
+        "RTE" => Some(OperationType::Rte),
+        "RTS" => Some(OperationType::Rts),
+        "STOP" => Some(OperationType::Stop),
+        "TRAP" => Some(OperationType::Trap),
+        "TRAPV" => Some(OperationType::Trapv),
+        name if name.starts_with('B') => condition(&name[1..]).map(OperationType::Bcc),
+        _ => None,
+    }
+}
+
+fn condition(name: &str) -> Option<Condition> {
+    Some(match name {
+        "HI" => Condition::Hi,
+        "LS" => Condition::Ls,
+        "CC" | "HS" => Condition::Cc,
+        "CS" | "LO" => Condition::Cs,
+        "NE" => Condition::Ne,
+        "EQ" => Condition::Eq,
+        "VC" => Condition::Vc,
+        "VS" => Condition::Vs,
+        "PL" => Condition::Pl,
+        "MI" => Condition::Mi,
+        "GE" => Condition::Ge,
+        "LT" => Condition::Lt,
+        "GT" => Condition::Gt,
+        "LE" => Condition::Le,
+        _ => return None,
+    })
+}
+
+fn to_long_word(range: Range, value: i64) -> Result<LongWord, Error> {
+    if (-0x8000_0000..=0xffff_ffff).contains(&value) {
+        Ok(value as LongWord)
+    } else {
+        Err(Error::number_out_of_range(range, -0x8000_0000, 0xffff_ffff))
+    }
+}
+
+fn to_word(range: Range, value: i64) -> Result<Word, Error> {
+    if (-0x8000..=0xffff).contains(&value) || (0xffff_8000..=0xffff_ffff).contains(&value) {
+        Ok(value as Word)
+    } else {
+        Err(Error::number_out_of_range(range, -0x8000, 0xffff))
+    }
+}
+
+fn to_displacement_word(range: Range, value: i64) -> Result<Word, Error> {
+    if (-0x8000..0x8000).contains(&value) {
+        Ok(value as Word)
+    } else {
+        Err(Error::number_out_of_range(range, -0x8000, 0x7fff))
+    }
+}
+
+fn to_displacement_byte(range: Range, value: i64) -> Result<Byte, Error> {
+    if (-0x80..0x80).contains(&value) {
+        Ok(value as Byte)
+    } else {
+        Err(Error::number_out_of_range(range, -0x80, 0x7f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::format_operand;
+    use m68k_reloaded_scanner::{scan, scan_dialect};
+    use proptest::prelude::*;
+
+    fn parse_source(source: &str) -> (Program, ErrorCollector) {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        (program, errors)
+    }
+
+    fn parse_operand(source: &str) -> EffectiveAddress {
+        let (mut program, errors) = parse_source(&format!(" ADD.W {},D0", source));
+        assert!(errors.is_empty(), "Parsing {} failed.", source);
+        match program.remove(0).value {
+            Statement::Operation(mut operation) => operation.operands.remove(0).value,
+            statement => panic!("Expected an operation, got {:?}.", statement),
+        }
+    }
+
+    fn stmt<T>(range: Range, value: T) -> Stmt<T> {
+        Stmt { range, value }
+    }
+
+    #[test]
+    fn test_parse_operation() {
+        let (program, errors) = parse_source(" add.l D3, a6");
+        assert!(errors.is_empty());
+        assert_eq!(
+            program,
+            vec![stmt(
+                1..13,
+                Statement::Operation(Operation {
+                    operation_type: stmt(1..4, OperationType::Add),
+                    size: Some(stmt(4..6, Size::LongWord)),
+                    operands: vec![
+                        stmt(7..9, EffectiveAddress::Dn(stmt(7..9, Dn { index: 3 }))),
+                        stmt(11..13, EffectiveAddress::An(stmt(11..13, An { index: 6 }))),
+                    ],
+                })
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_branches() {
+        let (program, errors) = parse_source(" BNE.S loop\n bra .next\n bhs.w $400");
+        assert!(errors.is_empty());
+        let operations: Vec<(OperationType, Option<Size>)> = program
+            .iter()
+            .map(|statement| match &statement.value {
+                Statement::Operation(operation) => (
+                    operation.operation_type.value,
+                    operation.size.as_ref().map(|size| size.value),
+                ),
+                statement => panic!("Expected an operation, got {:?}.", statement),
+            })
+            .collect();
+        assert_eq!(
+            operations,
+            vec![
+                (OperationType::Bcc(Condition::Ne), Some(Size::Byte)),
+                (OperationType::Bra, None),
+                (OperationType::Bcc(Condition::Cc), Some(Size::Word)),
+            ]
+        );
+        assert_eq!(program[1].range, 13..22);
+
+        let (_, errors) = parse_source(" ADD.S D0,D1\n BFOO loop");
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["unknown_size", "unknown_operation"]);
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let (program, errors) = parse_source("start\nloop: ADDQ.W #1,D0\n  end: ; done");
+        assert!(errors.is_empty());
+        assert_eq!(program.len(), 5);
+        assert_eq!(
+            program[0],
+            stmt(0..5, Statement::Label("start".to_string()))
+        );
+        assert_eq!(
+            program[1],
+            stmt(6..11, Statement::Label("loop".to_string()))
+        );
+        assert!(matches!(program[2].value, Statement::Operation(_)));
+        assert_eq!(
+            program[3],
+            stmt(27..31, Statement::Label("end".to_string()))
+        );
+        assert_eq!(
+            program[4],
+            stmt(32..38, Statement::Comment("; done".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_label_forms() {
+        let (program, errors) = parse_source(
+            "main\n.loop\n 1$ ADDQ.W #1,D0\n .next: ADD.W .loop,D0\n loop\\@: ADD.L 1$,D0",
+        );
+        assert!(errors.is_empty());
+        let labels: Vec<(&str, LabelScope)> = program
+            .iter()
+            .filter_map(|statement| match &statement.value {
+                Statement::Label(name) => Some((name.as_str(), LabelScope::of(name))),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("main", LabelScope::Global),
+                (".loop", LabelScope::Local),
+                ("1$", LabelScope::Numeric),
+                (".next", LabelScope::Local),
+                ("loop\\@", LabelScope::Generated),
+            ]
+        );
+        assert_eq!(program.len(), 8);
+    }
+
+    #[test]
+    fn test_parse_effective_addresses() {
+        assert!(matches!(parse_operand("D7"), EffectiveAddress::Dn(dn) if dn.index == 7));
+        assert!(matches!(parse_operand("sp"), EffectiveAddress::An(an) if an.index == 7));
+        assert!(matches!(parse_operand("(A1)"), EffectiveAddress::AnInd(an) if an.index == 1));
+        assert!(
+            matches!(parse_operand("(A1)+"), EffectiveAddress::AnIndWithPostInc(an) if an.index == 1)
+        );
+        assert!(
+            matches!(parse_operand("-(A1)"), EffectiveAddress::AnIndWithPreDec(an) if an.index == 1)
+        );
+        assert!(matches!(
+            parse_operand("-4(A1)"),
+            EffectiveAddress::AnIndWithDisplacement(displacement, an)
+                if displacement.value == 0xfffc && an.index == 1
+        ));
+        assert!(matches!(
+            parse_operand("8(A1,D2)"),
+            EffectiveAddress::AnIndWithIndex(displacement, an, xn)
+                if displacement.value == 8 && an.index == 1 && matches!(xn.register, Xn::Dn(_))
+        ));
+        assert!(matches!(
+            parse_operand("(A1,A2.L)"),
+            EffectiveAddress::AnIndWithIndex(_, _, index)
+                if index.range == (11..15) && matches!(&index.size, Some(size) if size.value == Size::LongWord)
+        ));
+        assert!(matches!(
+            parse_operand("(A1,A2)"),
+            EffectiveAddress::AnIndWithIndex(displacement, _, _) if displacement.value == 0
+        ));
+        assert!(matches!(
+            parse_operand("$10(pc)"),
+            EffectiveAddress::PcIndWithDisplacement(displacement) if displacement.value == 16
+        ));
+        assert!(matches!(
+            parse_operand("-1(PC,A0)"),
+            EffectiveAddress::PcIndWithIndex(displacement, _) if displacement.value == 0xff
+        ));
+        assert!(matches!(
+            parse_operand("$4000"),
+            EffectiveAddress::AbsoluteWord(address) if address.value == 0x4000
+        ));
+        assert!(matches!(
+            parse_operand("$4000.L"),
+            EffectiveAddress::AbsoluteLongWord(address) if address.value == 0x4000
+        ));
+        assert!(matches!(
+            parse_operand("$ff8240"),
+            EffectiveAddress::AbsoluteLongWord(address) if address.value == 0xff8240
+        ));
+        assert!(matches!(
+            parse_operand("$ffff8240"),
+            EffectiveAddress::AbsoluteWord(address) if address.value == 0x8240
+        ));
+        assert!(matches!(
+            parse_operand("#-1"),
+            EffectiveAddress::Immediate(value) if value.value == 0xffff_ffff
+        ));
+    }
+
+    #[test]
+    fn test_parse_parenthesized_displacements() {
+        assert!(matches!(
+            parse_operand("(-4,A1)"),
+            EffectiveAddress::AnIndWithDisplacement(displacement, an)
+                if displacement.value == 0xfffc && displacement.range == (8..10) && an.index == 1
+        ));
+        assert!(matches!(
+            parse_operand("(8,A1,D2.W)"),
+            EffectiveAddress::AnIndWithIndex(displacement, an, _)
+                if displacement.value == 8 && an.index == 1
+        ));
+        assert!(matches!(
+            parse_operand("($10,PC)"),
+            EffectiveAddress::PcIndWithDisplacement(displacement) if displacement.value == 16
+        ));
+        assert!(matches!(
+            parse_operand("(2,PC,A0)"),
+            EffectiveAddress::PcIndWithIndex(displacement, _) if displacement.value == 2
+        ));
+    }
+
+    #[test]
+    fn test_parse_parenthesized_absolute_addresses() {
+        let (program, errors) = parse_source(" ADD.W ($4000).L,D0");
+        assert!(errors.is_empty());
+        match &program[0].value {
+            Statement::Operation(operation) => {
+                assert_eq!(operation.operands[0].range, 7..16);
+                assert!(matches!(
+                    &operation.operands[0].value,
+                    EffectiveAddress::AbsoluteLongWord(address) if address.value == 0x4000
+                ));
+            }
+            statement => panic!("Expected an operation, got {:?}.", statement),
+        }
+        assert!(matches!(
+            parse_operand("($ff8240)"),
+            EffectiveAddress::AbsoluteLongWord(address) if address.value == 0xff8240
+        ));
+        assert!(matches!(
+            parse_operand("(-2).W"),
+            EffectiveAddress::AbsoluteWord(address) if address.value == 0xfffe
+        ));
+    }
+
+    #[test]
+    fn test_parse_backtracking_keeps_the_furthest_error() {
+        // All alternatives fail at the data register, so the first one reports it.
+        let (_, errors) = parse_source(" ADD.W (D0),D1");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].range, 8..10);
+        assert!(errors[0].message.contains("an address register"));
+
+        // Only the displacement got as far as the base register.
+        let (_, errors) = parse_source(" ADD.W (4,D0),D1");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].range, 10..12);
+        assert!(errors[0].message.contains("an address register or PC"));
+
+        let (_, errors) = parse_source(" ADD.W (40000,A0),D1");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "number_out_of_range");
+    }
+
+    #[test]
+    fn test_parse_sections() {
+        let (program, errors) = parse_source(" SECTION code,CODE\n DATA\n section bss");
+        assert!(errors.is_empty());
+        assert_eq!(
+            program,
+            vec![
+                stmt(
+                    1..18,
+                    Statement::Directive(Directive::Section(Section {
+                        name: stmt(9..13, "code".to_string()),
+                        section_type: stmt(14..18, SectionType::Code),
+                    }))
+                ),
+                stmt(
+                    20..24,
+                    Statement::Directive(Directive::Section(Section {
+                        name: stmt(20..24, "DATA".to_string()),
+                        section_type: stmt(20..24, SectionType::Data),
+                    }))
+                ),
+                stmt(
+                    26..37,
+                    Statement::Directive(Directive::Section(Section {
+                        name: stmt(34..37, "bss".to_string()),
+                        section_type: stmt(34..37, SectionType::Bss),
+                    }))
+                ),
+            ]
+        );
+
+        let (_, errors) = parse_source(" SECTION tables");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "unknown_section_type");
+    }
+
+    #[test]
+    fn test_parse_org() {
+        let (program, errors) = parse_source(" ORG $FC0000\n org 1024");
+        assert!(errors.is_empty());
+        assert_eq!(
+            program,
+            vec![
+                stmt(
+                    1..12,
+                    Statement::Directive(Directive::Org(stmt(5..12, 0xfc_0000)))
+                ),
+                stmt(
+                    14..22,
+                    Statement::Directive(Directive::Org(stmt(18..22, 1024)))
+                ),
+            ]
+        );
+
+        let (_, errors) = parse_source(" ORG start");
+        assert_eq!(errors[0].code, "unexpected_token");
+    }
+
+    #[test]
+    fn test_parse_visibility_and_label_operands() {
+        let (program, errors) =
+            parse_source(" XDEF main, exit\n xref print\n.loop ADD.L print,.loop");
+        assert!(errors.is_empty());
+        assert_eq!(
+            program[0],
+            stmt(
+                1..16,
+                Statement::Directive(Directive::Export(vec![
+                    stmt(6..10, "main".to_string()),
+                    stmt(12..16, "exit".to_string()),
+                ]))
+            )
+        );
+        assert_eq!(
+            program[1],
+            stmt(
+                18..28,
+                Statement::Directive(Directive::Import(vec![stmt(23..28, "print".to_string())]))
+            )
+        );
+        assert_eq!(
+            program[2],
+            stmt(29..34, Statement::Label(".loop".to_string()))
+        );
+        assert!(matches!(
+            parse_operand("counter"),
+            EffectiveAddress::Label(label) if label.value == "counter"
+        ));
+
+        let (_, errors) = parse_source(" XDEF\n XREF main,");
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["unexpected_token", "unexpected_token"]);
+    }
+
+    #[test]
+    fn test_parse_keywords_ignore_case() {
+        assert!(matches!(parse_operand("d2"), EffectiveAddress::Dn(dn) if dn.index == 2));
+        assert!(matches!(parse_operand("Sp"), EffectiveAddress::An(an) if an.index == 7));
+        assert!(matches!(
+            parse_operand("4(pC)"),
+            EffectiveAddress::PcIndWithDisplacement(_)
+        ));
+        assert!(matches!(parse_operand("move"), EffectiveAddress::Label(_)));
+
+        let (program, errors) = parse_source(" Move.b (a0)+,D1\n bra.s loop");
+        assert!(errors.is_empty());
+        let sizes: Vec<Option<Size>> = program
+            .iter()
+            .map(|statement| match &statement.value {
+                Statement::Operation(operation) => operation.size.as_ref().map(|size| size.value),
+                statement => panic!("Expected an operation, got {:?}.", statement),
+            })
+            .collect();
+        assert_eq!(sizes, vec![Some(Size::Byte), Some(Size::Byte)]);
+
+        let (_, errors) = parse_source(" ADD.W USP,D0\n ADD.W (4,D0),D1");
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["unexpected_token", "unexpected_token"]);
+    }
+
+    #[test]
+    fn test_parse_labels_shadowing_registers() {
+        let (program, errors) = parse_source("a0 MOVEQ #0,D0\n sp: RTS\n XDEF main,pc\nmain RTS");
+        let codes: Vec<(&str, Range)> = errors
+            .iter()
+            .map(|error| (error.code, error.range.clone()))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                ("label_shadows_register", 0..2),
+                ("label_shadows_register", 16..18),
+                ("label_shadows_register", 35..37),
+            ]
+        );
+        assert_eq!(
+            errors[0].message,
+            "'a0' is a register, so it can't be used as a label."
+        );
+        assert_eq!(
+            errors[1].message,
+            "'sp' is an alias of the register A7, so it can't be used as a label."
+        );
+        assert_eq!(
+            errors[2].message,
+            "'pc' is a register, so it can't be used as a label."
+        );
+        assert_eq!(program.len(), 4);
+    }
+
+    #[test]
+    fn test_all_mnemonics_are_operations() {
+        for mnemonic in m68k_reloaded_scanner::keywords::MNEMONICS {
+            assert!(operation_type(mnemonic).is_some(), "{}", mnemonic);
+        }
+    }
+
+    #[test]
+    fn test_parse_dialects() {
+        let sources = [
+            (
+                Dialect::Motorola,
+                " MOVE.L (8,A0),(A1)+\n ADD.W -(SP),D0\n MOVE.B (-2,PC,D0.L),$400.W",
+            ),
+            (
+                Dialect::Mit,
+                " movel %a0@(8),%a1@+\n addw sp@-,%d0\n moveb %pc@(-2,%d0:l),0x400:w",
+            ),
+            (
+                Dialect::Devpac,
+                " MOVE.L 8(A0),(A1)+ copy\n ADD.W -(SP),D0\n MOVE.B -2(PC,D0.L),$400.W",
+            ),
+            (
+                Dialect::Vasm,
+                " move.l (8,a0),(a1)+\n add.w -(sp),d0 ; add\n move.b (-2,pc,d0.l),$400.w",
+            ),
+        ];
+        let mut operands = vec![];
+        for (dialect, source) in sources.iter() {
+            let mut errors = vec![];
+            let tokens: Vec<Token> = scan_dialect(source, *dialect, &mut errors).collect();
+            let program = parse_dialect(tokens, *dialect, &mut errors);
+            assert!(errors.is_empty(), "Parsing {:?} failed.", dialect);
+            operands.push(
+                program
+                    .iter()
+                    .filter_map(|statement| match &statement.value {
+                        Statement::Operation(operation) => Some(
+                            operation
+                                .operands
+                                .iter()
+                                .map(|operand| format_operand(operand, Dialect::Motorola))
+                                .collect::<Vec<_>>(),
+                        ),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(
+            operands[0],
+            vec![
+                vec!["(8,A0)", "(A1)+"],
+                vec!["-(A7)", "D0"],
+                vec!["(-2,PC,D0.L)", "$400.W"],
+            ]
+        );
+        assert!(operands.iter().all(|other| *other == operands[0]));
+    }
+
+    #[test]
+    fn test_parse_dialect_directives() {
+        let mut errors = vec![];
+        let source = "\t.text\n\t.globl main\n\t.section tables,data\n.loop:";
+        let tokens: Vec<Token> = scan_dialect(source, Dialect::Mit, &mut errors).collect();
+        let program = parse_dialect(tokens, Dialect::Mit, &mut errors);
+        assert!(errors.is_empty());
+        assert!(matches!(
+            &program[0].value,
+            Statement::Directive(Directive::Section(section)) if section.name.value == "text"
+        ));
+        assert!(matches!(
+            program[1].value,
+            Statement::Directive(Directive::Global(_))
+        ));
+        assert_eq!(program[3].value, Statement::Label(".loop".to_string()));
+
+        let source = " SECTION gfx,DATA_C\n SECTION sprites,data,chip\n BSS_F";
+        let tokens: Vec<Token> = scan_dialect(source, Dialect::Devpac, &mut errors).collect();
+        let program = parse_dialect(tokens, Dialect::Devpac, &mut errors);
+        assert!(errors.is_empty());
+        let types: Vec<SectionType> = program
+            .iter()
+            .map(|statement| match &statement.value {
+                Statement::Directive(Directive::Section(section)) => section.section_type.value,
+                statement => panic!("Expected a section, got {:?}.", statement),
+            })
+            .collect();
+        assert_eq!(
+            types,
+            vec![SectionType::Data, SectionType::Data, SectionType::Bss]
+        );
+
+        // Memory types are only known to some dialects.
+        let (_, errors) = parse_source(" SECTION gfx,DATA_C");
+        assert_eq!(errors[0].code, "unknown_section_type");
+    }
+
+    proptest! {
+        /// Parsing reports errors instead of panicking, whatever the tokens are.
+        #[test]
+        fn test_parse_any_tokens(
+            source in "([ \\t\\n,.:;#$%@()+-]|[0-9]{1,6}|%?[dDaA][0-7]|pc|sp|[a-z]{1,6}\\$?|\\.[a-z]{1,4}){0,40}",
+            dialect in 0..4usize,
+        ) {
+            let dialect = Dialect::ALL[dialect];
+            let mut errors = vec![];
+            let tokens: Vec<Token> = scan_dialect(&source, dialect, &mut errors).collect();
+            parse_dialect(tokens, dialect, &mut errors);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors_skip_the_line() {
+        let (program, errors) = parse_source(
+            " FOO.W D0\n ADD D0,D1\n ADD.W 4(D0),D1\n ADD.Q D0,D1\n ADDX.B D0,D1\n ADD.W D0,",
+        );
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                "unknown_operation",
+                "unexpected_token",
+                "unknown_size",
+                "unexpected_token"
+            ]
+        );
+        assert_eq!(errors[3].range, 74..74);
+        assert_eq!(program.len(), 2);
+
+        let (_, errors) = parse_source(" ADD.B 200(A0,D0),D1");
+        assert_eq!(errors[0].code, "number_out_of_range");
+    }
+}
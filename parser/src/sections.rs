@@ -0,0 +1,149 @@
+use crate::statements::*;
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+
+/// The name of the section that statements before the first section directive belong to.
+pub const DEFAULT_SECTION: &str = "TEXT";
+
+/// All statements of a section. A section can be continued several times throughout a program
+/// (e.g. by switching between `TEXT` and `DATA`), so the statements don't need to be contiguous.
+#[derive(Eq, PartialEq, Debug)]
+pub struct ProgramSection<'a> {
+    pub name: String,
+    pub section_type: SectionType,
+    /// The address of the section's `ORG`. Sections without one follow the section before them.
+    pub address: Option<&'a Stmt<LongWord>>,
+    pub statements: Vec<&'a Stmt<Statement>>,
+}
+
+/// Groups the statements of a program by section, in the order in which the sections first
+/// appear. Section names are case-insensitive. Each section can only have one `ORG`.
+pub fn split_into_sections<'a>(
+    program: &'a Program,
+    errors: &mut ErrorCollector,
+) -> Vec<ProgramSection<'a>> {
+    let mut sections = vec![ProgramSection {
+        name: DEFAULT_SECTION.to_string(),
+        section_type: SectionType::Code,
+        address: None,
+        statements: vec![],
+    }];
+    let mut current = 0;
+
+    for statement in program {
+        let section = match &statement.value {
+            Statement::Directive(Directive::Section(section)) => section,
+            Statement::Directive(Directive::Org(address)) => {
+                let section = &mut sections[current];
+                match section.address {
+                    Some(first) => errors.push(Error::duplicate_org(
+                        statement.range.clone(),
+                        &section.name,
+                        first.range.clone(),
+                    )),
+                    None => section.address = Some(address),
+                }
+                continue;
+            }
+            _ => {
+                sections[current].statements.push(statement);
+                continue;
+            }
+        };
+        match sections
+            .iter()
+            .position(|other| other.name.eq_ignore_ascii_case(&section.name))
+        {
+            Some(index) => {
+                if sections[index].section_type != *section.section_type {
+                    errors.push(Error::conflicting_section_type(
+                        section.section_type.range.clone(),
+                        &section.name,
+                    ));
+                }
+                current = index;
+            }
+            None => {
+                sections.push(ProgramSection {
+                    name: section.name.value.clone(),
+                    section_type: *section.section_type,
+                    address: None,
+                    statements: vec![],
+                });
+                current = sections.len() - 1;
+            }
+        }
+    }
+
+    // Drop the default section if the program starts with a section directive.
+    if sections[0].statements.is_empty() && sections[0].address.is_none() && sections.len() > 1 {
+        sections.remove(0);
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    #[test]
+    fn test_split_into_sections() {
+        let source = "a ADD.W D0,D1\n DATA\nb ADD.W D1,D2\n text\nc ADD.W D2,D3\n SECTION bss";
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        let sections = split_into_sections(&program, &mut errors);
+
+        assert!(errors.is_empty());
+        let summary: Vec<(&str, SectionType, usize)> = sections
+            .iter()
+            .map(|section| {
+                (
+                    section.name.as_str(),
+                    section.section_type,
+                    section.statements.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("TEXT", SectionType::Code, 4),
+                ("DATA", SectionType::Data, 2),
+                ("bss", SectionType::Bss, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_org_places_its_section() {
+        let source = " ORG $1000\n NOP\n SECTION rom,DATA\n ORG $2000\n BSS\n TEXT\n org $3000";
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        let sections = split_into_sections(&program, &mut errors);
+
+        let addresses: Vec<Option<LongWord>> = sections
+            .iter()
+            .map(|section| section.address.map(|address| address.value))
+            .collect();
+        assert_eq!(addresses, vec![Some(0x1000), Some(0x2000), None]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "duplicate_org");
+        assert_eq!(errors[0].range, 57..66);
+    }
+
+    #[test]
+    fn test_conflicting_section_types() {
+        let source = " SECTION tables,DATA\n SECTION tables,BSS";
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        let sections = split_into_sections(&program, &mut errors);
+
+        assert_eq!(sections.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "conflicting_section_type");
+    }
+}
@@ -1,4 +1,5 @@
 use m68k_reloaded_common::Range;
+use std::fmt::{self, Display};
 use std::ops::Deref;
 
 /// A statement in the abstract syntax tree built by the parser. Wraps a value
@@ -43,6 +44,14 @@ pub enum Xn {
     Dn(Stmt<Dn>),
 }
 
+/// The index register of an indexed address, like `D0.L`. Without a size, the lower word of the
+/// register is used.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Index {
+    pub register: Xn,
+    pub size: Option<Stmt<Size>>,
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub enum EffectiveAddress {
     Dn(Stmt<Dn>),
@@ -51,11 +60,15 @@ pub enum EffectiveAddress {
     AnIndWithPostInc(Stmt<An>),
     AnIndWithPreDec(Stmt<An>),
     AnIndWithDisplacement(Stmt<Word>, Stmt<An>),
-    AnIndWithIndex(Stmt<Byte>, Stmt<An>, Stmt<Xn>),
+    AnIndWithIndex(Stmt<Byte>, Stmt<An>, Stmt<Index>),
     AbsoluteWord(Stmt<Word>),
     AbsoluteLongWord(Stmt<LongWord>),
     PcIndWithDisplacement(Stmt<Word>),
-    PcIndWithIndex(Stmt<Byte>, Stmt<Xn>),
+    PcIndWithIndex(Stmt<Byte>, Stmt<Index>),
+    Immediate(Stmt<LongWord>),
+    /// A label used as an address. Its value is only known after the label is placed, or even
+    /// after linking if it's imported.
+    Label(Stmt<Label>),
 }
 
 // impl std::string::ToString for Register {
@@ -71,16 +84,112 @@ pub enum EffectiveAddress {
 
 pub type Operand = EffectiveAddress;
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum OperationType {
     Add,
     Adda,
     Addi,
     Addq,
-    Addx, // ...
+    Addx,
+    Bcc(Condition),
+    Bra,
+    Bsr,
+    Chk,
+    Clr,
+    Divs,
+    Divu,
+    Jsr,
+    Lea,
+    Move,
+    Moveq,
+    Nop,
+    Rte,
+    Rts,
+    Stop,
+    Trap,
+    Trapv, // ...
 }
 
-#[derive(Eq, PartialEq, Debug)]
+impl OperationType {
+    pub fn is_branch(self) -> bool {
+        matches!(
+            self,
+            OperationType::Bcc(_) | OperationType::Bra | OperationType::Bsr
+        )
+    }
+}
+
+impl Display for OperationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationType::Add => f.write_str("ADD"),
+            OperationType::Adda => f.write_str("ADDA"),
+            OperationType::Addi => f.write_str("ADDI"),
+            OperationType::Addq => f.write_str("ADDQ"),
+            OperationType::Addx => f.write_str("ADDX"),
+            OperationType::Bcc(condition) => write!(f, "B{}", condition),
+            OperationType::Bra => f.write_str("BRA"),
+            OperationType::Bsr => f.write_str("BSR"),
+            OperationType::Chk => f.write_str("CHK"),
+            OperationType::Clr => f.write_str("CLR"),
+            OperationType::Divs => f.write_str("DIVS"),
+            OperationType::Divu => f.write_str("DIVU"),
+            OperationType::Jsr => f.write_str("JSR"),
+            OperationType::Lea => f.write_str("LEA"),
+            OperationType::Move => f.write_str("MOVE"),
+            OperationType::Moveq => f.write_str("MOVEQ"),
+            OperationType::Nop => f.write_str("NOP"),
+            OperationType::Rte => f.write_str("RTE"),
+            OperationType::Rts => f.write_str("RTS"),
+            OperationType::Stop => f.write_str("STOP"),
+            OperationType::Trap => f.write_str("TRAP"),
+            OperationType::Trapv => f.write_str("TRAPV"),
+        }
+    }
+}
+
+/// The conditions of conditional operations like `Bcc`. `HS` and `LO` are aliases of `CC` and `CS`.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Condition {
+    Hi,
+    Ls,
+    Cc,
+    Cs,
+    Ne,
+    Eq,
+    Vc,
+    Vs,
+    Pl,
+    Mi,
+    Ge,
+    Lt,
+    Gt,
+    Le,
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Condition::Hi => "HI",
+            Condition::Ls => "LS",
+            Condition::Cc => "CC",
+            Condition::Cs => "CS",
+            Condition::Ne => "NE",
+            Condition::Eq => "EQ",
+            Condition::Vc => "VC",
+            Condition::Vs => "VS",
+            Condition::Pl => "PL",
+            Condition::Mi => "MI",
+            Condition::Ge => "GE",
+            Condition::Lt => "LT",
+            Condition::Gt => "GT",
+            Condition::Le => "LE",
+        })
+    }
+}
+
+/// For branches, `.S` is the same as `.B`.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum Size {
     Byte,
     Word,
@@ -90,19 +199,90 @@ pub enum Size {
 #[derive(Eq, PartialEq, Debug)]
 pub struct Operation {
     pub operation_type: Stmt<OperationType>,
-    pub size: Stmt<Size>,
+    /// The size as written in the source. If it's omitted, the assembler infers it.
+    pub size: Option<Stmt<Size>>,
     pub operands: Vec<Stmt<Operand>>,
 }
 
 pub type Comment = String;
 
+/// The name of a label. Its [LabelScope] follows from the name.
 pub type Label = String;
 
+/// Where a label is visible, which depends on the label's name.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum LabelScope {
+    /// Labels like `main` are visible in the whole program and start a new scope for local labels.
+    Global,
+    /// Labels like `.loop` are only visible between the global labels before and after them.
+    Local,
+    /// Labels like `1$` are scoped just like [LabelScope::Local] labels.
+    Numeric,
+    /// Labels like `loop\@` contain a unique number when expanded by a macro. They are visible in
+    /// the whole program, but don't start a new scope, so they don't break local labels around the
+    /// macro invocation.
+    Generated,
+}
+
+impl LabelScope {
+    pub fn of(label: &str) -> LabelScope {
+        if label.starts_with('.') {
+            LabelScope::Local
+        } else if label.ends_with('$')
+            && label[..label.len() - 1].bytes().all(|b| b.is_ascii_digit())
+        {
+            LabelScope::Numeric
+        } else if label.contains("\\@") {
+            LabelScope::Generated
+        } else {
+            LabelScope::Global
+        }
+    }
+
+    /// Whether labels with this scope belong to the global label before them.
+    pub fn is_local(self) -> bool {
+        matches!(self, LabelScope::Local | LabelScope::Numeric)
+    }
+}
+
+/// The kind of content a section holds. Sections with the same name get merged, so their types
+/// have to match.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum SectionType {
+    Code,
+    Data,
+    Bss,
+}
+
+/// Starts a new section, like `SECTION name,CODE` or the shorthands `TEXT`, `DATA` and `BSS`.
+/// Sections started by a shorthand are named after their type.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Section {
+    pub name: Stmt<String>,
+    pub section_type: Stmt<SectionType>,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum Directive {
+    Section(Section),
+    /// `XDEF labels`: Makes labels of this program visible to other programs.
+    Export(Vec<Stmt<Label>>),
+    /// `XREF labels`: Declares labels that are defined in other programs.
+    Import(Vec<Stmt<Label>>),
+    /// `GLOBAL labels` or `PUBLIC labels`: Exports the labels that are defined in this program and
+    /// imports all others.
+    Global(Vec<Stmt<Label>>),
+    /// `ORG address`: Places the section it's in at a fixed address, like in a ROM image. The
+    /// linker places the sections of objects instead.
+    Org(Stmt<LongWord>),
+}
+
 /// About a single line in the assembler program.
 #[derive(Eq, PartialEq, Debug)]
 pub enum Statement {
     Label(Label),
     Operation(Operation),
+    Directive(Directive),
     Comment(Comment),
 }
 
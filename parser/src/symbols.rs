@@ -0,0 +1,289 @@
+//! The symbol table knows all labels of a program and whether other programs can see them.
+//!
+//! Local labels (`.loop` or `1$`, see [LabelScope]) belong to the global label before them, so several routines can
+//! each have their own `.loop`. In the symbol table, they're qualified with the name of that global
+//! label, like `main.loop`.
+
+use crate::statements::*;
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use m68k_reloaded_common::Range;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Visibility {
+    /// Defined in this program and only visible in it.
+    Local,
+    /// Defined in this program and visible to other programs (`XDEF`).
+    Exported,
+    /// Defined in another program (`XREF`).
+    Imported,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct Symbol {
+    /// The qualified name of the label.
+    pub name: Label,
+    /// Where the label is defined or, for imported labels, where it's imported.
+    pub range: Range,
+    pub visibility: Visibility,
+}
+
+/// A label used as an operand.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Reference {
+    pub range: Range,
+    /// The qualified name of the label.
+    pub name: Label,
+}
+
+#[derive(Eq, PartialEq, Debug, Default)]
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+    /// All references to known labels. References to undefined labels are reported as errors
+    /// instead.
+    pub references: Vec<Reference>,
+}
+
+impl SymbolTable {
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+        self.symbols.iter_mut().find(|symbol| symbol.name == name)
+    }
+
+    /// References to imported labels. Their addresses are only known after linking, so they end
+    /// up as relocation entries in the object file.
+    pub fn external_references(&self) -> impl Iterator<Item = &Reference> {
+        self.references.iter().filter(move |reference| {
+            matches!(
+                self.get(&reference.name),
+                Some(Symbol {
+                    visibility: Visibility::Imported,
+                    ..
+                })
+            )
+        })
+    }
+}
+
+/// Qualifies local labels with the name of their scope, which is the last global label. Returns
+/// `None` for local labels without a scope.
+pub fn qualify(name: &str, scope: Option<&str>) -> Option<Label> {
+    if !LabelScope::of(name).is_local() {
+        return Some(name.to_string());
+    }
+    scope.map(|scope| format!("{}.{}", scope, name.trim_start_matches('.')))
+}
+
+#[derive(Clone, Copy)]
+enum Declaration {
+    Export,
+    Import,
+    Global,
+}
+
+/// Collects all labels of the program and checks that every label used as an operand is either
+/// defined or imported.
+pub fn build_symbol_table(program: &Program, errors: &mut ErrorCollector) -> SymbolTable {
+    let mut table = SymbolTable::default();
+    let mut declarations = vec![];
+    let mut uses = vec![];
+    let mut scope: Option<&str> = None;
+
+    for statement in program {
+        match &statement.value {
+            Statement::Label(name) => {
+                if LabelScope::of(name) == LabelScope::Global {
+                    scope = Some(name);
+                }
+                let name = match qualify(name, scope) {
+                    Some(name) => name,
+                    None => {
+                        errors.push(Error::local_label_without_scope(
+                            statement.range.clone(),
+                            name,
+                        ));
+                        continue;
+                    }
+                };
+                if let Some(first) = table.get(&name) {
+                    errors.push(Error::duplicate_label(
+                        statement.range.clone(),
+                        &name,
+                        first.range.clone(),
+                    ));
+                    continue;
+                }
+                table.symbols.push(Symbol {
+                    name,
+                    range: statement.range.clone(),
+                    visibility: Visibility::Local,
+                });
+            }
+            Statement::Directive(directive) => {
+                let (declaration, labels) = match directive {
+                    Directive::Export(labels) => (Declaration::Export, labels),
+                    Directive::Import(labels) => (Declaration::Import, labels),
+                    Directive::Global(labels) => (Declaration::Global, labels),
+                    Directive::Section(_) | Directive::Org(_) => continue,
+                };
+                for label in labels {
+                    match qualify(label, scope) {
+                        Some(name) => declarations.push((declaration, label.range.clone(), name)),
+                        None => errors
+                            .push(Error::local_label_without_scope(label.range.clone(), label)),
+                    }
+                }
+            }
+            Statement::Operation(operation) => {
+                for operand in &operation.operands {
+                    if let EffectiveAddress::Label(label) = &operand.value {
+                        match qualify(label, scope) {
+                            Some(name) => uses.push((label.range.clone(), name)),
+                            None => errors
+                                .push(Error::local_label_without_scope(label.range.clone(), label)),
+                        }
+                    }
+                }
+            }
+            Statement::Comment(_) => {}
+        }
+    }
+
+    for (declaration, range, name) in declarations {
+        let definition = table
+            .get(&name)
+            .filter(|symbol| symbol.visibility != Visibility::Imported)
+            .map(|symbol| symbol.range.clone());
+        match (declaration, definition.is_some()) {
+            (Declaration::Export, false) => errors.push(Error::undefined_export(range, &name)),
+            (Declaration::Import, true) => errors.push(Error::imported_label_defined(
+                range,
+                &name,
+                definition.unwrap(),
+            )),
+            (Declaration::Export, true) | (Declaration::Global, true) => {
+                table.get_mut(&name).unwrap().visibility = Visibility::Exported;
+            }
+            (Declaration::Import, false) | (Declaration::Global, false) => {
+                if table.get(&name).is_none() {
+                    table.symbols.push(Symbol {
+                        name,
+                        range,
+                        visibility: Visibility::Imported,
+                    });
+                }
+            }
+        }
+    }
+
+    for (range, name) in uses {
+        if table.get(&name).is_some() {
+            table.references.push(Reference { range, name });
+        } else {
+            errors.push(Error::undefined_label(range, &name));
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn build(source: &str) -> (SymbolTable, ErrorCollector) {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        assert!(errors.is_empty());
+        let table = build_symbol_table(&program, &mut errors);
+        (table, errors)
+    }
+
+    #[test]
+    fn test_local_labels_are_scoped() {
+        let (table, errors) = build(
+            "first\n.loop ADDQ.W #1,D0\n ADD.L .loop,D1\nsecond\n.loop: ADD.L .loop,D1\n ADD.L first,D1",
+        );
+        assert!(errors.is_empty());
+        let names: Vec<&str> = table
+            .symbols
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["first", "first.loop", "second", "second.loop"]);
+        let references: Vec<&str> = table
+            .references
+            .iter()
+            .map(|reference| reference.name.as_str())
+            .collect();
+        assert_eq!(references, vec!["first.loop", "second.loop", "first"]);
+        assert_eq!(qualify("1$", Some("main")), Some("main.1$".to_string()));
+        assert_eq!(
+            qualify("loop\\@", Some("main")),
+            Some("loop\\@".to_string())
+        );
+        assert_eq!(qualify("1$", None), None);
+    }
+
+    #[test]
+    fn test_generated_labels_keep_the_scope() {
+        let (table, errors) = build("main\n1$ ADD.L 1$,D0\nskip\\@\n2$ ADD.L 1$,D0\nnext\n1$");
+        assert!(errors.is_empty());
+        let names: Vec<&str> = table
+            .symbols
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["main", "main.1$", "skip\\@", "main.2$", "next", "next.1$"]
+        );
+    }
+
+    #[test]
+    fn test_visibility() {
+        let (table, errors) = build(
+            " XREF print\n XDEF main\n GLOBAL helper,exit\nmain ADD.L print,D0\nhelper ADD.L exit,D0\nlocal",
+        );
+        assert!(errors.is_empty());
+        let visibility = |name: &str| table.get(name).unwrap().visibility;
+        assert_eq!(visibility("print"), Visibility::Imported);
+        assert_eq!(visibility("exit"), Visibility::Imported);
+        assert_eq!(visibility("main"), Visibility::Exported);
+        assert_eq!(visibility("helper"), Visibility::Exported);
+        assert_eq!(visibility("local"), Visibility::Local);
+
+        let external: Vec<&str> = table
+            .external_references()
+            .map(|reference| reference.name.as_str())
+            .collect();
+        assert_eq!(external, vec!["print", "exit"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let (_, errors) = build(
+            ".orphan\nmain\nmain\n XDEF missing\n XREF main\n ADD.L unknown,D0\n ADD.L .unknown,D0",
+        );
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                "local_label_without_scope",
+                "duplicate_label",
+                "undefined_export",
+                "imported_label_defined",
+                "undefined_label",
+                "undefined_label",
+            ]
+        );
+        assert_eq!(errors[1].range, 13..17);
+        assert_eq!(errors[1].details.related[0].range, 8..12);
+        assert_eq!(errors[3].details.related[0].range, 8..12);
+        assert!(errors[5].message.contains("main.unknown"));
+    }
+}
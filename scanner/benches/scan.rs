@@ -0,0 +1,34 @@
+//! Scans generated sources of growing size and prints the throughput, which should stay about the
+//! same if scanning takes linear time. Run it using `cargo bench`.
+
+use m68k_reloaded_scanner::scan;
+use std::time::Instant;
+
+const LINES: &str = "main MOVE.L #$ff8240,D0 ; sets up the palette
+.loop ADD.W 4(A0,D1.L),D2
+ BNE.S .loop ; 繰り返す
+ RTS
+";
+
+fn main() {
+    let mut rates = vec![];
+    for megabytes in [1, 2, 4, 8].iter() {
+        let source = LINES.repeat(megabytes * 1024 * 1024 / LINES.len());
+        let mut errors = vec![];
+        let start = Instant::now();
+        let count = scan(&source, &mut errors).count();
+        let seconds = start.elapsed().as_secs_f64();
+        assert!(errors.is_empty());
+        let rate = source.len() as f64 / seconds / 1024.0 / 1024.0;
+        println!(
+            "{} MiB: {} tokens in {:.3} s ({:.1} MiB/s)",
+            megabytes, count, seconds, rate
+        );
+        rates.push(rate);
+    }
+    let slowdown = rates[0] / rates[rates.len() - 1];
+    println!(
+        "The largest source was scanned {:.2}x slower per byte.",
+        slowdown
+    );
+}
@@ -0,0 +1,62 @@
+//! The syntaxes that sources from different ecosystems are written in. They all describe the same
+//! programs, so they are parsed into the same statements.
+
+/// The dialects differ in how registers, addressing modes, sizes and comments are written:
+///
+/// | Dialect  | Operand               | Size      | Comments                              |
+/// |----------|-----------------------|-----------|---------------------------------------|
+/// | Motorola | `(8,A0)` or `8(A0)`   | `MOVE.L`  | `;` or `*`                            |
+/// | Mit      | `%a0@(8)` or `a0@(8)` | `movel`   | `\|`, `*` or `#` at the line start    |
+/// | Devpac   | `8(A0)` or `(8,A0)`   | `MOVE.L`  | `;`, `*` at the line start or after   |
+/// |          |                       |           | the operands                          |
+/// | Vasm     | like Devpac           | `MOVE.L`  | like Devpac                           |
+///
+/// Easy68K sources use the Motorola dialect.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum Dialect {
+    #[default]
+    Motorola,
+    /// The MIT syntax of the GNU assembler.
+    Mit,
+    /// HiSoft Devpac, which also accepts the memory types of sections like `CODE_C`.
+    Devpac,
+    /// The `mot` syntax module of vasm, which also accepts `SECTION name,CODE,CHIP`.
+    Vasm,
+}
+
+impl Dialect {
+    pub const ALL: [Dialect; 4] = [
+        Dialect::Motorola,
+        Dialect::Mit,
+        Dialect::Devpac,
+        Dialect::Vasm,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dialect::Motorola => "motorola",
+            Dialect::Mit => "mit",
+            Dialect::Devpac => "devpac",
+            Dialect::Vasm => "vasm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Dialect> {
+        Dialect::ALL
+            .iter()
+            .copied()
+            .find(|dialect| dialect.name() == name)
+    }
+
+    /// Whether operands end at whitespace, so that anything after them is a comment, like in
+    /// `MOVE.W D0,D1 copy it`.
+    pub fn has_comments_after_operands(self) -> bool {
+        matches!(self, Dialect::Devpac | Dialect::Vasm)
+    }
+
+    /// Whether sections can have a memory type like `CODE_C` or `SECTION name,CODE,CHIP`. It's
+    /// ignored, because the targets don't have chip and fast memory.
+    pub fn has_memory_types(self) -> bool {
+        matches!(self, Dialect::Devpac | Dialect::Vasm)
+    }
+}
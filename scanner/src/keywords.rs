@@ -0,0 +1,86 @@
+//! Names that the scanner turns into dedicated tokens instead of identifiers. They are matched
+//! case-insensitively.
+
+use std::fmt::{self, Display};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Register {
+    Dn(u8),
+    /// `SP` is `A7`.
+    An(u8),
+    Pc,
+    Sr,
+    Ccr,
+    Usp,
+}
+
+impl Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::Dn(index) => write!(f, "D{}", index),
+            Register::An(index) => write!(f, "A{}", index),
+            Register::Pc => f.write_str("PC"),
+            Register::Sr => f.write_str("SR"),
+            Register::Ccr => f.write_str("CCR"),
+            Register::Usp => f.write_str("USP"),
+        }
+    }
+}
+
+/// The size suffixes of operations and operands, like the `.W` of `MOVE.W`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SizeSuffix {
+    Byte,
+    /// `.S`, which branches use for a byte displacement.
+    Short,
+    Word,
+    LongWord,
+}
+
+/// The operations of the 68000 that the assembler knows, in upper case.
+pub const MNEMONICS: &[&str] = &[
+    "ADD", "ADDA", "ADDI", "ADDQ", "ADDX", "BCC", "BCS", "BEQ", "BGE", "BGT", "BHI", "BHS", "BLE",
+    "BLO", "BLS", "BLT", "BMI", "BNE", "BPL", "BRA", "BSR", "BVC", "BVS", "CHK", "CLR", "DIVS",
+    "DIVU", "JSR", "LEA", "MOVE", "MOVEQ", "NOP", "RTE", "RTS", "STOP", "TRAP", "TRAPV",
+];
+
+pub fn register(name: &str) -> Option<Register> {
+    let name = name.to_ascii_uppercase();
+    match name.as_str() {
+        "SP" => return Some(Register::An(7)),
+        "PC" => return Some(Register::Pc),
+        "SR" => return Some(Register::Sr),
+        "CCR" => return Some(Register::Ccr),
+        "USP" => return Some(Register::Usp),
+        _ => {}
+    }
+    match name.as_bytes() {
+        [b'D', index @ b'0'..=b'7'] => Some(Register::Dn(index - b'0')),
+        [b'A', index @ b'0'..=b'7'] => Some(Register::An(index - b'0')),
+        _ => None,
+    }
+}
+
+pub fn is_mnemonic(name: &str) -> bool {
+    MNEMONICS
+        .iter()
+        .any(|mnemonic| mnemonic.eq_ignore_ascii_case(name))
+}
+
+/// Whether the operation takes operands. In dialects where operands end at whitespace, anything
+/// after an operation without operands is a comment.
+pub fn has_operands(mnemonic: &str) -> bool {
+    !["NOP", "RTE", "RTS", "TRAPV"]
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(mnemonic))
+}
+
+pub fn size_suffix(letter: char) -> Option<SizeSuffix> {
+    match letter.to_ascii_uppercase() {
+        'B' => Some(SizeSuffix::Byte),
+        'S' => Some(SizeSuffix::Short),
+        'W' => Some(SizeSuffix::Word),
+        'L' => Some(SizeSuffix::LongWord),
+        _ => None,
+    }
+}
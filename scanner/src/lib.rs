@@ -1,5 +1,8 @@
+mod dialect;
+pub mod keywords;
 mod scan;
 mod token;
 
-pub use scan::{scan, Scanner};
-pub use token::Token;
+pub use dialect::Dialect;
+pub use scan::{scan, scan_dialect, Scanner};
+pub use token::{Token, TokenKind};
@@ -1,54 +1,103 @@
+use crate::dialect::Dialect;
+use crate::keywords::{has_operands, is_mnemonic, register, size_suffix};
 use crate::token::{Range, Token};
 use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use std::str::CharIndices;
 
 pub fn scan<'s, 'e>(source: &'s str, errors: &'e mut ErrorCollector) -> Scanner<'s, 'e> {
+    scan_dialect(source, Dialect::Motorola, errors)
+}
+
+pub fn scan_dialect<'s, 'e>(
+    source: &'s str,
+    dialect: Dialect,
+    errors: &'e mut ErrorCollector,
+) -> Scanner<'s, 'e> {
     Scanner {
-        offset: 0,
-        rest: source,
-        cursor: 0,
+        source,
+        chars: source.char_indices(),
+        start: 0,
+        dialect,
+        follows_operand: false,
+        attached_size: false,
+        has_operation: false,
+        field: Field::Label,
         errors,
     }
 }
 
+/// Scans the source in a single pass. Every step looks at most two characters ahead, so scanning
+/// takes linear time, and the tokens borrow their lexemes from the source.
 pub struct Scanner<'s, 'e> {
-    /// The rest of the original source code.
-    rest: &'s str,
-    /// The offset to the start of the original source.
-    offset: usize,
-    /// The cursor relative to the offset.
-    cursor: usize,
+    source: &'s str,
+    /// The characters after the cursor with their byte offsets into the source.
+    chars: CharIndices<'s>,
+    /// The byte offset where the current token starts.
+    start: usize,
+    dialect: Dialect,
+    /// Whether the previous token can be followed by a size suffix like `.W`. Otherwise, a dot
+    /// starts a local label like `.loop`.
+    follows_operand: bool,
+    /// Whether the next letter is the size of a mnemonic in the MIT dialect, like the `l` of
+    /// `movel`.
+    attached_size: bool,
+    /// Whether the line already has an operation, so that the following identifiers are operands.
+    has_operation: bool,
+    /// The field of the line, for dialects where whitespace after the operands starts a comment.
+    field: Field,
     errors: &'e mut ErrorCollector,
 }
 
+/// The whitespace-separated fields of a line, like `label MOVE.W D0,D1 comment`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Field {
+    Label,
+    Operation,
+    Operands,
+    Comment,
+}
+
+impl Field {
+    fn next(self) -> Field {
+        match self {
+            Field::Label => Field::Operation,
+            Field::Operation => Field::Operands,
+            Field::Operands | Field::Comment => Field::Comment,
+        }
+    }
+}
+
 impl<'s> Scanner<'s, '_> {
+    /// The byte offset of the next character.
+    fn cursor(&self) -> usize {
+        self.chars.offset()
+    }
+
     fn is_at_end(&self) -> bool {
-        self.rest[self.cursor..].is_empty()
+        self.chars.as_str().is_empty()
     }
 
     fn flush(&mut self) {
-        self.offset += self.cursor;
-        self.rest = &self.rest[self.cursor..];
-        self.cursor = 0;
+        self.start = self.cursor();
     }
 
-    fn lexeme(&self) -> String {
-        self.rest.chars().take(self.cursor).collect()
+    fn lexeme(&self) -> &'s str {
+        &self.source[self.start..self.cursor()]
     }
 
     fn peek(&self) -> char {
-        match self.rest.chars().nth(self.cursor) {
-            Some(character) => character,
-            None => '\0',
-        }
+        self.chars.as_str().chars().next().unwrap_or('\0')
+    }
+
+    fn peek_second(&self) -> char {
+        self.chars.as_str().chars().nth(1).unwrap_or('\0')
     }
 
     fn advance(&mut self) -> char {
-        let removed = self.peek();
-        self.cursor += 1;
-        removed
+        self.chars.next().map_or('\0', |(_, c)| c)
     }
 
-    fn advance_while<Test>(&mut self, test: Test) -> String
+    fn advance_while<Test>(&mut self, test: Test) -> &'s str
     where
         Test: Fn(char) -> bool,
     {
@@ -59,24 +108,66 @@ impl<'s> Scanner<'s, '_> {
     }
 
     fn range(&self) -> Range {
-        self.offset..self.offset + self.cursor
+        self.start..self.cursor()
+    }
+
+    /// Whether the byte before the offset is a space or tab.
+    fn follows_whitespace(&self, offset: usize) -> bool {
+        offset > 0 && matches!(self.source.as_bytes()[offset - 1], b' ' | b'\t')
+    }
+
+    fn is_at_line_start(&self) -> bool {
+        self.start == 0 || self.source.as_bytes()[self.start - 1] == b'\n'
     }
 
-    fn scan_next_token(&mut self) -> Result<Token, Error> {
+    fn scan_next_token(&mut self) -> Result<Token<'s>, Error> {
+        if self.field == Field::Comment && !matches!(self.peek(), ' ' | '\t' | '\r' | '\n') {
+            let token = self.parse_comment();
+            self.flush();
+            return token;
+        }
+        let is_mit = self.dialect == Dialect::Mit;
+        let is_at_line_start = self.is_at_line_start();
         let token = match (self.advance(), self.peek()) {
+            (letter, _) if self.attached_size => {
+                self.attached_size = false;
+                Ok(Token::Size(self.range(), size_suffix(letter).unwrap()))
+            }
             ('(', _) => Ok(Token::OpeningParen(self.range())),
             (')', _) => Ok(Token::ClosingParen(self.range())),
             (',', _) => Ok(Token::Comma(self.range())),
+            ('.', next) if !self.follows_operand && (next.is_ascii_alphabetic() || next == '_') => {
+                self.parse_identifier()
+            }
+            ('.', next) if self.follows_operand && size_suffix(next).is_some() => {
+                self.parse_size(next)
+            }
             ('.', _) => Ok(Token::Dot(self.range())),
             ('+', _) => Ok(Token::Plus(self.range())),
+            ('#', _) if is_mit && is_at_line_start => self.parse_comment(),
             ('#', _) => Ok(Token::NumberSign(self.range())),
+            (':', next) if is_mit && self.follows_operand && size_suffix(next).is_some() => {
+                self.parse_size(next)
+            }
             (':', _) => Ok(Token::Colon(self.range())),
-            ('0'..='9', _) | ('-', '0'..='9') => self.parse_decimal_number(),
+            ('@', _) if is_mit => Ok(Token::At(self.range())),
+            ('%', next) if is_mit && next.is_ascii_alphabetic() => self.parse_prefixed_register(),
+            ('0', 'x') | ('0', 'X') if is_mit => {
+                self.advance();
+                self.parse_hex_number()
+            }
+            // Negative numbers are scanned as a minus followed by a number, so that -(A0) and -4(A0)
+            // look the same to the parser.
+            ('0'..='9', _) => self.parse_decimal_number(),
             ('$', _) => self.parse_hex_number(),
             ('-', _) => Ok(Token::Minus(self.range())),
             // TODO(marcelgarus): Merge the following branches into one as soon as or-patterns are supported.
             (';', _) => self.parse_comment(),
-            ('*', _) => self.parse_comment(),
+            // Other dialects use `*` for the current address.
+            ('*', _) if self.dialect == Dialect::Motorola || is_at_line_start => {
+                self.parse_comment()
+            }
+            ('|', _) if is_mit => self.parse_comment(),
             // TODO(marcelgarus): Merge the following branches into one as soon as or-patterns are supported.
             (' ', _) => Ok(Token::Whitespace(self.range())),
             ('\t', _) => Ok(Token::Whitespace(self.range())),
@@ -97,47 +188,150 @@ impl<'s> Scanner<'s, '_> {
         token
     }
 
-    fn parse_decimal_number(&mut self) -> Result<Token, Error> {
-        let number = self.advance_while(|c| ('0'..'9').contains(&c));
+    /// Parses a decimal number or a numeric local label like `1$`.
+    fn parse_decimal_number(&mut self) -> Result<Token<'s>, Error> {
+        let number = self.advance_while(|c| c.is_ascii_digit());
+        if self.peek() == '$' {
+            self.advance();
+            return Ok(Token::Identifier(self.range(), self.lexeme()));
+        }
         match number.parse() {
             Ok(number) => Ok(Token::Number(self.range(), number)),
             Err(_) => Err(Error::cannot_parse_decimal_number(self.range())),
         }
     }
 
-    fn parse_hex_number(&mut self) -> Result<Token, Error> {
-        let number = self.advance_while(|c| ('0'..'9').contains(&c) || ('a'..'f').contains(&c));
-        match u32::from_str_radix(&number, 16) {
+    /// Parses the digits of a hexadecimal number after its `$` or `0x`.
+    fn parse_hex_number(&mut self) -> Result<Token<'s>, Error> {
+        let prefix = self.lexeme().len();
+        let number = self.advance_while(|c| c.is_ascii_hexdigit());
+        match u32::from_str_radix(&number[prefix..], 16) {
             Ok(number) => Ok(Token::Number(self.range(), number)),
             Err(_) => Err(Error::cannot_parse_hex_number(self.range())),
         }
     }
 
-    fn parse_comment(&mut self) -> Result<Token, Error> {
+    fn parse_comment(&mut self) -> Result<Token<'s>, Error> {
         let content = self.advance_while(|c| c != '\n' && c != '\r');
         Ok(Token::Comment(self.range(), content))
     }
 
-    fn parse_identifier(&mut self) -> Result<Token, Error> {
-        let identifier = self.advance_while(|c| {
-            ('a'..='z').contains(&c)
-                || ('A'..='Z').contains(&c)
-                || ('0'..='9').contains(&c)
-                || c == '_'
-        });
-        Ok(Token::Identifier(self.range(), identifier))
+    /// Parses a size suffix like `.W`, or `:w` in the MIT dialect. Otherwise, like for `.Wx`, the
+    /// dot or colon is a token of its own.
+    fn parse_size(&mut self, letter: char) -> Result<Token<'s>, Error> {
+        let second = self.peek_second();
+        if second.is_ascii_alphanumeric() || second == '_' {
+            return Ok(match self.lexeme() {
+                ":" => Token::Colon(self.range()),
+                _ => Token::Dot(self.range()),
+            });
+        }
+        self.advance();
+        Ok(Token::Size(self.range(), size_suffix(letter).unwrap()))
+    }
+
+    /// Parses a register with the `%` prefix of the MIT dialect, like `%d0`.
+    fn parse_prefixed_register(&mut self) -> Result<Token<'s>, Error> {
+        let name = &self.advance_while(|c| c.is_ascii_alphanumeric())[1..];
+        match register(name) {
+            Some(register) => Ok(Token::Register(self.range(), name, register)),
+            None => Err(Error::unknown_register(self.range(), name)),
+        }
+    }
+
+    /// Identifiers can contain `\@`, which macros replace with a unique number to generate labels.
+    /// Registers and mnemonics are recognized regardless of their case. In the MIT dialect,
+    /// operations can have their size attached, like `movel`. Labels like `adds:` and operands
+    /// keep their name, because they're never operations: Labels start in the first column or
+    /// end with a colon, and operands follow the operation.
+    fn parse_identifier(&mut self) -> Result<Token<'s>, Error> {
+        let after_first = self.chars.clone();
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (c, _) if c.is_ascii_alphanumeric() || c == '_' => {
+                    self.advance();
+                }
+                ('\\', '@') => {
+                    self.advance();
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        let lexeme = self.lexeme();
+        let is_operation = !self.has_operation && !self.is_at_line_start() && self.peek() != ':';
+        self.has_operation |= is_operation;
+        if self.dialect == Dialect::Mit
+            && is_operation
+            && register(lexeme).is_none()
+            && !is_mnemonic(lexeme)
+        {
+            let (name, size) = lexeme.split_at(lexeme.len() - 1);
+            if size.chars().all(|size| size_suffix(size).is_some()) && is_mnemonic(name) {
+                self.chars = after_first;
+                while self.cursor() < self.start + name.len() {
+                    self.advance();
+                }
+                self.attached_size = true;
+                return Ok(Token::Mnemonic(self.range(), name));
+            }
+        }
+        Ok(if let Some(register) = register(lexeme) {
+            Token::Register(self.range(), lexeme, register)
+        } else if is_mnemonic(lexeme) {
+            Token::Mnemonic(self.range(), lexeme)
+        } else {
+            Token::Identifier(self.range(), lexeme)
+        })
+    }
+
+    /// Whitespace moves on to the next field, unless it follows other whitespace. Indented labels
+    /// like `  loop:` or `  .loop` and operations without operands are fields of their own.
+    fn next_field(&self, token: &Token) -> Field {
+        match token {
+            Token::Newline(_) => Field::Label,
+            Token::Whitespace(range) if self.follows_whitespace(range.start) => self.field,
+            Token::Whitespace(_) => self.field.next(),
+            Token::Colon(_) if self.field == Field::Operation => Field::Label,
+            Token::Identifier(_, name)
+                if self.field == Field::Operation
+                    && (name.starts_with('.') || name.ends_with('$')) =>
+            {
+                Field::Label
+            }
+            Token::Mnemonic(_, name) if self.field == Field::Operation && !has_operands(name) => {
+                Field::Operands
+            }
+            _ => self.field,
+        }
     }
 
     // pub fn peek_token(&mut self)
 }
 
-impl Iterator for Scanner<'_, '_> {
-    type Item = Token;
+impl<'s> Iterator for Scanner<'s, '_> {
+    type Item = Token<'s>;
 
-    fn next(&mut self) -> std::option::Option<Token> {
+    fn next(&mut self) -> Option<Token<'s>> {
         while !self.is_at_end() {
             match self.scan_next_token() {
-                Ok(token) => return Some(token),
+                Ok(token) => {
+                    if let Token::Newline(_) = token {
+                        self.has_operation = false;
+                    }
+                    if self.dialect.has_comments_after_operands() {
+                        self.field = self.next_field(&token);
+                    }
+                    self.follows_operand = matches!(
+                        token,
+                        Token::Identifier(_, _)
+                            | Token::Register(_, _, _)
+                            | Token::Mnemonic(_, _)
+                            | Token::Number(_, _)
+                            | Token::ClosingParen(_)
+                    );
+                    return Some(token);
+                }
                 Err(error) => self.errors.push(error),
             }
         }
@@ -148,6 +342,9 @@ impl Iterator for Scanner<'_, '_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::keywords::{Register, SizeSuffix};
+    use m68k_reloaded_common::errors::PrintErrors;
+    use proptest::prelude::*;
     use std::collections::HashMap;
 
     #[test]
@@ -194,50 +391,291 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_scan_numbers() {
+        expect_scanned_tokens("42", vec![&Token::Number(0..2, 42)]);
+        expect_scanned_tokens("$fF", vec![&Token::Number(0..3, 255)]);
+        expect_scanned_tokens("-8", vec![&Token::Minus(0..1), &Token::Number(1..2, 8)]);
+    }
+
     #[test]
     fn test_scan_comment_empty() {
-        expect_scanned_tokens("*", vec![&Token::Comment(0..1, String::from("*"))]);
-        expect_scanned_tokens(";", vec![&Token::Comment(0..1, String::from(";"))]);
+        expect_scanned_tokens("*", vec![&Token::Comment(0..1, "*")]);
+        expect_scanned_tokens(";", vec![&Token::Comment(0..1, ";")]);
     }
     #[test]
     fn test_scan_comment_simple() {
         let comment = "*comment...";
-        expect_scanned_tokens(comment, vec![&Token::Comment(0..11, String::from(comment))]);
+        expect_scanned_tokens(comment, vec![&Token::Comment(0..11, comment)]);
     }
     #[test]
     fn test_scan_comment_unicode() {
-        let comment = "äöüß é¡™£¢∞§¶•ªº–≠製漢語 ด้้้้้็็็็็้้้้้็็็็็้้้้้้้้็็็็็้้้้้็็็็็้้้้้้้้็็็็็้้้้้็็็็็้้้้้้้้็็็็็้้้้้็็็็❤️🇺🇸🇷🇺🇸 Ṱ̺̺̕o͞ ̷i̲̬͇̪͙n̝̗͕v̟̜̘̦͟o̶̙̰̠kè͚̮̺̪̹̱̤ ᴉlɐ";
-        expect_scanned_tokens(comment, vec![&Token::Comment(0..11, String::from(comment))]);
+        let comment = "*äöüß é¡™£¢∞§¶•ªº–≠製漢語 ด้้้้้็็็็็้้้้้็็็็็้้้้้้้้็็็็็้้้้้็็็็็้้้้้้้้็็็็็้้้้้็็็็็้้้้้้้้็็็็็้้้้้็็็็❤️🇺🇸🇷🇺🇸 Ṱ̺̺̕o͞ ̷i̲̬͇̪͙n̝̗͕v̟̜̘̦͟o̶̙̰̠kè͚̮̺̪̹̱̤ ᴉlɐ";
+        expect_scanned_tokens(comment, vec![&Token::Comment(0..comment.len(), comment)]);
+    }
+
+    #[test]
+    fn test_scan_after_non_ascii_characters() {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan("ä ADD ;ü\nx", &mut errors).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Whitespace(2..3),
+                Token::Mnemonic(3..6, "ADD"),
+                Token::Whitespace(6..7),
+                Token::Comment(7..10, ";ü"),
+                Token::Newline(10..11),
+                Token::Identifier(11..12, "x"),
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].range, 0..2);
     }
 
     #[test]
     fn test_scan_identifier() {
-        let identifiers = vec![
+        let identifiers = [
         "i",
         "id",
         "_id",
         "id123",
-        "D0",
-        "D7",
-        "A0",
-        "A7",
+        "D8",
+        "A",
+        "MOVEM",
         "Loremipsumdolorsitametconsecteturadipiscingelit_Maurisvitaeerosblanditipsumviverraposuereetanibh_Curabiturnislmetuslaciniautmagnaultricieselementumtempormassa",
         ];
 
         for identifier in identifiers.iter() {
             expect_scanned_tokens(
                 identifier,
-                vec![&Token::Identifier(
-                    0..identifier.len(),
-                    String::from(*identifier),
-                )],
+                vec![&Token::Identifier(0..identifier.len(), identifier)],
+            );
+        }
+    }
+
+    #[test]
+    fn test_scan_labels() {
+        expect_scanned_tokens(".loop", vec![&Token::Identifier(0..5, ".loop")]);
+        expect_scanned_tokens("1$", vec![&Token::Identifier(0..2, "1$")]);
+        expect_scanned_tokens(
+            "loop\\@:",
+            vec![&Token::Identifier(0..6, "loop\\@"), &Token::Colon(6..7)],
+        );
+        expect_scanned_tokens(
+            "ADD.W .x,12$",
+            vec![
+                &Token::Mnemonic(0..3, "ADD"),
+                &Token::Size(3..5, SizeSuffix::Word),
+                &Token::Whitespace(5..6),
+                &Token::Identifier(6..8, ".x"),
+                &Token::Comma(8..9),
+                &Token::Identifier(9..12, "12$"),
+            ],
+        );
+        expect_scanned_tokens(
+            "$10.L",
+            vec![
+                &Token::Number(0..3, 16),
+                &Token::Size(3..5, SizeSuffix::LongWord),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_scan_keywords() {
+        expect_scanned_tokens(
+            "d0 A7 sp Pc sr CCR usp",
+            vec![
+                &Token::Register(0..2, "d0", Register::Dn(0)),
+                &Token::Whitespace(2..3),
+                &Token::Register(3..5, "A7", Register::An(7)),
+                &Token::Whitespace(5..6),
+                &Token::Register(6..8, "sp", Register::An(7)),
+                &Token::Whitespace(8..9),
+                &Token::Register(9..11, "Pc", Register::Pc),
+                &Token::Whitespace(11..12),
+                &Token::Register(12..14, "sr", Register::Sr),
+                &Token::Whitespace(14..15),
+                &Token::Register(15..18, "CCR", Register::Ccr),
+                &Token::Whitespace(18..19),
+                &Token::Register(19..22, "usp", Register::Usp),
+            ],
+        );
+        expect_scanned_tokens(
+            "bne.s (a0,d1.w).l",
+            vec![
+                &Token::Mnemonic(0..3, "bne"),
+                &Token::Size(3..5, SizeSuffix::Short),
+                &Token::Whitespace(5..6),
+                &Token::OpeningParen(6..7),
+                &Token::Register(7..9, "a0", Register::An(0)),
+                &Token::Comma(9..10),
+                &Token::Register(10..12, "d1", Register::Dn(1)),
+                &Token::Size(12..14, SizeSuffix::Word),
+                &Token::ClosingParen(14..15),
+                &Token::Size(15..17, SizeSuffix::LongWord),
+            ],
+        );
+        // Only single letters are sizes.
+        expect_scanned_tokens(
+            "ADD.Wx",
+            vec![
+                &Token::Mnemonic(0..3, "ADD"),
+                &Token::Dot(3..4),
+                &Token::Identifier(4..6, "Wx"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_scan_mit_dialect() {
+        expect_scanned_dialect_tokens(
+            " movel %a0@(0x1F,d0:w),a1@+ | copy",
+            Dialect::Mit,
+            vec![
+                &Token::Whitespace(0..1),
+                &Token::Mnemonic(1..5, "move"),
+                &Token::Size(5..6, SizeSuffix::LongWord),
+                &Token::Whitespace(6..7),
+                &Token::Register(7..10, "a0", Register::An(0)),
+                &Token::At(10..11),
+                &Token::OpeningParen(11..12),
+                &Token::Number(12..16, 31),
+                &Token::Comma(16..17),
+                &Token::Register(17..19, "d0", Register::Dn(0)),
+                &Token::Size(19..21, SizeSuffix::Word),
+                &Token::ClosingParen(21..22),
+                &Token::Comma(22..23),
+                &Token::Register(23..25, "a1", Register::An(1)),
+                &Token::At(25..26),
+                &Token::Plus(26..27),
+                &Token::Whitespace(27..28),
+                &Token::Comment(28..34, "| copy"),
+            ],
+        );
+        expect_scanned_dialect_tokens(
+            "# comment\nloop: bras loop",
+            Dialect::Mit,
+            vec![
+                &Token::Comment(0..9, "# comment"),
+                &Token::Newline(9..10),
+                &Token::Identifier(10..14, "loop"),
+                &Token::Colon(14..15),
+                &Token::Whitespace(15..16),
+                &Token::Mnemonic(16..19, "bra"),
+                &Token::Size(19..20, SizeSuffix::Short),
+                &Token::Whitespace(20..21),
+                &Token::Identifier(21..25, "loop"),
+            ],
+        );
+
+        // Labels and operands never have an attached size.
+        expect_scanned_dialect_tokens(
+            "adds:\n movel: bras adds\nmovew",
+            Dialect::Mit,
+            vec![
+                &Token::Identifier(0..4, "adds"),
+                &Token::Colon(4..5),
+                &Token::Newline(5..6),
+                &Token::Whitespace(6..7),
+                &Token::Identifier(7..12, "movel"),
+                &Token::Colon(12..13),
+                &Token::Whitespace(13..14),
+                &Token::Mnemonic(14..17, "bra"),
+                &Token::Size(17..18, SizeSuffix::Short),
+                &Token::Whitespace(18..19),
+                &Token::Identifier(19..23, "adds"),
+                &Token::Newline(23..24),
+                &Token::Identifier(24..29, "movew"),
+            ],
+        );
+
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan_dialect("%d8", Dialect::Mit, &mut errors).collect();
+        assert!(tokens.is_empty());
+        assert_eq!(errors[0].code, "unknown_register");
+    }
+
+    #[test]
+    fn test_scan_comments_after_operands() {
+        let source = "* header\nx MOVE.W D0,D1 copy it\n  .l: RTS done\n NOP";
+        for dialect in [Dialect::Devpac, Dialect::Vasm].iter() {
+            let mut errors = vec![];
+            let comments: Vec<Token> = scan_dialect(source, *dialect, &mut errors)
+                .filter(|token| matches!(token, Token::Comment(_, _)))
+                .collect();
+            assert!(errors.is_empty());
+            assert_eq!(
+                comments,
+                vec![
+                    Token::Comment(0..8, "* header"),
+                    Token::Comment(24..31, "copy it"),
+                    Token::Comment(42..46, "done"),
+                ]
             );
         }
+
+        // Other dialects allow whitespace between operands.
+        expect_scanned_tokens(
+            "D0, D1",
+            vec![
+                &Token::Register(0..2, "D0", Register::Dn(0)),
+                &Token::Comma(2..3),
+                &Token::Whitespace(3..4),
+                &Token::Register(4..6, "D1", Register::Dn(1)),
+            ],
+        );
+    }
+
+    /// Random text, and lines made of fragments of all dialects, which are more likely to reach the
+    /// interesting parts of the scanner.
+    const SOURCE: &str = concat!(
+        ".*|",
+        "([ \\t\\r\\n,.:;*|#$%@()+-]|[0-9a-fA-FxX]{1,3}|%?[dDaA][0-7]|move[lwbsq]?|\\.[bwlsq_]|ä){0,40}",
+    );
+
+    proptest! {
+        /// Every byte of the source ends up in exactly one token or error.
+        #[test]
+        fn test_scan_covers_every_byte(source in SOURCE, dialect in 0..4usize) {
+            let mut errors = vec![];
+            let tokens: Vec<Token> =
+                scan_dialect(&source, Dialect::ALL[dialect], &mut errors).collect();
+            let mut ranges: Vec<Range> = tokens
+                .iter()
+                .map(Token::range)
+                .chain(errors.iter().map(|error| error.range.clone()))
+                .collect();
+            ranges.sort_by_key(|range| range.start);
+            let mut end = 0;
+            for range in ranges {
+                prop_assert_eq!(range.start, end);
+                prop_assert!(range.start < range.end);
+                end = range.end;
+            }
+            prop_assert_eq!(end, source.len());
+        }
+
+        /// Numbers take all of their digits, including 9 and uppercase hex digits.
+        #[test]
+        fn test_scan_all_digits(number in any::<u32>(), digits in "[0-9a-fA-F]{1,8}") {
+            let decimal = number.to_string();
+            let expected = Token::Number(0..decimal.len(), number);
+            expect_scanned_tokens(&decimal, vec![&expected]);
+            let hex = format!("${}", digits);
+            let expected = Token::Number(0..hex.len(), u32::from_str_radix(&digits, 16).unwrap());
+            expect_scanned_tokens(&hex, vec![&expected]);
+        }
     }
 
     fn expect_scanned_tokens(source: &str, expected_tokens: Vec<&Token>) {
+        expect_scanned_dialect_tokens(source, Dialect::Motorola, expected_tokens);
+    }
+
+    fn expect_scanned_dialect_tokens(source: &str, dialect: Dialect, expected_tokens: Vec<&Token>) {
         let mut errors: Vec<Error> = Default::default();
-        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let tokens: Vec<Token> = scan_dialect(source, dialect, &mut errors).collect();
 
         errors.print();
         assert!(errors.is_empty());
@@ -1,7 +1,9 @@
+use crate::keywords::{Register, SizeSuffix};
+use m68k_reloaded_common::cursor::Item;
 pub use m68k_reloaded_common::Range;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
-pub enum Token {
+pub enum Token<'s> {
   // Single characters.
   OpeningParen(Range), // (
   ClosingParen(Range), // )
@@ -11,18 +13,24 @@ pub enum Token {
   Plus(Range),         // +
   NumberSign(Range),   // #
   Colon(Range),        // :
+  At(Range),           // @, only in the MIT dialect
 
   // Literals.
-  Comment(Range, String),
-  Identifier(Range, String),
+  Comment(Range, &'s str),
+  Identifier(Range, &'s str),
   Number(Range, u32),
 
+  // Keywords, which are matched case-insensitively.
+  Register(Range, &'s str, Register), // The name as written, like sp, without MIT's %
+  Mnemonic(Range, &'s str),
+  Size(Range, SizeSuffix), // .B, .S, .W or .L
+
   // Whitespace.
   Whitespace(Range),
   Newline(Range),
 }
 
-impl Token {
+impl Token<'_> {
   pub fn range(&self) -> Range {
     match self {
       Token::OpeningParen(range)
@@ -33,15 +41,71 @@ impl Token {
       | Token::Plus(range)
       | Token::NumberSign(range)
       | Token::Colon(range)
+      | Token::At(range)
       | Token::Comment(range, _)
       | Token::Identifier(range, _)
       | Token::Number(range, _)
+      | Token::Register(range, _, _)
+      | Token::Mnemonic(range, _)
+      | Token::Size(range, _)
       | Token::Whitespace(range)
       | Token::Newline(range) => range.clone(),
     }
   }
 }
 
+/// The kind of a [Token], without its data.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum TokenKind {
+  OpeningParen,
+  ClosingParen,
+  Comma,
+  Dot,
+  Minus,
+  Plus,
+  NumberSign,
+  Colon,
+  At,
+  Comment,
+  Identifier,
+  Number,
+  Register,
+  Mnemonic,
+  Size,
+  Whitespace,
+  Newline,
+}
+
+impl Item for Token<'_> {
+  type Kind = TokenKind;
+
+  fn kind(&self) -> TokenKind {
+    match self {
+      Token::OpeningParen(_) => TokenKind::OpeningParen,
+      Token::ClosingParen(_) => TokenKind::ClosingParen,
+      Token::Comma(_) => TokenKind::Comma,
+      Token::Dot(_) => TokenKind::Dot,
+      Token::Minus(_) => TokenKind::Minus,
+      Token::Plus(_) => TokenKind::Plus,
+      Token::NumberSign(_) => TokenKind::NumberSign,
+      Token::Colon(_) => TokenKind::Colon,
+      Token::At(_) => TokenKind::At,
+      Token::Comment(_, _) => TokenKind::Comment,
+      Token::Identifier(_, _) => TokenKind::Identifier,
+      Token::Number(_, _) => TokenKind::Number,
+      Token::Register(_, _, _) => TokenKind::Register,
+      Token::Mnemonic(_, _) => TokenKind::Mnemonic,
+      Token::Size(_, _) => TokenKind::Size,
+      Token::Whitespace(_) => TokenKind::Whitespace,
+      Token::Newline(_) => TokenKind::Newline,
+    }
+  }
+
+  fn range(&self) -> Range {
+    Token::range(self)
+  }
+}
+
 /*class Token {
   String toString() {
     return '${type.toString().substring('TokenType.'.length)} at $location: "$lexeme" (Literal: $literal)';
@@ -0,0 +1,190 @@
+//! The interface between the CPU and everything it accesses: RAM, ROM and other devices.
+//!
+//! Devices are mapped into the address space by [Memory](crate::memory::Memory), which passes them
+//! addresses relative to the start of their region and reports accesses outside of all regions as
+//! bus errors.
+
+use crate::exception::Exception;
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use m68k_reloaded_parser::statements::Size;
+
+pub trait Bus {
+    /// Reads a value of the size. Reading the registers of devices can have side effects, like
+    /// taking a received byte.
+    fn read(&mut self, address: LongWord, size: Size) -> Result<LongWord, Exception>;
+
+    fn write(&mut self, address: LongWord, size: Size, value: LongWord) -> Result<(), Exception>;
+
+    /// Reads without side effects, like debuggers and disassemblers do. Registers that can't be
+    /// read like that report a bus error.
+    fn peek(&self, address: LongWord, size: Size) -> Result<LongWord, Exception>;
+
+    /// Copies the bytes into memory, like a loader or debugger does. Unlike writes, this also works
+    /// for ROM.
+    fn load(&mut self, address: LongWord, bytes: &[Byte]) -> Result<(), Exception> {
+        for (offset, &byte) in bytes.iter().enumerate() {
+            self.write(
+                address.wrapping_add(offset as LongWord),
+                Size::Byte,
+                LongWord::from(byte),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Advances the time by one instruction and returns the interrupt level requested afterwards.
+    /// The simulator isn't cycle-exact, so devices count instructions instead of clock cycles.
+    fn tick(&mut self) -> Option<Byte> {
+        None
+    }
+
+    /// Whether interrupts may be requested in the future, so that a stopped CPU waits for them.
+    fn can_interrupt(&self) -> bool {
+        false
+    }
+
+    fn read_word(&mut self, address: LongWord) -> Result<Word, Exception> {
+        self.read(address, Size::Word).map(|value| value as Word)
+    }
+
+    fn read_long_word(&mut self, address: LongWord) -> Result<LongWord, Exception> {
+        self.read(address, Size::LongWord)
+    }
+}
+
+/// Something that can be mapped into a region of the address space.
+pub trait Device: Bus {
+    /// The number of bytes of the region.
+    fn size(&self) -> LongWord;
+}
+
+pub fn bytes_of(size: Size) -> LongWord {
+    match size {
+        Size::Byte => 1,
+        Size::Word => 2,
+        Size::LongWord => 4,
+    }
+}
+
+fn read_bytes(bytes: &[Byte], offset: LongWord, size: Size) -> Result<LongWord, Exception> {
+    let start = offset as usize;
+    let end = start + bytes_of(size) as usize;
+    match bytes.get(start..end) {
+        Some(bytes) => Ok(bytes
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | LongWord::from(byte))),
+        None => Err(Exception::BusError(offset)),
+    }
+}
+
+fn write_bytes(
+    bytes: &mut [Byte],
+    offset: LongWord,
+    size: Size,
+    value: LongWord,
+) -> Result<(), Exception> {
+    let length = bytes_of(size) as usize;
+    let start = offset as usize;
+    match bytes.get_mut(start..start + length) {
+        Some(bytes) => {
+            bytes.copy_from_slice(&value.to_be_bytes()[4 - length..]);
+            Ok(())
+        }
+        None => Err(Exception::BusError(offset)),
+    }
+}
+
+pub struct Ram {
+    bytes: Vec<Byte>,
+}
+
+impl Ram {
+    /// Cleared RAM of the size.
+    pub fn new(size: LongWord) -> Ram {
+        Ram {
+            bytes: vec![0; size as usize],
+        }
+    }
+}
+
+impl Bus for Ram {
+    fn read(&mut self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        read_bytes(&self.bytes, address, size)
+    }
+
+    fn write(&mut self, address: LongWord, size: Size, value: LongWord) -> Result<(), Exception> {
+        write_bytes(&mut self.bytes, address, size, value)
+    }
+
+    fn peek(&self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        read_bytes(&self.bytes, address, size)
+    }
+}
+
+impl Device for Ram {
+    fn size(&self) -> LongWord {
+        self.bytes.len() as LongWord
+    }
+}
+
+/// Read-only memory. Writes by the CPU cause bus errors.
+pub struct Rom {
+    bytes: Vec<Byte>,
+}
+
+impl Rom {
+    pub fn new(bytes: Vec<Byte>) -> Rom {
+        Rom { bytes }
+    }
+}
+
+impl Bus for Rom {
+    fn read(&mut self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        read_bytes(&self.bytes, address, size)
+    }
+
+    fn write(&mut self, address: LongWord, _: Size, _: LongWord) -> Result<(), Exception> {
+        Err(Exception::BusError(address))
+    }
+
+    fn peek(&self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        read_bytes(&self.bytes, address, size)
+    }
+
+    fn load(&mut self, address: LongWord, bytes: &[Byte]) -> Result<(), Exception> {
+        let start = address as usize;
+        match self.bytes.get_mut(start..start + bytes.len()) {
+            Some(target) => {
+                target.copy_from_slice(bytes);
+                Ok(())
+            }
+            None => Err(Exception::BusError(address)),
+        }
+    }
+}
+
+impl Device for Rom {
+    fn size(&self) -> LongWord {
+        self.bytes.len() as LongWord
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ram_and_rom() {
+        let mut ram = Ram::new(8);
+        ram.write(2, Size::LongWord, 0x1234_5678).unwrap();
+        assert_eq!(ram.read(4, Size::Word), Ok(0x5678));
+        assert_eq!(ram.peek(3, Size::Byte), Ok(0x34));
+        assert_eq!(ram.read(6, Size::LongWord), Err(Exception::BusError(6)));
+
+        let mut rom = Rom::new(vec![0; 4]);
+        rom.load(0, &[0x4e, 0x71]).unwrap();
+        assert_eq!(rom.read_word(0), Ok(0x4e71));
+        assert_eq!(rom.write(0, Size::Word, 0), Err(Exception::BusError(0)));
+        assert_eq!(rom.load(2, &[1, 2, 3]), Err(Exception::BusError(2)));
+    }
+}
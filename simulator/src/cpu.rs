@@ -0,0 +1,893 @@
+//! Executes instructions on the registers of a 68000.
+//!
+//! Exceptions are processed like on the real CPU: the PC and status register are pushed on the
+//! supervisor stack, bus and address errors additionally push the accessed address, and execution
+//! continues at the address in the exception's vector. Vectors that are zero count as not
+//! installed, so that programs without a vector table stop at their first exception instead of
+//! jumping to address 0.
+
+use crate::bus::{bytes_of, Bus};
+use crate::decode::{decode, IndexRegister, Instruction, Operand};
+use crate::exception::{Exception, RESET_PC, RESET_STACK_POINTER};
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use m68k_reloaded_parser::statements::{Condition, OperationType, Size};
+
+/// The bits of the status register.
+pub const CARRY: Word = 0x0001;
+pub const OVERFLOW: Word = 0x0002;
+pub const ZERO: Word = 0x0004;
+pub const NEGATIVE: Word = 0x0008;
+pub const EXTEND: Word = 0x0010;
+pub const INTERRUPT_MASK: Word = 0x0700;
+pub const SUPERVISOR: Word = 0x2000;
+pub const TRACE: Word = 0x8000;
+
+/// The bits of the status register that exist on the 68000.
+const IMPLEMENTED: Word = TRACE | SUPERVISOR | INTERRUPT_MASK | 0x1f;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Cpu {
+    pub d: [LongWord; 8],
+    /// A7 is the stack pointer of the current mode.
+    pub a: [LongWord; 8],
+    pub pc: LongWord,
+    /// Only change the supervisor flag with [Cpu::set_sr], which switches the stack pointers.
+    pub sr: Word,
+    /// The stack pointer of the other mode: the USP in supervisor mode and the SSP in user mode.
+    pub inactive_stack_pointer: LongWord,
+    /// Set by `STOP` until the next interrupt.
+    pub is_stopped: bool,
+    /// A bit for every interrupt level that was requested and not yet processed.
+    interrupts: Byte,
+    /// The level devices currently assert on the interrupt lines.
+    interrupt_lines: Byte,
+}
+
+/// What a call to [Cpu::step] did.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Step {
+    /// `None` if no instruction was executed, because the CPU is stopped, an interrupt was
+    /// processed instead or the instruction couldn't be decoded.
+    pub instruction: Option<Instruction>,
+    /// The exception whose handler the PC now points to.
+    pub exception: Option<Exception>,
+}
+
+/// Where an operand is read from and written to.
+#[derive(Clone, Copy)]
+enum Location {
+    DataRegister(usize),
+    AddressRegister(usize),
+    Memory(LongWord),
+    Immediate(LongWord),
+}
+
+pub fn mask(size: Size) -> LongWord {
+    match size {
+        Size::Byte => 0xff,
+        Size::Word => 0xffff,
+        Size::LongWord => 0xffff_ffff,
+    }
+}
+
+fn most_significant_bit(size: Size) -> LongWord {
+    mask(size) ^ (mask(size) >> 1)
+}
+
+fn sign_extend(value: LongWord, size: Size) -> LongWord {
+    match size {
+        Size::Byte => value as u8 as i8 as LongWord,
+        Size::Word => value as Word as i16 as LongWord,
+        Size::LongWord => value,
+    }
+}
+
+impl Cpu {
+    /// The state after a reset: in supervisor mode with all interrupts masked.
+    pub fn new(pc: LongWord, stack_pointer: LongWord) -> Cpu {
+        let mut a = [0; 8];
+        a[7] = stack_pointer;
+        Cpu {
+            d: [0; 8],
+            a,
+            pc,
+            sr: SUPERVISOR | INTERRUPT_MASK,
+            inactive_stack_pointer: 0,
+            is_stopped: false,
+            interrupts: 0,
+            interrupt_lines: 0,
+        }
+    }
+
+    /// Resets the CPU like the hardware does, with the stack pointer and PC from the vector table.
+    pub fn reset(bus: &mut dyn Bus) -> Result<Cpu, Exception> {
+        let mut vector = |number: Byte| bus.read_long_word(LongWord::from(number) * 4);
+        let stack_pointer = vector(RESET_STACK_POINTER)?;
+        Ok(Cpu::new(vector(RESET_PC)?, stack_pointer))
+    }
+
+    pub fn is_supervisor(&self) -> bool {
+        self.flag(SUPERVISOR)
+    }
+
+    pub fn usp(&self) -> LongWord {
+        if self.is_supervisor() {
+            self.inactive_stack_pointer
+        } else {
+            self.a[7]
+        }
+    }
+
+    pub fn ssp(&self) -> LongWord {
+        if self.is_supervisor() {
+            self.a[7]
+        } else {
+            self.inactive_stack_pointer
+        }
+    }
+
+    /// Sets the status register and switches to the stack pointer of the new mode.
+    pub fn set_sr(&mut self, sr: Word) {
+        if (sr ^ self.sr) & SUPERVISOR != 0 {
+            std::mem::swap(&mut self.a[7], &mut self.inactive_stack_pointer);
+        }
+        self.sr = sr & IMPLEMENTED;
+    }
+
+    /// Requests a single autovectored interrupt of the level 1 to 7, like pressing an interrupt
+    /// button. The request is kept until the interrupt mask allows processing it, which is
+    /// immediately for level 7, because it can't be masked.
+    pub fn request_interrupt(&mut self, level: Byte) {
+        assert!(
+            (1..=7).contains(&level),
+            "Invalid interrupt level {}.",
+            level
+        );
+        self.interrupts |= 1 << level;
+    }
+
+    /// Sets the level devices assert on the interrupt lines, 0 for none. Devices keep asserting
+    /// it until their handler acknowledges the interrupt, so unlike requests, it interrupts
+    /// again after the handler returns as long as it's asserted. Level 7 is the exception: it's
+    /// only processed once when it's asserted, because it can't be masked.
+    pub fn set_interrupt_lines(&mut self, level: Byte) {
+        if level == 7 && self.interrupt_lines != 7 {
+            self.request_interrupt(7);
+        }
+        self.interrupt_lines = level;
+    }
+
+    /// The highest requested or asserted interrupt level above the interrupt mask.
+    pub fn pending_interrupt(&self) -> Option<Byte> {
+        let mask = ((self.sr & INTERRUPT_MASK) >> 8) as Byte;
+        let requests = self.interrupts | ((1 << self.interrupt_lines) & 0x7e);
+        (1..=7)
+            .rev()
+            .find(|&level| requests & (1 << level) != 0 && (level > mask || level == 7))
+    }
+
+    pub fn flag(&self, flag: Word) -> bool {
+        self.sr & flag != 0
+    }
+
+    fn set_flag(&mut self, flag: Word, value: bool) {
+        if value {
+            self.sr |= flag;
+        } else {
+            self.sr &= !flag;
+        }
+    }
+
+    /// Sets N and Z according to the result and clears V and C, like most operations do.
+    fn set_logic_flags(&mut self, result: LongWord, size: Size) {
+        self.set_flag(NEGATIVE, result & most_significant_bit(size) != 0);
+        self.set_flag(ZERO, result & mask(size) == 0);
+        self.set_flag(OVERFLOW, false);
+        self.set_flag(CARRY, false);
+    }
+
+    pub fn condition(&self, condition: Condition) -> bool {
+        let (c, v, z, n) = (
+            self.flag(CARRY),
+            self.flag(OVERFLOW),
+            self.flag(ZERO),
+            self.flag(NEGATIVE),
+        );
+        match condition {
+            Condition::Hi => !c && !z,
+            Condition::Ls => c || z,
+            Condition::Cc => !c,
+            Condition::Cs => c,
+            Condition::Ne => !z,
+            Condition::Eq => z,
+            Condition::Vc => !v,
+            Condition::Vs => v,
+            Condition::Pl => !n,
+            Condition::Mi => n,
+            Condition::Ge => n == v,
+            Condition::Lt => n != v,
+            Condition::Gt => !z && n == v,
+            Condition::Le => z || n != v,
+        }
+    }
+
+    fn index_value(&self, index: &IndexRegister) -> LongWord {
+        let register = index.register as usize;
+        let value = if index.is_address {
+            self.a[register]
+        } else {
+            self.d[register]
+        };
+        if index.is_long {
+            value
+        } else {
+            sign_extend(value, Size::Word)
+        }
+    }
+
+    /// The address of a memory operand without side effects, as used by LEA and JSR.
+    fn address_of(&self, operand: &Operand) -> Option<LongWord> {
+        Some(match *operand {
+            Operand::AnInd(register) => self.a[register as usize],
+            Operand::AnIndWithDisplacement(displacement, register) => {
+                self.a[register as usize].wrapping_add(displacement as LongWord)
+            }
+            Operand::AnIndWithIndex(displacement, register, index) => self.a[register as usize]
+                .wrapping_add(displacement as LongWord)
+                .wrapping_add(self.index_value(&index)),
+            Operand::AbsoluteWord(address) => sign_extend(LongWord::from(address), Size::Word),
+            Operand::AbsoluteLongWord(address) | Operand::Target(address) => address,
+            Operand::PcIndWithDisplacement(displacement, base) => {
+                base.wrapping_add(displacement as LongWord)
+            }
+            Operand::PcIndWithIndex(displacement, index, base) => base
+                .wrapping_add(displacement as LongWord)
+                .wrapping_add(self.index_value(&index)),
+            _ => return None,
+        })
+    }
+
+    /// Resolves the operand and applies the side effects of `(An)+` and `-(An)`.
+    fn locate(&mut self, operand: &Operand, size: Size) -> Location {
+        // The stack pointer always stays even.
+        let step = |register: u8| match (register, size) {
+            (7, Size::Byte) => 2,
+            _ => bytes_of(size),
+        };
+        match *operand {
+            Operand::Dn(register) => Location::DataRegister(register as usize),
+            Operand::An(register) => Location::AddressRegister(register as usize),
+            Operand::AnIndWithPostInc(register) => {
+                let address = self.a[register as usize];
+                self.a[register as usize] = address.wrapping_add(step(register));
+                Location::Memory(address)
+            }
+            Operand::AnIndWithPreDec(register) => {
+                let address = self.a[register as usize].wrapping_sub(step(register));
+                self.a[register as usize] = address;
+                Location::Memory(address)
+            }
+            Operand::Immediate(value) => Location::Immediate(value),
+            ref operand => Location::Memory(self.address_of(operand).unwrap()),
+        }
+    }
+
+    fn read(
+        &self,
+        bus: &mut dyn Bus,
+        location: Location,
+        size: Size,
+    ) -> Result<LongWord, Exception> {
+        Ok(match location {
+            Location::DataRegister(register) => self.d[register] & mask(size),
+            Location::AddressRegister(register) => self.a[register] & mask(size),
+            Location::Memory(address) => bus.read(address, size)?,
+            Location::Immediate(value) => value & mask(size),
+        })
+    }
+
+    /// Writes the lower part of registers. Address registers are always written completely.
+    fn write(
+        &mut self,
+        bus: &mut dyn Bus,
+        location: Location,
+        size: Size,
+        value: LongWord,
+    ) -> Result<(), Exception> {
+        match location {
+            Location::DataRegister(register) => {
+                self.d[register] = (self.d[register] & !mask(size)) | (value & mask(size))
+            }
+            Location::AddressRegister(register) => self.a[register] = value,
+            Location::Memory(address) => bus.write(address, size, value)?,
+            Location::Immediate(_) => unreachable!("Immediates can't be written."),
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, bus: &mut dyn Bus, value: LongWord) -> Result<(), Exception> {
+        self.a[7] = self.a[7].wrapping_sub(4);
+        bus.write(self.a[7], Size::LongWord, value)
+    }
+
+    fn pop(&mut self, bus: &mut dyn Bus) -> Result<LongWord, Exception> {
+        let value = bus.read_long_word(self.a[7])?;
+        self.a[7] = self.a[7].wrapping_add(4);
+        Ok(value)
+    }
+
+    fn push_word(&mut self, bus: &mut dyn Bus, value: Word) -> Result<(), Exception> {
+        self.a[7] = self.a[7].wrapping_sub(2);
+        bus.write(self.a[7], Size::Word, LongWord::from(value))
+    }
+
+    fn pop_word(&mut self, bus: &mut dyn Bus) -> Result<Word, Exception> {
+        let value = bus.read_word(self.a[7])?;
+        self.a[7] = self.a[7].wrapping_add(2);
+        Ok(value)
+    }
+
+    /// Adds and sets all flags. With `extend`, the X flag is added as well and Z is only cleared,
+    /// so that it stays valid across the words of a multi-precision addition.
+    fn add(
+        &mut self,
+        source: LongWord,
+        destination: LongWord,
+        size: Size,
+        extend: bool,
+    ) -> LongWord {
+        let carry_in = LongWord::from(extend && self.flag(EXTEND));
+        let sum = u64::from(source & mask(size))
+            + u64::from(destination & mask(size))
+            + u64::from(carry_in);
+        let result = sum as LongWord & mask(size);
+        let msb = most_significant_bit(size);
+        let carry = sum > u64::from(mask(size));
+        let overflow = (source & msb) == (destination & msb) && (result & msb) != (source & msb);
+        self.set_flag(CARRY, carry);
+        self.set_flag(EXTEND, carry);
+        self.set_flag(OVERFLOW, overflow);
+        self.set_flag(NEGATIVE, result & msb != 0);
+        if !extend || result != 0 {
+            self.set_flag(ZERO, result == 0);
+        }
+        result
+    }
+
+    /// Processes a pending interrupt or executes the instruction at the PC, followed by a trace
+    /// exception if the trace flag was set.
+    ///
+    /// Returns the exception if it can't be processed because its vector isn't installed or the
+    /// CPU halted on a bus or address error while processing another one. The registers are then
+    /// left like before the exception, so the PC points to the instruction causing it.
+    pub fn step(&mut self, bus: &mut dyn Bus) -> Result<Step, Exception> {
+        if let Some(level) = self.pending_interrupt() {
+            self.interrupts &= !(1 << level);
+            let interrupt = Exception::Interrupt(level);
+            self.process(interrupt, bus)?;
+            return Ok(Step {
+                instruction: None,
+                exception: Some(interrupt),
+            });
+        }
+        if self.is_stopped {
+            return Ok(Step {
+                instruction: None,
+                exception: None,
+            });
+        }
+
+        let is_tracing = self.flag(TRACE);
+        let before = self.clone();
+        match self.execute(bus) {
+            Ok(instruction) => {
+                let exception = if is_tracing {
+                    self.process(Exception::Trace, bus)?;
+                    Some(Exception::Trace)
+                } else {
+                    None
+                };
+                Ok(Step {
+                    instruction: Some(instruction),
+                    exception,
+                })
+            }
+            Err(exception) => {
+                let instruction = if exception.completes_instruction() {
+                    decode(bus, before.pc).ok()
+                } else {
+                    *self = before.clone();
+                    None
+                };
+                if let Err(error) = self.process(exception, bus) {
+                    *self = before;
+                    return Err(error);
+                }
+                Ok(Step {
+                    instruction,
+                    exception: Some(exception),
+                })
+            }
+        }
+    }
+
+    /// Processes the exception. Bus and address errors while processing other exceptions are
+    /// processed themselves, while those during bus and address errors halt the CPU. On errors,
+    /// the registers stay unchanged.
+    fn process(&mut self, exception: Exception, bus: &mut dyn Bus) -> Result<(), Exception> {
+        let before = self.clone();
+        match self.enter_handler(exception, bus) {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                *self = before;
+                if error == exception || exception.is_group_0() {
+                    Err(error)
+                } else {
+                    self.process(error, bus)
+                }
+            }
+        }
+    }
+
+    /// Builds the stack frame and jumps to the handler. Returns the exception itself if its vector
+    /// isn't installed.
+    fn enter_handler(&mut self, exception: Exception, bus: &mut dyn Bus) -> Result<(), Exception> {
+        let handler = bus.read_long_word(LongWord::from(exception.vector()) * 4)?;
+        if handler == 0 {
+            return Err(exception);
+        }
+        let sr = self.sr;
+        let mut new_sr = (sr | SUPERVISOR) & !TRACE;
+        if let Exception::Interrupt(level) = exception {
+            new_sr = (new_sr & !INTERRUPT_MASK) | (Word::from(level) << 8);
+        }
+        self.set_sr(new_sr);
+        self.push(bus, self.pc)?;
+        self.push_word(bus, sr)?;
+        if let Exception::BusError(address) | Exception::AddressError(address) = exception {
+            let instruction_register = bus.peek(self.pc, Size::Word).unwrap_or(0) as Word;
+            // The special status word marks a read with the function code of data accesses in
+            // the mode before the exception. The simulator doesn't record the actual access.
+            let function_code = if sr & SUPERVISOR != 0 { 5 } else { 1 };
+            self.push_word(bus, instruction_register)?;
+            self.push(bus, address)?;
+            self.push_word(bus, 0x10 | function_code)?;
+        }
+        self.pc = handler;
+        self.is_stopped = false;
+        Ok(())
+    }
+
+    fn execute(&mut self, bus: &mut dyn Bus) -> Result<Instruction, Exception> {
+        if self.pc & 1 != 0 {
+            return Err(Exception::AddressError(self.pc));
+        }
+        let instruction = decode(bus, self.pc)?;
+        let illegal = Exception::IllegalInstruction(bus.peek(self.pc, Size::Word)? as Word);
+        let next = self.pc.wrapping_add(instruction.length);
+        self.pc = next;
+
+        let operands = &instruction.operands;
+        let size = instruction.size.unwrap_or(Size::LongWord);
+        let is_privileged = matches!(
+            (instruction.operation_type, &operands[..]),
+            (OperationType::Rte, _)
+                | (OperationType::Stop, _)
+                | (OperationType::Move, [_, Operand::Sr])
+                | (OperationType::Move, [Operand::Usp, _])
+                | (OperationType::Move, [_, Operand::Usp])
+        );
+        if is_privileged && !self.is_supervisor() {
+            return Err(Exception::PrivilegeViolation);
+        }
+        match (instruction.operation_type, &operands[..]) {
+            (OperationType::Add, [source, destination])
+            | (OperationType::Addi, [source, destination])
+            | (OperationType::Addq, [source, destination])
+            | (OperationType::Addx, [source, destination]) => {
+                let source = self.locate(source, size);
+                let destination = self.locate(destination, size);
+                let source_value = self.read(bus, source, size)?;
+                if let Location::AddressRegister(register) = destination {
+                    // ADDQ to address registers always uses the whole register and doesn't
+                    // change the flags.
+                    self.a[register] = self.a[register].wrapping_add(source_value);
+                } else {
+                    let destination_value = self.read(bus, destination, size)?;
+                    let extend = instruction.operation_type == OperationType::Addx;
+                    let result = self.add(source_value, destination_value, size, extend);
+                    self.write(bus, destination, size, result)?;
+                }
+            }
+            (OperationType::Adda, [source, Operand::An(register)]) => {
+                let source = self.locate(source, size);
+                let value = sign_extend(self.read(bus, source, size)?, size);
+                let register = *register as usize;
+                self.a[register] = self.a[register].wrapping_add(value);
+            }
+            (OperationType::Bcc(_), [target])
+            | (OperationType::Bra, [target])
+            | (OperationType::Bsr, [target]) => {
+                let is_taken = match instruction.operation_type {
+                    OperationType::Bcc(condition) => self.condition(condition),
+                    OperationType::Bsr => {
+                        self.push(bus, next)?;
+                        true
+                    }
+                    _ => true,
+                };
+                if is_taken {
+                    self.pc = self.address_of(target).ok_or(illegal)?;
+                }
+            }
+            (OperationType::Chk, [source, Operand::Dn(register)]) => {
+                let source = self.locate(source, size);
+                let bound = self.read(bus, source, size)? as Word as i16;
+                let value = self.d[*register as usize] as Word as i16;
+                if value < 0 || value > bound {
+                    self.set_flag(NEGATIVE, value < 0);
+                    return Err(Exception::Chk);
+                }
+            }
+            (OperationType::Clr, [destination]) => {
+                let destination = self.locate(destination, size);
+                self.write(bus, destination, size, 0)?;
+                self.set_logic_flags(0, size);
+            }
+            (OperationType::Divs, [source, Operand::Dn(register)])
+            | (OperationType::Divu, [source, Operand::Dn(register)]) => {
+                let source = self.locate(source, size);
+                let divisor = self.read(bus, source, size)?;
+                if divisor == 0 {
+                    return Err(Exception::ZeroDivide);
+                }
+                let register = *register as usize;
+                let dividend = self.d[register];
+                // The remainder has the sign of the dividend, like in Rust.
+                let (quotient, remainder, is_overflow) =
+                    if instruction.operation_type == OperationType::Divs {
+                        let dividend = i64::from(dividend as i32);
+                        let divisor = i64::from(divisor as Word as i16);
+                        let quotient = dividend / divisor;
+                        let is_overflow = quotient != i64::from(quotient as i16);
+                        (
+                            quotient as LongWord,
+                            (dividend % divisor) as LongWord,
+                            is_overflow,
+                        )
+                    } else {
+                        let quotient = dividend / divisor;
+                        (quotient, dividend % divisor, quotient > 0xffff)
+                    };
+                // On an overflow, the register stays unchanged.
+                if is_overflow {
+                    self.set_flag(OVERFLOW, true);
+                    self.set_flag(CARRY, false);
+                } else {
+                    let quotient = quotient & 0xffff;
+                    self.d[register] = ((remainder & 0xffff) << 16) | quotient;
+                    self.set_logic_flags(quotient, Size::Word);
+                }
+            }
+            (OperationType::Jsr, [target]) => {
+                let target = self.address_of(target).ok_or(illegal)?;
+                self.push(bus, next)?;
+                self.pc = target;
+            }
+            (OperationType::Lea, [source, Operand::An(register)]) => {
+                self.a[*register as usize] = self.address_of(source).ok_or(illegal)?;
+            }
+            (OperationType::Move, [source, Operand::Sr]) => {
+                let source = self.locate(source, size);
+                let value = self.read(bus, source, size)?;
+                self.set_sr(value as Word);
+            }
+            (OperationType::Move, [Operand::Sr, destination]) => {
+                let destination = self.locate(destination, size);
+                self.write(bus, destination, size, LongWord::from(self.sr))?;
+            }
+            (OperationType::Move, [Operand::An(register), Operand::Usp]) => {
+                self.inactive_stack_pointer = self.a[*register as usize];
+            }
+            (OperationType::Move, [Operand::Usp, Operand::An(register)]) => {
+                self.a[*register as usize] = self.inactive_stack_pointer;
+            }
+            (OperationType::Move, [source, destination]) => {
+                let source = self.locate(source, size);
+                let value = self.read(bus, source, size)?;
+                let destination = self.locate(destination, size);
+                if let Location::AddressRegister(register) = destination {
+                    // MOVEA sign-extends and doesn't change the flags.
+                    self.a[register] = sign_extend(value, size);
+                } else {
+                    self.write(bus, destination, size, value)?;
+                    self.set_logic_flags(value, size);
+                }
+            }
+            (OperationType::Moveq, [Operand::Immediate(value), Operand::Dn(register)]) => {
+                self.d[*register as usize] = *value;
+                self.set_logic_flags(*value, Size::LongWord);
+            }
+            (OperationType::Nop, []) => {}
+            (OperationType::Rte, []) => {
+                let sr = self.pop_word(bus)?;
+                self.pc = self.pop(bus)?;
+                self.set_sr(sr);
+            }
+            (OperationType::Rts, []) => self.pc = self.pop(bus)?,
+            (OperationType::Stop, [Operand::Immediate(sr)]) => {
+                self.set_sr(*sr as Word);
+                self.is_stopped = true;
+            }
+            (OperationType::Trap, [Operand::Immediate(number)]) => {
+                return Err(Exception::Trap(*number as Byte));
+            }
+            (OperationType::Trapv, []) => {
+                if self.flag(OVERFLOW) {
+                    return Err(Exception::Trapv);
+                }
+            }
+            _ => return Err(illegal),
+        }
+        Ok(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use m68k_reloaded_assembler::assemble::{assemble, Assembled};
+    use m68k_reloaded_parser::parse::parse;
+    use m68k_reloaded_scanner::{scan, Token};
+
+    fn load(source: &str) -> (Cpu, Memory, Assembled) {
+        let mut errors = vec![];
+        let tokens: Vec<Token> = scan(source, &mut errors).collect();
+        let program = parse(tokens, &mut errors);
+        let assembled = assemble(&program, 0x1000, &mut errors);
+        assert!(errors.is_empty(), "Assembling failed.");
+        let mut memory = Memory::new(0x10000);
+        for segment in &assembled.segments {
+            memory.load(segment.address, &segment.bytes).unwrap();
+        }
+        (Cpu::new(0x1000, 0x10000), memory, assembled)
+    }
+
+    /// Steps until the PC reaches the end of the program.
+    fn run_to_end(cpu: &mut Cpu, memory: &mut Memory, assembled: &Assembled) {
+        let last = assembled.segments.last().unwrap();
+        let end = last.address + last.bytes.len() as LongWord;
+        for _ in 0..1000 {
+            if cpu.pc == end {
+                return;
+            }
+            cpu.step(memory).unwrap();
+        }
+        panic!("The program didn't end.");
+    }
+
+    /// Runs the source until the PC reaches its end.
+    fn run(source: &str) -> (Cpu, Memory) {
+        let (mut cpu, mut memory, assembled) = load(source);
+        run_to_end(&mut cpu, &mut memory, &assembled);
+        (cpu, memory)
+    }
+
+    fn label(assembled: &Assembled, name: &str) -> LongWord {
+        assembled.labels[name]
+    }
+
+    #[test]
+    fn test_add_flags() {
+        let (cpu, _) = run(" MOVE.W #$7fff,D0\n ADDQ.W #1,D0");
+        assert_eq!(cpu.d[0], 0x8000);
+        assert_eq!(cpu.sr & 0x1f, NEGATIVE | OVERFLOW);
+
+        let (cpu, _) = run(" MOVEQ #-1,D0\n ADD.B #1,D0");
+        assert_eq!(cpu.d[0], 0xffff_ff00);
+        assert_eq!(cpu.sr & 0x1f, EXTEND | ZERO | CARRY);
+    }
+
+    #[test]
+    fn test_multi_precision_addition() {
+        // Adds $1_ffffffff and $1 in D1:D0.
+        let (cpu, _) = run(
+            " MOVEQ #-1,D0\n MOVEQ #1,D1\n MOVEQ #1,D2\n MOVEQ #0,D3\n ADD.L D2,D0\n ADDX.L D3,D1",
+        );
+        assert_eq!((cpu.d[1], cpu.d[0]), (2, 0));
+        assert!(!cpu.flag(ZERO));
+    }
+
+    #[test]
+    fn test_loop_and_subroutine() {
+        let (cpu, memory) = run(" LEA $2000,A0
+ MOVEQ #3,D0
+loop MOVE.B D0,(A0)+
+ ADDQ.W #1,D1
+ ADD.W #-1,D0
+ BNE loop
+ BSR sub
+ BRA end
+sub MOVE.L A0,-(A7)
+ ADDA.W #$ffff,A0
+ MOVE.L (A7)+,D2
+ RTS
+end NOP");
+        assert_eq!(cpu.d[1], 3);
+        assert_eq!(cpu.a[0], 0x2002);
+        assert_eq!(cpu.d[2], 0x2003);
+        assert_eq!(cpu.a[7], 0x10000);
+        assert_eq!(memory.peek(0x2000, Size::LongWord), Ok(0x0302_0100));
+    }
+
+    #[test]
+    fn test_trap_and_rte() {
+        let (cpu, _) = run(" LEA handler,A0
+ MOVE.L A0,$84
+ MOVEQ #5,D0
+ TRAP #1
+ ADDQ.W #1,D0
+ BRA end
+handler ADDQ.W #2,D1
+ RTE
+end NOP");
+        assert_eq!((cpu.d[0], cpu.d[1]), (6, 2));
+        assert_eq!(cpu.a[7], 0x10000);
+        assert_eq!(cpu.sr, SUPERVISOR | INTERRUPT_MASK);
+    }
+
+    #[test]
+    fn test_user_mode_and_privilege_violation() {
+        let (mut cpu, mut memory, assembled) = load(
+            " LEA privileged,A0
+ MOVE.L A0,$20
+ LEA user,A0
+ MOVE.L A0,-(A7)
+ MOVE.W #0,-(A7)
+ RTE
+user MOVE.L A7,D0
+violation RTE
+privileged MOVE.L A7,D1
+ MOVE.W (A7),D2
+ MOVE.L 2(A7),D3",
+        );
+        cpu.inactive_stack_pointer = 0x8000;
+        run_to_end(&mut cpu, &mut memory, &assembled);
+        assert_eq!(cpu.d[0], 0x8000);
+        assert_eq!(cpu.d[1], 0x10000 - 6);
+        assert_eq!(cpu.d[2], 0);
+        assert_eq!(cpu.d[3], label(&assembled, "violation"));
+        assert!(cpu.is_supervisor());
+        assert_eq!((cpu.usp(), cpu.ssp()), (0x8000, 0x10000 - 6));
+    }
+
+    #[test]
+    fn test_address_error_frame() {
+        let (mut cpu, mut memory, _) = load(" NOP\n MOVE.W $1001,D0");
+        memory.write(0x0c, Size::LongWord, 0x2000).unwrap();
+        memory.write(0x2000, Size::Word, 0x4e71).unwrap();
+        cpu.step(&mut memory).unwrap();
+        let step = cpu.step(&mut memory).unwrap();
+        assert_eq!(step.instruction, None);
+        assert_eq!(step.exception, Some(Exception::AddressError(0x1001)));
+        assert_eq!(cpu.pc, 0x2000);
+        let frame = cpu.a[7];
+        assert_eq!(frame, 0x10000 - 14);
+        assert_eq!(memory.read_word(frame), Ok(0x15));
+        assert_eq!(memory.read_long_word(frame + 2), Ok(0x1001));
+        assert_eq!(memory.read_word(frame + 6), Ok(0x3038));
+        assert_eq!(memory.read_word(frame + 8), Ok(0x2700));
+        assert_eq!(memory.read_long_word(frame + 10), Ok(0x1002));
+    }
+
+    #[test]
+    fn test_unhandled_exceptions() {
+        let (mut cpu, mut memory, _) = load(" MOVEQ #1,D0\n DIVU #0,D0");
+        cpu.step(&mut memory).unwrap();
+        let before = cpu.clone();
+        assert_eq!(cpu.step(&mut memory), Err(Exception::ZeroDivide));
+        assert_eq!(cpu, before);
+
+        // With an installed vector, the frame is built on the stack. If that faults, the CPU
+        // tries to process the bus error and halts when its frame faults as well.
+        memory.write(0x14, Size::LongWord, 0x2000).unwrap();
+        cpu.a[7] = 0x20000;
+        assert_eq!(cpu.step(&mut memory), Err(Exception::BusError(0x1fffc)));
+        assert_eq!(cpu.pc, 0x1002);
+        cpu.a[7] = 0x10000;
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.pc, 0x2000);
+        assert_eq!(memory.read_long_word(0xfffc), Ok(0x1006));
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let (mut cpu, mut memory, _) = load(" NOP\n NOP\n STOP #$2000\n NOP");
+        memory.write(0x70, Size::LongWord, 0x1002).unwrap();
+        memory.write(0x7c, Size::LongWord, 0x1004).unwrap();
+        cpu.request_interrupt(4);
+        assert_eq!(cpu.pending_interrupt(), None);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.pc, 0x1002);
+
+        cpu.set_sr(0x2300);
+        let step = cpu.step(&mut memory).unwrap();
+        assert_eq!(step.exception, Some(Exception::Interrupt(4)));
+        assert_eq!((cpu.pc, cpu.sr), (0x1002, 0x2400));
+        assert_eq!(memory.read_word(cpu.a[7]), Ok(0x2300));
+
+        // Level 7 can't be masked.
+        cpu.set_sr(0x2700);
+        cpu.request_interrupt(7);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!((cpu.pc, cpu.sr), (0x1004, 0x2700));
+
+        cpu.step(&mut memory).unwrap();
+        assert!(cpu.is_stopped);
+        assert_eq!(cpu.sr, 0x2000);
+        let step = cpu.step(&mut memory).unwrap();
+        assert_eq!((step.instruction, step.exception), (None, None));
+        cpu.request_interrupt(2);
+        assert_eq!(cpu.step(&mut memory), Err(Exception::Interrupt(2)));
+        assert_eq!(cpu.pending_interrupt(), None);
+        cpu.request_interrupt(4);
+        cpu.step(&mut memory).unwrap();
+        assert!(!cpu.is_stopped);
+
+        // Asserted levels stay pending, except for level 7.
+        cpu.set_sr(0x2000);
+        cpu.set_interrupt_lines(4);
+        cpu.step(&mut memory).unwrap();
+        cpu.set_sr(0x2000);
+        assert_eq!(cpu.pending_interrupt(), Some(4));
+        cpu.set_interrupt_lines(7);
+        cpu.set_sr(0x2700);
+        assert_eq!(cpu.pending_interrupt(), Some(7));
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.pending_interrupt(), None);
+        cpu.set_interrupt_lines(0);
+        cpu.set_sr(0x2000);
+        assert_eq!(cpu.pending_interrupt(), None);
+    }
+
+    #[test]
+    fn test_division_and_chk() {
+        let (cpu, _) = run(" MOVEQ #100,D0\n DIVU #7,D0");
+        assert_eq!(cpu.d[0], (2 << 16) | 14);
+        let (cpu, _) = run(" MOVEQ #-7,D0\n DIVS #2,D0");
+        assert_eq!(cpu.d[0], 0xffff_fffd);
+        assert!(cpu.flag(NEGATIVE));
+        let (cpu, _) = run(" MOVE.L #$10000,D0\n DIVU #1,D0");
+        assert_eq!(cpu.d[0], 0x10000);
+        assert!(cpu.flag(OVERFLOW));
+
+        let (mut cpu, mut memory, _) = load(" MOVEQ #-1,D0\n CHK #5,D0");
+        memory.write(0x18, Size::LongWord, 0x2000).unwrap();
+        cpu.step(&mut memory).unwrap();
+        let step = cpu.step(&mut memory).unwrap();
+        assert_eq!(step.exception, Some(Exception::Chk));
+        assert!(cpu.flag(NEGATIVE));
+        // The stacked PC points after CHK.
+        assert_eq!(memory.read_long_word(cpu.a[7] + 2), Ok(0x1006));
+    }
+
+    #[test]
+    fn test_trace_and_line_a() {
+        let (mut cpu, mut memory, _) = load(" NOP\n NOP");
+        memory.write(0x1002, Size::Word, 0xa123).unwrap();
+        memory.write(0x24, Size::LongWord, 0x2000).unwrap();
+        memory.write(0x28, Size::LongWord, 0x3000).unwrap();
+        cpu.set_sr(TRACE | SUPERVISOR);
+        let step = cpu.step(&mut memory).unwrap();
+        assert_eq!(step.exception, Some(Exception::Trace));
+        assert_eq!(cpu.pc, 0x2000);
+        assert!(!cpu.flag(TRACE));
+        assert_eq!(memory.read_long_word(cpu.a[7] + 2), Ok(0x1002));
+
+        cpu.pc = 0x1002;
+        let step = cpu.step(&mut memory).unwrap();
+        assert_eq!(step.exception, Some(Exception::LineA(0xa123)));
+        assert_eq!(memory.read_long_word(cpu.a[7] + 2), Ok(0x1002));
+    }
+}
@@ -0,0 +1,1014 @@
+//! An interactive debugger for programs running in the simulator.
+//!
+//! Programs are either assembled from source, in which case every instruction maps back to its
+//! statement, or loaded from a GEMDOS program file, in which case only its symbols are known.
+//! Commands are plain text, so the debugger can be driven by a terminal as well as by tests.
+
+use crate::bus::Bus;
+use crate::cpu::*;
+use crate::decode::decode;
+use crate::exception::Exception;
+use crate::memory::Memory;
+use crate::profiler::{Flow, Profiler, Sample};
+use m68k_reloaded_assembler::assemble::{assemble, SourceLine};
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use m68k_reloaded_object::prg::{self, LoadError};
+use m68k_reloaded_object::Section;
+use m68k_reloaded_parser::parse::parse;
+use m68k_reloaded_parser::statements::{OperationType, Size};
+use m68k_reloaded_scanner::{scan, Token};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Where programs are loaded. The memory below is left free, like the exception vectors and
+/// system variables on a real machine.
+pub const ORIGIN: LongWord = 0x1000;
+pub const MEMORY_SIZE: usize = 0x10_0000;
+
+/// Commands that run the program stop after this many instructions, so that endless loops don't
+/// hang the debugger.
+const MAX_STEPS: usize = 10_000_000;
+
+const HELP: &str = "Commands:
+  break <location>                Stop when the PC reaches the location.
+  watch <location> [<length>]     Stop after a write to the memory range.
+  delete <location>               Delete the breakpoint or watchpoint at the location.
+  info                            List all breakpoints and watchpoints.
+  step [<count>]                  Execute instructions.
+  next                            Execute an instruction, stepping over subroutine calls.
+  finish                          Run until the current subroutine returns.
+  continue                        Run until a breakpoint or watchpoint is hit.
+  registers                       Show the registers.
+  interrupt <level>               Request an autovectored interrupt of the level 1 to 7.
+  memory <location> [<length>]    Show the memory as hex dump.
+  disassemble [<location>] [<count>]
+                                  Disassemble the instructions around the PC.
+  list                            Show the source around the PC.
+  trace <file> | off              Log every executed instruction to the file.
+  profile start | stop | report   Count the executed instructions and their cycles.
+  profile stacks <file>           Write the call stacks of the profile for flame graphs.
+  quit                            Exit the debugger.
+Locations are labels or addresses like $1000. Commands can be abbreviated to their first
+letter. An empty line repeats the last command.";
+
+/// Why the program stopped.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Stop {
+    /// The command is done.
+    Done,
+    Breakpoint(LongWord),
+    /// A write of `length` bytes at `address` touched a watched range.
+    Watchpoint {
+        address: LongWord,
+        length: LongWord,
+    },
+    /// An exception whose vector isn't installed.
+    Exception(Exception),
+    /// The CPU executed `STOP` and no interrupt is pending or can be requested by a device.
+    Stopped,
+    StepLimit,
+}
+
+pub struct Debugger {
+    pub cpu: Cpu,
+    pub memory: Memory,
+    /// Sorted by address.
+    symbols: Vec<(String, LongWord)>,
+    /// Empty for programs loaded without source.
+    source: String,
+    lines: Vec<SourceLine>,
+    breakpoints: Vec<LongWord>,
+    /// Start address and length of watched memory ranges.
+    watchpoints: Vec<(LongWord, LongWord)>,
+    /// Where every executed instruction is logged.
+    trace: Option<Box<dyn Write>>,
+    pub profiler: Option<Profiler>,
+}
+
+impl Debugger {
+    fn new(
+        memory: Memory,
+        mut symbols: Vec<(String, LongWord)>,
+        source: String,
+        lines: Vec<SourceLine>,
+    ) -> Debugger {
+        symbols.sort_by(|(a_name, a), (b_name, b)| a.cmp(b).then(a_name.cmp(b_name)));
+        Debugger {
+            cpu: Cpu::new(ORIGIN, MEMORY_SIZE as LongWord),
+            memory,
+            symbols,
+            source,
+            lines,
+            breakpoints: vec![],
+            watchpoints: vec![],
+            trace: None,
+            profiler: None,
+        }
+    }
+
+    /// Assembles the source and loads it at [ORIGIN]. Sections placed outside of the memory by an
+    /// `ORG` are reported.
+    pub fn from_source(source: &str, errors: &mut ErrorCollector) -> Debugger {
+        let tokens: Vec<Token> = scan(source, errors).collect();
+        let program = parse(tokens, errors);
+        let assembled = assemble(&program, ORIGIN, errors);
+        assembled.report_imports(errors);
+        let mut memory = Memory::new(MEMORY_SIZE);
+        for segment in &assembled.segments {
+            if memory.load(segment.address, &segment.bytes).is_err() {
+                let range = assembled
+                    .line_at(segment.address)
+                    .map_or(0..0, |line| line.range.clone());
+                errors.push(Error::section_outside_memory(
+                    range,
+                    MEMORY_SIZE as LongWord,
+                ));
+            }
+        }
+        Debugger::new(
+            memory,
+            assembled.labels.into_iter().collect(),
+            source.to_string(),
+            assembled.lines,
+        )
+    }
+
+    /// Loads a GEMDOS program file at [ORIGIN].
+    pub fn from_program(bytes: &[Byte]) -> Result<Debugger, LoadError> {
+        let program = prg::load(bytes, ORIGIN)?;
+        let available = MEMORY_SIZE - ORIGIN as usize;
+        if program.memory.len() > available {
+            return Err(LoadError::OutOfMemory {
+                size: program.memory.len() as u64,
+                available: available as u64,
+            });
+        }
+        let mut memory = Memory::new(MEMORY_SIZE);
+        memory
+            .load(ORIGIN, &program.memory)
+            .expect("The program doesn't fit into memory.");
+        let symbols = program
+            .symbols
+            .iter()
+            .map(|symbol| {
+                let start = match symbol.section {
+                    Section::Text => program.text_start(),
+                    Section::Data => program.data_start(),
+                    Section::Bss => program.bss_start(),
+                };
+                (symbol.name.clone(), start + symbol.value)
+            })
+            .collect();
+        Ok(Debugger::new(memory, symbols, String::new(), vec![]))
+    }
+
+    /// Executes a single instruction and checks the watchpoints. Returns the operation type
+    /// unless an interrupt was processed instead.
+    fn execute(&mut self) -> Result<Option<OperationType>, Stop> {
+        let before = self.cpu.clone();
+        let step = self.cpu.step(&mut self.memory).map_err(Stop::Exception)?;
+        self.record(&before, &step);
+        let level = self.memory.tick();
+        self.cpu.set_interrupt_lines(level.unwrap_or(0));
+        for (address, length) in self.memory.take_writes() {
+            let is_watched = self
+                .watchpoints
+                .iter()
+                .any(|&(start, watched)| address < start + watched && start < address + length);
+            if is_watched {
+                return Err(Stop::Watchpoint { address, length });
+            }
+        }
+        let can_wake = self.cpu.pending_interrupt().is_some() || self.memory.can_interrupt();
+        if self.cpu.is_stopped && !can_wake {
+            return Err(Stop::Stopped);
+        }
+        Ok(step
+            .instruction
+            .map(|instruction| instruction.operation_type))
+    }
+
+    /// Executes instructions until `is_done` returns true after one of them or a breakpoint or
+    /// watchpoint is hit. The first instruction is always executed, so that the program can
+    /// continue from a breakpoint.
+    fn run_until(&mut self, mut is_done: impl FnMut(&Cpu, Option<OperationType>) -> bool) -> Stop {
+        for _ in 0..MAX_STEPS {
+            let operation_type = match self.execute() {
+                Ok(operation_type) => operation_type,
+                Err(stop) => return stop,
+            };
+            if is_done(&self.cpu, operation_type) {
+                return Stop::Done;
+            }
+            if self.breakpoints.contains(&self.cpu.pc) {
+                return Stop::Breakpoint(self.cpu.pc);
+            }
+        }
+        Stop::StepLimit
+    }
+
+    pub fn step(&mut self) -> Stop {
+        self.run_until(|_, _| true)
+    }
+
+    /// Steps, but runs subroutine calls until they return.
+    pub fn step_over(&mut self) -> Stop {
+        let stack_pointer = self.cpu.a[7];
+        let return_address = match decode(&self.memory, self.cpu.pc) {
+            Ok(instruction)
+                if matches!(
+                    instruction.operation_type,
+                    OperationType::Bsr | OperationType::Jsr
+                ) =>
+            {
+                self.cpu.pc + instruction.length
+            }
+            _ => return self.step(),
+        };
+        // Comparing the stack pointer makes this work for recursive subroutines.
+        self.run_until(|cpu, _| cpu.pc == return_address && cpu.a[7] >= stack_pointer)
+    }
+
+    /// Runs until the current subroutine returns.
+    pub fn step_out(&mut self) -> Stop {
+        let stack_pointer = self.cpu.a[7];
+        self.run_until(|cpu, operation_type| {
+            operation_type == Some(OperationType::Rts) && cpu.a[7] > stack_pointer
+        })
+    }
+
+    pub fn resume(&mut self) -> Stop {
+        self.run_until(|_, _| false)
+    }
+
+    /// Logs every executed instruction with the registers it changed to the output, or stops
+    /// logging.
+    pub fn set_trace(&mut self, output: Option<Box<dyn Write>>) {
+        self.trace = output;
+    }
+
+    /// Writes the step to the trace and adds it to the profile.
+    fn record(&mut self, before: &Cpu, step: &Step) {
+        if self.trace.is_none() && self.profiler.is_none() {
+            return;
+        }
+        let description = match (&step.instruction, step.exception) {
+            (Some(instruction), _) => {
+                format!("{} {}", self.describe_address(before.pc), instruction)
+            }
+            (None, Some(exception)) => exception.to_string(),
+            // The CPU is stopped.
+            (None, None) => return,
+        };
+        if let Some(trace) = &mut self.trace {
+            let mut line = format!("{:<48}{}", description, register_deltas(before, &self.cpu));
+            if let (Some(_), Some(exception)) = (&step.instruction, step.exception) {
+                line.push_str(&format!("  {}", exception));
+            }
+            if writeln!(trace, "{}", line.trim_end()).is_err() {
+                self.trace = None;
+            }
+        }
+
+        let instruction = match &step.instruction {
+            Some(instruction) => instruction,
+            None => {
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.record_interrupt();
+                }
+                return;
+            }
+        };
+        let cycles = self
+            .lines
+            .iter()
+            .find(|line| line.address == before.pc)
+            .and_then(|line| {
+                let timing = line.timing?;
+                let is_taken = self.cpu.pc != line.address + line.size;
+                Some(match timing.not_taken {
+                    Some(not_taken) if !is_taken => not_taken,
+                    _ => timing.cycles,
+                })
+            })
+            .unwrap_or(0);
+        let flow = match step.exception {
+            Some(exception) if exception != Exception::Trace => Flow::Call,
+            _ => Flow::of(instruction.operation_type),
+        };
+        let routine = self.routine_of(before.pc).unwrap_or("?").to_string();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(before.pc, &routine, u64::from(cycles), flow);
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: LongWord) {
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, address: LongWord, length: LongWord) {
+        self.watchpoints.push((address, length));
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    /// Returns whether there was a breakpoint at the address.
+    pub fn remove_breakpoint(&mut self, address: LongWord) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|&breakpoint| breakpoint != address);
+        count != self.breakpoints.len()
+    }
+
+    /// Returns whether there was a watchpoint starting at the address.
+    pub fn remove_watchpoint(&mut self, address: LongWord) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|&(start, _)| start != address);
+        count != self.watchpoints.len()
+    }
+
+    /// The address of a label, or an address written as `$1000`, `0x1000` or `4096`.
+    pub fn parse_location(&self, text: &str) -> Option<LongWord> {
+        if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+            return LongWord::from_str_radix(hex, 16).ok();
+        }
+        if let Ok(address) = text.parse() {
+            return Some(address);
+        }
+        self.symbols
+            .iter()
+            .find(|(name, _)| name == text)
+            .map(|&(_, address)| address)
+    }
+
+    /// Describes the address relative to the closest label before it, like `loop+4`.
+    pub fn symbolize(&self, address: LongWord) -> Option<String> {
+        let (name, start) = self
+            .symbols
+            .iter()
+            .rev()
+            .find(|(_, start)| *start <= address)?;
+        Some(match address - start {
+            0 => name.clone(),
+            offset => format!("{}+{}", name, offset),
+        })
+    }
+
+    /// The closest global label before the address. Local labels are qualified by their global
+    /// label, like `main.loop`.
+    fn routine_of(&self, address: LongWord) -> Option<&str> {
+        self.symbols
+            .iter()
+            .rev()
+            .filter(|(name, _)| !name.contains('.'))
+            .find(|(_, start)| *start <= address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn describe_address(&self, address: LongWord) -> String {
+        match self.symbolize(address) {
+            Some(symbol) => format!("${:08X} <{}>", address, symbol),
+            None => format!("${:08X}", address),
+        }
+    }
+
+    /// The line number and text of the statement at the address.
+    pub fn source_line(&self, address: LongWord) -> Option<(usize, &str)> {
+        let line = self.lines.iter().find(|line| line.address == address)?;
+        let start = self.source[..line.range.start]
+            .rfind('\n')
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        let end = self.source[start..]
+            .find('\n')
+            .map(|index| start + index)
+            .unwrap_or_else(|| self.source.len());
+        let number = self.source[..start].matches('\n').count() + 1;
+        Some((number, &self.source[start..end]))
+    }
+
+    /// A line describing the instruction at the PC.
+    fn current(&self) -> String {
+        let pc = self.cpu.pc;
+        let instruction = match decode(&self.memory, pc) {
+            Ok(instruction) => instruction.to_string(),
+            Err(exception) => exception.to_string(),
+        };
+        let mut line = format!("{} {}", self.describe_address(pc), instruction);
+        if let Some((number, text)) = self.source_line(pc) {
+            line.push_str(&format!("\n{:5}  {}", number, text.trim_end()));
+        }
+        line
+    }
+
+    /// Executes a command and returns the output.
+    pub fn run_command(&mut self, command: &str) -> String {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let (name, arguments) = match words.split_first() {
+            Some((name, arguments)) => (*name, arguments),
+            None => return String::new(),
+        };
+        let location = |index: usize| -> Result<Option<LongWord>, String> {
+            match arguments.get(index) {
+                Some(text) => match self.parse_location(text) {
+                    Some(address) => Ok(Some(address)),
+                    None => Err(format!("Unknown location {}.", text)),
+                },
+                None => Ok(None),
+            }
+        };
+        let number = |index: usize, default: LongWord| -> Result<LongWord, String> {
+            match arguments.get(index) {
+                Some(text) => text
+                    .parse()
+                    .map_err(|_| format!("Expected a number, found {}.", text)),
+                None => Ok(default),
+            }
+        };
+        let result = match name {
+            "help" | "h" => Ok(HELP.to_string()),
+            "break" | "b" => location(0).and_then(|address| {
+                let address = address.ok_or_else(|| "Expected a location.".to_string())?;
+                self.add_breakpoint(address);
+                Ok(format!("Breakpoint at {}.", self.describe_address(address)))
+            }),
+            "watch" | "w" => location(0).and_then(|address| {
+                let address = address.ok_or_else(|| "Expected a location.".to_string())?;
+                let length = number(1, 1)?;
+                self.add_watchpoint(address, length);
+                Ok(format!(
+                    "Watching {} bytes at {}.",
+                    length,
+                    self.describe_address(address)
+                ))
+            }),
+            "delete" => location(0).and_then(|address| {
+                let address = address.ok_or_else(|| "Expected a location.".to_string())?;
+                let had_breakpoint = self.remove_breakpoint(address);
+                let had_watchpoint = self.remove_watchpoint(address);
+                if !had_breakpoint && !had_watchpoint {
+                    Err(format!(
+                        "There's nothing at {}.",
+                        self.describe_address(address)
+                    ))
+                } else {
+                    Ok(format!("Deleted {}.", self.describe_address(address)))
+                }
+            }),
+            "info" | "i" => Ok(self.info()),
+            "step" | "s" => number(0, 1).map(|count| {
+                let mut stop = Stop::Done;
+                for _ in 0..count {
+                    stop = self.step();
+                    if stop != Stop::Done {
+                        break;
+                    }
+                }
+                self.report(stop)
+            }),
+            "next" | "n" => Ok(self.step_over()).map(|stop| self.report(stop)),
+            "finish" | "f" => Ok(self.step_out()).map(|stop| self.report(stop)),
+            "continue" | "c" => Ok(self.resume()).map(|stop| self.report(stop)),
+            "registers" | "r" => Ok(self.registers()),
+            "interrupt" => number(0, 0).and_then(|level| match level {
+                1..=7 => {
+                    self.cpu.request_interrupt(level as Byte);
+                    Ok(format!("Requested an interrupt of level {}.", level))
+                }
+                _ => Err("Expected an interrupt level from 1 to 7.".to_string()),
+            }),
+            "memory" | "m" | "x" => location(0).and_then(|address| {
+                let address = address.ok_or_else(|| "Expected a location.".to_string())?;
+                Ok(self.dump(address, number(1, 64)?))
+            }),
+            "disassemble" | "d" => location(0)
+                .and_then(|address| Ok(self.disassemble(address, number(1, 10)? as usize))),
+            "list" | "l" => Ok(self.list()),
+            "trace" | "t" => match arguments.first() {
+                Some(&"off") => {
+                    self.set_trace(None);
+                    Ok("Stopped tracing.".to_string())
+                }
+                Some(path) => match File::create(path) {
+                    Ok(file) => {
+                        self.set_trace(Some(Box::new(BufWriter::new(file))));
+                        Ok(format!("Tracing to {}.", path))
+                    }
+                    Err(error) => Err(format!("Couldn't create {}: {}", path, error)),
+                },
+                None => Err("Expected a file or off.".to_string()),
+            },
+            "profile" | "p" => match (arguments.first(), &self.profiler) {
+                (Some(&"start"), _) => {
+                    self.profiler = Some(Profiler::new());
+                    Ok("Profiling.".to_string())
+                }
+                (Some(&"stop"), _) => {
+                    self.profiler = None;
+                    Ok("Stopped profiling.".to_string())
+                }
+                (Some(&"report"), Some(profiler)) => Ok(self.profile_report(profiler)),
+                (Some(&"stacks"), Some(profiler)) => match arguments.get(1) {
+                    Some(path) => std::fs::write(path, profiler.collapsed_stacks())
+                        .map(|_| format!("Wrote the call stacks to {}.", path))
+                        .map_err(|error| format!("Couldn't write {}: {}", path, error)),
+                    None => Err("Expected a file.".to_string()),
+                },
+                (Some(&"report"), None) | (Some(&"stacks"), None) => {
+                    Err("The profiler isn't running. Start it with profile start.".to_string())
+                }
+                _ => Err("Expected start, stop, report or stacks.".to_string()),
+            },
+            _ => Err(format!(
+                "Unknown command {}. Type help for a list of commands.",
+                name
+            )),
+        };
+        result.unwrap_or_else(|error| error)
+    }
+
+    fn report(&self, stop: Stop) -> String {
+        let reason = match stop {
+            Stop::Done => None,
+            Stop::Breakpoint(address) => {
+                Some(format!("Breakpoint at {}.", self.describe_address(address)))
+            }
+            Stop::Watchpoint { address, length } => Some(format!(
+                "Watchpoint hit by a write of {} bytes at {}.",
+                length,
+                self.describe_address(address)
+            )),
+            Stop::Exception(exception) => Some(exception.to_string()),
+            Stop::Stopped => Some("The CPU is stopped until an interrupt.".to_string()),
+            Stop::StepLimit => Some(format!("Stopped after {} instructions.", MAX_STEPS)),
+        };
+        match reason {
+            Some(reason) => format!("{}\n{}", reason, self.current()),
+            None => self.current(),
+        }
+    }
+
+    fn info(&self) -> String {
+        let mut lines = vec![];
+        for &address in &self.breakpoints {
+            lines.push(format!("Breakpoint at {}", self.describe_address(address)));
+        }
+        for &(address, length) in &self.watchpoints {
+            lines.push(format!(
+                "Watchpoint on {} bytes at {}",
+                length,
+                self.describe_address(address)
+            ));
+        }
+        if lines.is_empty() {
+            "There are no breakpoints or watchpoints.".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    fn registers(&self) -> String {
+        // Four registers per row.
+        let rows = |name: char, registers: &[LongWord]| {
+            registers
+                .iter()
+                .enumerate()
+                .map(|(index, value)| format!("{}{} {:08X}", name, index, value))
+                .collect::<Vec<_>>()
+                .chunks(4)
+                .map(|row| row.join("  "))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let sr = self.cpu.sr;
+        let flag = |bit: Word, name: char| if sr & bit != 0 { name } else { '-' };
+        format!(
+            "{}\n{}\nPC {:08X}  SR {:04X}  {}{} I{} {}{}{}{}{}  {} {:08X}",
+            rows('D', &self.cpu.d),
+            rows('A', &self.cpu.a),
+            self.cpu.pc,
+            sr,
+            flag(TRACE, 'T'),
+            if sr & SUPERVISOR != 0 { 'S' } else { 'U' },
+            (sr & INTERRUPT_MASK) >> 8,
+            flag(EXTEND, 'X'),
+            flag(NEGATIVE, 'N'),
+            flag(ZERO, 'Z'),
+            flag(OVERFLOW, 'V'),
+            flag(CARRY, 'C'),
+            if sr & SUPERVISOR != 0 { "USP" } else { "SSP" },
+            self.cpu.inactive_stack_pointer,
+        )
+    }
+
+    fn dump(&self, address: LongWord, length: LongWord) -> String {
+        let mut lines = vec![];
+        let end = address.saturating_add(length);
+        let mut start = address;
+        while start < end {
+            let bytes: Vec<Option<Byte>> = (start..end.min(start + 16))
+                .map(|address| {
+                    self.memory
+                        .peek(address, Size::Byte)
+                        .ok()
+                        .map(|byte| byte as Byte)
+                })
+                .collect();
+            let hex: Vec<String> = bytes
+                .iter()
+                .map(|byte| match byte {
+                    Some(byte) => format!("{:02X}", byte),
+                    None => "??".to_string(),
+                })
+                .collect();
+            let text: String = bytes
+                .iter()
+                .map(|byte| match byte {
+                    Some(byte @ 0x20..=0x7e) => *byte as char,
+                    _ => '.',
+                })
+                .collect();
+            lines.push(format!("{:08X}  {:<47}  {}", start, hex.join(" "), text));
+            start += 16;
+        }
+        lines.join("\n")
+    }
+
+    /// Disassembles from the address. Without an address, it starts a few instructions before the
+    /// PC if the program was assembled from source, because then the instruction boundaries are
+    /// known.
+    fn disassemble(&self, address: Option<LongWord>, count: usize) -> String {
+        let pc = self.cpu.pc;
+        let mut address = address.unwrap_or_else(|| {
+            match self.lines.iter().position(|line| line.address == pc) {
+                Some(index) => self.lines[index.saturating_sub(3)].address,
+                None => pc,
+            }
+        });
+        let mut lines = vec![];
+        for _ in 0..count {
+            if let Some((name, _)) = self.symbols.iter().find(|(_, start)| *start == address) {
+                lines.push(format!("{}:", name));
+            }
+            let marker = if address == pc { "=>" } else { "  " };
+            match decode(&self.memory, address) {
+                Ok(instruction) => {
+                    lines.push(format!("{} {:08X}  {}", marker, address, instruction));
+                    address += instruction.length;
+                }
+                Err(Exception::IllegalInstruction(word))
+                | Err(Exception::LineA(word))
+                | Err(Exception::LineF(word)) => {
+                    lines.push(format!("{} {:08X}  DC.W ${:04X}", marker, address, word));
+                    address += 2;
+                }
+                Err(exception) => {
+                    lines.push(exception.to_string());
+                    break;
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// The instructions and cycles per label and source line, the most expensive first.
+    pub fn profile_report(&self, profiler: &Profiler) -> String {
+        let mut labels: BTreeMap<String, Sample> = BTreeMap::new();
+        let mut lines: BTreeMap<(usize, &str), Sample> = BTreeMap::new();
+        for (&address, sample) in profiler.samples() {
+            let label = self
+                .symbols
+                .iter()
+                .rev()
+                .find(|(_, start)| *start <= address)
+                .map_or("?", |(name, _)| name.as_str());
+            *labels.entry(label.to_string()).or_default() += *sample;
+            if let Some(line) = self.source_line(address) {
+                *lines.entry(line).or_default() += *sample;
+            }
+        }
+
+        let total = profiler.total();
+        // Programs loaded without source don't have cycles, so they are weighted by instructions.
+        let weight = |sample: &Sample| {
+            if total.cycles > 0 {
+                sample.cycles
+            } else {
+                sample.count
+            }
+        };
+        let row = |sample: &Sample, name: String| {
+            format!(
+                "{:>12}  {:>10}  {:>5.1}%  {}",
+                sample.count,
+                sample.cycles,
+                100.0 * weight(sample) as f64 / weight(&total).max(1) as f64,
+                name
+            )
+        };
+        let mut labels: Vec<(String, Sample)> = labels.into_iter().collect();
+        labels.sort_by_key(|(_, sample)| std::cmp::Reverse(weight(sample)));
+        let mut lines: Vec<((usize, &str), Sample)> = lines.into_iter().collect();
+        lines.sort_by_key(|(_, sample)| std::cmp::Reverse(weight(sample)));
+
+        let mut report = vec![format!(
+            "{:>12}  {:>10}  {:>6}  Label",
+            "Instructions", "Cycles", "%"
+        )];
+        for (label, sample) in &labels {
+            report.push(row(sample, label.clone()));
+        }
+        if !lines.is_empty() {
+            report.push(String::new());
+            report.push(format!(
+                "{:>12}  {:>10}  {:>6}  Line",
+                "Instructions", "Cycles", "%"
+            ));
+            for ((number, text), sample) in &lines {
+                report.push(row(sample, format!("{:4}  {}", number, text.trim())));
+            }
+        }
+        report.push(String::new());
+        report.push(row(&total, "Total".to_string()));
+        report.join("\n")
+    }
+
+    fn list(&self) -> String {
+        let current = match self.source_line(self.cpu.pc) {
+            Some((number, _)) => number,
+            None => return format!("No source at {}.", self.describe_address(self.cpu.pc)),
+        };
+        self.source
+            .lines()
+            .enumerate()
+            .map(|(index, text)| (index + 1, text))
+            .filter(|(number, _)| current.saturating_sub(3) <= *number && *number <= current + 3)
+            .map(|(number, text)| {
+                let marker = if number == current { "=>" } else { "  " };
+                format!("{} {:4}  {}", marker, number, text)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// The registers the step changed with their new values, like `D0=00000002 SR=2704`.
+fn register_deltas(before: &Cpu, after: &Cpu) -> String {
+    let mut deltas = vec![];
+    for (name, before, after) in [('D', &before.d, &after.d), ('A', &before.a, &after.a)] {
+        for (index, (before, after)) in before.iter().zip(after).enumerate() {
+            if before != after {
+                deltas.push(format!("{}{}={:08X}", name, index, after));
+            }
+        }
+    }
+    if before.sr != after.sr {
+        deltas.push(format!("SR={:04X}", after.sr));
+    }
+    deltas.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::tests::SharedOutput;
+    use crate::timer::Timer;
+
+    const SOURCE: &str = "start MOVEQ #2,D0
+ LEA buffer,A0
+.loop BSR store
+ ADD.W #-1,D0
+ BNE .loop
+ NOP
+store MOVE.B D0,(A0)+
+ RTS
+buffer NOP";
+
+    fn debugger() -> Debugger {
+        let mut errors = vec![];
+        let debugger = Debugger::from_source(SOURCE, &mut errors);
+        assert!(errors.is_empty(), "Assembling failed.");
+        debugger
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger();
+        assert_eq!(
+            debugger.run_command("break store"),
+            "Breakpoint at $00001012 <store>."
+        );
+        let output = debugger.run_command("continue");
+        assert_eq!(
+            output,
+            "Breakpoint at $00001012 <store>.\n$00001012 <store> MOVE.B D0,(A0)+\n    7  store MOVE.B D0,(A0)+"
+        );
+        debugger.run_command("c");
+        assert_eq!(debugger.cpu.d[0], 1);
+        debugger.run_command("delete store");
+        debugger.run_command("break $1010");
+        debugger.run_command("c");
+        assert_eq!(debugger.cpu.pc, 0x1010);
+        assert_eq!(debugger.cpu.d[0], 0);
+    }
+
+    #[test]
+    fn test_sections_placed_by_org() {
+        let mut errors = vec![];
+        let source = " BSR sub\n SECTION library,CODE\n ORG $8000\nsub MOVEQ #1,D0\n RTS";
+        let mut debugger = Debugger::from_source(source, &mut errors);
+        assert!(errors.is_empty(), "Assembling failed.");
+        debugger.run_command("step");
+        assert_eq!(debugger.symbolize(debugger.cpu.pc).unwrap(), "sub");
+        assert_eq!(debugger.cpu.pc, 0x8000);
+
+        Debugger::from_source(" ORG $FC0000\n NOP", &mut errors);
+        let codes: Vec<&str> = errors.iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["section_outside_memory"]);
+        assert_eq!(errors[0].range, 14..17);
+    }
+
+    #[test]
+    fn test_program_must_fit_into_memory() {
+        // A header with 2 MiB of BSS and an empty fixup stream.
+        let mut bytes = vec![0x60, 0x1a, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x20, 0, 0];
+        bytes.resize(32, 0);
+
+        assert_eq!(
+            Debugger::from_program(&bytes).err(),
+            Some(LoadError::OutOfMemory {
+                size: 0x20_0000,
+                available: 0xf_f000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut debugger = debugger();
+        debugger.run_command("step 2");
+        assert_eq!(debugger.symbolize(debugger.cpu.pc).unwrap(), "start.loop");
+        debugger.run_command("next");
+        assert_eq!(debugger.symbolize(debugger.cpu.pc).unwrap(), "start.loop+2");
+        assert_eq!(debugger.memory.peek(0x1016, Size::Byte), Ok(2));
+
+        debugger.run_command("n");
+        debugger.run_command("n");
+        debugger.run_command("s");
+        assert_eq!(debugger.symbolize(debugger.cpu.pc).unwrap(), "store");
+        debugger.run_command("finish");
+        assert_eq!(debugger.symbolize(debugger.cpu.pc).unwrap(), "start.loop+2");
+        assert_eq!(debugger.cpu.a[7], MEMORY_SIZE as LongWord);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = debugger();
+        debugger.run_command("watch buffer 2");
+        let output = debugger.run_command("c");
+        assert!(output.starts_with("Watchpoint hit by a write of 1 bytes at $00001016 <buffer>."));
+        assert_eq!(debugger.symbolize(debugger.cpu.pc).unwrap(), "store+2");
+        debugger.run_command("c");
+        // The program falls through into the subroutine, which has no return address.
+        let output = debugger.run_command("c");
+        assert!(output.starts_with("Bus error at $00100000."));
+        assert_eq!(debugger.symbolize(debugger.cpu.pc).unwrap(), "store+2");
+    }
+
+    #[test]
+    fn test_inspection() {
+        let mut debugger = debugger();
+        debugger.run_command("s 3");
+        assert_eq!(
+            debugger.run_command("registers"),
+            "D0 00000002  D1 00000000  D2 00000000  D3 00000000
+D4 00000000  D5 00000000  D6 00000000  D7 00000000
+A0 00001016  A1 00000000  A2 00000000  A3 00000000
+A4 00000000  A5 00000000  A6 00000000  A7 000FFFFC
+PC 00001012  SR 2700  -S I7 -----  USP 00000000"
+        );
+        assert_eq!(
+            debugger.run_command("memory buffer 4"),
+            "00001016  4E 71 00 00                                      Nq.."
+        );
+        assert_eq!(
+            debugger.run_command("disassemble"),
+            "   0000100A  ADD.W #$FFFF,D0
+   0000100E  BNE.S $1008
+   00001010  NOP
+store:
+=> 00001012  MOVE.B D0,(A0)+
+   00001014  RTS
+buffer:
+   00001016  NOP
+   00001018  DC.W $0000
+   0000101A  DC.W $0000
+   0000101C  DC.W $0000
+   0000101E  DC.W $0000"
+        );
+        assert_eq!(
+            debugger.run_command("d start 2"),
+            "start:
+   00001000  MOVEQ #2,D0
+   00001002  LEA $1016,A0"
+        );
+        assert_eq!(
+            debugger.run_command("list"),
+            "      4   ADD.W #-1,D0
+      5   BNE .loop
+      6   NOP
+=>    7  store MOVE.B D0,(A0)+
+      8   RTS
+      9  buffer NOP"
+        );
+        assert_eq!(
+            debugger.run_command("b nowhere"),
+            "Unknown location nowhere."
+        );
+    }
+
+    #[test]
+    fn test_exceptions_and_interrupts() {
+        let mut errors = vec![];
+        let mut debugger = Debugger::from_source(
+            " LEA handler,A0\n MOVE.L A0,$64\n STOP #$2000\n TRAP #0\nhandler RTE",
+            &mut errors,
+        );
+        assert!(errors.is_empty(), "Assembling failed.");
+        let output = debugger.run_command("c");
+        assert!(output.starts_with("The CPU is stopped until an interrupt."));
+        assert_eq!(
+            debugger.run_command("interrupt 8"),
+            "Expected an interrupt level from 1 to 7."
+        );
+        debugger.run_command("interrupt 1");
+        // The handler returns to the instruction after STOP.
+        let output = debugger.run_command("c");
+        assert!(output.starts_with("TRAP #0."), "{}", output);
+        assert_eq!(debugger.cpu.pc, 0x100e);
+    }
+
+    #[test]
+    fn test_trace_and_profile() {
+        let mut debugger = debugger();
+        let trace = SharedOutput::default();
+        debugger.set_trace(Some(Box::new(trace.clone())));
+        assert_eq!(
+            debugger.run_command("profile report"),
+            "The profiler isn't running. Start it with profile start."
+        );
+        assert_eq!(debugger.run_command("profile start"), "Profiling.");
+        debugger.run_command("step 12");
+        let trace = String::from_utf8(trace.0.borrow().clone()).unwrap();
+        assert_eq!(
+            trace.lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "$00001000 <start> MOVEQ #2,D0                   D0=00000002",
+                "$00001002 <start+2> LEA $1016,A0                A0=00001016",
+                "$00001008 <start.loop> BSR.S $1012              A7=000FFFFC",
+            ]
+        );
+        assert_eq!(trace.lines().count(), 12);
+
+        let report = debugger.run_command("profile report");
+        let report: Vec<&str> = report.lines().collect();
+        assert_eq!(report[1], "           6          70   52.2%  start.loop");
+        // The taken branch takes 10 cycles, the one that isn't taken 8.
+        assert!(report.contains(&"           2          18   13.4%     5  BNE .loop"));
+        assert_eq!(
+            report.last(),
+            Some(&"          12         134  100.0%  Total")
+        );
+        assert_eq!(
+            debugger.profiler.as_ref().unwrap().collapsed_stacks(),
+            "start 86\nstart;store 48\n"
+        );
+        assert_eq!(debugger.run_command("profile stop"), "Stopped profiling.");
+    }
+
+    #[test]
+    fn test_devices() {
+        let mut errors = vec![];
+        let mut debugger = Debugger::from_source(
+            " LEA handler,A0\n MOVE.L A0,$78\n MOVE.L #5,$800000\n MOVE.W #3,$800008\n \
+             STOP #$2000\n TRAP #0\nhandler MOVE.W #1,$80000A\n RTE",
+            &mut errors,
+        );
+        assert!(errors.is_empty(), "Assembling failed.");
+        debugger.memory.map(0x80_0000, Timer::new(6)).unwrap();
+        // The CPU waits for the timer instead of reporting that it's stopped.
+        let output = debugger.run_command("c");
+        assert!(output.starts_with("TRAP #0."), "{}", output);
+        assert_eq!(debugger.memory.peek(0x80_000a, Size::Word), Ok(0));
+
+        let mut debugger = Debugger::from_source(" MOVE.W $F00000,D0", &mut errors);
+        let output = debugger.run_command("c");
+        assert!(output.starts_with("Bus error at $00F00000."), "{}", output);
+    }
+}
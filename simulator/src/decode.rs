@@ -0,0 +1,491 @@
+//! Decodes machine code into instructions, which also serves as a disassembler.
+//!
+//! Only the operations the assembler supports are decoded, as well as moves from and to the status
+//! register and user stack pointer, which operating systems need. Everything else is an illegal
+//! instruction or, if it starts with `$A` or `$F`, an unimplemented instruction.
+
+use crate::bus::Bus;
+use crate::exception::Exception;
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use m68k_reloaded_parser::statements::{Condition, OperationType, Size};
+use std::fmt::{self, Display};
+
+/// The index register of an indexed address.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct IndexRegister {
+    pub is_address: bool,
+    pub register: Byte,
+    /// Without it, the lower word of the register is sign-extended.
+    pub is_long: bool,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Operand {
+    Dn(Byte),
+    An(Byte),
+    AnInd(Byte),
+    AnIndWithPostInc(Byte),
+    AnIndWithPreDec(Byte),
+    AnIndWithDisplacement(i16, Byte),
+    AnIndWithIndex(i8, Byte, IndexRegister),
+    AbsoluteWord(Word),
+    AbsoluteLongWord(LongWord),
+    /// PC-relative addresses are relative to the address of their extension word, which is stored
+    /// as the second value.
+    PcIndWithDisplacement(i16, LongWord),
+    PcIndWithIndex(i8, IndexRegister, LongWord),
+    Immediate(LongWord),
+    /// The target of a branch.
+    Target(LongWord),
+    /// The status register.
+    Sr,
+    /// The user stack pointer.
+    Usp,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Instruction {
+    pub operation_type: OperationType,
+    /// `None` for operations that only exist in one size.
+    pub size: Option<Size>,
+    pub operands: Vec<Operand>,
+    /// The number of bytes including all extension words.
+    pub length: LongWord,
+}
+
+/// Reads the words of an instruction one after another.
+struct Reader<'a> {
+    bus: &'a dyn Bus,
+    address: LongWord,
+    start: LongWord,
+}
+
+impl Reader<'_> {
+    fn word(&mut self) -> Result<Word, Exception> {
+        let word = self.bus.peek(self.address, Size::Word)? as Word;
+        self.address += 2;
+        Ok(word)
+    }
+
+    fn long_word(&mut self) -> Result<LongWord, Exception> {
+        let high = self.word()?;
+        let low = self.word()?;
+        Ok((LongWord::from(high) << 16) | LongWord::from(low))
+    }
+
+    /// Reads the extension words of the effective address in the lower six bits of `bits`.
+    fn operand(&mut self, bits: Word, size: Size) -> Result<Option<Operand>, Exception> {
+        let register = (bits & 7) as Byte;
+        Ok(Some(match (bits >> 3) & 7 {
+            0 => Operand::Dn(register),
+            1 => Operand::An(register),
+            2 => Operand::AnInd(register),
+            3 => Operand::AnIndWithPostInc(register),
+            4 => Operand::AnIndWithPreDec(register),
+            5 => Operand::AnIndWithDisplacement(self.word()? as i16, register),
+            6 => {
+                let (displacement, index) = self.brief_extension_word()?;
+                Operand::AnIndWithIndex(displacement, register, index)
+            }
+            _ => match register {
+                0 => Operand::AbsoluteWord(self.word()?),
+                1 => Operand::AbsoluteLongWord(self.long_word()?),
+                2 => {
+                    let base = self.address;
+                    Operand::PcIndWithDisplacement(self.word()? as i16, base)
+                }
+                3 => {
+                    let base = self.address;
+                    let (displacement, index) = self.brief_extension_word()?;
+                    Operand::PcIndWithIndex(displacement, index, base)
+                }
+                4 => Operand::Immediate(match size {
+                    Size::Byte => LongWord::from(self.word()? & 0xff),
+                    Size::Word => LongWord::from(self.word()?),
+                    Size::LongWord => self.long_word()?,
+                }),
+                _ => return Ok(None),
+            },
+        }))
+    }
+
+    fn brief_extension_word(&mut self) -> Result<(i8, IndexRegister), Exception> {
+        let word = self.word()?;
+        let index = IndexRegister {
+            is_address: word & 0x8000 != 0,
+            register: ((word >> 12) & 7) as Byte,
+            is_long: word & 0x0800 != 0,
+        };
+        Ok((word as Byte as i8, index))
+    }
+}
+
+fn size_from_bits(bits: Word) -> Option<Size> {
+    match bits & 3 {
+        0 => Some(Size::Byte),
+        1 => Some(Size::Word),
+        2 => Some(Size::LongWord),
+        _ => None,
+    }
+}
+
+pub fn condition_from_bits(bits: Word) -> Option<Condition> {
+    Some(match bits & 15 {
+        2 => Condition::Hi,
+        3 => Condition::Ls,
+        4 => Condition::Cc,
+        5 => Condition::Cs,
+        6 => Condition::Ne,
+        7 => Condition::Eq,
+        8 => Condition::Vc,
+        9 => Condition::Vs,
+        10 => Condition::Pl,
+        11 => Condition::Mi,
+        12 => Condition::Ge,
+        13 => Condition::Lt,
+        14 => Condition::Gt,
+        15 => Condition::Le,
+        _ => return None,
+    })
+}
+
+/// Decodes the instruction at the address. Instructions are read without side effects, so that
+/// disassembling doesn't disturb devices.
+pub fn decode(bus: &dyn Bus, address: LongWord) -> Result<Instruction, Exception> {
+    let mut reader = Reader {
+        bus,
+        address,
+        start: address,
+    };
+    let word = reader.word()?;
+    let illegal = Exception::IllegalInstruction(word);
+    let register = ((word >> 9) & 7) as Byte;
+    let size = size_from_bits(word >> 6);
+    // Operations on data, like the status register or divisions, don't allow address registers.
+    let is_address_register = word & 0x38 == 0x08;
+    let mut operand = |bits: Word, size: Size| reader.operand(bits, size)?.ok_or(illegal);
+
+    let (operation_type, size, operands) = match word {
+        0x4e71 => (OperationType::Nop, None, vec![]),
+        0x4e72 => {
+            let data = LongWord::from(reader.word()?);
+            (OperationType::Stop, None, vec![Operand::Immediate(data)])
+        }
+        0x4e73 => (OperationType::Rte, None, vec![]),
+        0x4e75 => (OperationType::Rts, None, vec![]),
+        0x4e76 => (OperationType::Trapv, None, vec![]),
+        0x4e40..=0x4e4f => (
+            OperationType::Trap,
+            None,
+            vec![Operand::Immediate(LongWord::from(word & 15))],
+        ),
+        0x4e60..=0x4e67 => (
+            OperationType::Move,
+            None,
+            vec![Operand::An((word & 7) as Byte), Operand::Usp],
+        ),
+        0x4e68..=0x4e6f => (
+            OperationType::Move,
+            None,
+            vec![Operand::Usp, Operand::An((word & 7) as Byte)],
+        ),
+        _ if word & 0xffc0 == 0x40c0 && !is_address_register => {
+            let destination = operand(word, Size::Word)?;
+            (
+                OperationType::Move,
+                Some(Size::Word),
+                vec![Operand::Sr, destination],
+            )
+        }
+        _ if word & 0xffc0 == 0x46c0 && !is_address_register => {
+            let source = operand(word, Size::Word)?;
+            (
+                OperationType::Move,
+                Some(Size::Word),
+                vec![source, Operand::Sr],
+            )
+        }
+        _ if word & 0xf1c0 == 0x4180 && !is_address_register => {
+            let source = operand(word, Size::Word)?;
+            (
+                OperationType::Chk,
+                Some(Size::Word),
+                vec![source, Operand::Dn(register)],
+            )
+        }
+        _ if word & 0xf0c0 == 0x80c0 && !is_address_register => {
+            let operation_type = if word & 0x0100 != 0 {
+                OperationType::Divs
+            } else {
+                OperationType::Divu
+            };
+            let source = operand(word, Size::Word)?;
+            (
+                operation_type,
+                Some(Size::Word),
+                vec![source, Operand::Dn(register)],
+            )
+        }
+        _ if word & 0xffc0 == 0x4e80 => (
+            OperationType::Jsr,
+            None,
+            vec![operand(word, Size::LongWord)?],
+        ),
+        _ if word & 0xf1c0 == 0x41c0 => {
+            let source = operand(word, Size::LongWord)?;
+            (
+                OperationType::Lea,
+                None,
+                vec![source, Operand::An(register)],
+            )
+        }
+        _ if word & 0xff00 == 0x4200 && size.is_some() => {
+            let size = size.unwrap();
+            (OperationType::Clr, Some(size), vec![operand(word, size)?])
+        }
+        _ if word & 0xff00 == 0x0600 && size.is_some() => {
+            let size = size.unwrap();
+            let source = operand(0x3c, size)?;
+            let destination = operand(word, size)?;
+            (OperationType::Addi, Some(size), vec![source, destination])
+        }
+        _ if word & 0xf100 == 0x5000 && size.is_some() => {
+            let size = size.unwrap();
+            let data = match register {
+                0 => 8,
+                data => LongWord::from(data),
+            };
+            let destination = operand(word, size)?;
+            (
+                OperationType::Addq,
+                Some(size),
+                vec![Operand::Immediate(data), destination],
+            )
+        }
+        _ if word & 0xf000 == 0x6000 => {
+            let operation_type = match (word >> 8) & 15 {
+                0 => OperationType::Bra,
+                1 => OperationType::Bsr,
+                bits => OperationType::Bcc(condition_from_bits(bits).unwrap()),
+            };
+            let base = address + 2;
+            let (size, displacement) = match word & 0xff {
+                0 => (
+                    Size::Word,
+                    LongWord::from(reader.word()?) as i16 as LongWord,
+                ),
+                displacement => (Size::Byte, displacement as Byte as i8 as LongWord),
+            };
+            let target = Operand::Target(base.wrapping_add(displacement));
+            (operation_type, Some(size), vec![target])
+        }
+        _ if word & 0xf100 == 0x7000 => {
+            let data = word as Byte as i8 as LongWord;
+            (
+                OperationType::Moveq,
+                None,
+                vec![Operand::Immediate(data), Operand::Dn(register)],
+            )
+        }
+        _ if word & 0xf000 == 0xd000 => {
+            let operation_mode = (word >> 6) & 7;
+            match operation_mode {
+                3 | 7 => {
+                    let size = if operation_mode == 7 {
+                        Size::LongWord
+                    } else {
+                        Size::Word
+                    };
+                    let source = operand(word, size)?;
+                    (
+                        OperationType::Adda,
+                        Some(size),
+                        vec![source, Operand::An(register)],
+                    )
+                }
+                0..=2 => {
+                    let size = size.unwrap();
+                    let source = operand(word, size)?;
+                    (
+                        OperationType::Add,
+                        Some(size),
+                        vec![source, Operand::Dn(register)],
+                    )
+                }
+                _ => {
+                    let size = size.unwrap();
+                    let source = (word & 7) as Byte;
+                    match (word >> 3) & 7 {
+                        0 => (
+                            OperationType::Addx,
+                            Some(size),
+                            vec![Operand::Dn(source), Operand::Dn(register)],
+                        ),
+                        1 => (
+                            OperationType::Addx,
+                            Some(size),
+                            vec![
+                                Operand::AnIndWithPreDec(source),
+                                Operand::AnIndWithPreDec(register),
+                            ],
+                        ),
+                        _ => {
+                            let destination = operand(word, size)?;
+                            (
+                                OperationType::Add,
+                                Some(size),
+                                vec![Operand::Dn(register), destination],
+                            )
+                        }
+                    }
+                }
+            }
+        }
+        _ if word & 0xc000 == 0 && word & 0x3000 != 0 => {
+            let size = match (word >> 12) & 3 {
+                1 => Size::Byte,
+                3 => Size::Word,
+                _ => Size::LongWord,
+            };
+            let source = operand(word, size)?;
+            // The destination's mode and register are swapped.
+            let destination_bits = (((word >> 6) & 7) << 3) | ((word >> 9) & 7);
+            let destination = operand(destination_bits, size)?;
+            (OperationType::Move, Some(size), vec![source, destination])
+        }
+        _ if word & 0xf000 == 0xa000 => return Err(Exception::LineA(word)),
+        _ if word & 0xf000 == 0xf000 => return Err(Exception::LineF(word)),
+        _ => return Err(illegal),
+    };
+    Ok(Instruction {
+        operation_type,
+        size,
+        operands,
+        length: reader.address - reader.start,
+    })
+}
+
+impl Display for IndexRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.is_address { 'A' } else { 'D' };
+        let size = if self.is_long { 'L' } else { 'W' };
+        write!(f, "{}{}.{}", kind, self.register, size)
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Dn(register) => write!(f, "D{}", register),
+            Operand::An(register) => write!(f, "A{}", register),
+            Operand::AnInd(register) => write!(f, "(A{})", register),
+            Operand::AnIndWithPostInc(register) => write!(f, "(A{})+", register),
+            Operand::AnIndWithPreDec(register) => write!(f, "-(A{})", register),
+            Operand::AnIndWithDisplacement(displacement, register) => {
+                write!(f, "{}(A{})", displacement, register)
+            }
+            Operand::AnIndWithIndex(displacement, register, index) => {
+                write!(f, "{}(A{},{})", displacement, register, index)
+            }
+            Operand::AbsoluteWord(address) => write!(f, "${:X}.W", address),
+            Operand::AbsoluteLongWord(address) => write!(f, "${:X}", address),
+            Operand::PcIndWithDisplacement(displacement, _) => write!(f, "{}(PC)", displacement),
+            Operand::PcIndWithIndex(displacement, index, _) => {
+                write!(f, "{}(PC,{})", displacement, index)
+            }
+            Operand::Immediate(value) => {
+                let signed = *value as i32;
+                if (-16..16).contains(&signed) {
+                    write!(f, "#{}", signed)
+                } else {
+                    write!(f, "#${:X}", value)
+                }
+            }
+            Operand::Target(address) => write!(f, "${:X}", address),
+            Operand::Sr => f.write_str("SR"),
+            Operand::Usp => f.write_str("USP"),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.operation_type)?;
+        match (self.size, self.operation_type.is_branch()) {
+            (Some(Size::Byte), true) => f.write_str(".S")?,
+            (Some(Size::Word), true) | (None, _) => {}
+            (Some(Size::Byte), false) => f.write_str(".B")?,
+            (Some(Size::Word), false) => f.write_str(".W")?,
+            (Some(Size::LongWord), _) => f.write_str(".L")?,
+        }
+        for (index, operand) in self.operands.iter().enumerate() {
+            f.write_str(if index == 0 { " " } else { "," })?;
+            write!(f, "{}", operand)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    fn disassemble(words: &[Word]) -> String {
+        let mut memory = Memory::new(0x2000);
+        let bytes: Vec<Byte> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        memory.load(0x1000, &bytes).unwrap();
+        let instruction = decode(&memory, 0x1000).unwrap();
+        assert_eq!(instruction.length, bytes.len() as LongWord);
+        instruction.to_string()
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(disassemble(&[0xd441]), "ADD.W D1,D2");
+        assert_eq!(disassemble(&[0xd7a8, 0x0004]), "ADD.L D3,4(A0)");
+        assert_eq!(disassemble(&[0x0610, 0x0001]), "ADDI.B #1,(A0)");
+        assert_eq!(disassemble(&[0xd3c0]), "ADDA.L D0,A1");
+        assert_eq!(disassemble(&[0x5041]), "ADDQ.W #8,D1");
+        assert_eq!(disassemble(&[0xd388]), "ADDX.L -(A0),-(A1)");
+        assert_eq!(disassemble(&[0x42b9, 0x1234, 0x5678]), "CLR.L $12345678");
+        assert_eq!(disassemble(&[0x4e90]), "JSR (A0)");
+        assert_eq!(disassemble(&[0x41fb, 0x0802]), "LEA 2(PC,D0.L),A0");
+        assert_eq!(disassemble(&[0x2f00]), "MOVE.L D0,-(A7)");
+        assert_eq!(
+            disassemble(&[0x13bc, 0x00ff, 0xa000]),
+            "MOVE.B #$FF,0(A1,A2.W)"
+        );
+        assert_eq!(disassemble(&[0x7eff]), "MOVEQ #-1,D7");
+        assert_eq!(disassemble(&[0x660e]), "BNE.S $1010");
+        assert_eq!(disassemble(&[0x6100, 0xfffe]), "BSR $1000");
+        assert_eq!(disassemble(&[0x4e75]), "RTS");
+        assert_eq!(disassemble(&[0x4e4f]), "TRAP #15");
+        assert_eq!(disassemble(&[0x4e72, 0x2000]), "STOP #$2000");
+        assert_eq!(disassemble(&[0x46fc, 0x0700]), "MOVE.W #$700,SR");
+        assert_eq!(disassemble(&[0x40e7]), "MOVE.W SR,-(A7)");
+        assert_eq!(disassemble(&[0x4e60]), "MOVE A0,USP");
+        assert_eq!(disassemble(&[0x4390]), "CHK.W (A0),D1");
+        assert_eq!(disassemble(&[0x85c1]), "DIVS.W D1,D2");
+        assert_eq!(disassemble(&[0x80fc, 0x0003]), "DIVU.W #3,D0");
+    }
+
+    #[test]
+    fn test_decode_illegal_instructions() {
+        let mut memory = Memory::new(0x10);
+        memory
+            .load(0, &[0x4a, 0xfc, 0x42, 0xc0, 0xa0, 0x0a, 0xf2, 0x00])
+            .unwrap();
+        assert_eq!(
+            decode(&memory, 0),
+            Err(Exception::IllegalInstruction(0x4afc))
+        );
+        assert_eq!(
+            decode(&memory, 2),
+            Err(Exception::IllegalInstruction(0x42c0))
+        );
+        assert_eq!(decode(&memory, 1), Err(Exception::AddressError(1)));
+        assert_eq!(decode(&memory, 4), Err(Exception::LineA(0xa00a)));
+        assert_eq!(decode(&memory, 6), Err(Exception::LineF(0xf200)));
+    }
+}
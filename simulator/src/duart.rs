@@ -0,0 +1,389 @@
+//! A Motorola MC68681 dual UART with its counter/timer, as used by many 68000 boards.
+//!
+//! The registers are bytes at odd addresses, like on boards that connect the DUART to the lower
+//! half of the data bus, so register n is at offset 2n + 1. The baud rates, the input port and
+//! the modes are stored, but have no effect on the simulation. Interrupts are autovectored, so the
+//! interrupt vector register is only stored as well.
+
+use crate::bus::{Bus, Device};
+use crate::exception::Exception;
+use crate::serial::Serial;
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use m68k_reloaded_parser::statements::Size;
+
+// The register numbers. Registers that share a number are read and written respectively.
+
+/// The mode registers 1 and 2 of channel A, which share an address.
+pub const MRA: Byte = 0;
+/// The status register and clock select register of channel A.
+pub const SRA: Byte = 1;
+/// The command register of channel A.
+pub const CRA: Byte = 2;
+/// The receive and transmit buffers of channel A.
+pub const RBA: Byte = 3;
+/// The input port change register and auxiliary control register.
+pub const ACR: Byte = 4;
+/// The interrupt status register and interrupt mask register.
+pub const ISR: Byte = 5;
+/// The upper and lower byte of the counter, and its preload value when written.
+pub const CUR: Byte = 6;
+pub const CLR: Byte = 7;
+pub const MRB: Byte = 8;
+pub const SRB: Byte = 9;
+pub const CRB: Byte = 10;
+pub const RBB: Byte = 11;
+pub const IVR: Byte = 12;
+/// The input port and output port configuration register.
+pub const IP: Byte = 13;
+/// Reading starts the counter, writing sets bits of the output port.
+pub const START_COUNTER: Byte = 14;
+/// Reading stops the counter, writing clears bits of the output port.
+pub const STOP_COUNTER: Byte = 15;
+
+/// The bits of the status registers.
+pub const RX_READY: Byte = 0x01;
+pub const TX_READY: Byte = 0x04;
+pub const TX_EMPTY: Byte = 0x08;
+
+/// The bits of the interrupt status and mask registers.
+pub const TX_READY_A: Byte = 0x01;
+pub const RX_READY_A: Byte = 0x02;
+pub const COUNTER_READY: Byte = 0x08;
+pub const TX_READY_B: Byte = 0x10;
+pub const RX_READY_B: Byte = 0x20;
+
+/// The bit of the auxiliary control register that selects the timer mode, where the counter
+/// starts over when it reaches zero, instead of stopping.
+const TIMER_MODE: Byte = 0x40;
+
+struct Channel {
+    serial: Serial,
+    modes: [Byte; 2],
+    /// Which mode register is accessed next. It advances after each access to the first one.
+    mode_pointer: usize,
+    is_receiving: bool,
+    is_transmitting: bool,
+}
+
+impl Channel {
+    fn new(serial: Serial) -> Channel {
+        Channel {
+            serial,
+            modes: [0; 2],
+            mode_pointer: 0,
+            is_receiving: false,
+            is_transmitting: false,
+        }
+    }
+
+    fn status(&self) -> Byte {
+        let mut status = 0;
+        if self.is_receiving && self.serial.is_receive_ready() {
+            status |= RX_READY;
+        }
+        if self.is_transmitting {
+            status |= TX_READY | TX_EMPTY;
+        }
+        status
+    }
+
+    fn command(&mut self, command: Byte) {
+        match command & 3 {
+            1 => self.is_receiving = true,
+            2 => self.is_receiving = false,
+            _ => {}
+        }
+        match (command >> 2) & 3 {
+            1 => self.is_transmitting = true,
+            2 => self.is_transmitting = false,
+            _ => {}
+        }
+        match (command >> 4) & 7 {
+            // Reset the mode register pointer.
+            1 => self.mode_pointer = 0,
+            // Reset the receiver, which also discards the received bytes.
+            2 => {
+                self.is_receiving = false;
+                while self.serial.receive().is_some() {}
+            }
+            // Reset the transmitter.
+            3 => self.is_transmitting = false,
+            _ => {}
+        }
+    }
+
+    fn read_mode(&mut self) -> Byte {
+        let mode = self.modes[self.mode_pointer];
+        self.mode_pointer = 1;
+        mode
+    }
+
+    fn write_mode(&mut self, value: Byte) {
+        self.modes[self.mode_pointer] = value;
+        self.mode_pointer = 1;
+    }
+
+    fn receive(&mut self) -> Byte {
+        if self.is_receiving {
+            self.serial.receive().unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    fn transmit(&mut self, byte: Byte) {
+        if self.is_transmitting {
+            self.serial.transmit(byte);
+        }
+    }
+}
+
+pub struct Duart {
+    level: Byte,
+    a: Channel,
+    b: Channel,
+    auxiliary_control: Byte,
+    interrupt_mask: Byte,
+    interrupt_vector: Byte,
+    output_port_configuration: Byte,
+    output_port: Byte,
+    preload: Word,
+    counter: Word,
+    is_counting: bool,
+    is_counter_ready: bool,
+}
+
+impl Duart {
+    /// A DUART after a reset that requests interrupts of the level.
+    pub fn new(level: Byte, a: Serial, b: Serial) -> Duart {
+        Duart {
+            level,
+            a: Channel::new(a),
+            b: Channel::new(b),
+            auxiliary_control: 0,
+            interrupt_mask: 0,
+            // The reset value of the interrupt vector register is the uninitialized vector.
+            interrupt_vector: 0x0f,
+            output_port_configuration: 0,
+            output_port: 0,
+            preload: 0,
+            counter: 0,
+            is_counting: false,
+            is_counter_ready: false,
+        }
+    }
+
+    fn interrupt_status(&self) -> Byte {
+        let (a, b) = (self.a.status(), self.b.status());
+        let bits = [
+            (a & TX_READY != 0, TX_READY_A),
+            (a & RX_READY != 0, RX_READY_A),
+            (self.is_counter_ready, COUNTER_READY),
+            (b & TX_READY != 0, TX_READY_B),
+            (b & RX_READY != 0, RX_READY_B),
+        ];
+        bits.iter()
+            .filter(|(is_set, _)| *is_set)
+            .fold(0, |status, (_, bit)| status | bit)
+    }
+
+    /// The register number of an offset, if there's a register.
+    fn register(address: LongWord, size: Size) -> Result<Byte, Exception> {
+        match size {
+            Size::Byte if address & 1 == 1 && address < 32 => Ok((address >> 1) as Byte),
+            _ => Err(Exception::BusError(address)),
+        }
+    }
+
+    /// Reads registers whose value doesn't change when they are read.
+    fn read_plain(&self, register: Byte) -> Option<Byte> {
+        Some(match register {
+            SRA => self.a.status(),
+            SRB => self.b.status(),
+            ACR => 0,
+            ISR => self.interrupt_status(),
+            CUR => (self.counter >> 8) as Byte,
+            CLR => self.counter as Byte,
+            IVR => self.interrupt_vector,
+            IP => 0,
+            _ => return None,
+        })
+    }
+}
+
+impl Bus for Duart {
+    fn read(&mut self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        let register = Duart::register(address, size)?;
+        self.a.serial.poll();
+        self.b.serial.poll();
+        let value = match register {
+            MRA => self.a.read_mode(),
+            MRB => self.b.read_mode(),
+            RBA => self.a.receive(),
+            RBB => self.b.receive(),
+            START_COUNTER => {
+                self.counter = self.preload;
+                self.is_counting = true;
+                0xff
+            }
+            STOP_COUNTER => {
+                // In timer mode, the command only acknowledges the interrupt.
+                if self.auxiliary_control & TIMER_MODE == 0 {
+                    self.is_counting = false;
+                }
+                self.is_counter_ready = false;
+                0xff
+            }
+            register => self.read_plain(register).unwrap_or(0xff),
+        };
+        Ok(LongWord::from(value))
+    }
+
+    fn write(&mut self, address: LongWord, size: Size, value: LongWord) -> Result<(), Exception> {
+        let register = Duart::register(address, size)?;
+        let value = value as Byte;
+        match register {
+            MRA => self.a.write_mode(value),
+            MRB => self.b.write_mode(value),
+            CRA => self.a.command(value),
+            CRB => self.b.command(value),
+            RBA => self.a.transmit(value),
+            RBB => self.b.transmit(value),
+            ACR => self.auxiliary_control = value,
+            ISR => self.interrupt_mask = value,
+            CUR => self.preload = (self.preload & 0x00ff) | (Word::from(value) << 8),
+            CLR => self.preload = (self.preload & 0xff00) | Word::from(value),
+            IVR => self.interrupt_vector = value,
+            IP => self.output_port_configuration = value,
+            START_COUNTER => self.output_port |= value,
+            STOP_COUNTER => self.output_port &= !value,
+            // The clock select registers.
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn peek(&self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        let register = Duart::register(address, size)?;
+        let value = match register {
+            MRA => Some(self.a.modes[self.a.mode_pointer]),
+            MRB => Some(self.b.modes[self.b.mode_pointer]),
+            RBA => self.a.serial.peek(),
+            RBB => self.b.serial.peek(),
+            register => self.read_plain(register),
+        };
+        value
+            .map(LongWord::from)
+            .ok_or(Exception::BusError(address))
+    }
+
+    fn tick(&mut self) -> Option<Byte> {
+        self.a.serial.poll();
+        self.b.serial.poll();
+        if self.is_counting {
+            self.counter = self.counter.wrapping_sub(1);
+            if self.counter == 0 {
+                self.is_counter_ready = true;
+                if self.auxiliary_control & TIMER_MODE != 0 {
+                    self.counter = self.preload;
+                } else {
+                    self.is_counting = false;
+                }
+            }
+        }
+        if self.interrupt_status() & self.interrupt_mask != 0 {
+            Some(self.level)
+        } else {
+            None
+        }
+    }
+
+    fn can_interrupt(&self) -> bool {
+        self.interrupt_mask != 0
+    }
+}
+
+impl Device for Duart {
+    fn size(&self) -> LongWord {
+        32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::tests::serial;
+
+    fn address(register: Byte) -> LongWord {
+        LongWord::from(register) * 2 + 1
+    }
+
+    fn write(duart: &mut Duart, register: Byte, value: Byte) {
+        duart
+            .write(address(register), Size::Byte, LongWord::from(value))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_channels() {
+        let (a, input, output) = serial();
+        let mut duart = Duart::new(5, a, Serial::disconnected());
+        write(&mut duart, MRA, 0x13);
+        write(&mut duart, MRA, 0x07);
+        write(&mut duart, CRA, 0x10);
+        assert_eq!(duart.read(address(MRA), Size::Byte), Ok(0x13));
+        assert_eq!(duart.read(address(MRA), Size::Byte), Ok(0x07));
+
+        // Nothing is sent or received before the channel is enabled.
+        input.send(b'x').unwrap();
+        write(&mut duart, RBA, b'-');
+        assert_eq!(duart.read(address(SRA), Size::Byte), Ok(0));
+        write(&mut duart, CRA, 0x05);
+        assert_eq!(duart.read(address(SRA), Size::Byte), Ok(0x0d));
+        assert_eq!(duart.peek(address(RBA), Size::Byte), Ok(0x78));
+        assert_eq!(duart.read(address(RBA), Size::Byte), Ok(0x78));
+        write(&mut duart, RBA, b'+');
+        assert_eq!(*output.0.borrow(), b"+");
+
+        // Receiving interrupts once it's enabled in the mask.
+        assert_eq!(duart.tick(), None);
+        write(&mut duart, ISR, RX_READY_A);
+        input.send(b'y').unwrap();
+        assert_eq!(duart.tick(), Some(5));
+        assert_eq!(duart.read(address(ISR), Size::Byte), Ok(0x03));
+        duart.read(address(RBA), Size::Byte).unwrap();
+        assert_eq!(duart.tick(), None);
+
+        assert_eq!(
+            duart.read(address(SRA) - 1, Size::Byte),
+            Err(Exception::BusError(2))
+        );
+        assert_eq!(
+            duart.read(address(SRA), Size::Word),
+            Err(Exception::BusError(3))
+        );
+    }
+
+    #[test]
+    fn test_counter() {
+        let mut duart = Duart::new(3, Serial::disconnected(), Serial::disconnected());
+        write(&mut duart, ACR, TIMER_MODE);
+        write(&mut duart, CUR, 0);
+        write(&mut duart, CLR, 2);
+        write(&mut duart, ISR, COUNTER_READY);
+        assert!(duart.can_interrupt());
+        duart.read(address(START_COUNTER), Size::Byte).unwrap();
+        assert_eq!(duart.tick(), None);
+        assert_eq!(duart.peek(address(CLR), Size::Byte), Ok(1));
+        assert_eq!(duart.tick(), Some(3));
+        assert_eq!(duart.peek(address(CLR), Size::Byte), Ok(2));
+        // Stopping acknowledges the interrupt, but the timer keeps running.
+        duart.read(address(STOP_COUNTER), Size::Byte).unwrap();
+        assert_eq!(duart.tick(), None);
+        assert_eq!(duart.tick(), Some(3));
+        assert_eq!(
+            duart.peek(address(START_COUNTER), Size::Byte),
+            Err(Exception::BusError(29))
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! The exceptions of the 68000 and their vectors.
+//!
+//! The vector table starts at address 0 and contains a long word for every vector: the initial
+//! supervisor stack pointer and PC used by a reset, followed by the addresses of the handlers.
+
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use std::fmt::{self, Display};
+
+/// The vectors read by a reset.
+pub const RESET_STACK_POINTER: Byte = 0;
+pub const RESET_PC: Byte = 1;
+
+/// The vector used for an interrupt that no device acknowledges.
+pub const SPURIOUS_INTERRUPT: Byte = 24;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Exception {
+    /// An access to an address without memory.
+    BusError(LongWord),
+    /// A word or long word access to an odd address.
+    AddressError(LongWord),
+    IllegalInstruction(Word),
+    ZeroDivide,
+    /// `CHK` found a register out of its bounds.
+    Chk,
+    /// `TRAPV` with the overflow flag set.
+    Trapv,
+    /// A privileged instruction in user mode.
+    PrivilegeViolation,
+    /// Taken after every instruction while the trace flag is set.
+    Trace,
+    /// Instruction words starting with `$A`, which are reserved for emulating instructions.
+    LineA(Word),
+    /// Instruction words starting with `$F`, which are used by coprocessors.
+    LineF(Word),
+    /// An autovectored interrupt of the level 1 to 7.
+    Interrupt(Byte),
+    /// `TRAP #n` with n from 0 to 15.
+    Trap(Byte),
+}
+
+impl Exception {
+    /// The number of the vector. Its handler's address is stored at four times this number.
+    pub fn vector(self) -> Byte {
+        match self {
+            Exception::BusError(_) => 2,
+            Exception::AddressError(_) => 3,
+            Exception::IllegalInstruction(_) => 4,
+            Exception::ZeroDivide => 5,
+            Exception::Chk => 6,
+            Exception::Trapv => 7,
+            Exception::PrivilegeViolation => 8,
+            Exception::Trace => 9,
+            Exception::LineA(_) => 10,
+            Exception::LineF(_) => 11,
+            Exception::Interrupt(level) => SPURIOUS_INTERRUPT + level,
+            Exception::Trap(number) => 32 + number,
+        }
+    }
+
+    /// Bus and address errors abort the current bus cycle and build a larger stack frame that
+    /// also contains the accessed address.
+    pub fn is_group_0(self) -> bool {
+        matches!(self, Exception::BusError(_) | Exception::AddressError(_))
+    }
+
+    /// Whether the instruction causing the exception completes before it is processed, so that
+    /// the stacked PC points to the next instruction.
+    pub fn completes_instruction(self) -> bool {
+        matches!(
+            self,
+            Exception::ZeroDivide | Exception::Chk | Exception::Trapv | Exception::Trap(_)
+        )
+    }
+}
+
+impl Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exception::BusError(address) => write!(f, "Bus error at ${:08X}.", address),
+            Exception::AddressError(address) => write!(f, "Address error at ${:08X}.", address),
+            Exception::IllegalInstruction(word) => write!(f, "Illegal instruction ${:04X}.", word),
+            Exception::ZeroDivide => f.write_str("Division by zero."),
+            Exception::Chk => f.write_str("CHK out of bounds."),
+            Exception::Trapv => f.write_str("TRAPV with overflow."),
+            Exception::PrivilegeViolation => f.write_str("Privilege violation."),
+            Exception::Trace => f.write_str("Trace."),
+            Exception::LineA(word) => write!(f, "Line A instruction ${:04X}.", word),
+            Exception::LineF(word) => write!(f, "Line F instruction ${:04X}.", word),
+            Exception::Interrupt(level) => write!(f, "Interrupt of level {}.", level),
+            Exception::Trap(number) => write!(f, "TRAP #{}.", number),
+        }
+    }
+}
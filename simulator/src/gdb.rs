@@ -0,0 +1,562 @@
+//! A stub for the GDB remote serial protocol, so that GDB and frontends built on it can debug
+//! programs running in the simulator.
+//!
+//! Packets look like `$data#checksum` and are acknowledged with `+`. The stub works on any stream,
+//! like a TCP connection or the standard input and output of a pipe. Programs only run while GDB
+//! waits for a stop reply, so GDB can't interrupt them; instead, they stop after a limit of
+//! instructions.
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::debugger::{Debugger, Stop};
+use crate::exception::Exception;
+use m68k_reloaded_common::{Byte, LongWord};
+use m68k_reloaded_parser::statements::Size;
+use std::io::{self, Read, Write};
+
+/// The registers in the order of GDB's m68k target: D0–D7, A0–A7, SR and PC. All of them are 32
+/// bits wide, even the SR.
+const REGISTER_COUNT: usize = 18;
+
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <architecture>m68k</architecture>
+  <feature name="org.gnu.gdb.m68k.core">
+    <reg name="d0" bitsize="32"/>
+    <reg name="d1" bitsize="32"/>
+    <reg name="d2" bitsize="32"/>
+    <reg name="d3" bitsize="32"/>
+    <reg name="d4" bitsize="32"/>
+    <reg name="d5" bitsize="32"/>
+    <reg name="d6" bitsize="32"/>
+    <reg name="d7" bitsize="32"/>
+    <reg name="a0" bitsize="32" type="data_ptr"/>
+    <reg name="a1" bitsize="32" type="data_ptr"/>
+    <reg name="a2" bitsize="32" type="data_ptr"/>
+    <reg name="a3" bitsize="32" type="data_ptr"/>
+    <reg name="a4" bitsize="32" type="data_ptr"/>
+    <reg name="a5" bitsize="32" type="data_ptr"/>
+    <reg name="fp" bitsize="32" type="data_ptr"/>
+    <reg name="sp" bitsize="32" type="data_ptr"/>
+    <reg name="ps" bitsize="32"/>
+    <reg name="pc" bitsize="32" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// The size of the largest packet, which is advertised to GDB.
+const PACKET_SIZE: usize = 0x4000;
+
+/// The signals reported in stop replies.
+const SIGINT: Byte = 2;
+const SIGILL: Byte = 4;
+const SIGTRAP: Byte = 5;
+const SIGFPE: Byte = 8;
+const SIGBUS: Byte = 10;
+
+fn checksum(data: &[Byte]) -> Byte {
+    data.iter()
+        .fold(0, |sum: Byte, &byte| sum.wrapping_add(byte))
+}
+
+pub fn frame(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data.as_bytes()))
+}
+
+/// The signal GDB shows for an exception without a handler.
+fn signal(exception: Exception) -> Byte {
+    match exception {
+        Exception::BusError(_) | Exception::AddressError(_) => SIGBUS,
+        Exception::IllegalInstruction(_)
+        | Exception::LineA(_)
+        | Exception::LineF(_)
+        | Exception::PrivilegeViolation => SIGILL,
+        Exception::ZeroDivide | Exception::Chk | Exception::Trapv => SIGFPE,
+        Exception::Trace | Exception::Trap(_) => SIGTRAP,
+        Exception::Interrupt(_) => SIGINT,
+    }
+}
+
+fn register(cpu: &Cpu, index: usize) -> Option<LongWord> {
+    Some(match index {
+        0..=7 => cpu.d[index],
+        8..=15 => cpu.a[index - 8],
+        16 => LongWord::from(cpu.sr),
+        17 => cpu.pc,
+        _ => return None,
+    })
+}
+
+fn set_register(cpu: &mut Cpu, index: usize, value: LongWord) -> bool {
+    match index {
+        0..=7 => cpu.d[index] = value,
+        8..=15 => cpu.a[index - 8] = value,
+        16 => cpu.set_sr(value as u16),
+        17 => cpu.pc = value,
+        _ => return false,
+    }
+    true
+}
+
+fn parse_hex(text: &str) -> Option<LongWord> {
+    LongWord::from_str_radix(text, 16).ok()
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<Byte>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| Byte::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `address,length`.
+fn parse_range(text: &str) -> Option<(LongWord, LongWord)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+pub struct Server<'a> {
+    debugger: &'a mut Debugger,
+    last_stop: Stop,
+    is_acknowledging: bool,
+    /// Whether GDB killed the program or detached.
+    is_done: bool,
+}
+
+impl Server<'_> {
+    pub fn new(debugger: &mut Debugger) -> Server<'_> {
+        Server {
+            debugger,
+            last_stop: Stop::Done,
+            is_acknowledging: true,
+            is_done: false,
+        }
+    }
+
+    fn stop_reply(&self) -> String {
+        match self.last_stop {
+            Stop::Done | Stop::Breakpoint(_) => format!("S{:02x}", SIGTRAP),
+            Stop::Watchpoint { address, .. } => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+            Stop::Exception(exception) => format!("S{:02x}", signal(exception)),
+            Stop::Stopped => format!("S{:02x}", SIGTRAP),
+            Stop::StepLimit => format!("S{:02x}", SIGINT),
+        }
+    }
+
+    /// Handles the data of a packet and returns the reply. An empty reply means that the packet
+    /// isn't supported. Returns `None` for packets that don't get a reply.
+    pub fn handle(&mut self, packet: &str) -> Option<String> {
+        let error = || "E01".to_string();
+        let cpu = &mut self.debugger.cpu;
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        Some(match command {
+            "?" => self.stop_reply(),
+            "g" => (0..REGISTER_COUNT)
+                .map(|index| format!("{:08x}", register(cpu, index).unwrap()))
+                .collect(),
+            "G" => match parse_hex_bytes(arguments) {
+                Some(bytes) if bytes.len() == 4 * REGISTER_COUNT => {
+                    for (index, value) in bytes.chunks(4).enumerate() {
+                        let value =
+                            LongWord::from_be_bytes([value[0], value[1], value[2], value[3]]);
+                        set_register(cpu, index, value);
+                    }
+                    "OK".to_string()
+                }
+                _ => error(),
+            },
+            "p" => match parse_hex(arguments).and_then(|index| register(cpu, index as usize)) {
+                Some(value) => format!("{:08x}", value),
+                None => error(),
+            },
+            "P" => {
+                let written = arguments.split_once('=').and_then(|(index, value)| {
+                    let value = parse_hex_bytes(value).filter(|bytes| bytes.len() == 4)?;
+                    let value = LongWord::from_be_bytes([value[0], value[1], value[2], value[3]]);
+                    Some(set_register(cpu, parse_hex(index)? as usize, value))
+                });
+                if written == Some(true) {
+                    "OK".to_string()
+                } else {
+                    error()
+                }
+            }
+            "m" => match parse_range(arguments) {
+                Some((address, length)) => {
+                    let memory = &self.debugger.memory;
+                    // Every byte takes two hex digits in the reply.
+                    let length = length.min((PACKET_SIZE / 2) as LongWord);
+                    let bytes: Vec<String> = (0..length)
+                        .map_while(|offset| {
+                            memory.peek(address.wrapping_add(offset), Size::Byte).ok()
+                        })
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect();
+                    if bytes.is_empty() && length > 0 {
+                        error()
+                    } else {
+                        bytes.concat()
+                    }
+                }
+                None => error(),
+            },
+            "M" => {
+                let written = arguments.split_once(':').and_then(|(range, data)| {
+                    let (address, length) = parse_range(range)?;
+                    let bytes =
+                        parse_hex_bytes(data).filter(|bytes| bytes.len() == length as usize)?;
+                    self.debugger.memory.load(address, &bytes).ok()
+                });
+                match written {
+                    Some(()) => "OK".to_string(),
+                    None => error(),
+                }
+            }
+            "Z" | "z" => {
+                let is_insertion = command == "Z";
+                let mut parts = arguments.split(',');
+                let (kind, address, length) = match (
+                    parts.next(),
+                    parts.next().and_then(parse_hex),
+                    parts.next().and_then(parse_hex),
+                ) {
+                    (Some(kind), Some(address), Some(length)) => (kind, address, length),
+                    _ => return Some(error()),
+                };
+                match (kind, is_insertion) {
+                    ("0", true) => self.debugger.add_breakpoint(address),
+                    ("0", false) => {
+                        self.debugger.remove_breakpoint(address);
+                    }
+                    ("2", true) => self.debugger.add_watchpoint(address, length),
+                    ("2", false) => {
+                        self.debugger.remove_watchpoint(address);
+                    }
+                    // Hardware breakpoints and read watchpoints aren't supported.
+                    _ => return Some(String::new()),
+                }
+                "OK".to_string()
+            }
+            "c" | "s" => {
+                if !arguments.is_empty() {
+                    match parse_hex(arguments) {
+                        Some(address) => self.debugger.cpu.pc = address,
+                        None => return Some(error()),
+                    }
+                }
+                self.last_stop = if command == "c" {
+                    self.debugger.resume()
+                } else {
+                    self.debugger.step()
+                };
+                self.stop_reply()
+            }
+            "H" | "T" => "OK".to_string(),
+            "k" => {
+                self.is_done = true;
+                return None;
+            }
+            "D" => {
+                self.debugger.clear_breakpoints();
+                self.is_done = true;
+                "OK".to_string()
+            }
+            "q" | "Q" => self.handle_query(packet),
+            _ => String::new(),
+        })
+    }
+
+    fn handle_query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+",
+                PACKET_SIZE
+            );
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let (offset, length) = match parse_range(range) {
+                Some(range) => range,
+                None => return "E01".to_string(),
+            };
+            let start = (offset as usize).min(TARGET_XML.len());
+            let end = (start + length as usize).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+            return format!("{}{}", marker, &TARGET_XML[start..end]);
+        }
+        match packet {
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "QStartNoAckMode" => {
+                self.is_acknowledging = false;
+                "OK".to_string()
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Serves a single GDB session on the stream.
+    pub fn serve(&mut self, stream: &mut (impl Read + Write)) -> io::Result<()> {
+        loop {
+            let packet = match read_packet(stream)? {
+                Some(Ok(packet)) => packet,
+                // GDB retransmits packets with a wrong checksum.
+                Some(Err(())) => {
+                    stream.write_all(b"-")?;
+                    continue;
+                }
+                None => return Ok(()),
+            };
+            if self.is_acknowledging {
+                stream.write_all(b"+")?;
+            }
+            if let Some(reply) = self.handle(&packet) {
+                stream.write_all(frame(&reply).as_bytes())?;
+            }
+            stream.flush()?;
+            if self.is_done {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn read_byte(stream: &mut impl Read) -> io::Result<Option<Byte>> {
+    let mut byte = [0];
+    match stream.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// Reads the next packet, skipping acknowledgements and interrupts in between. Returns `None` at
+/// the end of the stream and `Some(Err(()))` if the checksum is wrong.
+fn read_packet(stream: &mut impl Read) -> io::Result<Option<Result<String, ()>>> {
+    loop {
+        match read_byte(stream)? {
+            None => return Ok(None),
+            Some(b'$') => break,
+            Some(_) => continue,
+        }
+    }
+    let mut raw = vec![];
+    loop {
+        match read_byte(stream)? {
+            None => return Ok(None),
+            Some(b'#') => break,
+            // The escaped character can be a #, so it's read right away.
+            Some(b'}') => match read_byte(stream)? {
+                Some(byte) => raw.extend([b'}', byte]),
+                None => return Ok(None),
+            },
+            Some(byte) => raw.push(byte),
+        }
+    }
+    let mut digits = [0; 2];
+    stream.read_exact(&mut digits)?;
+    let expected = std::str::from_utf8(&digits)
+        .ok()
+        .and_then(|digits| Byte::from_str_radix(digits, 16).ok());
+    // The checksum covers the data as it was sent, before escaped characters are XORed with $20.
+    if expected != Some(checksum(&raw)) {
+        return Ok(Some(Err(())));
+    }
+    let mut data = vec![];
+    let mut bytes = raw.into_iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'}' => data.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            byte => data.push(byte),
+        }
+    }
+    Ok(Some(Ok(String::from_utf8_lossy(&data).into_owned())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SOURCE: &str = "start MOVEQ #2,D0
+.loop BSR store
+ ADD.W #-1,D0
+ BNE .loop
+ NOP
+store MOVE.B D0,$2000
+ RTS";
+
+    fn debugger() -> Debugger {
+        let mut errors = vec![];
+        let debugger = Debugger::from_source(SOURCE, &mut errors);
+        assert!(errors.is_empty(), "Assembling failed.");
+        debugger
+    }
+
+    /// Plays the packets like GDB would and returns everything the stub sent back.
+    fn run_script(debugger: &mut Debugger, packets: &[&str]) -> String {
+        let mut input = vec![];
+        for packet in packets {
+            input.extend(frame(packet).into_bytes());
+            input.push(b'+');
+        }
+        struct Script {
+            input: Cursor<Vec<Byte>>,
+            output: Vec<Byte>,
+        }
+        impl Read for Script {
+            fn read(&mut self, buffer: &mut [Byte]) -> io::Result<usize> {
+                self.input.read(buffer)
+            }
+        }
+        impl Write for Script {
+            fn write(&mut self, buffer: &[Byte]) -> io::Result<usize> {
+                self.output.write(buffer)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut script = Script {
+            input: Cursor::new(input),
+            output: vec![],
+        };
+        Server::new(debugger).serve(&mut script).unwrap();
+        String::from_utf8(script.output).unwrap()
+    }
+
+    #[test]
+    fn test_session() {
+        let mut debugger = debugger();
+        let output = run_script(
+            &mut debugger,
+            &[
+                "qSupported:swbreak+",
+                "?",
+                "Z0,1008,2",
+                "c",
+                "p11",
+                "z0,1008,2",
+                "k",
+                "?",
+            ],
+        );
+        let replies: Vec<String> = [
+            "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+",
+            "S05",
+            "OK",
+            "S05",
+            "00001008",
+            "OK",
+        ]
+        .iter()
+        .map(|reply| format!("+{}", frame(reply)))
+        .collect();
+        // Killing doesn't get a reply and ends the session.
+        assert_eq!(output, replies.concat() + "+");
+        assert_eq!(debugger.cpu.pc, 0x1008);
+    }
+
+    #[test]
+    fn test_wrong_checksums_are_rejected() {
+        let mut debugger = debugger();
+        let mut script = Cursor::new(b"$g#00$?#3f".to_vec());
+        let mut output = vec![];
+        {
+            struct Both<'a>(&'a mut Cursor<Vec<Byte>>, &'a mut Vec<Byte>);
+            impl Read for Both<'_> {
+                fn read(&mut self, buffer: &mut [Byte]) -> io::Result<usize> {
+                    self.0.read(buffer)
+                }
+            }
+            impl Write for Both<'_> {
+                fn write(&mut self, buffer: &[Byte]) -> io::Result<usize> {
+                    self.1.write(buffer)
+                }
+                fn flush(&mut self) -> io::Result<()> {
+                    Ok(())
+                }
+            }
+            let mut stream = Both(&mut script, &mut output);
+            Server::new(&mut debugger).serve(&mut stream).unwrap();
+        }
+        assert_eq!(String::from_utf8(output).unwrap(), "-+$S05#b8");
+    }
+
+    #[test]
+    fn test_escaped_packets() {
+        // The escaped ? is $1F, so the checksum is $7D + $1F and not the one of the unescaped ?.
+        let mut stream = Cursor::new(b"$}\x1f#9c$}\x1f#3f".to_vec());
+        assert_eq!(read_packet(&mut stream).unwrap(), Some(Ok("?".to_string())));
+        assert_eq!(read_packet(&mut stream).unwrap(), Some(Err(())));
+        // An escaped # doesn't end the packet.
+        let mut stream = Cursor::new(b"$a}\x03b#43".to_vec());
+        assert_eq!(
+            read_packet(&mut stream).unwrap(),
+            Some(Ok("a#b".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut debugger = debugger();
+        let mut server = Server::new(&mut debugger);
+        server.handle("s");
+        let registers = server.handle("g").unwrap();
+        assert_eq!(registers.len(), 8 * REGISTER_COUNT);
+        assert_eq!(&registers[..8], "00000002");
+        assert_eq!(&registers[8 * 15..], "001000000000270000001002");
+
+        assert_eq!(server.handle("P3=deadbeef").unwrap(), "OK");
+        assert_eq!(server.handle("p3").unwrap(), "deadbeef");
+        assert_eq!(server.handle("p12").unwrap(), "E01");
+        let mut registers = "0".repeat(8 * REGISTER_COUNT - 8);
+        registers.push_str("00001000");
+        assert_eq!(server.handle(&format!("G{}", registers)).unwrap(), "OK");
+        assert_eq!(debugger.cpu.pc, 0x1000);
+        assert_eq!(debugger.cpu.d[3], 0);
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut debugger = debugger();
+        let mut server = Server::new(&mut debugger);
+        assert_eq!(server.handle("m1000,4").unwrap(), "70026108");
+        assert_eq!(server.handle("M2000,2:abcd").unwrap(), "OK");
+        assert_eq!(server.handle("m2000,2").unwrap(), "abcd");
+        assert_eq!(server.handle("mffffe,4").unwrap(), "0000");
+        assert_eq!(server.handle("m200000,4").unwrap(), "E01");
+        // Replies are limited to the advertised packet size.
+        assert_eq!(server.handle("m0,10000").unwrap().len(), PACKET_SIZE);
+        assert_eq!(server.handle("M2000,2:ab").unwrap(), "E01");
+    }
+
+    #[test]
+    fn test_watchpoints_and_faults() {
+        let mut debugger = debugger();
+        let mut server = Server::new(&mut debugger);
+        assert_eq!(server.handle("Z2,2000,1").unwrap(), "OK");
+        assert_eq!(server.handle("c").unwrap(), "T05watch:2000;");
+        assert_eq!(server.handle("z2,2000,1").unwrap(), "OK");
+        // The program falls through into the subroutine, whose RTS has no return address.
+        assert_eq!(server.handle("c").unwrap(), "S0a");
+        assert_eq!(server.handle("Z1,2000,2").unwrap(), "");
+    }
+
+    #[test]
+    fn test_target_description() {
+        let mut debugger = debugger();
+        let mut server = Server::new(&mut debugger);
+        let first = server.handle("qXfer:features:read:target.xml:0,a").unwrap();
+        assert_eq!(first, "m<?xml vers");
+        let rest = server
+            .handle("qXfer:features:read:target.xml:a,10000")
+            .unwrap();
+        assert!(rest.starts_with("lion=\"1.0\"?>"));
+        assert!(rest.ends_with("</target>\n"));
+    }
+}
@@ -0,0 +1,12 @@
+pub mod bus;
+pub mod cpu;
+pub mod debugger;
+pub mod decode;
+pub mod duart;
+pub mod exception;
+pub mod gdb;
+pub mod memory;
+pub mod profiler;
+pub mod serial;
+pub mod testing;
+pub mod timer;
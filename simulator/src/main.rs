@@ -0,0 +1,247 @@
+use m68k_reloaded_common::errors::{PrintErrors, Severity};
+use m68k_reloaded_common::LongWord;
+use m68k_reloaded_object::prg;
+use m68k_reloaded_simulator::bus::Device;
+use m68k_reloaded_simulator::debugger::Debugger;
+use m68k_reloaded_simulator::duart::Duart;
+use m68k_reloaded_simulator::gdb::Server;
+use m68k_reloaded_simulator::serial::{Serial, Uart};
+use m68k_reloaded_simulator::testing::run_tests;
+use m68k_reloaded_simulator::timer::Timer;
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpListener;
+
+const USAGE: &str =
+    "Usage: debugger [--gdb <port> | --gdb stdio] [--trace <file>] [--profile <file>]
+                [--uart <address> | --duart <address>] [--timer <address>]
+                <source.s | program.prg>
+       debugger --test <source.s>
+
+With --gdb, the program is debugged by GDB using the remote serial protocol, either on a local
+TCP port (target remote localhost:<port>) or on the standard input and output
+(target remote | debugger --gdb stdio <file>).
+
+With --trace, every executed instruction is logged to the file. With --profile, the executed
+instructions and their cycles are counted; when the debugger exits, a report is printed and the
+call stacks are written to the file in the collapsed format of flame graph tools. Without --gdb,
+both run the program until it stops instead of starting the interactive debugger.
+
+The program runs with 1 MiB of RAM at address 0. --uart, --duart and --timer map devices at
+addresses like $FF0000 or 0xff0000: a polled UART or an MC68681 DUART, whose port A is
+connected to the standard input and output (port B is disconnected), and a timer. The DUART
+requests interrupts of level 5, the timer of level 6. Because the interactive debugger and
+--gdb stdio read the standard input themselves, the UART and the DUART need --gdb <port>,
+--trace or --profile.
+
+With --test, the tests declared in comments like ; test name: D0=1 => D0=2 are run for the
+routines following them.";
+
+/// The standard input and output as a single stream.
+struct Stdio;
+
+impl Read for Stdio {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        io::stdin().read(buffer)
+    }
+}
+
+impl Write for Stdio {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        io::stdout().write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [flag, path] = &args[..] {
+        if flag == "--test" {
+            return test(path);
+        }
+    }
+    let (mut gdb, mut trace, mut profile, mut path) = (None, None, None, None);
+    let (mut uart, mut duart, mut timer) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let option = match arg.as_str() {
+            "--gdb" => &mut gdb,
+            "--trace" => &mut trace,
+            "--profile" => &mut profile,
+            "--uart" => &mut uart,
+            "--duart" => &mut duart,
+            "--timer" => &mut timer,
+            _ if path.is_none() && !arg.starts_with("--") => {
+                path = Some(arg);
+                continue;
+            }
+            _ => fail(USAGE),
+        };
+        match args.next() {
+            Some(value) => *option = Some(value),
+            None => fail(USAGE),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => fail(USAGE),
+    };
+    let bytes = std::fs::read(path).expect("Couldn't read the file.");
+
+    let mut debugger = if bytes.starts_with(&prg::MAGIC.to_be_bytes()) {
+        match Debugger::from_program(&bytes) {
+            Ok(debugger) => debugger,
+            Err(error) => fail(&error.to_string()),
+        }
+    } else {
+        let source = String::from_utf8(bytes).expect("The source isn't valid UTF-8.");
+        let mut errors = vec![];
+        let debugger = Debugger::from_source(&source, &mut errors);
+        errors.print();
+        if errors.iter().any(|error| error.severity == Severity::Error) {
+            std::process::exit(1);
+        }
+        debugger
+    };
+
+    if uart.is_some() && duart.is_some() {
+        fail("The UART and the DUART can't both use the standard input and output.");
+    }
+    let reads_stdin = match gdb.map(|gdb| gdb.as_str()) {
+        Some(gdb) => gdb == "stdio",
+        None => trace.is_none() && profile.is_none(),
+    };
+    if (uart.is_some() || duart.is_some()) && reads_stdin {
+        fail(&format!(
+            "The UART and the DUART need --gdb <port>, --trace or --profile.\n{}",
+            USAGE
+        ));
+    }
+    map_device(&mut debugger, "--uart", uart, || Uart::new(Serial::stdio()));
+    map_device(&mut debugger, "--duart", duart, || {
+        Duart::new(5, Serial::stdio(), Serial::disconnected())
+    });
+    map_device(&mut debugger, "--timer", timer, || Timer::new(6));
+
+    if let Some(trace) = trace {
+        println!("{}", debugger.run_command(&format!("trace {}", trace)));
+    }
+    if profile.is_some() {
+        debugger.run_command("profile start");
+    }
+    match gdb.map(|gdb| gdb.as_str()) {
+        Some("stdio") => serve(&mut debugger, &mut Stdio),
+        Some(port) => {
+            let port: u16 = match port.parse() {
+                Ok(port) => port,
+                Err(_) => fail(&format!("{} isn't a port or stdio.\n{}", port, USAGE)),
+            };
+            let listener = match TcpListener::bind(("127.0.0.1", port)) {
+                Ok(listener) => listener,
+                Err(error) => fail(&format!("Couldn't listen on port {}: {}", port, error)),
+            };
+            eprintln!("Waiting for GDB on {}.", listener.local_addr().unwrap());
+            match listener.accept() {
+                Ok((mut stream, _)) => serve(&mut debugger, &mut stream),
+                Err(error) => eprintln!("Couldn't accept the connection: {}", error),
+            }
+        }
+        None if trace.is_some() || profile.is_some() => {
+            println!("{}", debugger.run_command("continue"))
+        }
+        None => repl(&mut debugger),
+    }
+    if let Some(profile) = profile {
+        println!("{}", debugger.run_command("profile report"));
+        println!(
+            "{}",
+            debugger.run_command(&format!("profile stacks {}", profile))
+        );
+    }
+}
+
+/// Maps the device at the address given to the option, if there is one.
+fn map_device<D: Device + 'static>(
+    debugger: &mut Debugger,
+    option: &str,
+    address: Option<&String>,
+    device: impl FnOnce() -> D,
+) {
+    let address = match address {
+        Some(address) => address,
+        None => return,
+    };
+    let parsed = match address
+        .strip_prefix('$')
+        .or_else(|| address.strip_prefix("0x"))
+    {
+        Some(hex) => LongWord::from_str_radix(hex, 16).ok(),
+        None => address.parse().ok(),
+    };
+    let address = match parsed {
+        Some(address) => address,
+        None => fail(&format!(
+            "{} expects an address like 16711680, $FF0000 or 0xff0000.",
+            option
+        )),
+    };
+    if let Err(error) = debugger.memory.map(address, device()) {
+        fail(&error.to_string());
+    }
+}
+
+fn test(path: &str) {
+    let source = std::fs::read_to_string(path).expect("Couldn't read the file.");
+    let mut errors = vec![];
+    let results = run_tests(&source, &mut errors);
+    for result in &results {
+        let outcome = if result.passed { "ok" } else { "FAILED" };
+        println!("test {}::{} ... {}", result.routine, result.name, outcome);
+    }
+    errors.print();
+    let failed = results.iter().filter(|result| !result.passed).count();
+    println!("{} passed, {} failed", results.len() - failed, failed);
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Prints the message, like the usage, and exits with a failure.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn serve(debugger: &mut Debugger, stream: &mut (impl Read + Write)) {
+    if let Err(error) = Server::new(debugger).serve(stream) {
+        eprintln!("The connection to GDB failed: {}", error);
+    }
+}
+
+fn repl(debugger: &mut Debugger) {
+    println!("{}", debugger.run_command("list"));
+    let mut last_command = String::new();
+    let stdin = io::stdin();
+    loop {
+        print!("(m68k) ");
+        io::stdout().flush().expect("Couldn't write the prompt.");
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let command = match line.trim() {
+            "" => last_command.clone(),
+            command => command.to_string(),
+        };
+        if command == "quit" || command == "q" {
+            break;
+        }
+        let output = debugger.run_command(&command);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+        last_command = command;
+    }
+}
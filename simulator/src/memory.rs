@@ -0,0 +1,253 @@
+//! The address space of the simulated machine, which maps regions to RAM, ROM and other devices.
+
+use crate::bus::{bytes_of, Bus, Device, Ram};
+use crate::exception::Exception;
+use m68k_reloaded_common::{Byte, LongWord};
+use m68k_reloaded_parser::statements::Size;
+use std::fmt::{self, Display};
+
+/// The 68000 only has 24 address lines, so the upper byte of addresses is ignored.
+pub const ADDRESS_MASK: LongWord = 0x00ff_ffff;
+
+struct Region {
+    start: LongWord,
+    device: Box<dyn Device>,
+}
+
+impl Region {
+    fn end(&self) -> LongWord {
+        self.start + self.device.size()
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum MapError {
+    /// The region doesn't fit into the 24-bit address space.
+    OutsideAddressSpace(LongWord),
+    Overlap(LongWord),
+}
+
+impl Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::OutsideAddressSpace(start) => write!(
+                f,
+                "The region at ${:06X} doesn't fit into the address space.",
+                start
+            ),
+            MapError::Overlap(start) => {
+                write!(f, "The region at ${:06X} overlaps another one.", start)
+            }
+        }
+    }
+}
+
+pub struct Memory {
+    /// Sorted by their start and not overlapping.
+    regions: Vec<Region>,
+    /// The address and length of every write since the last call to [Memory::take_writes].
+    writes: Vec<(LongWord, LongWord)>,
+}
+
+impl Memory {
+    /// An address space without any regions, where every access is a bus error.
+    pub fn empty() -> Memory {
+        Memory {
+            regions: vec![],
+            writes: vec![],
+        }
+    }
+
+    /// An address space with RAM of the size at address 0.
+    pub fn new(size: usize) -> Memory {
+        let mut memory = Memory::empty();
+        memory
+            .map(0, Ram::new(size as LongWord))
+            .expect("The RAM doesn't fit into the address space.");
+        memory
+    }
+
+    /// Maps the device to the region starting at the address, unless the region overlaps another
+    /// one or doesn't fit into the 24-bit address space.
+    pub fn map(&mut self, start: LongWord, device: impl Device + 'static) -> Result<(), MapError> {
+        let fits = start
+            .checked_add(device.size())
+            .is_some_and(|end| end <= ADDRESS_MASK + 1);
+        if !fits {
+            return Err(MapError::OutsideAddressSpace(start));
+        }
+        let region = Region {
+            start,
+            device: Box::new(device),
+        };
+        let index = self
+            .regions
+            .partition_point(|other| other.start < region.start);
+        let overlaps_previous = index > 0 && self.regions[index - 1].end() > region.start;
+        let overlaps_next = index < self.regions.len() && region.end() > self.regions[index].start;
+        if overlaps_previous || overlaps_next {
+            return Err(MapError::Overlap(start));
+        }
+        self.regions.insert(index, region);
+        Ok(())
+    }
+
+    pub fn take_writes(&mut self) -> Vec<(LongWord, LongWord)> {
+        std::mem::take(&mut self.writes)
+    }
+
+    /// The index of the region containing the whole access and the offset into it.
+    fn locate(&self, address: LongWord, length: LongWord) -> Result<(usize, LongWord), Exception> {
+        let address = address & ADDRESS_MASK;
+        if length > 1 && address & 1 != 0 {
+            return Err(Exception::AddressError(address));
+        }
+        let index = self
+            .regions
+            .partition_point(|region| region.start <= address)
+            .checked_sub(1)
+            .ok_or(Exception::BusError(address))?;
+        let region = &self.regions[index];
+        if address + length > region.end() {
+            return Err(Exception::BusError(address));
+        }
+        Ok((index, address - region.start))
+    }
+}
+
+/// Devices report errors at offsets into their region, which are turned back into addresses.
+fn at_address(address: LongWord) -> impl Fn(Exception) -> Exception {
+    let address = address & ADDRESS_MASK;
+    move |exception| match exception {
+        Exception::BusError(_) => Exception::BusError(address),
+        Exception::AddressError(_) => Exception::AddressError(address),
+        exception => exception,
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        let (index, offset) = self.locate(address, bytes_of(size))?;
+        self.regions[index]
+            .device
+            .read(offset, size)
+            .map_err(at_address(address))
+    }
+
+    fn write(&mut self, address: LongWord, size: Size, value: LongWord) -> Result<(), Exception> {
+        let length = bytes_of(size);
+        let (index, offset) = self.locate(address, length)?;
+        self.regions[index]
+            .device
+            .write(offset, size, value)
+            .map_err(at_address(address))?;
+        self.writes.push((address & ADDRESS_MASK, length));
+        Ok(())
+    }
+
+    fn peek(&self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        let (index, offset) = self.locate(address, bytes_of(size))?;
+        self.regions[index]
+            .device
+            .peek(offset, size)
+            .map_err(at_address(address))
+    }
+
+    /// Copies the bytes without recording writes.
+    fn load(&mut self, address: LongWord, bytes: &[Byte]) -> Result<(), Exception> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let (index, offset) = self.locate(address, 1)?;
+        let region = &mut self.regions[index];
+        if region.start + offset + bytes.len() as LongWord > region.end() {
+            return Err(Exception::BusError(address & ADDRESS_MASK));
+        }
+        region
+            .device
+            .load(offset, bytes)
+            .map_err(at_address(address))
+    }
+
+    /// Ticks all devices and returns the highest interrupt level they request.
+    fn tick(&mut self) -> Option<Byte> {
+        self.regions
+            .iter_mut()
+            .filter_map(|region| region.device.tick())
+            .max()
+    }
+
+    fn can_interrupt(&self) -> bool {
+        self.regions
+            .iter()
+            .any(|region| region.device.can_interrupt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Rom;
+
+    #[test]
+    fn test_memory() {
+        let mut memory = Memory::new(16);
+        memory
+            .write(0x0100_0002, Size::LongWord, 0x1234_5678)
+            .unwrap();
+        assert_eq!(memory.read_word(4), Ok(0x5678));
+        assert_eq!(memory.peek(3, Size::Byte), Ok(0x34));
+        assert_eq!(memory.take_writes(), vec![(2, 4)]);
+        assert_eq!(memory.read_word(3), Err(Exception::AddressError(3)));
+        assert_eq!(memory.read_long_word(14), Err(Exception::BusError(14)));
+        assert!(memory.take_writes().is_empty());
+    }
+
+    #[test]
+    fn test_regions() {
+        let mut memory = Memory::new(0x100);
+        memory
+            .map(0xfc_0000, Rom::new(vec![0x12, 0x34, 0x56, 0x78]))
+            .unwrap();
+        assert_eq!(memory.read_long_word(0xfc_0000), Ok(0x1234_5678));
+        assert_eq!(
+            memory.write(0xfc_0002, Size::Word, 0),
+            Err(Exception::BusError(0xfc_0002))
+        );
+        assert_eq!(
+            memory.read_word(0xfc_0004),
+            Err(Exception::BusError(0xfc_0004))
+        );
+        assert_eq!(
+            memory.read_word(0x80_0000),
+            Err(Exception::BusError(0x80_0000))
+        );
+        // Accesses must not cross into the unmapped space after a region.
+        assert_eq!(memory.read_long_word(0xfe), Err(Exception::BusError(0xfe)));
+        memory.load(0xfc_0002, &[0xab]).unwrap();
+        assert_eq!(memory.peek(0xfc_0002, Size::Byte), Ok(0xab));
+        assert!(memory.take_writes().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_regions() {
+        let mut memory = Memory::new(0x100);
+        assert_eq!(
+            memory.map(0xf0, Ram::new(0x20)),
+            Err(MapError::Overlap(0xf0))
+        );
+        assert_eq!(
+            memory.map(0xff_fff0, Ram::new(0x20)),
+            Err(MapError::OutsideAddressSpace(0xff_fff0))
+        );
+        assert_eq!(
+            memory.map(0xffff_fff0, Ram::new(0x20)),
+            Err(MapError::OutsideAddressSpace(0xffff_fff0))
+        );
+        assert_eq!(memory.map(0x100, Ram::new(0x20)), Ok(()));
+        assert_eq!(
+            memory.map(0xe0, Ram::new(0x20)),
+            Err(MapError::Overlap(0xe0))
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Counts the executed instructions and their estimated cycles, by address and by call stack.
+//!
+//! The cycles come from the timing tables the assembler uses, so they are only known for programs
+//! assembled from source. Call stacks are tracked by watching subroutine calls, returns and
+//! exceptions, and are written in the collapsed format that flame graph tools read.
+
+use m68k_reloaded_common::LongWord;
+use m68k_reloaded_parser::statements::OperationType;
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+/// The cycles the 68000 takes to process an interrupt, including the acknowledge cycle.
+pub const INTERRUPT_CYCLES: u64 = 44;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub count: u64,
+    pub cycles: u64,
+}
+
+impl Sample {
+    fn add(&mut self, cycles: u64) {
+        self.count += 1;
+        self.cycles += cycles;
+    }
+}
+
+impl AddAssign for Sample {
+    fn add_assign(&mut self, other: Sample) {
+        self.count += other.count;
+        self.cycles += other.cycles;
+    }
+}
+
+/// How an instruction changed the call stack.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Flow {
+    Call,
+    Return,
+    Continue,
+}
+
+impl Flow {
+    pub fn of(operation_type: OperationType) -> Flow {
+        match operation_type {
+            OperationType::Bsr | OperationType::Jsr => Flow::Call,
+            OperationType::Rts | OperationType::Rte => Flow::Return,
+            _ => Flow::Continue,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    /// By the address of the instruction.
+    samples: BTreeMap<LongWord, Sample>,
+    /// By the routines on the call stack, outermost first, joined by semicolons.
+    stacks: BTreeMap<String, Sample>,
+    /// The routines that are called but didn't return yet. The last one is the routine of the
+    /// current instruction.
+    calls: Vec<String>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// Records an instruction at the address that belongs to the routine. Exceptions entering a
+    /// handler count as calls.
+    pub fn record(&mut self, address: LongWord, routine: &str, cycles: u64, flow: Flow) {
+        self.samples.entry(address).or_default().add(cycles);
+        // Jumps and falling through into the next routine replace the current routine.
+        match self.calls.last_mut() {
+            Some(current) if current != routine => *current = routine.to_string(),
+            Some(_) => {}
+            None => self.calls.push(routine.to_string()),
+        }
+        self.stacks
+            .entry(self.calls.join(";"))
+            .or_default()
+            .add(cycles);
+        match flow {
+            // The callee's name is only known when its first instruction is recorded.
+            Flow::Call => self.calls.push(String::new()),
+            Flow::Return => {
+                self.calls.pop();
+            }
+            Flow::Continue => {}
+        }
+    }
+
+    /// Records an interrupt, whose handler is entered like a call.
+    pub fn record_interrupt(&mut self) {
+        if !self.calls.is_empty() {
+            self.stacks
+                .entry(self.calls.join(";"))
+                .or_default()
+                .add(INTERRUPT_CYCLES);
+        }
+        self.calls.push(String::new());
+    }
+
+    pub fn samples(&self) -> &BTreeMap<LongWord, Sample> {
+        &self.samples
+    }
+
+    pub fn total(&self) -> Sample {
+        let mut total = Sample::default();
+        for &sample in self.samples.values() {
+            total += sample;
+        }
+        total
+    }
+
+    /// One line per call stack with the cycles spent in it, like `main;draw;plot 1234`. Without
+    /// any known cycles, the instructions are counted instead.
+    pub fn collapsed_stacks(&self) -> String {
+        let use_cycles = self.total().cycles > 0;
+        self.stacks
+            .iter()
+            .map(|(stack, sample)| {
+                let weight = if use_cycles {
+                    sample.cycles
+                } else {
+                    sample.count
+                };
+                format!("{} {}\n", stack, weight)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiler() {
+        let mut profiler = Profiler::new();
+        profiler.record(0x1000, "main", 4, Flow::Continue);
+        profiler.record(0x1002, "main", 18, Flow::Call);
+        profiler.record(0x2000, "sub", 4, Flow::Continue);
+        profiler.record(0x2000, "sub", 4, Flow::Continue);
+        profiler.record(0x2002, "sub", 16, Flow::Return);
+        profiler.record_interrupt();
+        profiler.record(0x3000, "handler", 20, Flow::Return);
+        profiler.record(0x1006, "main", 4, Flow::Continue);
+        // Falling through into another routine replaces the current one.
+        profiler.record(0x1008, "exit", 4, Flow::Continue);
+
+        assert_eq!(
+            profiler.samples()[&0x2000],
+            Sample {
+                count: 2,
+                cycles: 8
+            }
+        );
+        assert_eq!(
+            profiler.total(),
+            Sample {
+                count: 8,
+                cycles: 74
+            }
+        );
+        assert_eq!(
+            profiler.collapsed_stacks(),
+            "exit 4\nmain 70\nmain;handler 20\nmain;sub 24\n"
+        );
+    }
+}
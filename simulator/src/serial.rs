@@ -0,0 +1,200 @@
+//! Serial ports connecting the simulated machine to the outside, and a simple UART using one.
+
+use crate::bus::{Bus, Device};
+use crate::exception::Exception;
+use m68k_reloaded_common::{Byte, LongWord};
+use m68k_reloaded_parser::statements::Size;
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// The outside end of a serial port: the bytes sent to the machine and where its output goes.
+pub struct Serial {
+    input: Receiver<Byte>,
+    received: VecDeque<Byte>,
+    output: Box<dyn Write>,
+}
+
+impl Serial {
+    pub fn new(input: Receiver<Byte>, output: Box<dyn Write>) -> Serial {
+        Serial {
+            input,
+            received: VecDeque::new(),
+            output,
+        }
+    }
+
+    /// Connected to the standard input and output. The input is read by a thread, so that the
+    /// simulation keeps running while there is nothing to read.
+    pub fn stdio() -> Serial {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(io::stdin()).bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        Serial::new(receiver, Box::new(io::stdout()))
+    }
+
+    /// Receives nothing and discards the output.
+    pub fn disconnected() -> Serial {
+        let (_, receiver) = mpsc::channel();
+        Serial::new(receiver, Box::new(io::sink()))
+    }
+
+    /// Takes the bytes that arrived since the last call.
+    pub fn poll(&mut self) {
+        while let Ok(byte) = self.input.try_recv() {
+            self.received.push_back(byte);
+        }
+    }
+
+    pub fn is_receive_ready(&self) -> bool {
+        !self.received.is_empty()
+    }
+
+    /// The next received byte without taking it.
+    pub fn peek(&self) -> Option<Byte> {
+        self.received.front().copied()
+    }
+
+    pub fn receive(&mut self) -> Option<Byte> {
+        self.received.pop_front()
+    }
+
+    /// Sends the byte. Errors of the output are ignored, like a cable that isn't plugged in.
+    pub fn transmit(&mut self, byte: Byte) {
+        let _ = self.output.write_all(&[byte]);
+        let _ = self.output.flush();
+    }
+}
+
+/// The offsets of the UART's byte registers.
+pub const UART_DATA: LongWord = 0;
+pub const UART_STATUS: LongWord = 1;
+
+/// The bits of the status register.
+pub const RECEIVE_READY: Byte = 0x01;
+pub const TRANSMIT_READY: Byte = 0x02;
+
+/// A minimal UART that is polled by the program. Reading the data register takes a received byte
+/// and writing it transmits one. The status register tells whether a byte was received;
+/// transmitting is always possible.
+pub struct Uart {
+    serial: Serial,
+}
+
+impl Uart {
+    pub fn new(serial: Serial) -> Uart {
+        Uart { serial }
+    }
+
+    fn status(&self) -> Byte {
+        let receive_ready = if self.serial.is_receive_ready() {
+            RECEIVE_READY
+        } else {
+            0
+        };
+        receive_ready | TRANSMIT_READY
+    }
+}
+
+impl Bus for Uart {
+    fn read(&mut self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        self.serial.poll();
+        match (address, size) {
+            (UART_DATA, Size::Byte) => Ok(LongWord::from(self.serial.receive().unwrap_or(0))),
+            _ => self.peek(address, size),
+        }
+    }
+
+    fn write(&mut self, address: LongWord, size: Size, value: LongWord) -> Result<(), Exception> {
+        match (address, size) {
+            (UART_DATA, Size::Byte) => {
+                self.serial.transmit(value as Byte);
+                Ok(())
+            }
+            _ => Err(Exception::BusError(address)),
+        }
+    }
+
+    fn peek(&self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        match (address, size) {
+            (UART_DATA, Size::Byte) => Ok(LongWord::from(self.serial.peek().unwrap_or(0))),
+            (UART_STATUS, Size::Byte) => Ok(LongWord::from(self.status())),
+            _ => Err(Exception::BusError(address)),
+        }
+    }
+
+    fn tick(&mut self) -> Option<Byte> {
+        self.serial.poll();
+        None
+    }
+}
+
+impl Device for Uart {
+    fn size(&self) -> LongWord {
+        2
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc::Sender;
+
+    /// Output that can still be inspected after it's moved into a serial port.
+    #[derive(Clone, Default)]
+    pub struct SharedOutput(pub Rc<RefCell<Vec<Byte>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    pub fn serial() -> (Serial, Sender<Byte>, SharedOutput) {
+        let (sender, receiver) = mpsc::channel();
+        let output = SharedOutput::default();
+        (
+            Serial::new(receiver, Box::new(output.clone())),
+            sender,
+            output,
+        )
+    }
+
+    #[test]
+    fn test_uart() {
+        let (serial, input, output) = serial();
+        let mut uart = Uart::new(serial);
+        assert_eq!(uart.read(UART_STATUS, Size::Byte), Ok(0x02));
+        input.send(b'a').unwrap();
+        assert_eq!(uart.tick(), None);
+        assert_eq!(uart.peek(UART_STATUS, Size::Byte), Ok(0x03));
+        assert_eq!(uart.peek(UART_DATA, Size::Byte), Ok(0x61));
+        assert_eq!(uart.read(UART_DATA, Size::Byte), Ok(0x61));
+        assert_eq!(uart.read(UART_STATUS, Size::Byte), Ok(0x02));
+        uart.write(UART_DATA, Size::Byte, LongWord::from(b'!'))
+            .unwrap();
+        assert_eq!(*output.0.borrow(), b"!");
+        assert_eq!(
+            uart.write(UART_STATUS, Size::Byte, 0),
+            Err(Exception::BusError(1))
+        );
+        assert_eq!(
+            uart.read(UART_DATA, Size::Word),
+            Err(Exception::BusError(0))
+        );
+    }
+}
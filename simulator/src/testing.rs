@@ -0,0 +1,561 @@
+//! Unit tests for assembly routines, run headless in the simulator.
+//!
+//! Tests are declared in comments before the global label of the routine they test:
+//!
+//! ```text
+//! ; test doubles: D0=21 => D0=42 Z=0
+//! ; test stores: A0=buffer D0=$ff => (buffer).B=$ff
+//! double ADD.L D0,D0
+//!  RTS
+//! ```
+//!
+//! Before the `=>` come the registers and memory set up before the call, after it the values
+//! expected after the routine returned. Registers are `D0` to `D7` and `A0` to `A6`, memory is
+//! written as `(address)`, and both may have a size like `.B` (registers default to `.L`, memory to
+//! `.W`). Flags `X`, `N`, `Z`, `V` and `C` can be expected to be 0 or 1. Values are numbers like
+//! `42`, `-1`, `$2a` and `%101010` or labels, and have to fit into the size. `limit=<steps>`
+//! changes how many instructions the routine may execute.
+//!
+//! Every test starts with a freshly loaded program. The routine is called like `JSR` does and runs
+//! until it returns. Failures are reported as errors at the routine.
+
+use crate::bus::{bytes_of, Bus};
+use crate::cpu::*;
+use crate::debugger::{MEMORY_SIZE, ORIGIN};
+use crate::exception::Exception;
+use crate::memory::Memory;
+use m68k_reloaded_assembler::assemble::{assemble, Assembled};
+use m68k_reloaded_common::errors::{Error, ErrorCollector};
+use m68k_reloaded_common::{LongWord, Range, Word};
+use m68k_reloaded_parser::parse::parse;
+use m68k_reloaded_parser::statements::{LabelScope, Program, Size, Statement};
+use m68k_reloaded_scanner::{scan, Token};
+
+/// Routines return here, where there's no code. This is below [ORIGIN], so it can't be the address
+/// of an instruction of the program.
+const RETURN_ADDRESS: LongWord = 0x800;
+
+/// How many instructions a routine may execute by default, so that endless loops fail the test.
+const DEFAULT_LIMIT: usize = 100_000;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+enum Target {
+    D(usize, Size),
+    A(usize, Size),
+    Flag(Word),
+    Memory(LongWord, Size),
+}
+
+impl Target {
+    fn size(self) -> Size {
+        match self {
+            Target::D(_, size) | Target::A(_, size) | Target::Memory(_, size) => size,
+            Target::Flag(_) => Size::Byte,
+        }
+    }
+
+    fn describe(self) -> String {
+        let suffix = |size| match size {
+            Size::Byte => ".B",
+            Size::Word => ".W",
+            Size::LongWord => "",
+        };
+        match self {
+            Target::D(index, size) => format!("D{}{}", index, suffix(size)),
+            Target::A(index, size) => format!("A{}{}", index, suffix(size)),
+            Target::Flag(flag) => match flag {
+                EXTEND => "X",
+                NEGATIVE => "N",
+                ZERO => "Z",
+                OVERFLOW => "V",
+                _ => "C",
+            }
+            .to_string(),
+            Target::Memory(address, size) => {
+                let suffix = match size {
+                    Size::Byte => ".B",
+                    Size::Word => "",
+                    Size::LongWord => ".L",
+                };
+                format!("(${:06X}){}", address, suffix)
+            }
+        }
+    }
+}
+
+/// A test declared in a comment.
+#[derive(Eq, PartialEq, Debug)]
+struct Test {
+    name: String,
+    /// The range of the comment.
+    range: Range,
+    setup: Vec<(Target, LongWord)>,
+    expectations: Vec<(Target, LongWord)>,
+    limit: usize,
+}
+
+/// The outcome of a test. Why it failed is reported as an error.
+#[derive(Eq, PartialEq, Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub routine: String,
+    pub passed: bool,
+}
+
+/// Assembles the source and runs all tests declared in it. If the source has errors, no tests
+/// are run.
+pub fn run_tests(source: &str, errors: &mut ErrorCollector) -> Vec<TestResult> {
+    let tokens: Vec<Token> = scan(source, errors).collect();
+    let program = parse(tokens, errors);
+    let assembled = assemble(&program, ORIGIN, errors);
+    assembled.report_imports(errors);
+    if !errors.is_empty() {
+        return vec![];
+    }
+
+    let fits = assembled
+        .segments
+        .iter()
+        .all(|segment| segment.address as usize + segment.bytes.len() <= MEMORY_SIZE);
+    let mut results = vec![];
+    for (routine, range, tests) in find_tests(&program, &assembled, errors) {
+        let address = assembled.labels[&routine];
+        for test in tests {
+            if !fits {
+                errors.push(Error::invalid_test(
+                    test.range,
+                    "The program doesn't fit into memory.",
+                ));
+                continue;
+            }
+            let failures = run_test(&test, &assembled, address);
+            if !failures.is_empty() {
+                errors.push(Error::test_failed(range.clone(), &test.name, &failures));
+            }
+            results.push(TestResult {
+                name: test.name,
+                routine: routine.clone(),
+                passed: failures.is_empty(),
+            });
+        }
+    }
+    results
+}
+
+/// The tests of every routine, along with its name and the range from its label to its last
+/// statement.
+fn find_tests(
+    program: &Program,
+    assembled: &Assembled,
+    errors: &mut ErrorCollector,
+) -> Vec<(String, Range, Vec<Test>)> {
+    let mut routines: Vec<(String, Range, Vec<Test>)> = vec![];
+    let mut pending: Vec<Test> = vec![];
+    for statement in program {
+        match &statement.value {
+            Statement::Comment(comment) => {
+                let text = comment.get(1..).unwrap_or("").trim();
+                if let Some(spec) = text.strip_prefix("test ") {
+                    match parse_test(spec, assembled) {
+                        Ok(test) => pending.push(Test {
+                            range: statement.range.clone(),
+                            ..test
+                        }),
+                        Err(reason) => {
+                            errors.push(Error::invalid_test(statement.range.clone(), &reason))
+                        }
+                    }
+                }
+                continue;
+            }
+            Statement::Label(label) if LabelScope::of(label) == LabelScope::Global => {
+                routines.push((
+                    label.clone(),
+                    statement.range.clone(),
+                    std::mem::take(&mut pending),
+                ));
+                continue;
+            }
+            _ => {}
+        }
+        for test in pending.drain(..) {
+            errors.push(Error::test_without_routine(test.range, &test.name));
+        }
+        if let Some((_, range, _)) = routines.last_mut() {
+            range.end = statement.range.end;
+        }
+    }
+    for test in pending {
+        errors.push(Error::test_without_routine(test.range, &test.name));
+    }
+    routines.retain(|(_, _, tests)| !tests.is_empty());
+    routines
+}
+
+/// Parses a test like `name: D0=1 => D0=2`, which follows `test` in a comment.
+fn parse_test(spec: &str, assembled: &Assembled) -> Result<Test, String> {
+    let (name, rest) = spec
+        .split_once(':')
+        .ok_or("Expected a colon after the name.")?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err("Expected a name without spaces.".to_string());
+    }
+    let (setup, expectations) = rest
+        .split_once("=>")
+        .ok_or("Expected => between the setup and the expectations.")?;
+
+    let mut test = Test {
+        name: name.to_string(),
+        range: 0..0,
+        setup: vec![],
+        expectations: vec![],
+        limit: DEFAULT_LIMIT,
+    };
+    for item in setup.split_whitespace() {
+        let (target, value) = item
+            .split_once('=')
+            .ok_or_else(|| format!("Expected a value for {}.", item))?;
+        if target == "limit" {
+            test.limit = value
+                .parse()
+                .map_err(|_| format!("Expected a number of steps, found {}.", value))?;
+            continue;
+        }
+        let target = parse_target(target, assembled)?;
+        if let Target::Flag(_) = target {
+            return Err("Flags can only be expected, not set up.".to_string());
+        }
+        test.setup
+            .push((target, parse_value(value, target, assembled)?));
+    }
+    for item in expectations.split_whitespace() {
+        let (target, value) = item
+            .split_once('=')
+            .ok_or_else(|| format!("Expected a value for {}.", item))?;
+        let target = parse_target(target, assembled)?;
+        test.expectations
+            .push((target, parse_value(value, target, assembled)?));
+    }
+    Ok(test)
+}
+
+fn parse_target(text: &str, assembled: &Assembled) -> Result<Target, String> {
+    let (base, size) = match text.rsplit_once('.') {
+        Some((base, "B")) | Some((base, "b")) => (base, Some(Size::Byte)),
+        Some((base, "W")) | Some((base, "w")) => (base, Some(Size::Word)),
+        Some((base, "L")) | Some((base, "l")) => (base, Some(Size::LongWord)),
+        _ => (text, None),
+    };
+    if let Some(address) = base.strip_prefix('(').and_then(|b| b.strip_suffix(')')) {
+        let address = parse_number(address, assembled)
+            .ok_or_else(|| format!("Unknown address {}.", address))?;
+        if size != Some(Size::Byte) && address & 1 != 0 {
+            return Err(format!("The address ${:06X} is odd.", address));
+        }
+        return Ok(Target::Memory(address, size.unwrap_or(Size::Word)));
+    }
+    let size = size.unwrap_or(Size::LongWord);
+    let register = |kind: char| {
+        base.strip_prefix(kind)
+            .or_else(|| base.strip_prefix(kind.to_ascii_lowercase()))
+            .and_then(|index| index.parse::<usize>().ok())
+    };
+    match (register('D'), register('A'), base) {
+        (Some(index), _, _) if index < 8 => Ok(Target::D(index, size)),
+        (_, Some(7), _) => Err("A7 is the stack pointer used to return.".to_string()),
+        (_, Some(index), _) if index < 7 => Ok(Target::A(index, size)),
+        (_, _, "X") => Ok(Target::Flag(EXTEND)),
+        (_, _, "N") => Ok(Target::Flag(NEGATIVE)),
+        (_, _, "Z") => Ok(Target::Flag(ZERO)),
+        (_, _, "V") => Ok(Target::Flag(OVERFLOW)),
+        (_, _, "C") => Ok(Target::Flag(CARRY)),
+        _ => Err(format!("Unknown register or flag {}.", text)),
+    }
+}
+
+/// Parses a value, truncated to the size of the target.
+fn parse_value(text: &str, target: Target, assembled: &Assembled) -> Result<LongWord, String> {
+    let value = parse_number(text, assembled)
+        .ok_or_else(|| format!("Expected a number or label, found {}.", text))?;
+    if let Target::Flag(_) = target {
+        return match value {
+            0 | 1 => Ok(value),
+            _ => Err(format!("Flags are 0 or 1, found {}.", text)),
+        };
+    }
+    // Negative values are sign-extended, so they fit if their upper bits are all set.
+    let size = target.size();
+    let sign_bits = !(mask(size) >> 1);
+    if value & !mask(size) == 0 || value & sign_bits == sign_bits {
+        Ok(value & mask(size))
+    } else {
+        Err(format!("{} doesn't fit into {}.", text, target.describe()))
+    }
+}
+
+fn parse_number(text: &str, assembled: &Assembled) -> Option<LongWord> {
+    if let Some(negated) = text.strip_prefix('-') {
+        return parse_number(negated, assembled).map(LongWord::wrapping_neg);
+    }
+    if let Some(hex) = text.strip_prefix('$') {
+        LongWord::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix('%') {
+        LongWord::from_str_radix(binary, 2).ok()
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse().ok()
+    } else {
+        assembled.labels.get(text).copied()
+    }
+}
+
+/// Runs the test and returns why it failed, if it did.
+fn run_test(test: &Test, assembled: &Assembled, routine: LongWord) -> Vec<String> {
+    let mut memory = Memory::new(MEMORY_SIZE);
+    for segment in &assembled.segments {
+        memory
+            .load(segment.address, &segment.bytes)
+            .expect("The size of the program is checked before running tests.");
+    }
+    let mut cpu = Cpu::new(routine, MEMORY_SIZE as LongWord);
+    for &(target, value) in &test.setup {
+        if let Err(exception) = set(&mut cpu, &mut memory, target, value) {
+            return vec![format!(
+                "Setting up {} failed: {}",
+                target.describe(),
+                exception
+            )];
+        }
+    }
+    // Like JSR, push the address to return to.
+    cpu.a[7] -= 4;
+    let stack_pointer = cpu.a[7];
+    memory
+        .write(stack_pointer, Size::LongWord, RETURN_ADDRESS)
+        .expect("The stack isn't in memory.");
+
+    let mut steps = 0;
+    while !(cpu.pc == RETURN_ADDRESS && cpu.a[7] == stack_pointer + 4) {
+        if steps == test.limit {
+            return vec![format!(
+                "The routine didn't return within {} instructions.",
+                test.limit
+            )];
+        }
+        if let Err(exception) = cpu.step(&mut memory) {
+            return vec![format!(
+                "The routine caused an exception at ${:08X}: {}",
+                cpu.pc, exception
+            )];
+        }
+        steps += 1;
+    }
+
+    let mut failures = vec![];
+    for &(target, expected) in &test.expectations {
+        match get(&cpu, &memory, target) {
+            Ok(actual) if actual == expected => {}
+            Ok(actual) => failures.push(format!(
+                "{} is {}, but {} was expected.",
+                target.describe(),
+                format_value(actual, target),
+                format_value(expected, target)
+            )),
+            Err(exception) => failures.push(format!(
+                "Reading {} failed: {}",
+                target.describe(),
+                exception
+            )),
+        }
+    }
+    failures
+}
+
+fn format_value(value: LongWord, target: Target) -> String {
+    match target {
+        Target::Flag(_) => value.to_string(),
+        _ => format!(
+            "${:0width$X}",
+            value,
+            width = 2 * bytes_of(target.size()) as usize
+        ),
+    }
+}
+
+fn set(
+    cpu: &mut Cpu,
+    memory: &mut Memory,
+    target: Target,
+    value: LongWord,
+) -> Result<(), Exception> {
+    let merge = |register: &mut LongWord, size| {
+        *register = (*register & !mask(size)) | value;
+    };
+    match target {
+        Target::D(index, size) => merge(&mut cpu.d[index], size),
+        Target::A(index, size) => merge(&mut cpu.a[index], size),
+        Target::Flag(_) => unreachable!("Flags can't be set up."),
+        Target::Memory(address, size) => memory.write(address, size, value)?,
+    }
+    Ok(())
+}
+
+fn get(cpu: &Cpu, memory: &Memory, target: Target) -> Result<LongWord, Exception> {
+    match target {
+        Target::D(index, size) => Ok(cpu.d[index] & mask(size)),
+        Target::A(index, size) => Ok(cpu.a[index] & mask(size)),
+        Target::Flag(flag) => Ok(LongWord::from(cpu.flag(flag))),
+        Target::Memory(address, size) => memory.peek(address, size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "; test doubles: D0=21 => D0=42 Z=0 N=0
+; test overflows: D0=$40000000 => D0=$80000000 N=1 V=1
+double ADD.L D0,D0
+ RTS
+
+; test stores: A0=buffer D0=-1 limit=10 => (buffer).B=$ff (buffer)=$ff71
+store MOVE.B D0,(A0)
+ RTS
+
+; test loops: limit=100 =>
+loop BRA loop
+buffer NOP
+";
+
+    fn run(source: &str) -> (Vec<TestResult>, ErrorCollector) {
+        let mut errors = vec![];
+        let results = run_tests(source, &mut errors);
+        (results, errors)
+    }
+
+    #[test]
+    fn test_indented_tests() {
+        let (results, errors) =
+            run("    ; test doubles: D0=2 => D0=4\n    double: ADD.L D0,D0\n        RTS");
+        assert!(errors.is_empty());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].routine, "double");
+        assert!(results[0].passed);
+    }
+
+    #[test]
+    fn test_passing_and_failing_tests() {
+        let (results, errors) = run(SOURCE);
+        let passed: Vec<(&str, bool)> = results
+            .iter()
+            .map(|result| (result.name.as_str(), result.passed))
+            .collect();
+        assert_eq!(
+            passed,
+            vec![
+                ("doubles", true),
+                ("overflows", true),
+                ("stores", true),
+                ("loops", false)
+            ]
+        );
+        assert_eq!(results[2].routine, "store");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "test_failed");
+        assert_eq!(
+            errors[0].message,
+            "The test 'loops' failed. The routine didn't return within 100 instructions."
+        );
+        let routine = SOURCE.find("loop BRA").unwrap();
+        assert_eq!(errors[0].range, routine..routine + 13);
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let source = "; test wrong: D1=2 D0.W=$1234 => D0=$1234 D1.B=3 C=1 (0)=0\nf RTS";
+        let (results, errors) = run(source);
+        assert!(!results[0].passed);
+        assert_eq!(
+            errors[0].message,
+            "The test 'wrong' failed. D1.B is $02, but $03 was expected. \
+             C is 0, but 1 was expected."
+        );
+        assert_eq!(errors[0].range, 59..64);
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let (_, errors) = run("; test crashes: A0=1 =>\nf MOVE.W (A0),D0\n RTS");
+        assert_eq!(
+            errors[0].message,
+            "The test 'crashes' failed. The routine caused an exception at $00001000: \
+             Address error at $00000001."
+        );
+    }
+
+    #[test]
+    fn test_invalid_tests() {
+        let source = "; test a D0=1\n; test b: A7=0 =>\n; test c: => Q=1\n; test d: Z=1 =>\n\
+                      ; test e: D0=x =>\n; test g: D0.B=$1FF =>\n NOP\n; test f: =>";
+        let (results, errors) = run(source);
+        assert!(results.is_empty());
+        let messages: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|error| (error.code, error.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    "invalid_test",
+                    "The test can't be read: Expected a colon after the name."
+                ),
+                (
+                    "invalid_test",
+                    "The test can't be read: A7 is the stack pointer used to return."
+                ),
+                (
+                    "invalid_test",
+                    "The test can't be read: Unknown register or flag Q."
+                ),
+                (
+                    "invalid_test",
+                    "The test can't be read: Flags can only be expected, not set up."
+                ),
+                (
+                    "invalid_test",
+                    "The test can't be read: Expected a number or label, found x."
+                ),
+                (
+                    "invalid_test",
+                    "The test can't be read: $1FF doesn't fit into D0.B."
+                ),
+                (
+                    "test_without_routine",
+                    "The test 'f' has to be followed by the global label of the routine it tests."
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_values_are_sign_extended() {
+        let source =
+            "; test negative: D0.B=-1 (buffer).B=-128 => D0=$FF (buffer).B=$80\nf RTS\nbuffer";
+        let (results, errors) = run(source);
+        assert!(errors.is_empty());
+        assert!(results[0].passed);
+    }
+
+    #[test]
+    fn test_programs_larger_than_memory() {
+        let filler = " MOVE.L #$12345678,$12345678\n".repeat(MEMORY_SIZE / 10);
+        let (results, errors) = run(&format!("; test big: =>\nf RTS\n{}", filler));
+        assert!(results.is_empty());
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["The test can't be read: The program doesn't fit into memory."]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! A programmable timer that requests interrupts periodically.
+
+use crate::bus::{Bus, Device};
+use crate::exception::Exception;
+use m68k_reloaded_common::{Byte, LongWord, Word};
+use m68k_reloaded_parser::statements::Size;
+
+// The offsets of the registers.
+
+/// The number of instructions between two expirations, a long word.
+pub const TIMER_PERIOD: LongWord = 0;
+/// The instructions left until the next expiration, a read-only long word.
+pub const TIMER_COUNTER: LongWord = 4;
+/// A word with the bits [ENABLE] and [INTERRUPT_ENABLE].
+pub const TIMER_CONTROL: LongWord = 8;
+/// A word with the bit [EXPIRED], which is cleared by writing it.
+pub const TIMER_STATUS: LongWord = 10;
+
+pub const ENABLE: Word = 0x0001;
+pub const INTERRUPT_ENABLE: Word = 0x0002;
+pub const EXPIRED: Word = 0x0001;
+
+/// Counts down from its period after every instruction while it's enabled. When the counter
+/// reaches zero, it sets the expired bit, starts over and, if enabled, requests an interrupt
+/// until the program clears the bit.
+pub struct Timer {
+    level: Byte,
+    period: LongWord,
+    counter: LongWord,
+    control: Word,
+    status: Word,
+}
+
+impl Timer {
+    /// A disabled timer that requests interrupts of the level.
+    pub fn new(level: Byte) -> Timer {
+        Timer {
+            level,
+            period: 0,
+            counter: 0,
+            control: 0,
+            status: 0,
+        }
+    }
+}
+
+impl Bus for Timer {
+    fn read(&mut self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        self.peek(address, size)
+    }
+
+    fn write(&mut self, address: LongWord, size: Size, value: LongWord) -> Result<(), Exception> {
+        match (address, size) {
+            (TIMER_PERIOD, Size::LongWord) => self.period = value,
+            (TIMER_CONTROL, Size::Word) => {
+                let control = value as Word;
+                if control & ENABLE != 0 && self.control & ENABLE == 0 {
+                    self.counter = self.period;
+                }
+                self.control = control & (ENABLE | INTERRUPT_ENABLE);
+            }
+            (TIMER_STATUS, Size::Word) => self.status &= !(value as Word),
+            _ => return Err(Exception::BusError(address)),
+        }
+        Ok(())
+    }
+
+    fn peek(&self, address: LongWord, size: Size) -> Result<LongWord, Exception> {
+        match (address, size) {
+            (TIMER_PERIOD, Size::LongWord) => Ok(self.period),
+            (TIMER_COUNTER, Size::LongWord) => Ok(self.counter),
+            (TIMER_CONTROL, Size::Word) => Ok(LongWord::from(self.control)),
+            (TIMER_STATUS, Size::Word) => Ok(LongWord::from(self.status)),
+            _ => Err(Exception::BusError(address)),
+        }
+    }
+
+    fn tick(&mut self) -> Option<Byte> {
+        if self.control & ENABLE != 0 && self.period != 0 {
+            self.counter = self.counter.saturating_sub(1);
+            if self.counter == 0 {
+                self.status |= EXPIRED;
+                self.counter = self.period;
+            }
+        }
+        let is_requesting = self.status & EXPIRED != 0 && self.control & INTERRUPT_ENABLE != 0;
+        if is_requesting {
+            Some(self.level)
+        } else {
+            None
+        }
+    }
+
+    fn can_interrupt(&self) -> bool {
+        self.control & INTERRUPT_ENABLE != 0
+    }
+}
+
+impl Device for Timer {
+    fn size(&self) -> LongWord {
+        12
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer() {
+        let mut timer = Timer::new(6);
+        timer.write(TIMER_PERIOD, Size::LongWord, 3).unwrap();
+        assert_eq!(timer.tick(), None);
+        timer
+            .write(
+                TIMER_CONTROL,
+                Size::Word,
+                LongWord::from(ENABLE | INTERRUPT_ENABLE),
+            )
+            .unwrap();
+        assert!(timer.can_interrupt());
+        assert_eq!(timer.tick(), None);
+        assert_eq!(timer.read(TIMER_COUNTER, Size::LongWord), Ok(2));
+        assert_eq!(timer.tick(), None);
+        assert_eq!(timer.tick(), Some(6));
+        assert_eq!(timer.read(TIMER_STATUS, Size::Word), Ok(1));
+        assert_eq!(timer.read(TIMER_COUNTER, Size::LongWord), Ok(3));
+        // The request stays until the program clears the expired bit.
+        assert_eq!(timer.tick(), Some(6));
+        timer
+            .write(TIMER_STATUS, Size::Word, LongWord::from(EXPIRED))
+            .unwrap();
+        assert_eq!(timer.tick(), None);
+        assert_eq!(
+            timer.read(TIMER_CONTROL, Size::LongWord),
+            Err(Exception::BusError(8))
+        );
+    }
+}
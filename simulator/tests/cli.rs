@@ -0,0 +1,54 @@
+//! Runs the debugger binary on sources written to a temporary directory.
+
+use std::process::{Command, Output, Stdio};
+
+/// Writes the source to a directory of its own and runs it with the arguments, followed by the
+/// path of the source.
+fn debug(name: &str, source: &str, args: &[&str]) -> Output {
+    let directory = std::env::temp_dir().join(format!("m68k-debugger-{}", name));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("source.s");
+    std::fs::write(&path, source).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_debugger"))
+        .current_dir(&directory)
+        .args(args)
+        .arg(&path)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_uart_writes_to_stdout() {
+    let source = " LEA $FF0000,A0\n MOVE.B #72,(A0)\n MOVE.B #105,(A0)\n STOP #$2700";
+    let output = debug(
+        "uart",
+        source,
+        &["--uart", "$FF0000", "--trace", "trace.txt"],
+    );
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Hi"), "{}", stdout);
+}
+
+#[test]
+fn test_devices_must_not_overlap() {
+    let source = " RTS";
+    let args = [
+        "--uart",
+        "$FF0000",
+        "--timer",
+        "0xff0000",
+        "--trace",
+        "trace.txt",
+    ];
+    let output = debug("overlap", source, &args);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "The region at $FF0000 overlaps another one.\n"
+    );
+}